@@ -0,0 +1,34 @@
+//! Writes versions of the migrations embedded into the binary, used to check the database schema at startup.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+
+    let mut versions = fs::read_dir("migrations")
+        .expect("Can't read migrations directory")
+        .filter_map(|entry| {
+            let path = entry.expect("Can't read migrations directory").path();
+            if !path.is_dir() {
+                return None;
+            }
+            // the same as diesel migration version: name before the first `_` without dashes
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split('_').next())
+                .map(|version| version.replace('-', ""))
+        })
+        .collect::<Vec<_>>();
+    versions.sort();
+
+    let out_path = Path::new(&env::var("OUT_DIR").expect("OUT_DIR is not set")).join("migration_versions.rs");
+    let mut out = fs::File::create(out_path).expect("Can't create migration versions file");
+    writeln!(out, "&[").unwrap();
+    for version in versions {
+        writeln!(out, "    {:?},", version).unwrap();
+    }
+    writeln!(out, "]").unwrap();
+}
@@ -0,0 +1 @@
+DROP INDEX IF EXISTS user_addresses_user_id_priority_idx;
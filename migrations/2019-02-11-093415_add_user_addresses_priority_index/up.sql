@@ -0,0 +1,12 @@
+-- keep only the most recently updated priority address of each user
+UPDATE user_addresses
+SET is_priority = FALSE
+WHERE is_priority = TRUE
+  AND id NOT IN (
+    SELECT DISTINCT ON (user_id) id
+    FROM user_addresses
+    WHERE is_priority = TRUE
+    ORDER BY user_id, updated_at DESC, id DESC
+  );
+
+CREATE UNIQUE INDEX IF NOT EXISTS user_addresses_user_id_priority_idx ON user_addresses (user_id) WHERE is_priority = TRUE;
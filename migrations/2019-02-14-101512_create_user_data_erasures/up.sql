@@ -0,0 +1,10 @@
+CREATE TABLE user_data_erasures (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    erased_by INTEGER NOT NULL,
+    addresses_count INTEGER NOT NULL,
+    roles_count INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX user_data_erasures_user_id_idx ON user_data_erasures (user_id);
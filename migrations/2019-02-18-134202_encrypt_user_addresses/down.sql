@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS user_addresses_blind_index_idx;
+
+ALTER TABLE user_addresses DROP COLUMN IF EXISTS address_blind_index;
+ALTER TABLE user_addresses DROP COLUMN IF EXISTS encryption_key_id;
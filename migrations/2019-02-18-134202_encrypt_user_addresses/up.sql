@@ -0,0 +1,5 @@
+-- rows with NULL key id are plaintext until re-encrypted by reencrypt_user_addresses
+ALTER TABLE user_addresses ADD COLUMN encryption_key_id VARCHAR;
+ALTER TABLE user_addresses ADD COLUMN address_blind_index VARCHAR;
+
+CREATE INDEX user_addresses_blind_index_idx ON user_addresses (user_id, address_blind_index);
@@ -0,0 +1,13 @@
+CREATE TABLE outbox_events (
+    id SERIAL PRIMARY KEY,
+    event_type VARCHAR NOT NULL,
+    payload JSONB NOT NULL,
+    correlation_token VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    last_error VARCHAR,
+    sent_at TIMESTAMP
+);
+
+CREATE INDEX outbox_events_pending_idx ON outbox_events (id) WHERE sent_at IS NULL;
@@ -0,0 +1,40 @@
+-- companies and products had a default, packages never had one
+ALTER TABLE companies ADD COLUMN deliveries_from JSONB NOT NULL DEFAULT '[]';
+ALTER TABLE packages ADD COLUMN deliveries_to JSONB NOT NULL DEFAULT '[]';
+ALTER TABLE products ADD COLUMN deliveries_to JSONB NOT NULL DEFAULT '[]';
+
+-- codes kept in unmigrated_deliveries are put back with the migrated ones
+UPDATE companies SET deliveries_from = codes.alpha3s
+FROM (
+    SELECT company_id, jsonb_agg(country_alpha3 ORDER BY country_alpha3) AS alpha3s FROM (
+        SELECT company_id, country_alpha3 FROM companies_deliveries_from
+        UNION SELECT row_id, country_alpha3 FROM unmigrated_deliveries WHERE table_name = 'companies'
+    ) AS rows GROUP BY company_id
+) AS codes
+WHERE companies.id = codes.company_id;
+
+UPDATE packages SET deliveries_to = codes.alpha3s
+FROM (
+    SELECT package_id, jsonb_agg(country_alpha3 ORDER BY country_alpha3) AS alpha3s FROM (
+        SELECT package_id, country_alpha3 FROM packages_deliveries_to
+        UNION SELECT row_id, country_alpha3 FROM unmigrated_deliveries WHERE table_name = 'packages'
+    ) AS rows GROUP BY package_id
+) AS codes
+WHERE packages.id = codes.package_id;
+
+UPDATE products SET deliveries_to = codes.alpha3s
+FROM (
+    SELECT product_id, jsonb_agg(country_alpha3 ORDER BY country_alpha3) AS alpha3s FROM (
+        SELECT product_id, country_alpha3 FROM products_deliveries_to
+        UNION SELECT row_id, country_alpha3 FROM unmigrated_deliveries WHERE table_name = 'products'
+    ) AS rows GROUP BY product_id
+) AS codes
+WHERE products.id = codes.product_id;
+
+ALTER TABLE packages ALTER COLUMN deliveries_to DROP DEFAULT;
+
+DROP TABLE IF EXISTS unmigrated_deliveries;
+DROP TABLE IF EXISTS products_deliveries_to;
+DROP TABLE IF EXISTS packages_deliveries_to;
+DROP TABLE IF EXISTS companies_deliveries_from;
+ALTER TABLE countries DROP CONSTRAINT IF EXISTS countries_alpha3_key;
@@ -0,0 +1,66 @@
+ALTER TABLE countries ADD CONSTRAINT countries_alpha3_key UNIQUE (alpha3);
+
+CREATE TABLE companies_deliveries_from (
+    company_id INTEGER NOT NULL REFERENCES companies (id) ON DELETE CASCADE,
+    country_alpha3 VARCHAR NOT NULL REFERENCES countries (alpha3),
+    PRIMARY KEY (company_id, country_alpha3)
+);
+
+CREATE TABLE packages_deliveries_to (
+    package_id INTEGER NOT NULL REFERENCES packages (id) ON DELETE CASCADE,
+    country_alpha3 VARCHAR NOT NULL REFERENCES countries (alpha3),
+    PRIMARY KEY (package_id, country_alpha3)
+);
+
+CREATE TABLE products_deliveries_to (
+    product_id INTEGER NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    country_alpha3 VARCHAR NOT NULL REFERENCES countries (alpha3),
+    PRIMARY KEY (product_id, country_alpha3)
+);
+
+CREATE INDEX companies_deliveries_from_country_alpha3_idx ON companies_deliveries_from (country_alpha3);
+CREATE INDEX packages_deliveries_to_country_alpha3_idx ON packages_deliveries_to (country_alpha3);
+CREATE INDEX products_deliveries_to_country_alpha3_idx ON products_deliveries_to (country_alpha3);
+
+-- codes missing from countries can't be referenced by the join tables. Such codes of companies and packages
+-- were never shown by the API, products showed them as they were saved. They are kept here to be fixed by hand
+CREATE TABLE unmigrated_deliveries (
+    table_name VARCHAR NOT NULL,
+    row_id INTEGER NOT NULL,
+    country_alpha3 VARCHAR NOT NULL,
+    PRIMARY KEY (table_name, row_id, country_alpha3)
+);
+
+INSERT INTO companies_deliveries_from (company_id, country_alpha3)
+SELECT DISTINCT companies.id, codes.alpha3
+FROM companies, jsonb_array_elements_text(companies.deliveries_from) AS codes (alpha3)
+WHERE codes.alpha3 IN (SELECT alpha3 FROM countries);
+
+INSERT INTO packages_deliveries_to (package_id, country_alpha3)
+SELECT DISTINCT packages.id, codes.alpha3
+FROM packages, jsonb_array_elements_text(packages.deliveries_to) AS codes (alpha3)
+WHERE codes.alpha3 IN (SELECT alpha3 FROM countries);
+
+INSERT INTO products_deliveries_to (product_id, country_alpha3)
+SELECT DISTINCT products.id, codes.alpha3
+FROM products, jsonb_array_elements_text(products.deliveries_to) AS codes (alpha3)
+WHERE codes.alpha3 IN (SELECT alpha3 FROM countries);
+
+INSERT INTO unmigrated_deliveries (table_name, row_id, country_alpha3)
+SELECT DISTINCT 'companies', companies.id, codes.alpha3
+FROM companies, jsonb_array_elements_text(companies.deliveries_from) AS codes (alpha3)
+WHERE codes.alpha3 NOT IN (SELECT alpha3 FROM countries);
+
+INSERT INTO unmigrated_deliveries (table_name, row_id, country_alpha3)
+SELECT DISTINCT 'packages', packages.id, codes.alpha3
+FROM packages, jsonb_array_elements_text(packages.deliveries_to) AS codes (alpha3)
+WHERE codes.alpha3 NOT IN (SELECT alpha3 FROM countries);
+
+INSERT INTO unmigrated_deliveries (table_name, row_id, country_alpha3)
+SELECT DISTINCT 'products', products.id, codes.alpha3
+FROM products, jsonb_array_elements_text(products.deliveries_to) AS codes (alpha3)
+WHERE codes.alpha3 NOT IN (SELECT alpha3 FROM countries);
+
+ALTER TABLE companies DROP COLUMN deliveries_from;
+ALTER TABLE packages DROP COLUMN deliveries_to;
+ALTER TABLE products DROP COLUMN deliveries_to;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS outbox_endpoint_deliveries;
+DROP TABLE IF EXISTS outbox_lease;
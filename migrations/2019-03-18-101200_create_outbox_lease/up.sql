@@ -0,0 +1,16 @@
+-- Only the replica holding the lease dispatches outbox events and webhook deliveries
+CREATE TABLE outbox_lease (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    holder VARCHAR NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+);
+
+INSERT INTO outbox_lease (id, holder, expires_at) VALUES (1, '', current_timestamp);
+
+-- Endpoints an event has been delivered to, a retried event is sent only to the others
+CREATE TABLE outbox_endpoint_deliveries (
+    outbox_event_id INTEGER NOT NULL REFERENCES outbox_events (id) ON DELETE CASCADE,
+    endpoint VARCHAR NOT NULL,
+    delivered_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (outbox_event_id, endpoint)
+);
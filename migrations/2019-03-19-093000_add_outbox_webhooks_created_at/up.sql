@@ -0,0 +1,6 @@
+ALTER TABLE outbox_events ADD COLUMN webhooks_created_at TIMESTAMP;
+
+-- deliveries of sent events have been created together with marking them as sent
+UPDATE outbox_events SET webhooks_created_at = sent_at WHERE sent_at IS NOT NULL;
+
+CREATE INDEX outbox_events_webhooks_pending_idx ON outbox_events (id) WHERE webhooks_created_at IS NULL;
@@ -0,0 +1,321 @@
+//! Admin tool for delivery operations. Uses services of the library directly with the app config,
+//! so changes are checked by ACL of the acting user and written to the audit log.
+//! Run `delivery_admin --help` for the list of commands.
+
+extern crate base64;
+extern crate delivery_lib;
+#[macro_use]
+extern crate failure;
+extern crate diesel;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate stq_types;
+extern crate tokio_core;
+extern crate uuid;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use diesel::pg::PgConnection;
+use failure::Error as FailureError;
+use serde::Serialize;
+use tokio_core::reactor::Core;
+use uuid::Uuid;
+
+use stq_types::{Alpha3, CompanyPackageId, RoleId, UserId};
+
+use delivery_lib::config::Config;
+use delivery_lib::controller::context::DynamicContext;
+use delivery_lib::models::{get_countries_from_forest_by, CompanyPackage, Country, NewUserRole, RoleName, ShippingRateSource};
+use delivery_lib::repos::ReposFactory;
+use delivery_lib::services::companies::CompaniesService;
+use delivery_lib::services::companies_packages::{CompaniesPackagesService, ReplaceShippingRatesPayload};
+use delivery_lib::services::packages::PackagesService;
+use delivery_lib::services::user_roles::UserRolesService;
+use delivery_lib::services::Service;
+
+const USAGE: &str = "Usage: delivery_admin [--json] --user <superuser_id> <command> [args]
+
+Commands:
+    import-rates <company_package_id> <rates.csv> <zones.csv>   Replace shipping rates with rates from CSV files
+    export-rates <company_package_id> <from_alpha3>             Print shipping rates from the country
+    grant-role <user_id> <role> [data_json]                     Grant delivery role to the user
+    revoke-role <user_id> <role>                                Revoke delivery role from the user
+    list-company-packages                                       Print all company packages
+    check-coverage <company_package_id>                         Print destinations of the package without rates";
+
+/// Result of a command printed as JSON or as text
+struct Output {
+    json: serde_json::Value,
+    text: String,
+}
+
+impl Output {
+    fn new<T: Serialize>(value: &T, text: String) -> Result<Self, FailureError> {
+        Ok(Self {
+            json: serde_json::to_value(value)?,
+            text,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct CompanyPackageRow {
+    id: CompanyPackageId,
+    company_id: i32,
+    company_name: String,
+    package_id: i32,
+    package_name: String,
+    shipping_rate_source: ShippingRateSource,
+}
+
+#[derive(Serialize)]
+struct Coverage {
+    delivery_from: Alpha3,
+    covered: usize,
+    total: usize,
+    missing: Vec<Alpha3>,
+}
+
+fn main() {
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let json = take_flag(&mut args, "--json");
+    let user_id = take_option(&mut args, "--user")
+        .and_then(|user_id| user_id.parse().ok())
+        .map(UserId)
+        .unwrap_or_else(|| exit_with_usage("--user must be set to id of the acting superuser"));
+
+    let config = Config::new().expect("Can't load app config!");
+    let mut core = Core::new().expect("Unexpected error creating event loop core");
+    let context = delivery_lib::create_context(config, &core.handle());
+    let dynamic_context = DynamicContext::new(Some(user_id), format!("admin-{}", Uuid::new_v4()));
+    let service = Service::new(context, dynamic_context);
+
+    match run(&mut core, &service, &args) {
+        Ok(output) => {
+            if json {
+                println!("{}", serde_json::to_string_pretty(&output.json).expect("Can't serialize output"));
+            } else {
+                println!("{}", output.text);
+            }
+        }
+        Err(e) => {
+            let causes = e.causes().map(|cause| cause.to_string()).collect::<Vec<_>>();
+            eprintln!("Error: {}", causes.join(": "));
+            process::exit(1);
+        }
+    }
+}
+
+fn run<F: ReposFactory<PgConnection>>(
+    core: &mut Core,
+    service: &Service<PgConnection, diesel::r2d2::ConnectionManager<PgConnection>, F>,
+    args: &[String],
+) -> Result<Output, FailureError> {
+    let command = args.get(0).map(String::as_str).unwrap_or_default();
+    match command {
+        "import-rates" => {
+            let company_package_id = CompanyPackageId(parse_arg(args, 1, "company_package_id")?);
+            let rates_csv = fs::read(arg(args, 2, "rates.csv")?).map_err(|e| format_err!("Can't read rates CSV: {}", e))?;
+            let zones_csv = fs::read(arg(args, 3, "zones.csv")?).map_err(|e| format_err!("Can't read zones CSV: {}", e))?;
+            let payload = ReplaceShippingRatesPayload {
+                rates_csv_base64: base64::encode(&rates_csv),
+                zones_csv_base64: base64::encode(&zones_csv),
+            };
+            let shipping_rates = core.run(service.replace_shipping_rates(company_package_id, payload))?;
+            let text = match shipping_rates.first() {
+                Some(rates) => format!(
+                    "Imported rates of company package {} from {} to {} countries",
+                    company_package_id,
+                    rates.from_alpha3.0,
+                    shipping_rates.len()
+                ),
+                None => format!("Imported no rates of company package {}", company_package_id),
+            };
+            Output::new(&shipping_rates, text)
+        }
+        "export-rates" => {
+            let company_package_id = CompanyPackageId(parse_arg(args, 1, "company_package_id")?);
+            let delivery_from = Alpha3(arg(args, 2, "from_alpha3")?.to_string());
+            let shipping_rates = core.run(service.get_shipping_rates(company_package_id, delivery_from))?;
+            let text = shipping_rates
+                .iter()
+                .map(|rates| {
+                    let prices = rates
+                        .rates
+                        .iter()
+                        .map(|rate| format!("{}g: {}", rate.weight_g, rate.price))
+                        .collect::<Vec<_>>();
+                    format!("{} -> {}\t{}", rates.from_alpha3.0, rates.to_alpha3.0, prices.join(", "))
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Output::new(&shipping_rates, text)
+        }
+        "grant-role" => {
+            let user_id = UserId(parse_arg(args, 1, "user_id")?);
+            let role = parse_role(arg(args, 2, "role")?)?;
+            let data = match args.get(3) {
+                Some(data) => Some(serde_json::from_str(data).map_err(|e| format_err!("Invalid role data: {}", e))?),
+                None => None,
+            };
+            let user_role = core.run(service.create_role(NewUserRole {
+                id: RoleId::new(),
+                user_id,
+                name: role.clone(),
+                data,
+            }))?;
+            let text = format!("Granted role {:?} to user {}", role, user_id);
+            Output::new(&user_role, text)
+        }
+        "revoke-role" => {
+            let user_id = UserId(parse_arg(args, 1, "user_id")?);
+            let role = parse_role(arg(args, 2, "role")?)?;
+            let user_roles = core.run(service.revoke_role(user_id, role.clone()))?;
+            let text = format!("Revoked {} roles {:?} from user {}", user_roles.len(), role, user_id);
+            Output::new(&user_roles, text)
+        }
+        "list-company-packages" => {
+            let company_packages = core.run(service.list_company_packages())?;
+            let companies = core.run(service.list_companies())?;
+            let packages = core.run(service.list_packages())?;
+            let rows = company_packages
+                .into_iter()
+                .map(|company_package: CompanyPackage| CompanyPackageRow {
+                    id: company_package.id,
+                    company_id: company_package.company_id.0,
+                    company_name: companies
+                        .iter()
+                        .find(|company| company.id == company_package.company_id)
+                        .map(|company| company.name.clone())
+                        .unwrap_or_default(),
+                    package_id: company_package.package_id.0,
+                    package_name: packages
+                        .iter()
+                        .find(|package| package.id == company_package.package_id)
+                        .map(|package| package.name.clone())
+                        .unwrap_or_default(),
+                    shipping_rate_source: company_package.shipping_rate_source,
+                })
+                .collect::<Vec<_>>();
+            let text = rows
+                .iter()
+                .map(|row| {
+                    format!(
+                        "{}\t{} ({})\t{} ({})\t{:?}",
+                        row.id, row.company_name, row.company_id, row.package_name, row.package_id, row.shipping_rate_source
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Output::new(&rows, text)
+        }
+        "check-coverage" => {
+            let company_package_id = CompanyPackageId(parse_arg(args, 1, "company_package_id")?);
+            let company_package = core
+                .run(service.get_company_package(company_package_id))?
+                .ok_or_else(|| format_err!("Company package {} not found", company_package_id))?;
+            let company = core
+                .run(service.find_company(company_package.company_id))?
+                .ok_or_else(|| format_err!("Company {} not found", company_package.company_id.0))?;
+            let package = core
+                .run(service.find_packages(company_package.package_id))?
+                .ok_or_else(|| format_err!("Package {} not found", company_package.package_id.0))?;
+
+            let destinations = country_codes(&package.deliveries_to);
+            let mut coverage = vec![];
+            for delivery_from in country_codes(&company.deliveries_from) {
+                let shipping_rates = core.run(service.get_shipping_rates(company_package_id, delivery_from.clone()))?;
+                let covered = shipping_rates.into_iter().map(|rates| rates.to_alpha3).collect::<Vec<_>>();
+                let missing = destinations
+                    .iter()
+                    .filter(|destination| !covered.contains(*destination))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                coverage.push(Coverage {
+                    delivery_from,
+                    covered: destinations.len() - missing.len(),
+                    total: destinations.len(),
+                    missing,
+                });
+            }
+
+            let text = coverage
+                .iter()
+                .map(|coverage| {
+                    let missing = coverage.missing.iter().map(|alpha3| alpha3.0.clone()).collect::<Vec<_>>();
+                    format!(
+                        "{}: {} of {} destinations have rates, missing: {}",
+                        coverage.delivery_from.0,
+                        coverage.covered,
+                        coverage.total,
+                        if missing.is_empty() {
+                            "none".to_string()
+                        } else {
+                            missing.join(", ")
+                        }
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Output::new(&coverage, text)
+        }
+        _ => exit_with_usage(&format!("Unknown command {}", command)),
+    }
+}
+
+/// Codes of countries in the forest, regions are expanded to their countries
+fn country_codes(countries: &[Country]) -> Vec<Alpha3> {
+    let mut codes = get_countries_from_forest_by(countries.iter(), |country| country.level == Country::COUNTRY_LEVEL)
+        .into_iter()
+        .map(|country| country.alpha3)
+        .collect::<Vec<_>>();
+    codes.sort_by(|a, b| a.0.cmp(&b.0));
+    codes.dedup();
+    codes
+}
+
+fn parse_role(role: &str) -> Result<RoleName, FailureError> {
+    serde_json::from_value(serde_json::Value::String(role.to_string())).map_err(|_| format_err!("Unknown role {}", role))
+}
+
+fn arg<'a>(args: &'a [String], index: usize, name: &str) -> Result<&'a str, FailureError> {
+    args.get(index)
+        .map(String::as_str)
+        .ok_or_else(|| format_err!("Missing argument <{}>", name))
+}
+
+fn parse_arg(args: &[String], index: usize, name: &str) -> Result<i32, FailureError> {
+    arg(args, index, name)?
+        .parse()
+        .map_err(|_| format_err!("Argument <{}> must be a number", name))
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
+}
+
+fn take_option(args: &mut Vec<String>, option: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == option)?;
+    args.remove(index);
+    if index < args.len() {
+        Some(args.remove(index))
+    } else {
+        None
+    }
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(1);
+}
@@ -0,0 +1,41 @@
+//! Re-encrypts personal data of user addresses with the current encryption key.
+//! Add the new key to `encryption.keys`, make it `encryption.current_key_id`, then run:
+//! `reencrypt_user_addresses [batch_size]`. Old keys may be removed from the config afterwards.
+
+extern crate delivery_lib;
+extern crate diesel;
+
+use std::env;
+use std::process;
+
+use diesel::pg::PgConnection;
+use diesel::Connection;
+
+use delivery_lib::config::Config;
+use delivery_lib::repos::user_addresses::{reencrypt_user_addresses, UserAddressCipher};
+
+const DEFAULT_BATCH_SIZE: i64 = 100;
+
+fn main() {
+    let config = Config::new().expect("Can't load app config!");
+
+    let batch_size = env::args()
+        .nth(1)
+        .map(|arg| arg.parse::<i64>().ok().filter(|size| *size > 0).expect("Batch size must be a positive number"))
+        .unwrap_or(DEFAULT_BATCH_SIZE);
+
+    let encryption = config
+        .encryption
+        .as_ref()
+        .expect("Encryption keys must be set in `encryption` config section");
+    let cipher = UserAddressCipher::new(encryption).expect("Failed to create user address cipher");
+    let conn = PgConnection::establish(&config.server.database).expect("Failed to connect to database");
+
+    match reencrypt_user_addresses(&conn, &cipher, batch_size) {
+        Ok(count) => println!("Re-encrypted {} user addresses with key {}", count, encryption.current_key_id),
+        Err(e) => {
+            eprintln!("Re-encryption of user addresses failed: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -1,9 +1,12 @@
 //! Config module contains the top-level config for the app.
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
 
 use sentry_integration::SentryConfig;
 
 use config_crate::{Config as RawConfig, ConfigError, Environment, File};
+use jsonwebtoken::Algorithm;
 use stq_http;
 use stq_logging::GrayLogConfig;
 
@@ -12,8 +15,15 @@ use stq_logging::GrayLogConfig;
 pub struct Config {
     pub server: Server,
     pub client: Client,
+    /// Keys of user address encryption, addresses can't be written or decrypted if not set
+    pub encryption: Option<Encryption>,
+    /// JWT authentication, requests with `Authorization` header are rejected if not set
+    pub jwt: Option<Jwt>,
     pub graylog: Option<GrayLogConfig>,
     pub sentry: Option<SentryConfig>,
+    /// Delivery of outbox events and webhooks, the dispatcher is started with the defaults if not set
+    #[serde(default)]
+    pub outbox: Outbox,
 }
 
 /// Common server settings
@@ -22,9 +32,82 @@ pub struct Server {
     pub host: String,
     pub port: i32,
     pub database: String,
+    /// Read replica of `database` used by read-only endpoints, the primary is used if not set or the replica is unavailable
+    pub read_replica_database: Option<String>,
+    /// Time to wait for a replica connection before falling back to the primary
+    #[serde(default = "default_read_replica_timeout_ms")]
+    pub read_replica_timeout_ms: u64,
+    /// Time the replica is not tried after a failed connection, reads go to the primary meanwhile
+    #[serde(default = "default_read_replica_retry_ms")]
+    pub read_replica_retry_ms: u64,
     pub redis: Option<String>,
     pub thread_count: usize,
     pub cache_ttl_sec: u64,
+    /// Path to TOML file with ACL policy, built in policy is used if not set
+    pub acl_policy_path: Option<String>,
+    /// Allow superusers to act on behalf of other users with `X-Impersonate-User-Id` header
+    #[serde(default)]
+    pub impersonation_enabled: bool,
+    /// Apply pending database migrations at startup
+    #[serde(default)]
+    pub run_migrations: bool,
+    /// Start even if there are pending database migrations
+    #[serde(default)]
+    pub allow_pending_migrations: bool,
+    /// Time between failing readiness checks and closing the listener on SIGTERM or SIGINT,
+    /// load balancers keep sending requests until they notice the instance is not ready
+    #[serde(default = "default_shutdown_delay_ms")]
+    pub shutdown_delay_ms: u64,
+    /// Time given to in-flight requests and cpu pool jobs to finish after the listener is closed
+    #[serde(default = "default_shutdown_timeout_ms")]
+    pub shutdown_timeout_ms: u64,
+    /// Storage the repos work with
+    #[serde(default)]
+    pub repo_backend: RepoBackend,
+    /// Path to JSON file the in-memory backend is seeded from, the store starts empty if not set
+    pub memory_fixtures_path: Option<String>,
+}
+
+fn default_shutdown_delay_ms() -> u64 {
+    5000
+}
+
+fn default_shutdown_timeout_ms() -> u64 {
+    20000
+}
+
+fn default_read_replica_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_read_replica_retry_ms() -> u64 {
+    10000
+}
+
+/// Storage of the repos
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoBackend {
+    /// Postgres database from `database` setting
+    Postgres,
+    /// In-memory store living as long as the process, for development and contract tests only
+    Memory,
+}
+
+impl RepoBackend {
+    /// Name of the backend in readiness report and metrics
+    pub fn name(self) -> &'static str {
+        match self {
+            RepoBackend::Postgres => "postgres",
+            RepoBackend::Memory => "memory",
+        }
+    }
+}
+
+impl Default for RepoBackend {
+    fn default() -> Self {
+        RepoBackend::Postgres
+    }
 }
 
 /// Http client settings
@@ -36,6 +119,82 @@ pub struct Client {
     pub http_timeout_ms: u64,
 }
 
+/// Settings of outbox dispatcher delivering domain events to other services
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Outbox {
+    /// Urls every event is POSTed to
+    pub endpoints: Vec<String>,
+    /// Interval of checking the outbox for new events
+    pub poll_interval_ms: u64,
+    /// Number of events loaded from the outbox at once
+    pub batch_size: i64,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub retry_delay_ms: u64,
+    /// Upper bound of the retry delay
+    pub max_retry_delay_ms: u64,
+    /// Number of failed attempts after which a webhook delivery becomes a dead letter
+    pub webhook_max_attempts: i32,
+    /// Time the dispatcher lease is taken for, only the replica holding the lease dispatches.
+    /// Must be longer than a dispatch round, otherwise another replica may take over in the middle of it
+    pub lease_ttl_ms: u64,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self {
+            endpoints: vec![],
+            poll_interval_ms: 1000,
+            batch_size: 100,
+            retry_delay_ms: 1000,
+            max_retry_delay_ms: 300_000,
+            webhook_max_attempts: 10,
+            lease_ttl_ms: 60000,
+        }
+    }
+}
+
+/// Keys used to encrypt personal data of user addresses
+#[derive(Deserialize, Clone)]
+pub struct Encryption {
+    /// Id of the key used to encrypt new values
+    pub current_key_id: String,
+    /// Base64 encoded 256-bit keys by id, old keys are kept to decrypt values until they are re-encrypted
+    pub keys: HashMap<String, String>,
+    /// Base64 encoded 256-bit key of the blind index used to find equal addresses
+    pub blind_index_key: String,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("current_key_id", &self.current_key_id)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Settings of JWT authentication
+#[derive(Deserialize, Clone)]
+pub struct Jwt {
+    /// Algorithm tokens are signed with
+    pub algorithm: Algorithm,
+    /// Base64 encoded HMAC secret or DER encoded RSA public key
+    pub key: String,
+    /// Accept a plain user id in `Authorization` header, must be enabled for internal traffic only
+    #[serde(default)]
+    pub legacy_user_id_header: bool,
+}
+
+impl fmt::Debug for Jwt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Jwt")
+            .field("algorithm", &self.algorithm)
+            .field("legacy_user_id_header", &self.legacy_user_id_header)
+            .finish()
+    }
+}
+
 /// Creates new app config struct
 /// #Examples
 /// ```
@@ -0,0 +1,223 @@
+//! Authentication of requests by JWT passed in `Authorization` header
+use std::str::FromStr;
+
+use base64;
+use failure::Error as FailureError;
+use failure::Fail;
+use jsonwebtoken::{decode, Validation};
+
+use stq_types::UserId;
+
+use config::Jwt;
+use models::RoleName;
+use errors::Error;
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Claims of JWT issued to the user
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JwtClaims {
+    pub user_id: UserId,
+    pub exp: u64,
+    /// Roles granted by the issuer, the roles of the user are loaded from the database if the claim is missing
+    #[serde(default)]
+    pub roles: Option<Vec<RoleName>>,
+}
+
+/// Authenticated user of the request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authentication {
+    pub user_id: UserId,
+    /// Roles from the token claims, if the token carries them
+    pub roles: Option<Vec<RoleName>>,
+}
+
+pub struct JwtAuthenticator {
+    verifier: Option<JwtVerifier>,
+    legacy_user_id_header: bool,
+}
+
+struct JwtVerifier {
+    validation: Validation,
+    key: Vec<u8>,
+}
+
+impl JwtAuthenticator {
+    pub fn new(config: &Jwt) -> Result<Self, FailureError> {
+        let key = base64::decode(&config.key).map_err(|e| format_err!("JWT key is not valid base64: {}", e))?;
+        if key.is_empty() {
+            return Err(format_err!("JWT key must not be empty"));
+        }
+
+        Ok(Self {
+            verifier: Some(JwtVerifier {
+                validation: Validation::new(config.algorithm),
+                key,
+            }),
+            legacy_user_id_header: config.legacy_user_id_header,
+        })
+    }
+
+    /// Creates authenticator rejecting every request with `Authorization` header, anonymous requests are still served
+    pub fn disabled() -> Self {
+        Self {
+            verifier: None,
+            legacy_user_id_header: false,
+        }
+    }
+
+    /// Creates authenticator with the key from `jwt` config section if it is set
+    pub fn from_config(config: Option<&Jwt>) -> Result<Self, FailureError> {
+        match config {
+            Some(config) => Self::new(config),
+            None => {
+                warn!("JWT authentication is not configured, requests with `Authorization` header are rejected");
+                Ok(Self::disabled())
+            }
+        }
+    }
+
+    /// Returns authenticated user of `Authorization` header value, `None` if the header is missing.
+    /// Fails with `Error::Unauthorized` if the token is invalid or expired.
+    pub fn authenticate(&self, header: Option<&str>) -> Result<Option<Authentication>, FailureError> {
+        let header = match header {
+            Some(header) => header.trim(),
+            None => return Ok(None),
+        };
+
+        if self.legacy_user_id_header {
+            if let Ok(id) = i32::from_str(header) {
+                return Ok(Some(Authentication {
+                    user_id: UserId(id),
+                    roles: None,
+                }));
+            }
+        }
+
+        let verifier = match self.verifier {
+            Some(ref verifier) => verifier,
+            None => {
+                return Err(format_err!("JWT authentication is not configured, `jwt` config section is missing")
+                    .context(Error::Unauthorized)
+                    .into());
+            }
+        };
+
+        let token = if header.starts_with(BEARER_PREFIX) {
+            &header[BEARER_PREFIX.len()..]
+        } else {
+            header
+        };
+
+        decode::<JwtClaims>(token, &verifier.key, &verifier.validation)
+            .map(|token_data| {
+                Some(Authentication {
+                    user_id: token_data.claims.user_id,
+                    roles: token_data.claims.roles,
+                })
+            })
+            .map_err(|e| format_err!("Invalid JWT: {}", e).context(Error::Unauthorized).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use jsonwebtoken::{encode, Algorithm, Header};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"test-jwt-secret";
+
+    fn create_authenticator(legacy_user_id_header: bool) -> JwtAuthenticator {
+        JwtAuthenticator::new(&Jwt {
+            algorithm: Algorithm::HS256,
+            key: base64::encode(SECRET),
+            legacy_user_id_header,
+        })
+        .unwrap()
+    }
+
+    fn create_token(secret: &[u8], exp_offset_sec: i64, roles: Option<Vec<RoleName>>) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let claims = JwtClaims {
+            user_id: UserId(7),
+            exp: (now + exp_offset_sec) as u64,
+            roles,
+        };
+        encode(&Header::default(), &claims, secret).unwrap()
+    }
+
+    fn is_unauthorized(result: Result<Option<Authentication>, FailureError>) -> bool {
+        match result {
+            Err(e) => e.causes().any(|cause| match cause.downcast_ref::<Error>() {
+                Some(Error::Unauthorized) => true,
+                _ => false,
+            }),
+            Ok(_) => false,
+        }
+    }
+
+    #[test]
+    fn test_valid_token() {
+        let authenticator = create_authenticator(false);
+        let token = create_token(SECRET, 3600, Some(vec![RoleName::Superuser]));
+        let result = authenticator.authenticate(Some(&format!("Bearer {}", token))).unwrap();
+        assert_eq!(
+            result,
+            Some(Authentication {
+                user_id: UserId(7),
+                roles: Some(vec![RoleName::Superuser]),
+            })
+        );
+        assert_eq!(authenticator.authenticate(Some(&token)).unwrap().map(|a| a.user_id), Some(UserId(7)));
+    }
+
+    #[test]
+    fn test_token_without_roles() {
+        let authenticator = create_authenticator(false);
+        let token = create_token(SECRET, 3600, None);
+        assert_eq!(authenticator.authenticate(Some(&token)).unwrap().and_then(|a| a.roles), None);
+    }
+
+    #[test]
+    fn test_missing_header() {
+        let authenticator = create_authenticator(false);
+        assert_eq!(authenticator.authenticate(None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let authenticator = create_authenticator(false);
+        let token = create_token(SECRET, -3600, None);
+        assert!(is_unauthorized(authenticator.authenticate(Some(&token))));
+    }
+
+    #[test]
+    fn test_token_with_wrong_signature() {
+        let authenticator = create_authenticator(false);
+        let token = create_token(b"other-secret", 3600, None);
+        assert!(is_unauthorized(authenticator.authenticate(Some(&token))));
+    }
+
+    #[test]
+    fn test_legacy_user_id_header() {
+        assert!(is_unauthorized(create_authenticator(false).authenticate(Some("7"))));
+        assert_eq!(
+            create_authenticator(true).authenticate(Some("7")).unwrap(),
+            Some(Authentication {
+                user_id: UserId(7),
+                roles: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_disabled_authenticator() {
+        let authenticator = JwtAuthenticator::from_config(None).unwrap();
+        assert_eq!(authenticator.authenticate(None).unwrap(), None);
+        assert!(is_unauthorized(authenticator.authenticate(Some(&create_token(SECRET, 3600, None)))));
+        assert!(is_unauthorized(authenticator.authenticate(Some("7"))));
+    }
+}
@@ -0,0 +1,97 @@
+//! `Application` serves every response as JSON, plain text handlers set the content type of their response
+//! in `ResponseContentType` of the connection and it replaces the JSON one once the controller has responded.
+use std::sync::{Arc, Mutex};
+
+use futures::prelude::*;
+use hyper;
+use hyper::server::{Request, Response, Service};
+use hyper::StatusCode;
+
+/// Content type of plain text responses
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+/// Content type of Prometheus text exposition format served on `/metrics`
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Checks if plain text is requested with `format=text` query parameter
+pub fn is_text_format(query: Option<&str>) -> bool {
+    query.unwrap_or_default().split('&').any(|pair| pair == "format=text")
+}
+
+/// Content type of the response to the request being handled on a connection, `None` for JSON.
+/// A connection handles one request at a time, so the slot is shared by the controller and `PlainTextResponses` of it.
+#[derive(Clone, Default)]
+pub struct ResponseContentType(Arc<Mutex<Option<&'static str>>>);
+
+impl ResponseContentType {
+    /// Sets the content type of the response to the current request
+    pub fn set(&self, content_type: &'static str) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = Some(content_type);
+        }
+    }
+
+    /// Takes the content type of the response, the next request starts with JSON
+    pub fn take(&self) -> Option<&'static str> {
+        self.0.lock().ok().and_then(|mut current| current.take())
+    }
+}
+
+/// Wraps `Application` and sets content type of successful responses given by plain text handlers
+pub struct PlainTextResponses<S> {
+    inner: S,
+    content_type: ResponseContentType,
+}
+
+impl<S> PlainTextResponses<S> {
+    pub fn new(inner: S, content_type: ResponseContentType) -> Self {
+        Self { inner, content_type }
+    }
+}
+
+impl<S> Service for PlainTextResponses<S>
+where
+    S: Service<Request = Request, Response = Response, Error = hyper::Error>,
+    S::Future: 'static,
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let content_type = self.content_type.clone();
+
+        Box::new(self.inner.call(req).map(move |mut response| {
+            if let Some(content_type) = content_type.take() {
+                if response.status() == StatusCode::Ok {
+                    response.headers_mut().set_raw("Content-Type", content_type);
+                }
+            }
+            response
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_text_format() {
+        assert!(is_text_format(Some("format=text")));
+        assert!(is_text_format(Some("lang=en&format=text")));
+        assert!(!is_text_format(Some("format=json")));
+        assert!(!is_text_format(None));
+    }
+
+    #[test]
+    fn test_response_content_type() {
+        let content_type = ResponseContentType::default();
+        assert_eq!(content_type.take(), None);
+
+        content_type.clone().set(METRICS_CONTENT_TYPE);
+        assert_eq!(content_type.take(), Some(METRICS_CONTENT_TYPE));
+        assert_eq!(content_type.take(), None);
+    }
+}
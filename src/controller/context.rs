@@ -6,14 +6,20 @@ use diesel::pg::Pg;
 use diesel::Connection;
 use futures_cpupool::CpuPool;
 use r2d2::{ManageConnection, Pool};
+use r2d2_redis::RedisConnectionManager;
 
 use stq_http::client::ClientHandle;
 use stq_router::RouteParser;
-use stq_types::UserId;
+use stq_types::{RoleId, UserId};
 
+use super::auth::JwtAuthenticator;
 use super::routes::*;
 use config::Config;
+use models::{RoleName, UserRole};
+use replica::ReadReplica;
+use repos::acl::AclPolicy;
 use repos::repo_factory::*;
+use shutdown::Shutdown;
 
 /// Static context for all app
 pub struct StaticContext<T, M, F>
@@ -23,11 +29,19 @@ where
     F: ReposFactory<T>,
 {
     pub db_pool: Pool<M>,
+    /// Read replica used by read-only service methods, if a replica is configured
+    pub read_replica: Option<ReadReplica<M>>,
     pub cpu_pool: CpuPool,
     pub config: Arc<Config>,
     pub route_parser: Arc<RouteParser<Route>>,
     pub client_handle: ClientHandle,
     pub repo_factory: F,
+    pub authenticator: Arc<JwtAuthenticator>,
+    pub acl_policy: Arc<AclPolicy>,
+    /// Redis pool, checked by readiness check if Redis is configured
+    pub redis_pool: Option<Pool<RedisConnectionManager>>,
+    /// Tracks in-flight requests and cpu pool jobs drained on shutdown
+    pub shutdown: Shutdown,
 }
 
 impl<
@@ -37,15 +51,28 @@ impl<
     > StaticContext<T, M, F>
 {
     /// Create a new static context
-    pub fn new(db_pool: Pool<M>, cpu_pool: CpuPool, client_handle: ClientHandle, config: Arc<Config>, repo_factory: F) -> Self {
+    pub fn new(
+        db_pool: Pool<M>,
+        cpu_pool: CpuPool,
+        client_handle: ClientHandle,
+        config: Arc<Config>,
+        repo_factory: F,
+        authenticator: JwtAuthenticator,
+        acl_policy: Arc<AclPolicy>,
+    ) -> Self {
         let route_parser = Arc::new(create_route_parser());
         Self {
             route_parser,
             db_pool,
+            read_replica: None,
             cpu_pool,
             client_handle,
             config,
             repo_factory,
+            authenticator: Arc::new(authenticator),
+            acl_policy,
+            redis_pool: None,
+            shutdown: Shutdown::new(),
         }
     }
 }
@@ -60,10 +87,15 @@ impl<
         Self {
             cpu_pool: self.cpu_pool.clone(),
             db_pool: self.db_pool.clone(),
+            read_replica: self.read_replica.clone(),
             route_parser: self.route_parser.clone(),
             client_handle: self.client_handle.clone(),
             config: self.config.clone(),
             repo_factory: self.repo_factory.clone(),
+            authenticator: self.authenticator.clone(),
+            acl_policy: self.acl_policy.clone(),
+            redis_pool: self.redis_pool.clone(),
+            shutdown: self.shutdown.clone(),
         }
     }
 }
@@ -71,7 +103,12 @@ impl<
 /// Dynamic context for each request
 #[derive(Clone)]
 pub struct DynamicContext {
+    /// Effective user, ACL of this user is applied to the request
     pub user_id: Option<UserId>,
+    /// Authenticated user, differs from `user_id` if a superuser impersonates another user
+    pub real_user_id: Option<UserId>,
+    /// Roles of `user_id` resolved once per request, repos load them on their own if not resolved
+    pub user_roles: Option<Vec<UserRole>>,
     pub correlation_token: String,
 }
 
@@ -80,6 +117,37 @@ impl DynamicContext {
     pub fn new(user_id: Option<UserId>, correlation_token: String) -> Self {
         Self {
             user_id,
+            real_user_id: user_id,
+            user_roles: None,
+            correlation_token,
+        }
+    }
+
+    /// Sets roles of the user taken from the token claims, they are used instead of the roles from the database.
+    /// Roles from the token carry no `data`, so they own no stores or companies
+    pub fn with_token_roles(mut self, roles: Option<Vec<RoleName>>) -> Self {
+        if let (Some(user_id), Some(roles)) = (self.user_id, roles) {
+            self.user_roles = Some(
+                roles
+                    .into_iter()
+                    .map(|name| UserRole {
+                        id: RoleId::new(),
+                        user_id,
+                        name,
+                        data: None,
+                    })
+                    .collect(),
+            );
+        }
+        self
+    }
+
+    /// Create a new dynamic context of the request made by `real_user_id` on behalf of `user_id`
+    pub fn new_impersonated(user_id: UserId, real_user_id: UserId, correlation_token: String) -> Self {
+        Self {
+            user_id: Some(user_id),
+            real_user_id: Some(real_user_id),
+            user_roles: None,
             correlation_token,
         }
     }
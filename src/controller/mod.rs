@@ -1,11 +1,14 @@
+pub mod auth;
+pub mod content_type;
 pub mod context;
 pub mod routes;
 
-use std::str::FromStr;
+use std::time::Instant;
 
 use diesel::connection::AnsiTransactionManager;
 use diesel::pg::Pg;
 use diesel::Connection;
+use failure::Error as FailureError;
 use failure::Fail;
 use futures::future;
 use futures::prelude::*;
@@ -22,22 +25,36 @@ use stq_http::{
 };
 use stq_types::*;
 
+use self::content_type::ResponseContentType;
 use self::context::{DynamicContext, StaticContext};
 use self::routes::Route;
+use correlation::{self, WithCorrelationToken};
 use errors::Error;
+use metrics;
 use models::*;
 use repos::repo_factory::*;
 use repos::CountrySearch;
 use sentry_integration::log_and_capture_error;
+use services::api_keys::ApiKeysService;
+use services::audit_log::AuditLogService;
 use services::companies::CompaniesService;
 use services::companies_packages::{CompaniesPackagesService, GetDeliveryPrice, ReplaceShippingRatesPayload};
 use services::countries::CountriesService;
+use services::health::HealthService;
+use services::impersonation::ImpersonationService;
 use services::packages::PackagesService;
 use services::products::ProductsService;
 use services::user_addresses::UserAddressService;
+use services::user_data::UserDataService;
 use services::user_roles::UserRolesService;
+use services::webhooks::WebhooksService;
 use services::Service;
 
+/// Header with the api key of the service calling delivery
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+/// Header with the id of the user a superuser acts on behalf of
+pub const IMPERSONATE_USER_ID_HEADER: &str = "X-Impersonate-User-Id";
+
 /// Controller handles route parsing and calling `Service` layer
 pub struct ControllerImpl<T, M, F>
 where
@@ -46,6 +63,8 @@ where
     F: ReposFactory<T>,
 {
     pub static_context: StaticContext<T, M, F>,
+    /// Content type of the plain text response to the request being handled, `None` for JSON
+    pub response_content_type: ResponseContentType,
 }
 
 impl<
@@ -56,38 +75,23 @@ impl<
 {
     /// Create a new controller based on services
     pub fn new(static_context: StaticContext<T, M, F>) -> Self {
-        Self { static_context }
+        Self {
+            static_context,
+            response_content_type: ResponseContentType::default(),
+        }
     }
-}
-
-impl<
-        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
-        M: ManageConnection<Connection = T>,
-        F: ReposFactory<T>,
-    > Controller for ControllerImpl<T, M, F>
-{
-    /// Handle a request and get future response
-    fn call(&self, req: Request) -> ControllerFuture {
-        let headers = req.headers().clone();
-        let auth_header = headers.get::<Authorization<String>>();
-        let user_id = auth_header
-            .map(|auth| auth.0.clone())
-            .and_then(|id| i32::from_str(&id).ok())
-            .map(UserId);
-
-        let correlation_token = request_util::get_correlation_token(&req);
-
-        let dynamic_context = DynamicContext::new(user_id, correlation_token.clone());
-        let service = Service::new(self.static_context.clone(), dynamic_context);
 
+    /// Dispatches the request to the service by the route
+    fn route(service: Service<T, M, F>, req: Request, response_content_type: ResponseContentType) -> ControllerFuture {
         let path = req.path().to_string();
 
-        let fut = match (&req.method().clone(), self.static_context.route_parser.test(req.path())) {
+        match (&req.method().clone(), service.static_context.route_parser.test(req.path())) {
             (Get, Some(Route::RolesByUserId { user_id })) => serialize_future({ service.get_roles(user_id) }),
             (Post, Some(Route::Roles)) => {
                 serialize_future({ parse_body::<NewUserRole>(req.body()).and_then(move |data| service.create_role(data)) })
             }
             (Delete, Some(Route::RolesByUserId { user_id })) => serialize_future({ service.delete_by_user_id(user_id) }),
+            (Get, Some(Route::PermissionsByUserId { user_id })) => serialize_future({ service.get_permissions(user_id) }),
             (Delete, Some(Route::RoleById { id })) => serialize_future({ service.delete_by_id(id) }),
 
             // POST /products/<base_product_id>
@@ -280,6 +284,12 @@ impl<
                 }
             }
 
+            // GET /available_packages_for_user/<base_product_id>/by_user_address
+            (Get, Some(Route::AvailablePackagesForUserByAddress { base_product_id })) => {
+                let user_address_id = parse_query!(req.query().unwrap_or_default(), "user_address_id" => i32);
+                serialize_future(service.find_available_shipping_for_user_address(base_product_id, user_address_id))
+            }
+
             // GET /available_packages_for_user/products/:id/companies_packages/:id
 
             // DEPRECATED
@@ -462,6 +472,105 @@ impl<
             // DELETE /users/addresses/<id>
             (Delete, Some(Route::UserAddressById { user_address_id })) => serialize_future(service.delete_address(user_address_id)),
 
+            // GET /users/addresses/<id>/label, lines are returned as plain text with `format=text`
+            (Get, Some(Route::UserAddressLabel { user_address_id })) => {
+                if content_type::is_text_format(req.query()) {
+                    response_content_type.set(content_type::TEXT_CONTENT_TYPE);
+                    Box::new(service.get_address_label(user_address_id).map(|label| label.text))
+                } else {
+                    serialize_future(service.get_address_label(user_address_id))
+                }
+            }
+
+            // GET /users/<user_id>/data
+            (Get, Some(Route::UserData { user_id })) => serialize_future(service.export_user_data(user_id)),
+
+            // DELETE /users/<user_id>/data
+            (Delete, Some(Route::UserData { user_id })) => serialize_future(service.erase_user_data(user_id)),
+
+            // POST /api_keys
+            (Post, Some(Route::ApiKeys)) => serialize_future(
+                parse_body::<NewApiKey>(req.body())
+                    .map_err(|e| e.context("Parsing body failed, target: NewApiKey").context(Error::Parse).into())
+                    .and_then(move |new_api_key| {
+                        new_api_key
+                            .validate()
+                            .map_err(|e| format_err!("Validation failed, target: NewApiKey").context(Error::Validate(e)).into())
+                            .into_future()
+                            .and_then(move |_| service.create_api_key(new_api_key))
+                    }),
+            ),
+
+            // GET /api_keys
+            (Get, Some(Route::ApiKeys)) => serialize_future(service.list_api_keys()),
+
+            // DELETE /api_keys/<id>
+            (Delete, Some(Route::ApiKeyById { id })) => serialize_future(service.revoke_api_key(id)),
+
+            // POST /audit_log/search
+            (Post, Some(Route::AuditLogSearch)) => serialize_future(
+                parse_body::<AuditLogSearch>(req.body())
+                    .map_err(|e| e.context("Parsing body failed, target: AuditLogSearch").context(Error::Parse).into())
+                    .and_then(move |search| service.search_audit_log(search)),
+            ),
+
+            // POST /webhook_subscriptions
+            (Post, Some(Route::WebhookSubscriptions)) => serialize_future(
+                parse_body::<NewWebhookSubscription>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewWebhookSubscription")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |new_subscription| {
+                        new_subscription
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewWebhookSubscription")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.create_webhook_subscription(new_subscription))
+                    }),
+            ),
+
+            // GET /webhook_subscriptions
+            (Get, Some(Route::WebhookSubscriptions)) => serialize_future(service.list_webhook_subscriptions()),
+
+            // DELETE /webhook_subscriptions/<id>
+            (Delete, Some(Route::WebhookSubscriptionById { id })) => serialize_future(service.delete_webhook_subscription(id)),
+
+            // GET /webhook_subscriptions/<id>/deliveries
+            (Get, Some(Route::WebhookSubscriptionDeliveries { subscription_id })) => {
+                serialize_future(service.list_webhook_deliveries(subscription_id))
+            }
+
+            // POST /webhook_deliveries/<id>/redeliver
+            (Post, Some(Route::WebhookDeliveryRedeliver { id })) => serialize_future(service.redeliver_webhook_delivery(id)),
+
+            // GET /healthz
+            (Get, Some(Route::Healthz)) => serialize_future(service.liveness()),
+
+            // GET /readyz
+            (Get, Some(Route::Readyz)) => serialize_future(service.readiness()),
+
+            // GET /metrics
+            (Get, Some(Route::Metrics)) => {
+                metrics::observe_pool(
+                    service.static_context.config.server.repo_backend.name(),
+                    &service.static_context.db_pool,
+                );
+                if let Some(ref read_replica) = service.static_context.read_replica {
+                    metrics::observe_pool("postgres_replica", read_replica.pool());
+                }
+                if let Some(ref redis_pool) = service.static_context.redis_pool {
+                    metrics::observe_pool("redis", redis_pool);
+                }
+                response_content_type.set(content_type::METRICS_CONTENT_TYPE);
+                Box::new(future::result(metrics::render()))
+            }
+
             // Fallback
             (m, _) => Box::new(future::err(
                 format_err!("Request to non existing endpoint in delivery microservice! {:?} {:?}", m, path)
@@ -469,14 +578,120 @@ impl<
                     .into(),
             )),
         }
-        .map_err(|err| {
-            let wrapper = ErrorMessageWrapper::<Error>::from(&err);
-            if wrapper.inner.code == 500 {
-                log_and_capture_error(&err);
+    }
+
+    /// Authenticates the request, builds the service and routes the request to it
+    fn handle(&self, req: Request, correlation_token: String) -> ControllerFuture {
+        let headers = req.headers().clone();
+        let auth_header = headers.get::<Authorization<String>>().map(|auth| auth.0.as_str());
+        let authentication = match self.static_context.authenticator.authenticate(auth_header) {
+            Ok(authentication) => authentication,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let (user_id, token_roles) = match authentication {
+            Some(authentication) => (Some(authentication.user_id), authentication.roles),
+            None => (None, None),
+        };
+
+        let impersonated_user_id = match headers.get_raw(IMPERSONATE_USER_ID_HEADER).and_then(|raw| raw.one()) {
+            None => None,
+            Some(_) if !self.static_context.config.server.impersonation_enabled => {
+                return Box::new(future::err(format_err!("Impersonation is disabled").context(Error::Forbidden).into()));
             }
-            err
-        });
+            Some(value) => match String::from_utf8_lossy(value).trim().parse::<i32>() {
+                Ok(id) => Some(UserId(id)),
+                Err(e) => {
+                    return Box::new(future::err(
+                        format_err!("Invalid {} header: {}", IMPERSONATE_USER_ID_HEADER, e)
+                            .context(Error::Parse)
+                            .into(),
+                    ));
+                }
+            },
+        };
+
+        let dynamic_context = DynamicContext::new(user_id, correlation_token).with_token_roles(token_roles);
+        let service = Service::new(self.static_context.clone(), dynamic_context);
+
+        let api_key = headers
+            .get_raw(API_KEY_HEADER)
+            .and_then(|raw| raw.one())
+            .map(|key| String::from_utf8_lossy(key).trim().to_string());
+        let service: Box<Future<Item = Service<T, M, F>, Error = FailureError>> = match api_key {
+            Some(api_key) => Box::new(service.authenticate_api_key(api_key).map(move |api_key| {
+                let Service {
+                    mut static_context,
+                    dynamic_context,
+                } = service;
+                static_context.repo_factory = static_context.repo_factory.with_api_key_permissions(api_key.permissions);
+                Service::new(static_context, dynamic_context)
+            })),
+            None => Box::new(future::ok(service)),
+        };
+        let service: Box<Future<Item = Service<T, M, F>, Error = FailureError>> = match impersonated_user_id {
+            Some(impersonated_user_id) => {
+                let method = req.method().to_string();
+                let path = req.path().to_string();
+                Box::new(service.and_then(move |service| {
+                    service.impersonate(impersonated_user_id, method, path).map(move |real_user_id| {
+                        let Service {
+                            static_context,
+                            dynamic_context,
+                        } = service;
+                        let dynamic_context =
+                            DynamicContext::new_impersonated(impersonated_user_id, real_user_id, dynamic_context.correlation_token);
+                        Service::new(static_context, dynamic_context)
+                    })
+                }))
+            }
+            None => service,
+        };
+        let service = service.and_then(|service| service.resolve_user_roles());
+
+        let response_content_type = self.response_content_type.clone();
+        let fut = service
+            .and_then(move |service| Self::route(service, req, response_content_type))
+            .map_err(|err| {
+                let wrapper = ErrorMessageWrapper::<Error>::from(&err);
+                if wrapper.inner.code == 500 {
+                    log_and_capture_error(&err);
+                }
+                err
+            });
 
         Box::new(fut)
     }
 }
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > Controller for ControllerImpl<T, M, F>
+{
+    /// Handle a request and get future response
+    fn call(&self, req: Request) -> ControllerFuture {
+        let route = self
+            .static_context
+            .route_parser
+            .test(req.path())
+            .map_or("unknown", |route| metrics::route_name(&route));
+        let method = req.method().to_string();
+        let started_at = Instant::now();
+        let in_flight = self.static_context.shutdown.track_request();
+        let correlation_token = request_util::get_correlation_token(&req);
+
+        let fut = correlation::scope(&correlation_token, || self.handle(req, correlation_token.clone()));
+        let fut = WithCorrelationToken::new(correlation_token, fut);
+
+        Box::new(fut.then(move |result| {
+            let status = match result {
+                Ok(_) => "200".to_string(),
+                Err(ref err) => ErrorMessageWrapper::<Error>::from(err).inner.code.to_string(),
+            };
+            metrics::observe_request(route, &method, &status, started_at.elapsed());
+            drop(in_flight);
+            result
+        }))
+    }
+}
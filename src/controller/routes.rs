@@ -11,6 +11,9 @@ pub enum Route {
     RolesByUserId {
         user_id: UserId,
     },
+    PermissionsByUserId {
+        user_id: UserId,
+    },
     Countries,
     CountriesFlatten,
     CountryByAlpha2 {
@@ -65,6 +68,9 @@ pub enum Route {
     AvailablePackagesForUserV2 {
         base_product_id: BaseProductId,
     },
+    AvailablePackagesForUserByAddress {
+        base_product_id: BaseProductId,
+    },
     AvailablePackageForUser {
         base_product_id: BaseProductId,
         company_package_id: CompanyPackageId,
@@ -82,6 +88,30 @@ pub enum Route {
     UserAddressById {
         user_address_id: i32,
     },
+    UserAddressLabel {
+        user_address_id: i32,
+    },
+    UserData {
+        user_id: UserId,
+    },
+    ApiKeys,
+    ApiKeyById {
+        id: i32,
+    },
+    AuditLogSearch,
+    WebhookSubscriptions,
+    WebhookSubscriptionById {
+        id: i32,
+    },
+    WebhookSubscriptionDeliveries {
+        subscription_id: i32,
+    },
+    WebhookDeliveryRedeliver {
+        id: i32,
+    },
+    Healthz,
+    Readyz,
+    Metrics,
 }
 
 pub fn create_route_parser() -> RouteParser<Route> {
@@ -94,6 +124,12 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .and_then(|string_id| string_id.parse().ok())
             .map(|user_id| Route::RolesByUserId { user_id })
     });
+    route_parser.add_route_with_params(r"^/roles/by-user-id/(\d+)/permissions$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_id| Route::PermissionsByUserId { user_id })
+    });
     route_parser.add_route_with_params(r"^/roles/by-id/([a-zA-Z0-9-]+)$", |params| {
         params
             .get(0)
@@ -220,6 +256,12 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .and_then(|string_id| string_id.parse().ok())
             .map(|base_product_id| Route::AvailablePackagesForUserV2 { base_product_id })
     });
+    route_parser.add_route_with_params(r"^/available_packages_for_user/(\d+)/by_user_address$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|base_product_id| Route::AvailablePackagesForUserByAddress { base_product_id })
+    });
 
     route_parser.add_route_with_params(
         r"^/available_packages_for_user/products/(\d+)/companies_packages/(\d+)$",
@@ -262,5 +304,55 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .map(|user_address_id| Route::UserAddressById { user_address_id })
     });
 
+    // /users/addresses/:id/label route
+    route_parser.add_route_with_params(r"^/users/addresses/(\d+)/label$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_address_id| Route::UserAddressLabel { user_address_id })
+    });
+
+    // /users/:id/data route
+    route_parser.add_route_with_params(r"^/users/(\d+)/data$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_id| Route::UserData { user_id })
+    });
+
+    route_parser.add_route(r"^/api_keys$", || Route::ApiKeys);
+    route_parser.add_route_with_params(r"^/api_keys/(\d+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::ApiKeyById { id })
+    });
+
+    route_parser.add_route(r"^/audit_log/search$", || Route::AuditLogSearch);
+
+    route_parser.add_route(r"^/webhook_subscriptions$", || Route::WebhookSubscriptions);
+    route_parser.add_route_with_params(r"^/webhook_subscriptions/(\d+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::WebhookSubscriptionById { id })
+    });
+    route_parser.add_route_with_params(r"^/webhook_subscriptions/(\d+)/deliveries$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|subscription_id| Route::WebhookSubscriptionDeliveries { subscription_id })
+    });
+    route_parser.add_route_with_params(r"^/webhook_deliveries/(\d+)/redeliver$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::WebhookDeliveryRedeliver { id })
+    });
+
+    route_parser.add_route(r"^/healthz$", || Route::Healthz);
+    route_parser.add_route(r"^/readyz$", || Route::Readyz);
+    route_parser.add_route(r"^/metrics$", || Route::Metrics);
+
     route_parser
 }
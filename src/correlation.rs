@@ -0,0 +1,177 @@
+//! Correlation token of the request being handled, attached to log records,
+//! Sentry events and outgoing http requests
+
+use std::cell::RefCell;
+
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::{Future, Poll};
+use hyper::header::Headers;
+use hyper::Method;
+use sentry;
+use serde::de::DeserializeOwned;
+
+use stq_http::client::ClientHandle;
+
+use errors::Error;
+
+/// Header with the correlation token of the request
+pub const CORRELATION_TOKEN_HEADER: &str = "Correlation-Token";
+/// Sentry tag with the correlation token of the request
+pub const SENTRY_TAG: &str = "correlation_token";
+
+thread_local! {
+    static CORRELATION_TOKEN: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Returns correlation token of the request handled by the current thread
+pub fn current_token() -> Option<String> {
+    CORRELATION_TOKEN.with(|token| token.borrow().clone())
+}
+
+/// Runs `f` with the correlation token set for the current thread and Sentry scope
+pub fn scope<R, F>(token: &str, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    if token.is_empty() {
+        return f();
+    }
+
+    let previous = CORRELATION_TOKEN.with(|current| current.replace(Some(token.to_string())));
+    let _reset = ResetToken { previous };
+    sentry::with_scope(|scope| scope.set_tag(SENTRY_TAG, token), f)
+}
+
+/// Restores the token of the current thread, even if the scope panics
+struct ResetToken {
+    previous: Option<String>,
+}
+
+impl Drop for ResetToken {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CORRELATION_TOKEN.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Future polled within the scope of the correlation token
+pub struct WithCorrelationToken<F> {
+    token: String,
+    inner: F,
+}
+
+impl<F: Future> WithCorrelationToken<F> {
+    pub fn new(token: String, inner: F) -> Self {
+        Self { token, inner }
+    }
+}
+
+impl<F: Future> Future for WithCorrelationToken<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = &mut self.inner;
+        scope(&self.token, || inner.poll())
+    }
+}
+
+/// Http client forwarding the correlation token of the request in `Correlation-Token` header
+#[derive(Clone)]
+pub struct HttpClient {
+    client_handle: ClientHandle,
+    correlation_token: String,
+}
+
+impl HttpClient {
+    pub fn new(client_handle: ClientHandle, correlation_token: String) -> Self {
+        Self {
+            client_handle,
+            correlation_token,
+        }
+    }
+
+    pub fn request<T>(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<String>,
+        headers: Option<Headers>,
+    ) -> Box<Future<Item = T, Error = FailureError>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let headers = self.with_correlation_token(headers);
+
+        Box::new(
+            self.client_handle
+                .request::<T>(method.clone(), url.clone(), body, Some(headers))
+                .map_err(move |e| {
+                    e.context(format!("Request {} {} failed", method, url))
+                        .context(Error::HttpClient)
+                        .into()
+                }),
+        )
+    }
+
+    /// Sends the request and succeeds on any `2xx` response, the body of the response is not parsed
+    pub fn send(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<String>,
+        headers: Option<Headers>,
+    ) -> Box<Future<Item = (), Error = FailureError>> {
+        let headers = self.with_correlation_token(headers);
+
+        Box::new(
+            self.client_handle
+                .simple_request(method.clone(), url.clone(), body, Some(headers))
+                .map(|_| ())
+                .map_err(move |e| {
+                    e.context(format!("Request {} {} failed", method, url))
+                        .context(Error::HttpClient)
+                        .into()
+                }),
+        )
+    }
+
+    fn with_correlation_token(&self, headers: Option<Headers>) -> Headers {
+        let mut headers = headers.unwrap_or_else(Headers::new);
+        if !self.correlation_token.is_empty() {
+            headers.set_raw(CORRELATION_TOKEN_HEADER, self.correlation_token.clone());
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+
+    #[test]
+    fn test_scope_sets_and_restores_token() {
+        assert_eq!(current_token(), None);
+        scope("outer", || {
+            assert_eq!(current_token(), Some("outer".to_string()));
+            scope("inner", || assert_eq!(current_token(), Some("inner".to_string())));
+            assert_eq!(current_token(), Some("outer".to_string()));
+        });
+        assert_eq!(current_token(), None);
+    }
+
+    #[test]
+    fn test_empty_token_is_not_set() {
+        scope("", || assert_eq!(current_token(), None));
+    }
+
+    #[test]
+    fn test_future_is_polled_with_token() {
+        let fut = WithCorrelationToken::new("token".to_string(), future::lazy(|| future::ok::<_, ()>(current_token())));
+        assert_eq!(fut.wait(), Ok(Some("token".to_string())));
+        assert_eq!(current_token(), None);
+    }
+}
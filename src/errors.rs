@@ -14,6 +14,8 @@ pub enum Error {
     Parse,
     #[fail(display = "Validation error")]
     Validate(ValidationErrors),
+    #[fail(display = "Unauthorized")]
+    Unauthorized,
     #[fail(display = "Server is refusing to fullfil the request")]
     Forbidden,
     #[fail(display = "R2D2 connection error")]
@@ -22,6 +24,19 @@ pub enum Error {
     HttpClient,
     #[fail(display = "service error - internal")]
     Internal,
+    #[fail(display = "Service is not ready")]
+    NotReady(serde_json::Value),
+}
+
+impl Error {
+    /// Validation error of a row violating a unique constraint
+    pub fn not_unique(message: &str) -> Self {
+        let mut errors = ValidationErrors::new();
+        let mut error = ValidationError::new("not_unique");
+        error.add_param("message".into(), &message);
+        errors.add("repo", error);
+        Error::Validate(errors)
+    }
 }
 
 impl Codeable for Error {
@@ -31,7 +46,9 @@ impl Codeable for Error {
             Error::Parse => StatusCode::UnprocessableEntity,
             Error::Validate(_) => StatusCode::BadRequest,
             Error::HttpClient | Error::Connection | Error::Internal => StatusCode::InternalServerError,
+            Error::Unauthorized => StatusCode::Unauthorized,
             Error::Forbidden => StatusCode::Forbidden,
+            Error::NotReady(_) => StatusCode::ServiceUnavailable,
         }
     }
 }
@@ -40,6 +57,7 @@ impl PayloadCarrier for Error {
     fn payload(&self) -> Option<serde_json::Value> {
         match *self {
             Error::Validate(ref e) => serde_json::to_value(e.clone()).ok(),
+            Error::NotReady(ref readiness) => Some(readiness.clone()),
             _ => None,
         }
     }
@@ -48,14 +66,7 @@ impl PayloadCarrier for Error {
 impl<'a> From<DieselError> for Error {
     fn from(e: DieselError) -> Self {
         match e {
-            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info) => {
-                let mut errors = ValidationErrors::new();
-                let mut error = ValidationError::new("not_unique");
-                let message: &str = info.message();
-                error.add_param("message".into(), &message);
-                errors.add("repo", error);
-                Error::Validate(errors)
-            }
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info) => Error::not_unique(info.message()),
             DieselError::NotFound => Error::NotFound,
             _ => Error::Internal,
         }
@@ -5,6 +5,8 @@ extern crate config as config_crate;
 #[macro_use]
 extern crate diesel;
 #[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
 extern crate failure;
 extern crate futures;
 extern crate futures_cpupool;
@@ -12,15 +14,19 @@ extern crate hyper;
 extern crate hyper_tls;
 extern crate jsonwebtoken;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate log;
 extern crate r2d2;
 extern crate r2d2_redis;
 extern crate rand;
 extern crate regex;
+extern crate ring;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate mime;
+extern crate prometheus;
 extern crate serde_json;
 extern crate sha3;
 extern crate tokio_core;
@@ -41,47 +47,65 @@ extern crate stq_static_resources;
 extern crate stq_diesel_macro_derive;
 extern crate stq_types;
 
+#[macro_use]
+pub mod macros;
+
 pub mod config;
 pub mod controller;
+pub mod correlation;
 pub mod errors;
 pub mod extras;
-#[macro_use]
-pub mod macros;
+pub mod metrics;
+pub mod migrations;
 pub mod models;
+pub mod outbox;
+pub mod replica;
 pub mod repos;
 #[rustfmt::skip]
 pub mod schema;
 pub mod sentry_integration;
 pub mod services;
+pub mod shutdown;
 
+use std::net::SocketAddr;
 use std::process;
 use std::sync::Arc;
 use std::time::Duration;
 
-use diesel::pg::PgConnection;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::{Pg, PgConnection};
 use diesel::r2d2::ConnectionManager;
+use diesel::Connection;
 use futures::future;
 use futures::prelude::*;
+use futures::sync::oneshot;
 use futures_cpupool::CpuPool;
 use hyper::server::Http;
+use r2d2::ManageConnection;
 use r2d2_redis::RedisConnectionManager;
 use stq_cache::cache::{redis::RedisCache, Cache, NullCache, TypedCache};
+use stq_http::client::ClientHandle;
 use stq_http::controller::Application;
-use tokio_core::reactor::Core;
+use tokio_core::reactor::{Core, Handle, Timeout};
 
+use config::RepoBackend;
+use controller::auth::JwtAuthenticator;
+use controller::content_type::PlainTextResponses;
 use controller::context::StaticContext;
-use repos::acl::RolesCacheImpl;
+use outbox::OutboxDispatcher;
+use repos::acl::{AclPolicy, RolesCacheImpl};
 use repos::countries::CountryCacheImpl;
-use repos::repo_factory::ReposFactoryImpl;
+use repos::memory::{MemoryConnection, MemoryConnectionManager, MemoryFixtures, MemoryReposFactory, MemoryStore};
+use repos::repo_factory::{ReposFactory, ReposFactoryImpl};
+use repos::shipping_rates::ShippingRatesCacheImpl;
+use repos::user_addresses::UserAddressCipher;
 
-/// Starts new web service from provided `Config`
-pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: Option<i32>, callback: F) {
-    let thread_count = config.server.thread_count;
-    let cpu_pool = CpuPool::new(thread_count);
-
-    // Prepare reactor
-    let mut core = Core::new().expect("Unexpected error creating event loop core");
-    let handle = Arc::new(core.handle());
+/// Creates context of the app from provided `Config`, shared by the server and the admin tool
+pub fn create_context(
+    config: config::Config,
+    handle: &Handle,
+) -> StaticContext<PgConnection, ConnectionManager<PgConnection>, impl ReposFactory<PgConnection>> {
+    let cpu_pool = CpuPool::new(config.server.thread_count);
 
     // Prepare database pool
     let database_url: String = config.server.database.parse().expect("Database URL must be set in configuration");
@@ -90,13 +114,16 @@ pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: Option<
         .build(db_manager)
         .expect("Failed to create DB connection pool");
 
-    // Prepare server
-    let address = {
-        let port = port.as_ref().unwrap_or(&config.server.port);
-        format!("{}:{}", config.server.host, port).parse().expect("Could not parse address")
-    };
+    // Prepare read replica pool, connections are not checked so that the server starts while the replica is down
+    let read_replica = config.server.read_replica_database.as_ref().map(|replica_url| {
+        let replica_manager = ConnectionManager::<PgConnection>::new(replica_url.as_str());
+        let replica_pool = r2d2::Pool::builder()
+            .connection_timeout(Duration::from_millis(config.server.read_replica_timeout_ms))
+            .build_unchecked(replica_manager);
+        ReadReplica::new(replica_pool, Duration::from_millis(config.server.read_replica_retry_ms))
+    });
 
-    let (country_cache, roles_cache) = match &config.server.redis {
+    let (country_cache, roles_cache, shipping_rates_cache, redis_pool) = match &config.server.redis {
         Some(redis_url) => {
             // Prepare Redis pool
             let redis_url: String = redis_url.parse().expect("Redis URL must be set in configuration");
@@ -108,46 +135,201 @@ pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: Option<
             let ttl = Duration::from_secs(config.server.cache_ttl_sec);
 
             let country_cache_backend = Box::new(TypedCache::new(
-                RedisCache::new(redis_pool.clone(), "country".to_string()).with_ttl(ttl),
+                RedisCache::new(redis_pool.clone(), "versioned_country".to_string()).with_ttl(ttl),
             )) as Box<dyn Cache<_, Error = _> + Send + Sync>;
-            let country_cache = CountryCacheImpl::new(country_cache_backend);
+            let country_version_backend = Box::new(TypedCache::new(RedisCache::new(redis_pool.clone(), "country_version".to_string())))
+                as Box<dyn Cache<_, Error = _> + Send + Sync>;
+            let country_cache = CountryCacheImpl::new(country_cache_backend, country_version_backend);
 
             let roles_cache_backend = Box::new(TypedCache::new(
-                RedisCache::new(redis_pool.clone(), "roles".to_string()).with_ttl(ttl),
+                RedisCache::new(redis_pool.clone(), "user_roles".to_string()).with_ttl(ttl),
             )) as Box<dyn Cache<_, Error = _> + Send + Sync>;
             let roles_cache = RolesCacheImpl::new(roles_cache_backend);
 
-            (country_cache, roles_cache)
+            let shipping_rates_cache_backend = Box::new(TypedCache::new(
+                RedisCache::new(redis_pool.clone(), "shipping_rates".to_string()).with_ttl(ttl),
+            )) as Box<dyn Cache<_, Error = _> + Send + Sync>;
+            let shipping_rates_version_backend = Box::new(TypedCache::new(
+                RedisCache::new(redis_pool.clone(), "shipping_rates_version".to_string()).with_ttl(ttl),
+            )) as Box<dyn Cache<_, Error = _> + Send + Sync>;
+            let shipping_rates_cache = ShippingRatesCacheImpl::new(shipping_rates_cache_backend, shipping_rates_version_backend);
+
+            (country_cache, roles_cache, shipping_rates_cache, Some(redis_pool))
         }
         None => (
-            CountryCacheImpl::new(Box::new(NullCache::new()) as Box<_>),
+            CountryCacheImpl::new(Box::new(NullCache::new()) as Box<_>, Box::new(NullCache::new()) as Box<_>),
             RolesCacheImpl::new(Box::new(NullCache::new()) as Box<_>),
+            ShippingRatesCacheImpl::new(Box::new(NullCache::new()) as Box<_>, Box::new(NullCache::new()) as Box<_>),
+            None,
         ),
     };
 
+    let user_address_cipher = UserAddressCipher::from_config(config.encryption.as_ref()).expect("Failed to create user address cipher");
+
+    let acl_policy = load_acl_policy(&config);
+
     // Repo factory
-    let repo_factory = ReposFactoryImpl::new(country_cache, roles_cache);
+    let repo_factory = ReposFactoryImpl::new(
+        country_cache,
+        roles_cache,
+        shipping_rates_cache,
+        user_address_cipher,
+        acl_policy.clone(),
+    );
+
+    let client_handle = create_client_handle(&config, handle);
+
+    let authenticator = JwtAuthenticator::from_config(config.jwt.as_ref()).expect("Failed to create JWT authenticator");
+
+    let mut context = StaticContext::new(
+        db_pool,
+        cpu_pool,
+        client_handle,
+        Arc::new(config),
+        repo_factory,
+        authenticator,
+        acl_policy,
+    );
+    context.read_replica = read_replica;
+    context.redis_pool = redis_pool;
+
+    context
+}
+
+/// Creates context of the app working with the in-memory store seeded from `memory_fixtures_path`, Postgres and Redis are not used
+pub fn create_memory_context(
+    config: config::Config,
+    handle: &Handle,
+) -> StaticContext<MemoryConnection, MemoryConnectionManager, MemoryReposFactory> {
+    let cpu_pool = CpuPool::new(config.server.thread_count);
+
+    let fixtures = match config.server.memory_fixtures_path {
+        Some(ref path) => MemoryFixtures::from_file(path).expect("Failed to load in-memory backend fixtures"),
+        None => MemoryFixtures::default(),
+    };
+    let db_pool = r2d2::Pool::builder()
+        .build(MemoryConnectionManager::new(MemoryStore::new(fixtures)))
+        .expect("Failed to create in-memory connection pool");
+
+    let acl_policy = load_acl_policy(&config);
+    let repo_factory = MemoryReposFactory::new(acl_policy.clone());
 
-    let client = stq_http::client::Client::new(&config.to_http_config(), &handle);
+    let client_handle = create_client_handle(&config, handle);
+
+    let authenticator = JwtAuthenticator::from_config(config.jwt.as_ref()).expect("Failed to create JWT authenticator");
+
+    StaticContext::new(
+        db_pool,
+        cpu_pool,
+        client_handle,
+        Arc::new(config),
+        repo_factory,
+        authenticator,
+        acl_policy,
+    )
+}
+
+fn load_acl_policy(config: &config::Config) -> Arc<AclPolicy> {
+    Arc::new(match config.server.acl_policy_path {
+        Some(ref path) => AclPolicy::from_file(path).expect("Failed to load ACL policy"),
+        None => AclPolicy::default(),
+    })
+}
+
+fn create_client_handle(config: &config::Config, handle: &Handle) -> ClientHandle {
+    let client = stq_http::client::Client::new(&config.to_http_config(), handle);
     let client_handle = client.handle();
     let client_stream = client.stream();
     handle.spawn(client_stream.for_each(|_| Ok(())));
+    client_handle
+}
 
-    let context = StaticContext::new(db_pool, cpu_pool, client_handle, Arc::new(config), repo_factory);
+/// Time given to Sentry to send queued events on shutdown
+const SENTRY_FLUSH_TIMEOUT_MS: u64 = 2000;
+
+/// Starts new web service from provided `Config`, returns after graceful shutdown on SIGTERM or SIGINT
+pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: Option<i32>, callback: F) {
+    // Prepare reactor
+    let core = Core::new().expect("Unexpected error creating event loop core");
+    let handle = Arc::new(core.handle());
+
+    // Prepare server
+    let address = {
+        let port = port.as_ref().unwrap_or(&config.server.port);
+        format!("{}:{}", config.server.host, port).parse().expect("Could not parse address")
+    };
+
+    match config.server.repo_backend {
+        RepoBackend::Postgres => {
+            let context = create_context(config, &handle);
+            check_schema(&context);
+            serve(core, handle, address, context, callback)
+        }
+        RepoBackend::Memory => {
+            warn!("Starting with in-memory backend, data is lost on exit");
+            let context = create_memory_context(config, &handle);
+            serve(core, handle, address, context, callback)
+        }
+    }
+}
+
+/// Applies pending migrations if configured, exits if the schema is still behind the service
+fn check_schema<M, F>(context: &StaticContext<PgConnection, M, F>)
+where
+    M: ManageConnection<Connection = PgConnection>,
+    F: ReposFactory<PgConnection>,
+{
+    let conn = context.db_pool.get().expect("Failed to get DB connection");
+    if context.config.server.run_migrations {
+        migrations::run_pending(&*conn).expect("Failed to run database migrations");
+    }
+    let pending = migrations::pending_versions(&*conn).expect("Failed to check database migrations");
+    if !pending.is_empty() {
+        if context.config.server.allow_pending_migrations {
+            warn!("Starting with pending database migrations: {}", pending.join(", "));
+        } else {
+            error!(
+                "Database schema is behind the service, pending migrations: {}. Run `delivery migrate` first",
+                pending.join(", ")
+            );
+            process::exit(1);
+        }
+    }
+}
+
+/// Serves the app with the context until SIGTERM or SIGINT, then drains in-flight requests and jobs
+fn serve<T, M, F, C>(mut core: Core, handle: Arc<Handle>, address: SocketAddr, context: StaticContext<T, M, F>, callback: C)
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+    C: FnOnce() + 'static,
+{
+    let thread_count = context.config.server.thread_count;
+
+    let dispatcher = OutboxDispatcher::new(&context, context.config.outbox.clone());
+    handle.spawn(dispatcher.run(&handle));
+
+    let shutdown = context.shutdown.clone();
+    let shutdown_delay = Duration::from_millis(context.config.server.shutdown_delay_ms);
+    let shutdown_timeout = Duration::from_millis(context.config.server.shutdown_timeout_ms);
 
     let serve = Http::new()
         .serve_addr_handle(&address, &*handle, move || {
             // Prepare application
             let controller = controller::ControllerImpl::new(context.clone());
+            let content_type = controller.response_content_type.clone();
             let app = Application::<errors::Error>::new(controller);
 
-            Ok(app)
+            Ok(PlainTextResponses::new(app, content_type))
         })
         .unwrap_or_else(|reason| {
             eprintln!("Http Server Initialization Error: {}", reason);
             process::exit(1);
         });
 
+    // Dropping the listener on shutdown stops accepting connections, accepted ones are served until exit
+    let (stop_accepting, accepting_stopped) = oneshot::channel::<()>();
     handle.spawn(
         serve
             .for_each({
@@ -157,6 +339,9 @@ pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: Option<
                     Ok(())
                 }
             })
+            .map_err(|_| ())
+            .select(accepting_stopped.map_err(|_| ()))
+            .map(|_| ())
             .map_err(|_| ()),
     );
 
@@ -166,9 +351,36 @@ pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: Option<
         future::ok(())
     });
 
-    core.run(tokio_signal::ctrl_c().flatten_stream().take(1u64).for_each(|()| {
-        info!("Ctrl+C received. Exit");
-        Ok(())
-    }))
-    .unwrap();
+    let drained = core.run(shutdown::signal().and_then({
+        let shutdown = shutdown.clone();
+        move |signal| {
+            info!(
+                "{} received, shutting down with {} requests and {} jobs in flight",
+                signal,
+                shutdown.active_requests(),
+                shutdown.active_jobs()
+            );
+            // readiness fails first, so load balancers stop routing requests before the listener is closed
+            shutdown.start();
+            future::result(Timeout::new(shutdown_delay, &handle)).flatten().and_then(move |_| {
+                info!("Stopped accepting connections");
+                let _ = stop_accepting.send(());
+                shutdown.drain(shutdown_timeout, &handle)
+            })
+        }
+    }));
+
+    match drained {
+        Ok(true) => info!("In-flight requests and jobs are finished. Exit"),
+        Ok(false) => warn!(
+            "Shutdown timeout expired, exit with {} requests and {} jobs in flight",
+            shutdown.active_requests(),
+            shutdown.active_jobs()
+        ),
+        Err(e) => error!("Graceful shutdown failed: {}", e),
+    }
+
+    if !sentry_integration::flush(Duration::from_millis(SENTRY_FLUSH_TIMEOUT_MS)) {
+        warn!("Not all events were sent to Sentry before exit");
+    }
 }
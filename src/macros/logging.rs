@@ -0,0 +1,32 @@
+//! Logging macros shadowing the ones of `log` crate, they prefix the message
+//! with the correlation token of the request handled by the current thread
+
+#[doc(hidden)]
+macro_rules! log_with_correlation_token {
+    ($lvl:expr, $($arg:tt)+) => {
+        match ::correlation::current_token() {
+            Some(token) => log!($lvl, "[{}] {}", token, format_args!($($arg)+)),
+            None => log!($lvl, $($arg)+),
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => (log_with_correlation_token!(::log::Level::Error, $($arg)+))
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => (log_with_correlation_token!(::log::Level::Warn, $($arg)+))
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => (log_with_correlation_token!(::log::Level::Info, $($arg)+))
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => (log_with_correlation_token!(::log::Level::Debug, $($arg)+))
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => (log_with_correlation_token!(::log::Level::Trace, $($arg)+))
+}
@@ -1,2 +1,4 @@
 #[macro_use]
+pub mod logging;
+#[macro_use]
 pub mod validation_errors;
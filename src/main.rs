@@ -1,11 +1,32 @@
 //! Delivery is a microservice.
+//! `delivery` starts the server, `delivery migrate` applies pending database migrations.
 
 extern crate delivery_lib;
+extern crate diesel;
 extern crate stq_logging;
 
+use std::env;
+use std::process;
+
+use diesel::pg::PgConnection;
+use diesel::Connection;
+
+use delivery_lib::config::Config;
+
 fn main() {
-    let config = delivery_lib::config::Config::new().expect("Can't load app config!");
+    let config = Config::new().expect("Can't load app config!");
+
+    match env::args().nth(1).as_ref().map(String::as_str) {
+        None => serve(config),
+        Some("migrate") => migrate(&config),
+        Some(command) => {
+            eprintln!("Unknown command {}, usage: delivery [migrate]", command);
+            process::exit(1);
+        }
+    }
+}
 
+fn serve(config: Config) {
     // Prepare sentry integration
     let _sentry = delivery_lib::sentry_integration::init(config.sentry.as_ref());
 
@@ -14,3 +35,15 @@ fn main() {
 
     delivery_lib::start_server(config, None, || ());
 }
+
+fn migrate(config: &Config) {
+    let conn = PgConnection::establish(&config.server.database).expect("Failed to connect to database");
+
+    match delivery_lib::migrations::run_pending(&conn) {
+        Ok(()) => println!("Database is up to date"),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
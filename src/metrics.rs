@@ -0,0 +1,176 @@
+//! Metrics of the app exposed in Prometheus text format on `/metrics`
+
+use std::time::Duration;
+
+use failure::Error as FailureError;
+use prometheus::core::Collector;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use r2d2::{ManageConnection, Pool};
+
+use controller::routes::Route;
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+    static ref HTTP_REQUESTS_TOTAL: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of handled requests"),
+            &["route", "method", "status"]
+        )
+        .unwrap()
+    );
+    static ref HTTP_REQUEST_DURATION_SECONDS: HistogramVec = register(
+        HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Duration of handled requests"),
+            &["route", "method", "status"]
+        )
+        .unwrap()
+    );
+    static ref POOL_CONNECTIONS: IntGaugeVec = register(
+        IntGaugeVec::new(
+            Opts::new("pool_connections", "Number of connections of the pool by state"),
+            &["pool", "state"]
+        )
+        .unwrap()
+    );
+    static ref CACHE_REQUESTS_TOTAL: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new("cache_requests_total", "Number of cache lookups by result"),
+            &["cache", "result"]
+        )
+        .unwrap()
+    );
+    static ref SHIPPING_RATES_UPLOADS_TOTAL: IntCounter =
+        register(IntCounter::new("shipping_rates_uploads_total", "Number of uploaded shipping rate cards").unwrap());
+    static ref PRICING_MISSES_TOTAL: IntCounter = register(
+        IntCounter::new(
+            "pricing_misses_total",
+            "Number of price calculations of available packages without a shipping rate"
+        )
+        .unwrap()
+    );
+}
+
+fn register<T: Collector + Clone + 'static>(collector: T) -> T {
+    REGISTRY.register(Box::new(collector.clone())).expect("Failed to register metric");
+    collector
+}
+
+/// Name of the route used as a label, route params are left out to keep the number of series low
+pub fn route_name(route: &Route) -> &'static str {
+    match *route {
+        Route::Roles => "Roles",
+        Route::RoleById { .. } => "RoleById",
+        Route::RolesByUserId { .. } => "RolesByUserId",
+        Route::PermissionsByUserId { .. } => "PermissionsByUserId",
+        Route::Countries => "Countries",
+        Route::CountriesFlatten => "CountriesFlatten",
+        Route::CountryByAlpha2 { .. } => "CountryByAlpha2",
+        Route::CountryByAlpha3 { .. } => "CountryByAlpha3",
+        Route::CountryByNumeric { .. } => "CountryByNumeric",
+        Route::Products => "Products",
+        Route::ProductsById { .. } => "ProductsById",
+        Route::ProductsByIdAndCompanyPackageId { .. } => "ProductsByIdAndCompanyPackageId",
+        Route::Companies => "Companies",
+        Route::CompanyById { .. } => "CompanyById",
+        Route::Packages => "Packages",
+        Route::PackagesById { .. } => "PackagesById",
+        Route::CompaniesPackages => "CompaniesPackages",
+        Route::CompaniesPackagesById { .. } => "CompaniesPackagesById",
+        Route::CompaniesPackagesByIds { .. } => "CompaniesPackagesByIds",
+        Route::PackagesByCompanyId { .. } => "PackagesByCompanyId",
+        Route::CompaniesByPackageId { .. } => "CompaniesByPackageId",
+        Route::CompanyPackageDeliveryPrice { .. } => "CompanyPackageDeliveryPrice",
+        Route::CompanyPackageRates { .. } => "CompanyPackageRates",
+        Route::AvailablePackages => "AvailablePackages",
+        Route::AvailablePackagesForUser { .. } => "AvailablePackagesForUser",
+        Route::AvailablePackagesForUserV2 { .. } => "AvailablePackagesForUserV2",
+        Route::AvailablePackagesForUserByAddress { .. } => "AvailablePackagesForUserByAddress",
+        Route::AvailablePackageForUser { .. } => "AvailablePackageForUser",
+        Route::AvailablePackageForUserByShippingId { .. } => "AvailablePackageForUserByShippingId",
+        Route::AvailablePackageForUserByShippingIdV2 { .. } => "AvailablePackageForUserByShippingIdV2",
+        Route::UsersAddresses => "UsersAddresses",
+        Route::UserAddress { .. } => "UserAddress",
+        Route::UserAddressById { .. } => "UserAddressById",
+        Route::UserAddressLabel { .. } => "UserAddressLabel",
+        Route::UserData { .. } => "UserData",
+        Route::ApiKeys => "ApiKeys",
+        Route::ApiKeyById { .. } => "ApiKeyById",
+        Route::AuditLogSearch => "AuditLogSearch",
+        Route::WebhookSubscriptions => "WebhookSubscriptions",
+        Route::WebhookSubscriptionById { .. } => "WebhookSubscriptionById",
+        Route::WebhookSubscriptionDeliveries { .. } => "WebhookSubscriptionDeliveries",
+        Route::WebhookDeliveryRedeliver { .. } => "WebhookDeliveryRedeliver",
+        Route::Healthz => "Healthz",
+        Route::Readyz => "Readyz",
+        Route::Metrics => "Metrics",
+    }
+}
+
+/// Records handled request
+pub fn observe_request(route: &str, method: &str, status: &str, duration: Duration) {
+    let labels = [route, method, status];
+    HTTP_REQUESTS_TOTAL.with_label_values(&labels).inc();
+    HTTP_REQUEST_DURATION_SECONDS
+        .with_label_values(&labels)
+        .observe(duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9);
+}
+
+/// Records cache lookup
+pub fn observe_cache(cache: &str, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    CACHE_REQUESTS_TOTAL.with_label_values(&[cache, result]).inc();
+}
+
+/// Records upload of shipping rate card
+pub fn observe_shipping_rates_upload() {
+    SHIPPING_RATES_UPLOADS_TOTAL.inc();
+}
+
+/// Records price calculation of available package without a shipping rate
+pub fn observe_pricing_miss() {
+    PRICING_MISSES_TOTAL.inc();
+}
+
+/// Records state of r2d2 pool
+pub fn observe_pool<M: ManageConnection>(pool_name: &str, pool: &Pool<M>) {
+    let state = pool.state();
+    POOL_CONNECTIONS
+        .with_label_values(&[pool_name, "idle"])
+        .set(i64::from(state.idle_connections));
+    POOL_CONNECTIONS
+        .with_label_values(&[pool_name, "in_use"])
+        .set(i64::from(state.connections - state.idle_connections));
+    POOL_CONNECTIONS
+        .with_label_values(&[pool_name, "max"])
+        .set(i64::from(pool.max_size()));
+}
+
+/// Returns all metrics in Prometheus text format
+pub fn render() -> Result<String, FailureError> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer)?;
+    String::from_utf8(buffer).map_err(FailureError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use stq_types::RoleId;
+
+    #[test]
+    fn test_route_name() {
+        assert_eq!(route_name(&Route::Companies), "Companies");
+        assert_eq!(route_name(&Route::RoleById { id: RoleId::new() }), "RoleById");
+        assert_eq!(route_name(&Route::UserAddressLabel { user_address_id: 1 }), "UserAddressLabel");
+    }
+
+    #[test]
+    fn test_render() {
+        observe_request("Companies", "GET", "200", Duration::from_millis(5));
+        observe_cache("roles", false);
+        let metrics = render().unwrap();
+        assert!(metrics.contains(r#"http_requests_total{method="GET",route="Companies",status="200"}"#));
+        assert!(metrics.contains(r#"cache_requests_total{cache="roles",result="miss"}"#));
+    }
+}
@@ -0,0 +1,67 @@
+//! Database migrations embedded into the binary. They are applied with `delivery migrate`
+//! or at startup with `server.run_migrations`, the server refuses to start while some are pending.
+//! Replicas starting at once apply migrations one after another under an advisory lock.
+
+use std::io;
+
+use diesel;
+use diesel::dsl::sql;
+use diesel::pg::PgConnection;
+use diesel::sql_types::Bool;
+use diesel::{Connection, RunQueryDsl};
+use diesel_migrations::MigrationConnection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+embed_migrations!("migrations");
+
+/// Versions of all embedded migrations, oldest first
+pub const MIGRATION_VERSIONS: &[&str] = include!(concat!(env!("OUT_DIR"), "/migration_versions.rs"));
+
+/// Key of the advisory lock held while migrations are applied
+const MIGRATIONS_LOCK_KEY: i64 = 0x6465_6c69_7665_7279;
+
+/// Applies pending migrations, every applied migration is printed to stdout.
+/// Waits for migrations applied by another process to finish
+pub fn run_pending(conn: &PgConnection) -> Result<(), FailureError> {
+    conn.execute(&format!("SELECT pg_advisory_lock({})", MIGRATIONS_LOCK_KEY))
+        .map_err(|e| e.context("Taking database migrations lock failed"))?;
+    let result =
+        embedded_migrations::run_with_output(conn, &mut io::stdout()).map_err(|e| e.context("Running database migrations failed").into());
+    conn.execute(&format!("SELECT pg_advisory_unlock({})", MIGRATIONS_LOCK_KEY))
+        .map_err(|e| e.context("Releasing database migrations lock failed"))?;
+    result
+}
+
+/// Returns versions of embedded migrations not applied to the database yet, does not write to the database
+pub fn pending_versions(conn: &PgConnection) -> Result<Vec<String>, FailureError> {
+    diesel::select(sql::<Bool>("to_regclass('__diesel_schema_migrations') IS NOT NULL"))
+        .get_result::<bool>(conn)
+        .and_then(|is_set_up| {
+            if is_set_up {
+                conn.previously_run_migration_versions()
+            } else {
+                Ok(Default::default())
+            }
+        })
+        .map(|applied| {
+            MIGRATION_VERSIONS
+                .iter()
+                .filter(|version| !applied.contains(**version))
+                .map(|version| version.to_string())
+                .collect()
+        })
+        .map_err(|e| e.context("Checking pending database migrations failed").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migration_versions() {
+        assert_eq!(MIGRATION_VERSIONS.first(), Some(&"00000000000000"));
+        assert!(MIGRATION_VERSIONS.contains(&"20190305140512"));
+        assert!(MIGRATION_VERSIONS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,161 @@
+//! Models for API keys used by other services to call delivery
+
+use std::time::SystemTime;
+
+use base64;
+use failure::Error as FailureError;
+use failure::Fail;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json;
+use sha3::{Digest, Sha3_256};
+use validator::Validate;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::authorization::Permission;
+use models::validation_rules::*;
+use schema::api_keys;
+
+const API_KEY_LEN: usize = 32;
+
+/// API key without the key itself, only its hash is stored
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+    pub created_by: Option<UserId>,
+    pub created_at: SystemTime,
+    pub revoked_at: Option<SystemTime>,
+}
+
+/// Newly created API key, the key is returned only once
+#[derive(Serialize, Debug)]
+pub struct CreatedApiKey {
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Validate)]
+pub struct NewApiKey {
+    #[validate(length(min = "1", message = "Name must not be empty"))]
+    pub name: String,
+    #[validate(custom = "validate_api_key_permissions")]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Queryable, Clone, Debug)]
+pub struct ApiKeyRaw {
+    pub id: i32,
+    pub name: String,
+    pub key_hash: String,
+    pub permissions: serde_json::Value,
+    pub created_by: Option<UserId>,
+    pub created_at: SystemTime,
+    pub revoked_at: Option<SystemTime>,
+}
+
+impl ApiKeyRaw {
+    pub fn to_model(self) -> Result<ApiKey, FailureError> {
+        let permissions = serde_json::from_value(self.permissions)
+            .map_err(|e| e.context("Can not parse api key permissions from db").context(Error::Parse))?;
+
+        Ok(ApiKey {
+            id: self.id,
+            name: self.name,
+            permissions,
+            created_by: self.created_by,
+            created_at: self.created_at,
+            revoked_at: self.revoked_at,
+        })
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "api_keys"]
+pub struct NewApiKeyRaw {
+    pub name: String,
+    pub key_hash: String,
+    pub permissions: serde_json::Value,
+    pub created_by: Option<UserId>,
+}
+
+impl NewApiKeyRaw {
+    pub fn from_model(payload: NewApiKey, key_hash: String, created_by: Option<UserId>) -> Result<Self, FailureError> {
+        let permissions =
+            serde_json::to_value(payload.permissions).map_err(|e| e.context("Can not parse api key permissions").context(Error::Parse))?;
+
+        Ok(NewApiKeyRaw {
+            name: payload.name,
+            key_hash,
+            permissions,
+            created_by,
+        })
+    }
+}
+
+/// Generates a new random API key
+pub fn generate_api_key() -> Result<String, FailureError> {
+    let mut key = [0u8; API_KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| format_err!("Could not generate api key"))?;
+    Ok(base64::encode_config(&key, base64::URL_SAFE_NO_PAD))
+}
+
+/// Hash of the API key stored instead of the key
+pub fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha3_256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_api_key() {
+        let key = generate_api_key().unwrap();
+        assert_eq!(base64::decode_config(&key, base64::URL_SAFE_NO_PAD).unwrap().len(), API_KEY_LEN);
+        assert_ne!(key, generate_api_key().unwrap());
+    }
+
+    #[test]
+    fn test_new_api_key_validation() {
+        use models::authorization::{Action, Resource, Scope};
+
+        let permission = |resource, scope| Permission {
+            resource,
+            action: Action::All,
+            scope,
+        };
+        let mut api_key = NewApiKey {
+            name: "orders".to_string(),
+            permissions: vec![permission(Resource::ShippingRates, Scope::All)],
+        };
+        assert!(api_key.validate().is_ok());
+
+        api_key.permissions = vec![permission(Resource::ShippingRates, Scope::Owned)];
+        assert!(api_key.validate().is_err());
+
+        api_key.permissions = vec![
+            permission(Resource::ShippingRates, Scope::All),
+            permission(Resource::ApiKeys, Scope::All),
+        ];
+        assert!(api_key.validate().is_err());
+
+        api_key.permissions = vec![permission(Resource::UserRoles, Scope::All)];
+        assert!(api_key.validate().is_err());
+
+        api_key.permissions = vec![];
+        assert!(api_key.validate().is_err());
+    }
+
+    #[test]
+    fn test_hash_api_key() {
+        let hash = hash_api_key("key");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, hash_api_key("key"));
+        assert_ne!(hash, hash_api_key("other key"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Models for audit log of administrative changes
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+use serde::Serialize;
+use serde_json;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::authorization::{Action, Resource};
+use schema::audit_log;
+
+const DEFAULT_AUDIT_LOG_SEARCH_COUNT: i64 = 100;
+
+/// Audit log entry, written in the same transaction as the change
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone)]
+pub struct AuditLogEntry {
+    pub id: i32,
+    pub user_id: Option<UserId>,
+    pub correlation_token: String,
+    pub resource: Resource,
+    pub action: Action,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub created_at: SystemTime,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone)]
+#[table_name = "audit_log"]
+pub struct NewAuditLogEntry {
+    pub user_id: Option<UserId>,
+    pub correlation_token: String,
+    pub resource: Resource,
+    pub action: Action,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl NewAuditLogEntry {
+    pub fn new(user_id: Option<UserId>, correlation_token: String, resource: Resource, action: Action) -> Self {
+        Self {
+            user_id,
+            correlation_token,
+            resource,
+            action,
+            before: None,
+            after: None,
+        }
+    }
+
+    /// Sets state of the resource before the change
+    pub fn before<T: Serialize>(self, value: &T) -> Result<Self, FailureError> {
+        let before = to_audit_value(value)?;
+        Ok(Self { before: Some(before), ..self })
+    }
+
+    /// Sets state of the resource after the change
+    pub fn after<T: Serialize>(self, value: &T) -> Result<Self, FailureError> {
+        let after = to_audit_value(value)?;
+        Ok(Self { after: Some(after), ..self })
+    }
+}
+
+fn to_audit_value<T: Serialize>(value: &T) -> Result<serde_json::Value, FailureError> {
+    serde_json::to_value(value).map_err(|e| e.context("Can not serialize audit log value").context(Error::Parse).into())
+}
+
+/// Request made by a superuser on behalf of another user, written to audit log
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImpersonatedRequest {
+    pub user_id: UserId,
+    pub real_user_id: UserId,
+    pub method: String,
+    pub path: String,
+}
+
+/// Filters of audit log search, all filters are optional
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditLogSearch {
+    pub user_id: Option<UserId>,
+    pub correlation_token: Option<String>,
+    pub resource: Option<Resource>,
+    pub action: Option<Action>,
+    pub created_from: Option<SystemTime>,
+    pub created_to: Option<SystemTime>,
+    #[serde(default)]
+    pub offset: i64,
+    #[serde(default = "default_audit_log_search_count")]
+    pub count: i64,
+}
+
+fn default_audit_log_search_count() -> i64 {
+    DEFAULT_AUDIT_LOG_SEARCH_COUNT
+}
@@ -6,7 +6,8 @@ use std::fmt;
 // Create - create resource with id.
 // Update - update resource with id.
 // Delete - delete resource with id.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, DieselTypes)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     All,
     Read,
@@ -2,8 +2,20 @@
 
 use models::{Action, Resource, Scope};
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Permission {
     pub resource: Resource,
+    #[serde(default = "default_action")]
     pub action: Action,
+    #[serde(default = "default_scope")]
     pub scope: Scope,
 }
+
+fn default_action() -> Action {
+    Action::All
+}
+
+fn default_scope() -> Scope {
+    Scope::All
+}
@@ -1,31 +1,47 @@
 //! Enum for resources available in ACLs
 use std::fmt;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, DieselTypes)]
+#[serde(rename_all = "snake_case")]
 pub enum Resource {
+    ApiKeys,
+    AuditLog,
     Companies,
     CompaniesPackages,
     Countries,
+    Impersonations,
+    OutboxEvents,
     Packages,
     Pickups,
     Products,
     ShippingRates,
     UserAddresses,
+    UserDataErasures,
     UserRoles,
+    WebhookDeliveries,
+    WebhookSubscriptions,
 }
 
+/// The same snake case names as in ACL policy files and audit log
 impl fmt::Display for Resource {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Resource::ApiKeys => write!(f, "api_keys"),
+            Resource::AuditLog => write!(f, "audit_log"),
             Resource::Companies => write!(f, "companies"),
             Resource::CompaniesPackages => write!(f, "companies_packages"),
             Resource::Countries => write!(f, "countries"),
+            Resource::Impersonations => write!(f, "impersonations"),
+            Resource::OutboxEvents => write!(f, "outbox_events"),
             Resource::Packages => write!(f, "packages"),
             Resource::Pickups => write!(f, "pickups"),
             Resource::Products => write!(f, "products"),
-            Resource::ShippingRates => write!(f, "shipping rates"),
-            Resource::UserAddresses => write!(f, "user addresses"),
-            Resource::UserRoles => write!(f, "user roles"),
+            Resource::ShippingRates => write!(f, "shipping_rates"),
+            Resource::UserAddresses => write!(f, "user_addresses"),
+            Resource::UserDataErasures => write!(f, "user_data_erasures"),
+            Resource::UserRoles => write!(f, "user_roles"),
+            Resource::WebhookDeliveries => write!(f, "webhook_deliveries"),
+            Resource::WebhookSubscriptions => write!(f, "webhook_subscriptions"),
         }
     }
 }
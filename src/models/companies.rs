@@ -1,27 +1,30 @@
-use failure::Error as FailureError;
-use failure::Fail;
-use serde_json;
-
 use stq_static_resources::Currency;
 use stq_types::{Alpha3, CompanyId};
 
-use errors::Error;
 use models::Country;
 use repos::countries::create_tree_used_countries;
 use schema::companies;
+use schema::companies_deliveries_from;
 
-#[derive(Serialize, Deserialize, Associations, Queryable, Debug, QueryableByName)]
+#[derive(Serialize, Deserialize, Associations, Queryable, Clone, Debug, QueryableByName)]
 #[table_name = "companies"]
 pub struct CompanyRaw {
     pub id: CompanyId,
     pub name: String,
     pub label: String,
     pub description: Option<String>,
-    pub deliveries_from: serde_json::Value,
     pub logo: String,
     pub currency: Currency,
 }
 
+/// Country the company delivers from
+#[derive(Serialize, Deserialize, Queryable, Insertable, Clone, Debug)]
+#[table_name = "companies_deliveries_from"]
+pub struct CompanyDeliveryFrom {
+    pub company_id: CompanyId,
+    pub country_alpha3: Alpha3,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Company {
     pub id: CompanyId,
@@ -34,12 +37,10 @@ pub struct Company {
 }
 
 impl Company {
-    pub fn from_raw(from: CompanyRaw, countries_arg: &Country) -> Result<Self, FailureError> {
-        let used_codes: Vec<Alpha3> = serde_json::from_value(from.deliveries_from)
-            .map_err(|e| e.context("Can not parse deliveries_from from db").context(Error::Parse))?;
-        let deliveries_from = create_tree_used_countries(countries_arg, &used_codes);
+    pub fn from_raw(from: CompanyRaw, used_codes: &[Alpha3], countries_arg: &Country) -> Self {
+        let deliveries_from = create_tree_used_countries(countries_arg, used_codes);
 
-        Ok(Self {
+        Self {
             id: from.id,
             name: from.name,
             label: from.label,
@@ -47,7 +48,7 @@ impl Company {
             deliveries_from,
             currency: from.currency,
             logo: from.logo,
-        })
+        }
     }
 }
 
@@ -57,7 +58,6 @@ pub struct NewCompanyRaw {
     pub name: String,
     pub label: String,
     pub description: Option<String>,
-    pub deliveries_from: serde_json::Value,
     pub logo: String,
     pub currency: Currency,
 }
@@ -73,7 +73,8 @@ pub struct NewCompany {
 }
 
 impl NewCompany {
-    pub fn to_raw(self) -> Result<NewCompanyRaw, FailureError> {
+    /// Splits the payload into the company row and countries it delivers from
+    pub fn to_raw(self) -> (NewCompanyRaw, Vec<Alpha3>) {
         let Self {
             name,
             label,
@@ -83,17 +84,15 @@ impl NewCompany {
             logo,
         } = self;
 
-        let deliveries_from = serde_json::to_value(deliveries_from)
-            .map_err(|e| e.context("Can not parse deliveries_from from value").context(Error::Parse))?;
-
-        Ok(NewCompanyRaw {
+        let raw = NewCompanyRaw {
             name,
             label,
             description,
-            deliveries_from,
             currency,
             logo,
-        })
+        };
+
+        (raw, deliveries_from)
     }
 }
 
@@ -103,11 +102,17 @@ pub struct UpdateCompanyRaw {
     pub name: Option<String>,
     pub label: Option<String>,
     pub description: Option<String>,
-    pub deliveries_from: Option<serde_json::Value>,
     pub logo: Option<String>,
     pub currency: Option<Currency>,
 }
 
+impl UpdateCompanyRaw {
+    /// Returns true if no column is changed, such changeset can't be saved
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.label.is_none() && self.description.is_none() && self.logo.is_none() && self.currency.is_none()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UpdateCompany {
     pub name: Option<String>,
@@ -119,7 +124,8 @@ pub struct UpdateCompany {
 }
 
 impl UpdateCompany {
-    pub fn to_raw(self) -> Result<UpdateCompanyRaw, FailureError> {
+    /// Splits the payload into the company changeset and new countries it delivers from
+    pub fn to_raw(self) -> (UpdateCompanyRaw, Option<Vec<Alpha3>>) {
         let Self {
             name,
             label,
@@ -129,20 +135,14 @@ impl UpdateCompany {
             logo,
         } = self;
 
-        let deliveries_from = match deliveries_from {
-            Some(data) => {
-                Some(serde_json::to_value(data).map_err(|e| e.context("Can not parse deliveries_from from value").context(Error::Parse))?)
-            }
-            None => None,
-        };
-
-        Ok(UpdateCompanyRaw {
+        let raw = UpdateCompanyRaw {
             name,
             label,
             description,
-            deliveries_from,
             currency,
             logo,
-        })
+        };
+
+        (raw, deliveries_from)
     }
 }
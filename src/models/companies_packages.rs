@@ -5,7 +5,7 @@ use validator::{Validate, ValidationErrors};
 
 use models::{Country, Pickups, ShippingVariant};
 use stq_static_resources::Currency;
-use stq_types::{BaseProductId, CompanyId, CompanyPackageId, PackageId, ProductPrice, ShippingId, StoreId};
+use stq_types::{Alpha3, BaseProductId, CompanyId, CompanyPackageId, PackageId, ProductPrice, ShippingId, StoreId};
 
 use schema::companies_packages;
 
@@ -72,7 +72,7 @@ pub struct CompanyPackage {
     pub shipping_rate_source: ShippingRateSource,
 }
 
-#[derive(Serialize, Deserialize, Associations, Queryable, Debug)]
+#[derive(Serialize, Deserialize, Associations, Queryable, Clone, Debug)]
 #[table_name = "companies_packages"]
 pub struct CompaniesPackagesRaw {
     pub id: CompanyPackageId,
@@ -199,4 +199,15 @@ pub struct AvailablePackageForUser {
 pub struct AvailableShippingForUser {
     pub packages: Vec<AvailablePackageForUser>,
     pub pickups: Option<Pickups>,
+    /// Destination resolved from the saved user address, set only if shipping is found by the address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<UserAddressDestination>,
+}
+
+/// Country and subdivision of a saved user address
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UserAddressDestination {
+    pub country: Alpha3,
+    /// First level administrative area of the address (state, province, region), if given
+    pub subdivision: Option<String>,
 }
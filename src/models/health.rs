@@ -0,0 +1,53 @@
+//! Models for health and readiness checks
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Ok,
+    Error,
+    /// The service is shutting down and does not accept new requests
+    ShuttingDown,
+}
+
+/// Status of the service, returned by liveness check
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Health {
+    pub status: HealthStatus,
+}
+
+/// Status of a dependency checked by readiness check
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DependencyStatus {
+    pub name: String,
+    pub status: HealthStatus,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Status of the service and all its dependencies, returned by readiness check
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Readiness {
+    pub status: HealthStatus,
+    pub dependencies: Vec<DependencyStatus>,
+}
+
+impl Readiness {
+    /// Service is ready if all dependencies are ready
+    pub fn new(dependencies: Vec<DependencyStatus>) -> Self {
+        let status = if dependencies.iter().all(|dependency| dependency.status == HealthStatus::Ok) {
+            HealthStatus::Ok
+        } else {
+            HealthStatus::Error
+        };
+
+        Self { status, dependencies }
+    }
+
+    /// Service is not ready once shutdown has started, dependencies are not checked
+    pub fn shutting_down() -> Self {
+        Self {
+            status: HealthStatus::ShuttingDown,
+            dependencies: vec![],
+        }
+    }
+}
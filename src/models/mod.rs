@@ -1,7 +1,11 @@
+pub mod api_keys;
+pub mod audit_log;
 pub mod authorization;
 pub mod companies;
 pub mod companies_packages;
 pub mod countries;
+pub mod health;
+pub mod outbox;
 pub mod packages;
 pub mod pickups;
 pub mod products;
@@ -9,12 +13,18 @@ pub mod roles;
 pub mod shipping;
 pub mod shipping_rates;
 pub mod user_addresses;
+pub mod user_data;
 pub mod validation_rules;
+pub mod webhooks;
 
+pub use self::api_keys::*;
+pub use self::audit_log::*;
 pub use self::authorization::*;
 pub use self::companies::*;
 pub use self::companies_packages::*;
 pub use self::countries::*;
+pub use self::health::*;
+pub use self::outbox::*;
 pub use self::packages::*;
 pub use self::pickups::*;
 pub use self::products::*;
@@ -22,4 +32,6 @@ pub use self::roles::*;
 pub use self::shipping::*;
 pub use self::shipping_rates::*;
 pub use self::user_addresses::*;
+pub use self::user_data::*;
 pub use self::validation_rules::*;
+pub use self::webhooks::*;
@@ -0,0 +1,159 @@
+//! Models for the outbox of domain events. Events are written in the same
+//! transaction as the change and delivered to subscribers by the dispatcher.
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+use serde_json;
+
+use stq_types::{Alpha3, BaseProductId, CompanyPackageId};
+
+use errors::Error;
+use models::{Company, CompanyPackage, Packages, Shipping};
+use schema::outbox_events;
+
+/// Types of all domain events, see `DomainEvent::event_type`
+pub const DOMAIN_EVENT_TYPES: &[&str] = &[
+    "shipping_upserted",
+    "shipping_deleted",
+    "rates_replaced",
+    "company_package_deleted",
+    "company_updated",
+    "package_created",
+    "package_updated",
+    "package_deleted",
+];
+
+/// Change of shipping configuration other services are interested in
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum DomainEvent {
+    ShippingUpserted {
+        base_product_id: BaseProductId,
+        shipping: Shipping,
+    },
+    ShippingDeleted {
+        base_product_id: BaseProductId,
+    },
+    RatesReplaced {
+        company_package_id: CompanyPackageId,
+        delivery_from: Alpha3,
+        deliveries_to: Vec<Alpha3>,
+    },
+    CompanyPackageDeleted {
+        company_package: CompanyPackage,
+    },
+    CompanyUpdated {
+        company: Company,
+    },
+    PackageCreated {
+        package: Packages,
+    },
+    PackageUpdated {
+        package: Packages,
+    },
+    PackageDeleted {
+        package: Packages,
+    },
+}
+
+impl DomainEvent {
+    pub fn event_type(&self) -> &'static str {
+        match *self {
+            DomainEvent::ShippingUpserted { .. } => "shipping_upserted",
+            DomainEvent::ShippingDeleted { .. } => "shipping_deleted",
+            DomainEvent::RatesReplaced { .. } => "rates_replaced",
+            DomainEvent::CompanyPackageDeleted { .. } => "company_package_deleted",
+            DomainEvent::CompanyUpdated { .. } => "company_updated",
+            DomainEvent::PackageCreated { .. } => "package_created",
+            DomainEvent::PackageUpdated { .. } => "package_updated",
+            DomainEvent::PackageDeleted { .. } => "package_deleted",
+        }
+    }
+}
+
+/// Event stored in the outbox, `sent_at` is set once the event is delivered to the endpoints,
+/// `webhooks_created_at` once deliveries to webhook subscriptions are created
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone)]
+pub struct OutboxEvent {
+    pub id: i32,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub correlation_token: String,
+    pub created_at: SystemTime,
+    pub attempts: i32,
+    pub next_attempt_at: SystemTime,
+    pub last_error: Option<String>,
+    pub sent_at: Option<SystemTime>,
+    pub webhooks_created_at: Option<SystemTime>,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone)]
+#[table_name = "outbox_events"]
+pub struct NewOutboxEvent {
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub correlation_token: String,
+}
+
+impl NewOutboxEvent {
+    pub fn new(event: &DomainEvent, correlation_token: String) -> Result<Self, FailureError> {
+        let payload = serde_json::to_value(event).map_err(|e| e.context("Can not serialize domain event").context(Error::Parse))?;
+        Ok(Self {
+            event_type: event.event_type().to_string(),
+            payload,
+            correlation_token,
+        })
+    }
+}
+
+/// Delivery of an outbox event to one of `outbox.endpoints`
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone)]
+pub struct OutboxEndpointDelivery {
+    pub outbox_event_id: i32,
+    pub endpoint: String,
+    pub delivered_at: SystemTime,
+}
+
+/// Body of the request delivering an outbox event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OutboxMessage {
+    pub id: i32,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub created_at: SystemTime,
+}
+
+impl From<OutboxEvent> for OutboxMessage {
+    fn from(event: OutboxEvent) -> Self {
+        Self {
+            id: event.id,
+            event_type: event.event_type,
+            payload: event.payload,
+            created_at: event.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stq_types::*;
+
+    use super::*;
+
+    #[test]
+    fn test_new_outbox_event() {
+        let event = DomainEvent::RatesReplaced {
+            company_package_id: CompanyPackageId(1),
+            delivery_from: Alpha3("RUS".to_string()),
+            deliveries_to: vec![Alpha3("USA".to_string())],
+        };
+        let new_event = NewOutboxEvent::new(&event, "token".to_string()).unwrap();
+        assert_eq!(new_event.event_type, "rates_replaced");
+        assert_eq!(new_event.payload["company_package_id"], 1);
+        assert_eq!(new_event.payload["delivery_from"], "RUS");
+        assert_eq!(new_event.payload["deliveries_to"][0], "USA");
+        assert_eq!(new_event.correlation_token, "token");
+    }
+}
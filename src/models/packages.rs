@@ -1,13 +1,9 @@
-use failure::Error as FailureError;
-use failure::Fail;
-use serde_json;
-
 use stq_types::{Alpha3, PackageId};
 
-use errors::Error;
 use models::{Country, ShipmentMeasurements};
 use repos::countries::create_tree_used_countries;
 use schema::packages;
+use schema::packages_deliveries_to;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub enum MeasurementsOutOfRange {
@@ -31,7 +27,7 @@ pub enum MeasurementsOutOfRange {
     },
 }
 
-#[derive(Serialize, Deserialize, Associations, Queryable, Debug, QueryableByName)]
+#[derive(Serialize, Deserialize, Associations, Queryable, Clone, Debug, QueryableByName)]
 #[table_name = "packages"]
 pub struct PackagesRaw {
     pub id: PackageId,
@@ -40,7 +36,14 @@ pub struct PackagesRaw {
     pub min_size: i32,
     pub max_weight: i32,
     pub min_weight: i32,
-    pub deliveries_to: serde_json::Value,
+}
+
+/// Country the package can be delivered to
+#[derive(Serialize, Deserialize, Queryable, Insertable, Clone, Debug)]
+#[table_name = "packages_deliveries_to"]
+pub struct PackageDeliveryTo {
+    pub package_id: PackageId,
+    pub country_alpha3: Alpha3,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -96,12 +99,10 @@ impl Packages {
 }
 
 impl PackagesRaw {
-    pub fn to_packages(self, countries_arg: &Country) -> Result<Packages, FailureError> {
-        let used_codes: Vec<Alpha3> =
-            serde_json::from_value(self.deliveries_to).map_err(|e| e.context("Can not parse deliveries_to from db"))?;
-        let deliveries_to = create_tree_used_countries(countries_arg, &used_codes);
+    pub fn to_packages(self, used_codes: &[Alpha3], countries_arg: &Country) -> Packages {
+        let deliveries_to = create_tree_used_countries(countries_arg, used_codes);
 
-        Ok(Packages {
+        Packages {
             id: self.id,
             name: self.name,
             max_size: self.max_size as u32,
@@ -109,14 +110,7 @@ impl PackagesRaw {
             max_weight: self.max_weight as u32,
             min_weight: self.min_weight as u32,
             deliveries_to,
-        })
-    }
-
-    pub fn get_deliveries_to(&self) -> Result<Vec<Alpha3>, FailureError> {
-        let used_codes =
-            serde_json::from_value(self.deliveries_to.clone()).map_err(|e| e.context("Can not parse deliveries_to from db"))?;
-
-        Ok(used_codes)
+        }
     }
 }
 
@@ -128,7 +122,6 @@ pub struct NewPackagesRaw {
     pub min_size: i32,
     pub max_weight: i32,
     pub min_weight: i32,
-    pub deliveries_to: serde_json::Value,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -142,18 +135,17 @@ pub struct NewPackages {
 }
 
 impl NewPackages {
-    pub fn to_raw(self) -> Result<NewPackagesRaw, FailureError> {
-        let deliveries_to = serde_json::to_value(self.deliveries_to)
-            .map_err(|e| e.context("Can not parse deliveries_to from value").context(Error::Parse))?;
-
-        Ok(NewPackagesRaw {
+    /// Splits the payload into the package row and countries it can be delivered to
+    pub fn to_raw(self) -> (NewPackagesRaw, Vec<Alpha3>) {
+        let raw = NewPackagesRaw {
             name: self.name,
             max_size: self.max_size as i32,
             min_size: self.min_size as i32,
             max_weight: self.max_weight as i32,
             min_weight: self.min_weight as i32,
-            deliveries_to,
-        })
+        };
+
+        (raw, self.deliveries_to)
     }
 }
 
@@ -165,7 +157,13 @@ pub struct UpdatePackagesRaw {
     pub min_size: Option<i32>,
     pub max_weight: Option<i32>,
     pub min_weight: Option<i32>,
-    pub deliveries_to: Option<serde_json::Value>,
+}
+
+impl UpdatePackagesRaw {
+    /// Returns true if no column is changed, such changeset can't be saved
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.max_size.is_none() && self.min_size.is_none() && self.max_weight.is_none() && self.min_weight.is_none()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -179,21 +177,16 @@ pub struct UpdatePackages {
 }
 
 impl UpdatePackages {
-    pub fn to_raw(self) -> Result<UpdatePackagesRaw, FailureError> {
-        let deliveries_to = match self.deliveries_to {
-            Some(info) => {
-                Some(serde_json::to_value(info).map_err(|e| e.context("Can not parse deliveries_to from value").context(Error::Parse))?)
-            }
-            None => None,
-        };
-
-        Ok(UpdatePackagesRaw {
+    /// Splits the payload into the package changeset and new countries it can be delivered to
+    pub fn to_raw(self) -> (UpdatePackagesRaw, Option<Vec<Alpha3>>) {
+        let raw = UpdatePackagesRaw {
             name: self.name,
             max_size: self.max_size.map(|x| x as i32),
             min_size: self.min_size.map(|x| x as i32),
             max_weight: self.max_weight.map(|x| x as i32),
             min_weight: self.min_weight.map(|x| x as i32),
-            deliveries_to,
-        })
+        };
+
+        (raw, self.deliveries_to)
     }
 }
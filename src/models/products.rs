@@ -1,14 +1,11 @@
-use failure::Error as FailureError;
-use failure::Fail;
-use serde_json;
 use validator::{Validate, ValidationError, ValidationErrors};
 
 use stq_static_resources::Currency;
 use stq_types::{Alpha3, BaseProductId, CompanyPackageId, ProductPrice, ShippingId, StoreId};
 
-use errors::Error;
 use models::{get_country_from_forest, Company, Packages, ShipmentMeasurements, ShippingRate};
 use schema::products;
+use schema::products_deliveries_to;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, DieselTypes)]
 pub enum ShippingVariant {
@@ -16,7 +13,7 @@ pub enum ShippingVariant {
     International,
 }
 
-#[derive(Serialize, Queryable, Insertable, Debug, QueryableByName)]
+#[derive(Serialize, Queryable, Insertable, Clone, Debug, QueryableByName)]
 #[table_name = "products"]
 pub struct ProductsRaw {
     pub id: ShippingId,
@@ -24,11 +21,18 @@ pub struct ProductsRaw {
     pub store_id: StoreId,
     pub company_package_id: CompanyPackageId,
     pub price: Option<ProductPrice>,
-    pub deliveries_to: serde_json::Value,
     pub shipping: ShippingVariant,
     pub currency: Currency,
 }
 
+/// Country the product can be delivered to
+#[derive(Serialize, Deserialize, Queryable, Insertable, Clone, Debug)]
+#[table_name = "products_deliveries_to"]
+pub struct ProductDeliveryTo {
+    pub product_id: ShippingId,
+    pub country_alpha3: Alpha3,
+}
+
 #[derive(Serialize, Deserialize, Insertable, Clone, Debug)]
 #[table_name = "products"]
 pub struct NewProductsRaw {
@@ -36,7 +40,6 @@ pub struct NewProductsRaw {
     pub store_id: StoreId,
     pub company_package_id: CompanyPackageId,
     pub price: Option<ProductPrice>,
-    pub deliveries_to: serde_json::Value,
     pub shipping: ShippingVariant,
     pub currency: Currency,
 }
@@ -45,11 +48,17 @@ pub struct NewProductsRaw {
 #[table_name = "products"]
 pub struct UpdateProductsRaw {
     pub price: Option<ProductPrice>,
-    pub deliveries_to: Option<serde_json::Value>,
     pub shipping: Option<ShippingVariant>,
     pub currency: Option<Currency>,
 }
 
+impl UpdateProductsRaw {
+    /// Returns true if no column is changed, such changeset can't be saved
+    pub fn is_empty(&self) -> bool {
+        self.price.is_none() && self.shipping.is_none() && self.currency.is_none()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Products {
     pub id: ShippingId,
@@ -81,10 +90,8 @@ pub struct ProductWithShippingPrice {
 }
 
 impl ProductsRaw {
-    pub fn to_products(self) -> Result<Products, FailureError> {
-        let deliveries_to =
-            serde_json::from_value(self.deliveries_to).map_err(|e| e.context("Can not parse products from db").context(Error::Parse))?;
-        Ok(Products {
+    pub fn to_products(self, deliveries_to: Vec<Alpha3>) -> Products {
+        Products {
             id: self.id,
             base_product_id: self.base_product_id,
             store_id: self.store_id,
@@ -93,14 +100,7 @@ impl ProductsRaw {
             deliveries_to,
             shipping: self.shipping,
             currency: self.currency,
-        })
-    }
-
-    pub fn get_deliveries_to(&self) -> Result<Vec<Alpha3>, FailureError> {
-        let used_codes = serde_json::from_value(self.deliveries_to.clone())
-            .map_err(|e| e.context("Can not parse deliveries_to from db").context(Error::Parse))?;
-
-        Ok(used_codes)
+        }
     }
 }
 
@@ -235,18 +235,18 @@ impl Validate for NewProductValidation {
 }
 
 impl NewProducts {
-    pub fn to_raw(self) -> Result<NewProductsRaw, FailureError> {
-        let deliveries_to =
-            serde_json::to_value(self.deliveries_to).map_err(|e| e.context("Can not parse products from db").context(Error::Parse))?;
-        Ok(NewProductsRaw {
+    /// Splits the payload into the product row and countries it can be delivered to
+    pub fn to_raw(self) -> (NewProductsRaw, Vec<Alpha3>) {
+        let raw = NewProductsRaw {
             base_product_id: self.base_product_id,
             store_id: self.store_id,
             company_package_id: self.company_package_id,
             price: self.price,
-            deliveries_to,
             shipping: self.shipping,
             currency: self.currency,
-        })
+        };
+
+        (raw, self.deliveries_to)
     }
 }
 
@@ -259,19 +259,14 @@ pub struct UpdateProducts {
 }
 
 impl UpdateProducts {
-    pub fn to_raw(self) -> Result<UpdateProductsRaw, FailureError> {
-        let deliveries_to = match self.deliveries_to {
-            Some(v) => serde_json::to_value(v)
-                .map(Some)
-                .map_err(|e| e.context("Can not parse products from value").context(Error::Parse))?,
-            None => None,
-        };
-
-        Ok(UpdateProductsRaw {
+    /// Splits the payload into the product changeset and new countries it can be delivered to
+    pub fn to_raw(self) -> (UpdateProductsRaw, Option<Vec<Alpha3>>) {
+        let raw = UpdateProductsRaw {
             price: self.price,
-            deliveries_to,
             shipping: self.shipping,
             currency: self.currency,
-        })
+        };
+
+        (raw, self.deliveries_to)
     }
 }
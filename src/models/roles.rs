@@ -2,24 +2,124 @@
 
 use serde_json;
 
-use stq_types::{DeliveryRole, RoleId, UserId};
+use stq_types::{CompanyId, RoleId, StoreId, UserId};
 
+use models::authorization::Permission;
 use schema::roles;
 
-#[derive(Serialize, Deserialize, Queryable, Insertable, Debug)]
+/// Roles of delivery users, stored in `roles.name` by the snake case name.
+/// Defined here rather than taken from `stq_types::DeliveryRole`, which has no company manager role
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug, DieselTypes)]
+#[serde(rename_all = "snake_case")]
+pub enum RoleName {
+    Superuser,
+    User,
+    StoreManager,
+    CompanyManager,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Insertable, Debug, Clone)]
 #[table_name = "roles"]
 pub struct UserRole {
     pub id: RoleId,
     pub user_id: UserId,
-    pub name: DeliveryRole,
+    pub name: RoleName,
     pub data: Option<serde_json::Value>,
 }
 
+impl UserRole {
+    /// Ids of companies owned by company manager, `data` contains a company id or an array of them
+    pub fn owned_company_ids(&self) -> Vec<CompanyId> {
+        self.data_ids().into_iter().map(CompanyId).collect()
+    }
+
+    /// Ids of stores managed by store manager, `data` contains a store id or an array of them
+    pub fn owned_store_ids(&self) -> Vec<StoreId> {
+        self.data_ids().into_iter().map(StoreId).collect()
+    }
+
+    fn data_ids(&self) -> Vec<i32> {
+        match self.data {
+            Some(serde_json::Value::Array(ref values)) => values.iter().filter_map(as_id).collect(),
+            Some(ref value) => as_id(value).into_iter().collect(),
+            None => vec![],
+        }
+    }
+}
+
+fn as_id(value: &serde_json::Value) -> Option<i32> {
+    value.as_i64().map(|id| id as i32)
+}
+
+/// Checks that one of the roles is a company manager role owning the company
+pub fn owns_company(user_roles: &[UserRole], company_id: CompanyId) -> bool {
+    user_roles
+        .iter()
+        .filter(|user_role| user_role.name == RoleName::CompanyManager)
+        .any(|user_role| user_role.owned_company_ids().contains(&company_id))
+}
+
+/// Checks that one of the roles is a store manager role managing the store
+pub fn owns_store(user_roles: &[UserRole], store_id: StoreId) -> bool {
+    user_roles
+        .iter()
+        .filter(|user_role| user_role.name == RoleName::StoreManager)
+        .any(|user_role| user_role.owned_store_ids().contains(&store_id))
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Insertable)]
 #[table_name = "roles"]
 pub struct NewUserRole {
     pub id: RoleId,
     pub user_id: UserId,
-    pub name: DeliveryRole,
+    pub name: RoleName,
     pub data: Option<serde_json::Value>,
 }
+
+/// Permissions granted to the user by the roles
+#[derive(Serialize, Debug)]
+pub struct UserPermissions {
+    pub user_id: UserId,
+    pub roles: Vec<RoleName>,
+    pub permissions: Vec<Permission>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_role(data: Option<&str>) -> UserRole {
+        create_named_role(RoleName::CompanyManager, data)
+    }
+
+    fn create_named_role(name: RoleName, data: Option<&str>) -> UserRole {
+        UserRole {
+            id: RoleId::new(),
+            user_id: UserId(1),
+            name,
+            data: data.map(|data| serde_json::from_str(data).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_owned_company_ids() {
+        assert_eq!(create_role(Some("3")).owned_company_ids(), vec![CompanyId(3)]);
+        assert_eq!(create_role(Some("[1, 2]")).owned_company_ids(), vec![CompanyId(1), CompanyId(2)]);
+        assert!(create_role(Some(r#"{"company_id": 1}"#)).owned_company_ids().is_empty());
+        assert!(create_role(None).owned_company_ids().is_empty());
+    }
+
+    #[test]
+    fn test_owns_store() {
+        let user_roles = vec![
+            create_named_role(RoleName::User, None),
+            create_named_role(RoleName::StoreManager, Some("[5, 6]")),
+            create_named_role(RoleName::CompanyManager, Some("7")),
+        ];
+        assert!(owns_store(&user_roles, StoreId(5)));
+        assert!(owns_store(&user_roles, StoreId(6)));
+        assert!(!owns_store(&user_roles, StoreId(7)));
+        assert!(owns_company(&user_roles, CompanyId(7)));
+        assert!(!owns_company(&user_roles, CompanyId(5)));
+    }
+}
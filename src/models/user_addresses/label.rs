@@ -0,0 +1,293 @@
+//! Rendering of user delivery addresses into postal label lines.
+//! Every country orders address parts differently, so label formats
+//! are described by per-country templates.
+
+use super::UserAddress;
+
+/// Part of the user address that can be printed on a label
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressField {
+    StreetNumber,
+    Route,
+    Locality,
+    AdministrativeAreaLevel1,
+    AdministrativeAreaLevel2,
+    PostalCode,
+    Country,
+}
+
+/// Label line is a list of field groups. Fields inside a group are separated with a space,
+/// non-empty groups are separated with a comma.
+pub type LabelLineTemplate = &'static [&'static [AddressField]];
+
+/// Label format is an ordered list of line templates
+pub type LabelFormat = &'static [LabelLineTemplate];
+
+use self::AddressField::*;
+
+/// Format used for countries without a specific template, every part on its own line
+pub static DEFAULT_LABEL_FORMAT: LabelFormat = &[
+    &[&[StreetNumber, Route]],
+    &[&[Locality]],
+    &[&[AdministrativeAreaLevel1]],
+    &[&[PostalCode]],
+    &[&[Country]],
+];
+
+/// United States, Canada, Australia
+static NORTH_AMERICAN_LABEL_FORMAT: LabelFormat = &[
+    &[&[StreetNumber, Route]],
+    &[&[Locality], &[AdministrativeAreaLevel1, PostalCode]],
+    &[&[Country]],
+];
+
+/// Germany, Austria, Switzerland, Netherlands, Poland and others with postal code before the city
+static CONTINENTAL_LABEL_FORMAT: LabelFormat = &[&[&[Route, StreetNumber]], &[&[PostalCode, Locality]], &[&[Country]]];
+
+/// France, Belgium, Luxembourg
+static FRENCH_LABEL_FORMAT: LabelFormat = &[&[&[StreetNumber, Route]], &[&[PostalCode, Locality]], &[&[Country]]];
+
+/// Italy, Spain
+static SOUTHERN_EUROPEAN_LABEL_FORMAT: LabelFormat = &[
+    &[&[Route], &[StreetNumber]],
+    &[&[PostalCode, Locality, AdministrativeAreaLevel2]],
+    &[&[Country]],
+];
+
+/// United Kingdom, Ireland
+static BRITISH_LABEL_FORMAT: LabelFormat = &[
+    &[&[StreetNumber, Route]],
+    &[&[Locality]],
+    &[&[AdministrativeAreaLevel2]],
+    &[&[PostalCode]],
+    &[&[Country]],
+];
+
+/// Russia and other CIS countries
+static RUSSIAN_LABEL_FORMAT: LabelFormat = &[
+    &[&[Route], &[StreetNumber]],
+    &[&[Locality]],
+    &[&[AdministrativeAreaLevel1]],
+    &[&[PostalCode]],
+    &[&[Country]],
+];
+
+/// Japan, China, Korea - from the largest to the smallest part
+static EAST_ASIAN_LABEL_FORMAT: LabelFormat = &[
+    &[&[Country]],
+    &[&[PostalCode]],
+    &[&[AdministrativeAreaLevel1, Locality]],
+    &[&[Route, StreetNumber]],
+];
+
+/// Returns label format by alpha3 code of the country
+pub fn get_label_format(country_code: Option<&str>) -> LabelFormat {
+    let country_code = country_code.map(|code| code.to_uppercase());
+    match country_code.as_ref().map(String::as_str) {
+        Some("USA") | Some("CAN") | Some("AUS") => NORTH_AMERICAN_LABEL_FORMAT,
+        Some("DEU") | Some("AUT") | Some("CHE") | Some("NLD") | Some("POL") | Some("CZE") | Some("DNK") | Some("SWE") | Some("NOR")
+        | Some("FIN") => CONTINENTAL_LABEL_FORMAT,
+        Some("FRA") | Some("BEL") | Some("LUX") => FRENCH_LABEL_FORMAT,
+        Some("ITA") | Some("ESP") => SOUTHERN_EUROPEAN_LABEL_FORMAT,
+        Some("GBR") | Some("IRL") => BRITISH_LABEL_FORMAT,
+        Some("RUS") | Some("BLR") | Some("KAZ") | Some("UKR") => RUSSIAN_LABEL_FORMAT,
+        Some("JPN") | Some("CHN") | Some("KOR") => EAST_ASIAN_LABEL_FORMAT,
+        _ => DEFAULT_LABEL_FORMAT,
+    }
+}
+
+/// Address rendered into ordered label lines
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AddressLabel {
+    pub lines: Vec<String>,
+    pub text: String,
+}
+
+impl AddressLabel {
+    /// Renders user address using format of its country.
+    /// If the address has neither route nor street number, free-form `address` is printed as the first line.
+    pub fn from_user_address(user_address: &UserAddress) -> Self {
+        let format = get_label_format(user_address.country_code.as_ref().map(String::as_str));
+
+        let mut lines = vec![];
+        if user_address.route.is_none() && user_address.street_number.is_none() {
+            if let Some(address) = non_empty(user_address.address.as_ref()) {
+                lines.push(address.to_string());
+            }
+        }
+
+        lines.extend(format.iter().filter_map(|line| render_line(user_address, *line)));
+
+        let text = lines.join("\n");
+        AddressLabel { lines, text }
+    }
+}
+
+fn render_line(user_address: &UserAddress, line: LabelLineTemplate) -> Option<String> {
+    let groups = line
+        .iter()
+        .filter_map(|group| {
+            let values = group
+                .iter()
+                .filter_map(|field| get_field(user_address, *field))
+                .collect::<Vec<_>>();
+            if values.is_empty() {
+                None
+            } else {
+                Some(values.join(" "))
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if groups.is_empty() {
+        None
+    } else {
+        Some(groups.join(", "))
+    }
+}
+
+fn get_field(user_address: &UserAddress, field: AddressField) -> Option<String> {
+    match field {
+        StreetNumber => non_empty(user_address.street_number.as_ref()).map(str::to_string),
+        Route => non_empty(user_address.route.as_ref()).map(str::to_string),
+        Locality => non_empty(user_address.locality.as_ref()).map(str::to_string),
+        AdministrativeAreaLevel1 => non_empty(user_address.administrative_area_level_1.as_ref()).map(str::to_string),
+        AdministrativeAreaLevel2 => non_empty(user_address.administrative_area_level_2.as_ref()).map(str::to_string),
+        PostalCode => non_empty(Some(&user_address.postal_code)).map(str::to_string),
+        Country => non_empty(Some(&user_address.country)).map(str::to_uppercase),
+    }
+}
+
+fn non_empty(value: Option<&String>) -> Option<&str> {
+    value.map(|v| v.trim()).filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use stq_types::UserId;
+
+    use super::*;
+
+    fn create_user_address(country: &str, country_code: &str) -> UserAddress {
+        UserAddress {
+            id: 1,
+            user_id: UserId(1),
+            administrative_area_level_1: None,
+            administrative_area_level_2: None,
+            country: country.to_string(),
+            locality: None,
+            political: None,
+            postal_code: String::new(),
+            route: None,
+            street_number: None,
+            address: None,
+            is_priority: true,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+            country_code: Some(country_code.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_germany_postal_code_before_city() {
+        let mut address = create_user_address("Germany", "DEU");
+        address.route = Some("Unter den Linden".to_string());
+        address.street_number = Some("77".to_string());
+        address.postal_code = "10117".to_string();
+        address.locality = Some("Berlin".to_string());
+        address.administrative_area_level_1 = Some("Berlin".to_string());
+
+        let label = AddressLabel::from_user_address(&address);
+        assert_eq!(label.lines, vec!["Unter den Linden 77", "10117 Berlin", "GERMANY"]);
+        assert_eq!(label.text, "Unter den Linden 77\n10117 Berlin\nGERMANY");
+    }
+
+    #[test]
+    fn test_usa_postal_code_after_state() {
+        let mut address = create_user_address("United States", "usa");
+        address.route = Some("Pennsylvania Avenue NW".to_string());
+        address.street_number = Some("1600".to_string());
+        address.postal_code = "20500".to_string();
+        address.locality = Some("Washington".to_string());
+        address.administrative_area_level_1 = Some("DC".to_string());
+
+        let label = AddressLabel::from_user_address(&address);
+        assert_eq!(
+            label.lines,
+            vec!["1600 Pennsylvania Avenue NW", "Washington, DC 20500", "UNITED STATES"]
+        );
+    }
+
+    #[test]
+    fn test_united_kingdom_postal_code_on_separate_line() {
+        let mut address = create_user_address("United Kingdom", "GBR");
+        address.route = Some("Downing Street".to_string());
+        address.street_number = Some("10".to_string());
+        address.postal_code = "SW1A 2AA".to_string();
+        address.locality = Some("London".to_string());
+
+        let label = AddressLabel::from_user_address(&address);
+        assert_eq!(label.lines, vec!["10 Downing Street", "London", "SW1A 2AA", "UNITED KINGDOM"]);
+    }
+
+    #[test]
+    fn test_russia_region_before_postal_code() {
+        let mut address = create_user_address("Russia", "RUS");
+        address.route = Some("ul. Tverskaya".to_string());
+        address.street_number = Some("7".to_string());
+        address.postal_code = "125009".to_string();
+        address.locality = Some("Moscow".to_string());
+        address.administrative_area_level_1 = Some("Moscow".to_string());
+
+        let label = AddressLabel::from_user_address(&address);
+        assert_eq!(label.lines, vec!["ul. Tverskaya, 7", "Moscow", "Moscow", "125009", "RUSSIA"]);
+    }
+
+    #[test]
+    fn test_japan_from_largest_to_smallest() {
+        let mut address = create_user_address("Japan", "JPN");
+        address.route = Some("Chiyoda".to_string());
+        address.street_number = Some("1-1".to_string());
+        address.postal_code = "100-8111".to_string();
+        address.locality = Some("Chiyoda-ku".to_string());
+        address.administrative_area_level_1 = Some("Tokyo".to_string());
+
+        let label = AddressLabel::from_user_address(&address);
+        assert_eq!(label.lines, vec!["JAPAN", "100-8111", "Tokyo Chiyoda-ku", "Chiyoda 1-1"]);
+    }
+
+    #[test]
+    fn test_unknown_country_uses_default_format() {
+        let mut address = create_user_address("Brazil", "BRA");
+        address.route = Some("Avenida Paulista".to_string());
+        address.street_number = Some("1578".to_string());
+        address.postal_code = "01310-200".to_string();
+        address.locality = Some("Sao Paulo".to_string());
+
+        let label = AddressLabel::from_user_address(&address);
+        assert_eq!(label.lines, vec!["1578 Avenida Paulista", "Sao Paulo", "01310-200", "BRAZIL"]);
+    }
+
+    #[test]
+    fn test_free_form_address_without_route() {
+        let mut address = create_user_address("France", "FRA");
+        address.address = Some("Chateau de Versailles".to_string());
+        address.postal_code = "78000".to_string();
+        address.locality = Some("Versailles".to_string());
+
+        let label = AddressLabel::from_user_address(&address);
+        assert_eq!(label.lines, vec!["Chateau de Versailles", "78000 Versailles", "FRANCE"]);
+    }
+
+    #[test]
+    fn test_empty_fields_are_skipped() {
+        let mut address = create_user_address("Germany", "DEU");
+        address.locality = Some("  ".to_string());
+        address.postal_code = "10117".to_string();
+
+        let label = AddressLabel::from_user_address(&address);
+        assert_eq!(label.lines, vec!["10117", "GERMANY"]);
+    }
+}
@@ -0,0 +1,130 @@
+//! Models for managing user delivery address
+pub mod label;
+
+pub use self::label::*;
+
+use std::time::SystemTime;
+
+use validator::Validate;
+
+use stq_types::UserId;
+
+use schema::user_addresses;
+
+#[derive(Serialize, Clone, Debug, Deserialize)]
+pub struct UserAddress {
+    pub id: i32,
+    pub user_id: UserId,
+    pub administrative_area_level_1: Option<String>,
+    pub administrative_area_level_2: Option<String>,
+    pub country: String,
+    pub locality: Option<String>,
+    pub political: Option<String>,
+    pub postal_code: String,
+    pub route: Option<String>,
+    pub street_number: Option<String>,
+    pub address: Option<String>,
+    pub is_priority: bool,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    pub country_code: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct NewUserAddress {
+    pub user_id: UserId,
+    pub administrative_area_level_1: Option<String>,
+    pub administrative_area_level_2: Option<String>,
+    #[validate(length(min = "1", message = "Country must not be empty"))]
+    pub country: String,
+    pub locality: Option<String>,
+    pub political: Option<String>,
+    #[validate(length(min = "1", message = "Postal code must not be empty"))]
+    pub postal_code: String,
+    pub route: Option<String>,
+    pub street_number: Option<String>,
+    pub address: Option<String>,
+    pub is_priority: bool,
+    #[validate(length(min = "1", message = "Country code must not be empty"))]
+    pub country_code: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateUserAddress {
+    pub administrative_area_level_1: Option<String>,
+    pub administrative_area_level_2: Option<String>,
+    #[validate(length(min = "1", message = "Country must not be empty"))]
+    pub country: Option<String>,
+    pub locality: Option<String>,
+    pub political: Option<String>,
+    #[validate(length(min = "1", message = "Postal code must not be empty"))]
+    pub postal_code: Option<String>,
+    pub route: Option<String>,
+    pub street_number: Option<String>,
+    pub address: Option<String>,
+    pub is_priority: Option<bool>,
+    #[validate(length(min = "1", message = "Country code must not be empty"))]
+    pub country_code: Option<String>,
+}
+
+/// RawUserAddress is an object stored in PG. Postal code, route, street number and address
+/// are encrypted with the key `encryption_key_id`, or plaintext if the key is not set.
+#[derive(Debug, Queryable, Clone)]
+pub struct RawUserAddress {
+    pub id: i32,
+    pub user_id: UserId,
+    pub administrative_area_level_1: Option<String>,
+    pub administrative_area_level_2: Option<String>,
+    pub country: String,
+    pub locality: Option<String>,
+    pub political: Option<String>,
+    pub postal_code: String,
+    pub route: Option<String>,
+    pub street_number: Option<String>,
+    pub address: Option<String>,
+    pub is_priority: bool,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    pub country_code: Option<String>,
+    pub encryption_key_id: Option<String>,
+    pub address_blind_index: Option<String>,
+}
+
+/// Payload for inserting user address with encrypted fields
+#[derive(Debug, Insertable)]
+#[table_name = "user_addresses"]
+pub struct NewRawUserAddress {
+    pub user_id: UserId,
+    pub administrative_area_level_1: Option<String>,
+    pub administrative_area_level_2: Option<String>,
+    pub country: String,
+    pub locality: Option<String>,
+    pub political: Option<String>,
+    pub postal_code: String,
+    pub route: Option<String>,
+    pub street_number: Option<String>,
+    pub address: Option<String>,
+    pub is_priority: bool,
+    pub country_code: Option<String>,
+    pub encryption_key_id: String,
+    pub address_blind_index: String,
+}
+
+/// Payload for updating user address with encrypted fields
+#[derive(Debug, Default, AsChangeset)]
+#[table_name = "user_addresses"]
+pub struct UpdateRawUserAddress {
+    pub administrative_area_level_1: Option<String>,
+    pub administrative_area_level_2: Option<String>,
+    pub country: Option<String>,
+    pub locality: Option<String>,
+    pub political: Option<String>,
+    pub postal_code: Option<String>,
+    pub route: Option<String>,
+    pub street_number: Option<String>,
+    pub address: Option<String>,
+    pub is_priority: Option<bool>,
+    pub country_code: Option<String>,
+    pub encryption_key_id: Option<String>,
+    pub address_blind_index: Option<String>,
+}
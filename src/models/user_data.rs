@@ -0,0 +1,36 @@
+//! Models for exporting and erasing personal data of a user
+
+use std::time::SystemTime;
+
+use stq_types::UserId;
+
+use models::{UserAddress, UserRole};
+use schema::user_data_erasures;
+
+/// Bundle of all personal data stored for a user
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserDataExport {
+    pub user_id: UserId,
+    pub addresses: Vec<UserAddress>,
+    pub roles: Vec<UserRole>,
+}
+
+/// Audit record of user data erasure
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone)]
+pub struct UserDataErasure {
+    pub id: i32,
+    pub user_id: UserId,
+    pub erased_by: UserId,
+    pub addresses_count: i32,
+    pub roles_count: i32,
+    pub created_at: SystemTime,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone)]
+#[table_name = "user_data_erasures"]
+pub struct NewUserDataErasure {
+    pub user_id: UserId,
+    pub erased_by: UserId,
+    pub addresses_count: i32,
+    pub roles_count: i32,
+}
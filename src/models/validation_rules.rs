@@ -7,6 +7,9 @@ use validator::ValidationError;
 
 use stq_types::{Alpha2, Alpha3};
 
+use models::authorization::{Permission, Resource, Scope};
+use models::DOMAIN_EVENT_TYPES;
+
 pub fn validate_non_negative<T: Into<f64>>(val: T) -> Result<(), ValidationError> {
     if val.into() > 0f64 {
         Ok(())
@@ -74,3 +77,55 @@ pub fn validate_urls(text: &serde_json::Value) -> Result<(), ValidationError> {
 
     Ok(())
 }
+
+pub fn validate_api_key_permissions(permissions: &[Permission]) -> Result<(), ValidationError> {
+    if permissions.is_empty() {
+        return Err(ValidationError {
+            code: Cow::from("permissions"),
+            message: Some(Cow::from("Api key must have at least one permission.")),
+            params: HashMap::new(),
+        });
+    }
+
+    if permissions.iter().any(|permission| permission.scope != Scope::All) {
+        return Err(ValidationError {
+            code: Cow::from("permissions"),
+            message: Some(Cow::from("Api key permissions must have scope all, api key has no owner.")),
+            params: HashMap::new(),
+        });
+    }
+
+    // a key managing keys or roles could grant itself or its holder any permission
+    if permissions
+        .iter()
+        .any(|permission| permission.resource == Resource::ApiKeys || permission.resource == Resource::UserRoles)
+    {
+        return Err(ValidationError {
+            code: Cow::from("permissions"),
+            message: Some(Cow::from("Api key must not have permissions on api keys or user roles.")),
+            params: HashMap::new(),
+        });
+    }
+
+    Ok(())
+}
+
+pub fn validate_webhook_event_types(event_types: &[String]) -> Result<(), ValidationError> {
+    if event_types.is_empty() {
+        return Err(ValidationError {
+            code: Cow::from("event_types"),
+            message: Some(Cow::from("Subscription must have at least one event type.")),
+            params: HashMap::new(),
+        });
+    }
+
+    if let Some(unknown) = event_types.iter().find(|event_type| !DOMAIN_EVENT_TYPES.contains(&event_type.as_str())) {
+        return Err(ValidationError {
+            code: Cow::from("event_types"),
+            message: Some(Cow::from(format!("Unknown event type {}.", unknown))),
+            params: HashMap::new(),
+        });
+    }
+
+    Ok(())
+}
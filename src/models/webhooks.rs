@@ -0,0 +1,162 @@
+//! Models for webhook subscriptions of other services to domain events
+
+use std::time::SystemTime;
+
+use base64;
+use failure::Error as FailureError;
+use ring::digest;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use validator::Validate;
+
+use stq_types::UserId;
+
+use models::validation_rules::*;
+use models::OutboxEvent;
+use schema::{webhook_deliveries, webhook_subscriptions};
+
+const WEBHOOK_SECRET_LEN: usize = 32;
+
+/// Header with HMAC-SHA256 signature of the request body, `sha256=<hex>`
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Delivery-Signature";
+/// Header with type of the delivered event
+pub const WEBHOOK_EVENT_HEADER: &str = "X-Delivery-Event";
+/// Header with id of the delivery, redeliveries of the event keep the id
+pub const WEBHOOK_DELIVERY_HEADER: &str = "X-Delivery-Id";
+
+/// Subscription of a service to domain events, the secret is returned only once
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone)]
+pub struct WebhookSubscription {
+    pub id: i32,
+    pub url: String,
+    pub event_types: Vec<String>,
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    pub created_by: Option<UserId>,
+    pub created_at: SystemTime,
+}
+
+impl WebhookSubscription {
+    pub fn is_subscribed_to(&self, event_type: &str) -> bool {
+        self.event_types.iter().any(|subscribed| subscribed == event_type)
+    }
+}
+
+/// Newly created subscription with the secret deliveries are signed with
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatedWebhookSubscription {
+    pub subscription: WebhookSubscription,
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct NewWebhookSubscription {
+    #[validate(url)]
+    pub url: String,
+    #[validate(custom = "validate_webhook_event_types")]
+    pub event_types: Vec<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "webhook_subscriptions"]
+pub struct NewWebhookSubscriptionRaw {
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub secret: String,
+    pub created_by: Option<UserId>,
+}
+
+impl NewWebhookSubscriptionRaw {
+    pub fn from_model(payload: NewWebhookSubscription, secret: String, created_by: Option<UserId>) -> Self {
+        Self {
+            url: payload.url,
+            event_types: payload.event_types,
+            secret,
+            created_by,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, DieselTypes)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    /// Delivery failed too many times and is not retried until redelivered
+    DeadLetter,
+}
+
+/// Delivery of an outbox event to a subscription
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub subscription_id: i32,
+    pub outbox_event_id: i32,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: SystemTime,
+    pub last_error: Option<String>,
+    pub created_at: SystemTime,
+    pub delivered_at: Option<SystemTime>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "webhook_deliveries"]
+pub struct NewWebhookDelivery {
+    pub subscription_id: i32,
+    pub outbox_event_id: i32,
+}
+
+/// Delivery due to be sent with its subscription and event
+#[derive(Debug, Clone)]
+pub struct PendingWebhookDelivery {
+    pub delivery: WebhookDelivery,
+    pub subscription: WebhookSubscription,
+    pub event: OutboxEvent,
+}
+
+/// Generates a new random secret of webhook subscription
+pub fn generate_webhook_secret() -> Result<String, FailureError> {
+    let mut secret = [0u8; WEBHOOK_SECRET_LEN];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .map_err(|_| format_err!("Could not generate webhook secret"))?;
+    Ok(base64::encode_config(&secret, base64::URL_SAFE_NO_PAD))
+}
+
+/// Value of `X-Delivery-Signature` header of the request with `body`
+pub fn sign_webhook_payload(secret: &str, body: &str) -> String {
+    let key = hmac::SigningKey::new(&digest::SHA256, secret.as_bytes());
+    let signature = hmac::sign(&key, body.as_bytes());
+    let hex = signature.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_webhook_payload() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_webhook_payload("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_new_webhook_subscription_validation() {
+        let mut subscription = NewWebhookSubscription {
+            url: "http://orders/delivery_events".to_string(),
+            event_types: vec!["rates_replaced".to_string()],
+        };
+        assert!(subscription.validate().is_ok());
+
+        subscription.event_types = vec!["unknown".to_string()];
+        assert!(subscription.validate().is_err());
+
+        subscription.event_types = vec![];
+        assert!(subscription.validate().is_err());
+    }
+}
@@ -0,0 +1,425 @@
+//! Outbox dispatcher delivers domain events written to the outbox by services.
+//! Only the replica holding the lease dispatches, the others check the lease every poll interval.
+//! Events are delivered one by one in the order they were written, a failed event
+//! is retried with exponential backoff and blocks delivery of the events after it.
+//! A retried event is sent only to the endpoints it has not been delivered to.
+//! Independently of the endpoints, a webhook delivery is created for every subscription
+//! to the type of the event. Webhook deliveries are signed and retried independently,
+//! after `webhook_max_attempts` failures a delivery becomes a dead letter.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::prelude::*;
+use futures::stream;
+use futures_cpupool::CpuPool;
+use hyper::header::{ContentType, Headers};
+use hyper::Method;
+use r2d2::{ManageConnection, Pool};
+use serde_json;
+use tokio_core::reactor::{Handle, Interval};
+use uuid::Uuid;
+
+use stq_http::client::ClientHandle;
+
+use config::Outbox as OutboxConfig;
+use controller::context::StaticContext;
+use correlation::HttpClient;
+use errors::Error;
+use models::{
+    sign_webhook_payload, NewWebhookDelivery, OutboxEvent, OutboxMessage, PendingWebhookDelivery, WEBHOOK_DELIVERY_HEADER,
+    WEBHOOK_EVENT_HEADER, WEBHOOK_SIGNATURE_HEADER,
+};
+use repos::{OutboxRepo, ReposFactory};
+use shutdown::Shutdown;
+
+pub struct OutboxDispatcher<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    db_pool: Pool<M>,
+    cpu_pool: CpuPool,
+    repo_factory: F,
+    client_handle: ClientHandle,
+    config: Arc<OutboxConfig>,
+    shutdown: Shutdown,
+    lease_holder: Arc<String>,
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > OutboxDispatcher<T, M, F>
+{
+    pub fn new(context: &StaticContext<T, M, F>, config: OutboxConfig) -> Self {
+        Self {
+            db_pool: context.db_pool.clone(),
+            cpu_pool: context.cpu_pool.clone(),
+            repo_factory: context.repo_factory.clone(),
+            client_handle: context.client_handle.clone(),
+            config: Arc::new(config),
+            shutdown: context.shutdown.clone(),
+            lease_holder: Arc::new(Uuid::new_v4().to_string()),
+        }
+    }
+
+    /// Delivers pending events and webhooks every `poll_interval_ms` while holding the lease,
+    /// next check starts after the previous one is finished. Stops once shutdown has started
+    pub fn run(self, handle: &Handle) -> Box<Future<Item = (), Error = ()>> {
+        let interval = match Interval::new(Duration::from_millis(self.config.poll_interval_ms), handle) {
+            Ok(interval) => interval,
+            Err(e) => {
+                error!("Failed to start outbox dispatcher: {}", e);
+                return Box::new(future::ok(()));
+            }
+        };
+
+        Box::new(
+            interval
+                .map_err(|e| error!("Outbox dispatcher timer failed: {}", e))
+                .take_while({
+                    let shutdown = self.shutdown.clone();
+                    move |_| Ok(!shutdown.is_started())
+                })
+                .for_each(move |_| {
+                    let dispatcher = self.clone();
+                    self.acquire_lease()
+                        .then(|result| match result {
+                            Ok(is_holder) => Ok(is_holder),
+                            Err(e) => {
+                                error!("Outbox lease check failed: {}", e);
+                                Ok(false)
+                            }
+                        })
+                        .and_then(move |is_holder| -> Box<Future<Item = (), Error = ()>> {
+                            if is_holder {
+                                dispatcher.dispatch_round()
+                            } else {
+                                Box::new(future::ok(()))
+                            }
+                        })
+                }),
+        )
+    }
+
+    /// Takes the lease or extends the lease this dispatcher holds, returns `true` if the dispatcher holds the lease
+    pub fn acquire_lease(&self) -> Box<Future<Item = bool, Error = FailureError>> {
+        let lease_holder = self.lease_holder.clone();
+        let expires_at = SystemTime::now() + Duration::from_millis(self.config.lease_ttl_ms);
+        self.with_outbox_repo(move |outbox_repo| outbox_repo.acquire_lease(&lease_holder, expires_at))
+    }
+
+    fn dispatch_round(&self) -> Box<Future<Item = (), Error = ()>> {
+        let dispatcher = self.clone();
+        let webhooks_dispatcher = self.clone();
+        Box::new(
+            self.create_webhook_deliveries()
+                .then(|result| {
+                    if let Err(e) = result {
+                        error!("Creating webhook deliveries failed: {}", e);
+                    }
+                    Ok(())
+                })
+                .and_then(move |_| dispatcher.dispatch_pending())
+                .then(|result| {
+                    if let Err(e) = result {
+                        error!("Outbox dispatch failed: {}", e);
+                    }
+                    Ok(())
+                })
+                .and_then(move |_| {
+                    webhooks_dispatcher.dispatch_webhooks().then(|result| {
+                        if let Err(e) = result {
+                            error!("Webhooks dispatch failed: {}", e);
+                        }
+                        Ok(())
+                    })
+                }),
+        )
+    }
+
+    /// Creates a webhook delivery for every subscription to the type of events written since the last round.
+    /// Returns number of events deliveries are created for.
+    pub fn create_webhook_deliveries(&self) -> Box<Future<Item = usize, Error = FailureError>> {
+        let batch_size = self.config.batch_size;
+
+        self.with_repos(move |conn, repo_factory| {
+            let events = repo_factory
+                .create_outbox_repo_with_sys_acl(conn)
+                .list_pending_webhooks(batch_size)?;
+            let count = events.len();
+            for event in events {
+                conn.transaction::<(), FailureError, _>(|| {
+                    let subscriptions = repo_factory
+                        .create_webhook_subscriptions_repo_with_sys_acl(conn)
+                        .list_by_event_type(event.event_type.clone())?;
+                    let deliveries = subscriptions
+                        .into_iter()
+                        .map(|subscription| NewWebhookDelivery {
+                            subscription_id: subscription.id,
+                            outbox_event_id: event.id,
+                        })
+                        .collect();
+                    repo_factory
+                        .create_webhook_deliveries_repo_with_sys_acl(conn)
+                        .create_many(deliveries)?;
+                    repo_factory
+                        .create_outbox_repo_with_sys_acl(conn)
+                        .mark_webhooks_created(event.id)
+                        .map(|_| ())
+                })?;
+            }
+            Ok(count)
+        })
+    }
+
+    /// Delivers pending events in order, stops at the first failed event.
+    /// Returns number of delivered events.
+    pub fn dispatch_pending(&self) -> Box<Future<Item = usize, Error = FailureError>> {
+        let dispatcher = self.clone();
+        let batch_size = self.config.batch_size;
+
+        Box::new(
+            self.with_outbox_repo(move |outbox_repo| outbox_repo.list_pending(batch_size))
+                .and_then(move |events| {
+                    // events after the head of the queue wait for it, so the whole batch waits for its retry
+                    let is_due = events
+                        .first()
+                        .map(|event| event.next_attempt_at <= SystemTime::now())
+                        .unwrap_or(false);
+                    let events = if is_due { events } else { vec![] };
+                    let count = events.len();
+                    stream::iter_ok::<_, FailureError>(events)
+                        .for_each(move |event| dispatcher.deliver(event))
+                        .map(move |_| count)
+                }),
+        )
+    }
+
+    /// Sends pending webhook deliveries, a failed delivery does not block the others.
+    /// Returns number of successful deliveries.
+    pub fn dispatch_webhooks(&self) -> Box<Future<Item = usize, Error = FailureError>> {
+        let dispatcher = self.clone();
+        let batch_size = self.config.batch_size;
+
+        Box::new(
+            self.with_repos(move |conn, repo_factory| {
+                repo_factory
+                    .create_webhook_deliveries_repo_with_sys_acl(conn)
+                    .list_pending(batch_size)
+            })
+            .and_then(move |deliveries| {
+                let deliveries = deliveries
+                    .into_iter()
+                    .map(|pending| {
+                        let id = pending.delivery.id;
+                        dispatcher
+                            .deliver_webhook(pending)
+                            .then(move |result| -> Result<bool, FailureError> {
+                                match result {
+                                    Ok(_) => Ok(true),
+                                    Err(e) => {
+                                        warn!("Webhook delivery {} failed: {}", id, e);
+                                        Ok(false)
+                                    }
+                                }
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                future::join_all(deliveries).map(|results| results.into_iter().filter(|delivered| *delivered).count())
+            }),
+        )
+    }
+
+    /// Posts the event to every endpoint it has not been delivered to, marks it as sent or records the failure
+    fn deliver(&self, event: OutboxEvent) -> Box<Future<Item = (), Error = FailureError>> {
+        let id = event.id;
+        let dispatcher = self.clone();
+        Box::new(
+            self.with_outbox_repo(move |outbox_repo| outbox_repo.list_delivered_endpoints(id))
+                .and_then(move |delivered| dispatcher.deliver_to_endpoints(event, &delivered)),
+        )
+    }
+
+    fn deliver_to_endpoints(&self, event: OutboxEvent, delivered: &[String]) -> Box<Future<Item = (), Error = FailureError>> {
+        let id = event.id;
+        let attempts = event.attempts;
+        let client = HttpClient::new(self.client_handle.clone(), event.correlation_token.clone());
+        let body = match serde_json::to_string(&OutboxMessage::from(event)) {
+            Ok(body) => body,
+            Err(e) => return Box::new(future::err(e.context(Error::Parse).into())),
+        };
+
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        let requests = self
+            .config
+            .endpoints
+            .iter()
+            .filter(|url| !delivered.contains(url))
+            .map(|url| {
+                let dispatcher = self.clone();
+                let endpoint = url.clone();
+                client
+                    .send(Method::Post, url.clone(), Some(body.clone()), Some(headers.clone()))
+                    .and_then(move |_| dispatcher.with_outbox_repo(move |outbox_repo| outbox_repo.mark_endpoint_delivered(id, endpoint)))
+                    .then(|result| -> Result<Option<FailureError>, FailureError> { Ok(result.err()) })
+            })
+            .collect::<Vec<_>>();
+
+        let dispatcher = self.clone();
+        Box::new(
+            future::join_all(requests).and_then(move |results| -> Box<Future<Item = (), Error = FailureError>> {
+                let errors = results
+                    .into_iter()
+                    .filter_map(|error| error)
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>();
+                if errors.is_empty() {
+                    return dispatcher.with_outbox_repo(move |outbox_repo| outbox_repo.mark_sent(id).map(|_| ()));
+                }
+
+                let error = errors.join("; ");
+                let next_attempt_at = SystemTime::now() + retry_delay(&dispatcher.config, attempts);
+                Box::new(
+                    dispatcher
+                        .with_outbox_repo({
+                            let error = error.clone();
+                            move |outbox_repo| outbox_repo.mark_failed(id, error, next_attempt_at)
+                        })
+                        .and_then(move |_| Err(format_err!("Delivery of outbox event {} failed: {}", id, error))),
+                )
+            }),
+        )
+    }
+
+    /// Posts the signed event to the subscription, marks the delivery as delivered or records the failure
+    fn deliver_webhook(&self, pending: PendingWebhookDelivery) -> Box<Future<Item = (), Error = FailureError>> {
+        let PendingWebhookDelivery {
+            delivery,
+            subscription,
+            event,
+        } = pending;
+        let id = delivery.id;
+        let attempts = delivery.attempts;
+        let client = HttpClient::new(self.client_handle.clone(), event.correlation_token.clone());
+
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        headers.set_raw(WEBHOOK_EVENT_HEADER, event.event_type.clone());
+        headers.set_raw(WEBHOOK_DELIVERY_HEADER, id.to_string());
+        let body = match serde_json::to_string(&OutboxMessage::from(event)) {
+            Ok(body) => body,
+            Err(e) => return Box::new(future::err(e.context(Error::Parse).into())),
+        };
+        headers.set_raw(WEBHOOK_SIGNATURE_HEADER, sign_webhook_payload(&subscription.secret, &body));
+
+        let dispatcher = self.clone();
+        Box::new(client.send(Method::Post, subscription.url, Some(body), Some(headers)).then(
+            move |result| -> Box<Future<Item = (), Error = FailureError>> {
+                match result {
+                    Ok(_) => dispatcher.with_repos(move |conn, repo_factory| {
+                        repo_factory
+                            .create_webhook_deliveries_repo_with_sys_acl(conn)
+                            .mark_delivered(id)
+                            .map(|_| ())
+                    }),
+                    Err(e) => {
+                        let error = e.to_string();
+                        let next_attempt_at = SystemTime::now() + retry_delay(&dispatcher.config, attempts);
+                        let dead_letter = attempts + 1 >= dispatcher.config.webhook_max_attempts;
+                        if dead_letter {
+                            error!("Webhook delivery {} moved to dead letters after {} attempts", id, attempts + 1);
+                        }
+                        Box::new(
+                            dispatcher
+                                .with_repos(move |conn, repo_factory| {
+                                    repo_factory.create_webhook_deliveries_repo_with_sys_acl(conn).mark_failed(
+                                        id,
+                                        error,
+                                        next_attempt_at,
+                                        dead_letter,
+                                    )
+                                })
+                                .and_then(move |_| Err(e.context(format!("Delivery of webhook {} failed", id)).into())),
+                        )
+                    }
+                }
+            },
+        ))
+    }
+
+    fn with_outbox_repo<R, Func>(&self, f: Func) -> Box<Future<Item = R, Error = FailureError>>
+    where
+        Func: FnOnce(&OutboxRepo) -> Result<R, FailureError> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.with_repos(move |conn, repo_factory| f(&*repo_factory.create_outbox_repo_with_sys_acl(conn)))
+    }
+
+    fn with_repos<R, Func>(&self, f: Func) -> Box<Future<Item = R, Error = FailureError>>
+    where
+        Func: FnOnce(&T, &F) -> Result<R, FailureError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let db_pool = self.db_pool.clone();
+        let repo_factory = self.repo_factory.clone();
+        let in_flight = self.shutdown.track_job();
+        Box::new(self.cpu_pool.spawn_fn(move || {
+            let _in_flight = in_flight;
+            let conn = db_pool.get().map_err(|e| e.context(Error::Connection))?;
+            f(&*conn, &repo_factory)
+        }))
+    }
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > Clone for OutboxDispatcher<T, M, F>
+{
+    fn clone(&self) -> Self {
+        Self {
+            db_pool: self.db_pool.clone(),
+            cpu_pool: self.cpu_pool.clone(),
+            repo_factory: self.repo_factory.clone(),
+            client_handle: self.client_handle.clone(),
+            config: self.config.clone(),
+            shutdown: self.shutdown.clone(),
+            lease_holder: self.lease_holder.clone(),
+        }
+    }
+}
+
+/// Delay before the next attempt to deliver an event failed `attempts` times before
+pub fn retry_delay(config: &OutboxConfig, attempts: i32) -> Duration {
+    let factor = 1u64 << attempts.max(0).min(16);
+    Duration::from_millis(config.retry_delay_ms.saturating_mul(factor).min(config.max_retry_delay_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let config = OutboxConfig {
+            max_retry_delay_ms: 10_000,
+            ..OutboxConfig::default()
+        };
+        assert_eq!(retry_delay(&config, 0), Duration::from_millis(1000));
+        assert_eq!(retry_delay(&config, 2), Duration::from_millis(4000));
+        assert_eq!(retry_delay(&config, 10), Duration::from_millis(10_000));
+        assert_eq!(retry_delay(&config, i32::max_value()), Duration::from_millis(10_000));
+    }
+}
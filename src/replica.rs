@@ -0,0 +1,150 @@
+//! Read replica used by read-only service methods. After a failed connection the replica is not tried
+//! for `server.read_replica_retry_ms`, reads go to the primary without waiting for the connection timeout,
+//! then a single request probes the replica again.
+//! Reads from the replica may lag behind the primary, caches are not filled with them.
+
+use std::cell::Cell;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use r2d2::{ManageConnection, Pool, PooledConnection};
+
+thread_local! {
+    static READING_REPLICA: Cell<bool> = Cell::new(false);
+}
+
+/// Returns true if the current thread reads from the replica, results of such reads must not be cached
+pub fn is_reading() -> bool {
+    READING_REPLICA.with(|reading| reading.get())
+}
+
+/// Runs `f` marked as reading from the replica for the current thread
+pub fn scope<R, F>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let previous = READING_REPLICA.with(|reading| reading.replace(true));
+    let _reset = ResetReading { previous };
+    f()
+}
+
+/// Restores the mark of the current thread, even if the scope panics
+struct ResetReading {
+    previous: bool,
+}
+
+impl Drop for ResetReading {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        READING_REPLICA.with(|reading| reading.set(previous));
+    }
+}
+
+pub struct ReadReplica<M: ManageConnection> {
+    pool: Pool<M>,
+    retry_interval: Duration,
+    unavailable_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl<M: ManageConnection> ReadReplica<M> {
+    pub fn new(pool: Pool<M>, retry_interval: Duration) -> Self {
+        Self {
+            pool,
+            retry_interval,
+            unavailable_until: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn pool(&self) -> &Pool<M> {
+        &self.pool
+    }
+
+    /// Returns a replica connection, `None` if the replica is unavailable or failed recently
+    pub fn get(&self) -> Option<PooledConnection<M>> {
+        {
+            let mut unavailable_until = match self.unavailable_until.lock() {
+                Ok(unavailable_until) => unavailable_until,
+                Err(_) => return None,
+            };
+            if let Some(until) = *unavailable_until {
+                let now = Instant::now();
+                if now < until {
+                    return None;
+                }
+                // other requests keep using the primary while this one probes the replica
+                *unavailable_until = Some(now + self.retry_interval);
+            }
+        }
+
+        match self.pool.get() {
+            Ok(conn) => {
+                self.set_unavailable_until(None);
+                Some(conn)
+            }
+            Err(e) => {
+                warn!("Read replica is unavailable, falling back to the primary: {}", e);
+                self.set_unavailable_until(Some(Instant::now() + self.retry_interval));
+                None
+            }
+        }
+    }
+
+    fn set_unavailable_until(&self, until: Option<Instant>) {
+        if let Ok(mut unavailable_until) = self.unavailable_until.lock() {
+            *unavailable_until = until;
+        }
+    }
+}
+
+impl<M: ManageConnection> Clone for ReadReplica<M> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            retry_interval: self.retry_interval,
+            unavailable_until: self.unavailable_until.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repos::repo_factory::tests::MockConnectionManager;
+
+    fn create_replica(unavailable: bool) -> ReadReplica<MockConnectionManager> {
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(100))
+            .build_unchecked(MockConnectionManager { unavailable });
+        ReadReplica::new(pool, Duration::from_secs(60))
+    }
+
+    #[test]
+    fn test_scope() {
+        assert!(!is_reading());
+        assert!(scope(|| is_reading()));
+        assert!(!is_reading());
+    }
+
+    #[test]
+    fn test_available_replica() {
+        let replica = create_replica(false);
+        assert!(replica.get().is_some());
+        assert!(replica.get().is_some());
+    }
+
+    #[test]
+    fn test_failed_replica_is_not_waited_for() {
+        let replica = create_replica(true);
+        assert!(replica.get().is_none());
+
+        let started = Instant::now();
+        assert!(replica.clone().get().is_none());
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        // the replica is probed again after the retry interval
+        *replica.unavailable_until.lock().unwrap() = Some(Instant::now());
+        let started = Instant::now();
+        assert!(replica.get().is_none());
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -1,7 +1,11 @@
 use stq_types::UserId;
+
+use models::UserRole;
+
 /// Implement this trait on resource to signal if it's in the current scope
 pub trait CheckScope<Scope, T> {
-    fn is_in_scope(&self, user_id: UserId, scope: &Scope, obj: Option<&T>) -> bool;
+    /// `user_roles` are roles of the user with their data, loaded once for the request
+    fn is_in_scope(&self, user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&T>) -> bool;
 }
 
 /// Access control layer for repos. It tells if a user can do a certain action with
@@ -4,21 +4,23 @@
 #[macro_use]
 pub mod macros;
 pub mod legacy_acl;
+pub mod policy;
 pub mod roles_cache;
 
+pub use self::policy::AclPolicy;
 pub use self::roles_cache::RolesCacheImpl;
 
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use errors::Error;
 use failure::Error as FailureError;
 
-use stq_types::{DeliveryRole, UserId};
+use stq_types::UserId;
 
 use self::legacy_acl::{Acl, CheckScope};
 
 use models::authorization::*;
+use models::UserRole;
 
 pub fn check<T>(
     acl: &Acl<Resource, Action, Scope, FailureError, T>,
@@ -41,60 +43,21 @@ pub fn check<T>(
 /// ApplicationAcl contains main logic for manipulation with resources
 #[derive(Clone)]
 pub struct ApplicationAcl {
-    acls: Rc<HashMap<DeliveryRole, Vec<Permission>>>,
-    roles: Vec<DeliveryRole>,
+    policy: Arc<AclPolicy>,
+    user_roles: Vec<UserRole>,
     user_id: UserId,
 }
 
 impl ApplicationAcl {
-    pub fn new(roles: Vec<DeliveryRole>, user_id: UserId) -> Self {
-        let mut hash = ::std::collections::HashMap::new();
-
-        hash.insert(
-            DeliveryRole::Superuser,
-            vec![
-                permission!(Resource::Companies),
-                permission!(Resource::CompaniesPackages),
-                permission!(Resource::Countries),
-                permission!(Resource::Packages),
-                permission!(Resource::Pickups),
-                permission!(Resource::Products),
-                permission!(Resource::ShippingRates),
-                permission!(Resource::UserAddresses),
-                permission!(Resource::UserRoles),
-            ],
-        );
-
-        hash.insert(
-            DeliveryRole::User,
-            vec![
-                permission!(Resource::Companies, Action::Read),
-                permission!(Resource::CompaniesPackages, Action::Read),
-                permission!(Resource::Countries, Action::Read),
-                permission!(Resource::Packages, Action::Read),
-                permission!(Resource::Pickups, Action::Read),
-                permission!(Resource::Products, Action::Read),
-                permission!(Resource::ShippingRates, Action::Read),
-                permission!(Resource::UserAddresses, Action::All, Scope::Owned),
-                permission!(Resource::UserRoles, Action::Read, Scope::Owned),
-            ],
-        );
-
-        hash.insert(
-            DeliveryRole::StoreManager,
-            vec![
-                permission!(Resource::Pickups, Action::All, Scope::Owned),
-                permission!(Resource::Products, Action::All, Scope::Owned),
-            ],
-        );
-
+    pub fn new(policy: Arc<AclPolicy>, user_roles: Vec<UserRole>, user_id: UserId) -> Self {
         ApplicationAcl {
-            acls: Rc::new(hash),
-            roles,
+            policy,
+            user_roles,
             user_id,
         }
     }
 }
+
 impl<T> Acl<Resource, Action, Scope, FailureError, T> for ApplicationAcl {
     fn allows(
         &self,
@@ -103,15 +66,13 @@ impl<T> Acl<Resource, Action, Scope, FailureError, T> for ApplicationAcl {
         scope_checker: &CheckScope<Scope, T>,
         obj: Option<&T>,
     ) -> Result<bool, FailureError> {
-        let empty: Vec<Permission> = Vec::new();
         let user_id = &self.user_id;
-        let hashed_acls = self.acls.clone();
         let acls = self
-            .roles
+            .user_roles
             .iter()
-            .flat_map(|role| hashed_acls.get(role).unwrap_or(&empty))
+            .flat_map(|user_role| self.policy.role_permissions(&user_role.name))
             .filter(|permission| (permission.resource == resource) && ((permission.action == action) || (permission.action == Action::All)))
-            .filter(|permission| scope_checker.is_in_scope(*user_id, &permission.scope, obj));
+            .filter(|permission| scope_checker.is_in_scope(*user_id, &self.user_roles, &permission.scope, obj));
         if acls.count() > 0 {
             Ok(true)
         } else {
@@ -121,6 +82,72 @@ impl<T> Acl<Resource, Action, Scope, FailureError, T> for ApplicationAcl {
     }
 }
 
+/// ApiKeyAcl allows everything granted to the api key of the request,
+/// other requests are checked by the acl of the user
+pub struct ApiKeyAcl<T> {
+    permissions: Arc<Vec<Permission>>,
+    user_acl: Box<Acl<Resource, Action, Scope, FailureError, T>>,
+}
+
+impl<T> ApiKeyAcl<T> {
+    pub fn new(permissions: Arc<Vec<Permission>>, user_acl: Box<Acl<Resource, Action, Scope, FailureError, T>>) -> Self {
+        ApiKeyAcl { permissions, user_acl }
+    }
+}
+
+impl<T> Acl<Resource, Action, Scope, FailureError, T> for ApiKeyAcl<T> {
+    fn allows(
+        &self,
+        resource: Resource,
+        action: Action,
+        scope_checker: &CheckScope<Scope, T>,
+        obj: Option<&T>,
+    ) -> Result<bool, FailureError> {
+        let allowed_by_key = self.permissions.iter().any(|permission| {
+            (permission.resource == resource)
+                && ((permission.action == action) || (permission.action == Action::All))
+                && (permission.scope == Scope::All)
+        });
+        if allowed_by_key {
+            Ok(true)
+        } else {
+            self.user_acl.allows(resource, action, scope_checker, obj)
+        }
+    }
+}
+
+/// FailedRolesAcl is used for a user whose roles could not be loaded, every check fails with `Error::Internal`
+/// instead of treating the user as one without roles
+#[derive(Clone)]
+pub struct FailedRolesAcl {
+    user_id: UserId,
+}
+
+impl FailedRolesAcl {
+    pub fn new(user_id: UserId) -> Self {
+        FailedRolesAcl { user_id }
+    }
+}
+
+impl<T> Acl<Resource, Action, Scope, FailureError, T> for FailedRolesAcl {
+    fn allows(
+        &self,
+        resource: Resource,
+        action: Action,
+        _scope_checker: &CheckScope<Scope, T>,
+        _obj: Option<&T>,
+    ) -> Result<bool, FailureError> {
+        Err(format_err!(
+            "Roles of user {} are not loaded, can't check {} on {}",
+            self.user_id,
+            action,
+            resource
+        )
+        .context(Error::Internal)
+        .into())
+    }
+}
+
 /// UnauthorizedAcl contains main logic for manipulation with resources
 #[derive(Clone, Default)]
 pub struct UnauthorizedAcl;
@@ -152,5 +179,37 @@ impl<T> Acl<Resource, Action, Scope, FailureError, T> for UnauthorizedAcl {
 
 #[cfg(test)]
 mod tests {
-    // write tests
+    use super::*;
+
+    struct ScopeChecker;
+
+    impl CheckScope<Scope, ()> for ScopeChecker {
+        fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&()>) -> bool {
+            *scope == Scope::All
+        }
+    }
+
+    #[test]
+    fn test_api_key_acl() {
+        let acl = ApiKeyAcl::new(
+            Arc::new(vec![permission!(Resource::ShippingRates, Action::Read)]),
+            Box::new(UnauthorizedAcl::default()) as Box<Acl<Resource, Action, Scope, FailureError, ()>>,
+        );
+        assert!(acl.allows(Resource::ShippingRates, Action::Read, &ScopeChecker, None).unwrap());
+        assert!(!acl.allows(Resource::ShippingRates, Action::Delete, &ScopeChecker, None).unwrap());
+        assert!(!acl.allows(Resource::UserAddresses, Action::Read, &ScopeChecker, None).unwrap());
+        // falls back to the acl of the user
+        assert!(acl.allows(Resource::Companies, Action::Read, &ScopeChecker, None).unwrap());
+    }
+
+    #[test]
+    fn test_failed_roles_acl() {
+        let acl = FailedRolesAcl::new(UserId(1));
+        let result: Result<bool, FailureError> = acl.allows(Resource::Companies, Action::Read, &ScopeChecker, None);
+        let is_internal = result.unwrap_err().causes().any(|cause| match cause.downcast_ref::<Error>() {
+            Some(Error::Internal) => true,
+            _ => false,
+        });
+        assert!(is_internal);
+    }
 }
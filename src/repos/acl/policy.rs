@@ -0,0 +1,164 @@
+//! AclPolicy is a matrix of permissions granted to each role, loaded from a TOML file
+//! referenced in config or built in by default
+
+use std::collections::HashMap;
+
+use config_crate::{Config as RawConfig, File, FileFormat};
+use failure::Error as FailureError;
+
+use models::authorization::*;
+use models::RoleName;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AclPolicy {
+    pub roles: HashMap<RoleName, Vec<Permission>>,
+}
+
+impl AclPolicy {
+    /// Loads policy from the TOML file, fails on unknown roles, resources, actions or scopes
+    pub fn from_file(path: &str) -> Result<Self, FailureError> {
+        let mut s = RawConfig::new();
+        s.merge(File::new(path, FileFormat::Toml))
+            .and_then(|s| s.clone().try_into())
+            .map_err(|e| format_err!("Invalid ACL policy {}: {}", path, e))
+    }
+
+    /// Permissions granted to the role
+    pub fn role_permissions(&self, role: &RoleName) -> &[Permission] {
+        self.roles.get(role).map(|permissions| permissions.as_slice()).unwrap_or(&[])
+    }
+
+    /// Permissions granted to any of the roles, without duplicates
+    pub fn effective_permissions(&self, roles: &[RoleName]) -> Vec<Permission> {
+        let mut result: Vec<Permission> = vec![];
+        for permission in roles.iter().flat_map(|role| self.role_permissions(role)) {
+            if !result.contains(permission) {
+                result.push(*permission);
+            }
+        }
+        result
+    }
+}
+
+impl Default for AclPolicy {
+    fn default() -> Self {
+        let mut roles = HashMap::new();
+
+        roles.insert(
+            RoleName::Superuser,
+            vec![
+                permission!(Resource::ApiKeys),
+                permission!(Resource::AuditLog, Action::Read),
+                permission!(Resource::Companies),
+                permission!(Resource::CompaniesPackages),
+                permission!(Resource::Countries),
+                permission!(Resource::Impersonations),
+                permission!(Resource::Packages),
+                permission!(Resource::Pickups),
+                permission!(Resource::Products),
+                permission!(Resource::ShippingRates),
+                permission!(Resource::UserAddresses),
+                permission!(Resource::UserDataErasures),
+                permission!(Resource::UserRoles),
+                permission!(Resource::WebhookDeliveries),
+                permission!(Resource::WebhookSubscriptions),
+            ],
+        );
+
+        roles.insert(
+            RoleName::User,
+            vec![
+                permission!(Resource::Companies, Action::Read),
+                permission!(Resource::CompaniesPackages, Action::Read),
+                permission!(Resource::Countries, Action::Read),
+                permission!(Resource::Packages, Action::Read),
+                permission!(Resource::Pickups, Action::Read),
+                permission!(Resource::Products, Action::Read),
+                permission!(Resource::ShippingRates, Action::Read),
+                permission!(Resource::UserAddresses, Action::All, Scope::Owned),
+                permission!(Resource::UserRoles, Action::Read, Scope::Owned),
+            ],
+        );
+
+        roles.insert(
+            RoleName::CompanyManager,
+            vec![
+                permission!(Resource::Companies, Action::Read),
+                permission!(Resource::Companies, Action::Update, Scope::Owned),
+                permission!(Resource::CompaniesPackages, Action::Read),
+                permission!(Resource::CompaniesPackages, Action::All, Scope::Owned),
+                permission!(Resource::ShippingRates, Action::Read),
+                permission!(Resource::ShippingRates, Action::All, Scope::Owned),
+            ],
+        );
+
+        roles.insert(
+            RoleName::StoreManager,
+            vec![
+                permission!(Resource::Pickups, Action::All, Scope::Owned),
+                permission!(Resource::Products, Action::All, Scope::Owned),
+            ],
+        );
+
+        AclPolicy { roles }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(policy: &str) -> Result<AclPolicy, FailureError> {
+        let mut s = RawConfig::new();
+        s.merge(File::from_str(policy, FileFormat::Toml))
+            .and_then(|s| s.clone().try_into())
+            .map_err(FailureError::from)
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = parse(
+            r#"
+            [roles]
+            superuser = [{ resource = "companies" }]
+            user = [
+                { resource = "pickups", action = "read" },
+                { resource = "user_addresses", action = "all", scope = "owned" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            policy.role_permissions(&RoleName::Superuser),
+            &[permission!(Resource::Companies)][..]
+        );
+        assert_eq!(
+            policy.role_permissions(&RoleName::User),
+            &[
+                permission!(Resource::Pickups, Action::Read),
+                permission!(Resource::UserAddresses, Action::All, Scope::Owned),
+            ][..]
+        );
+        assert!(policy.role_permissions(&RoleName::StoreManager).is_empty());
+    }
+
+    #[test]
+    fn test_parse_policy_with_unknown_resource() {
+        assert!(parse(r#"roles = { user = [{ resource = "rockets" }] }"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_policy_with_unknown_action() {
+        assert!(parse(r#"roles = { user = [{ resource = "companies", action = "launch" }] }"#).is_err());
+    }
+
+    #[test]
+    fn test_effective_permissions() {
+        let policy = AclPolicy::default();
+        let permissions = policy.effective_permissions(&[RoleName::User, RoleName::CompanyManager]);
+        assert_eq!(permissions.iter().filter(|p| **p == permission!(Resource::Companies, Action::Read)).count(), 1);
+        assert!(permissions.contains(&permission!(Resource::ShippingRates, Action::All, Scope::Owned)));
+    }
+}
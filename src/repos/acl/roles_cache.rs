@@ -2,31 +2,37 @@
 
 use failure::Fail;
 use stq_cache::cache::Cache;
-use stq_types::{DeliveryRole, UserId};
+use stq_types::UserId;
+
+use metrics;
+use models::UserRole;
+use replica;
 
 pub struct RolesCacheImpl<C>
 where
-    C: Cache<Vec<DeliveryRole>>,
+    C: Cache<Vec<UserRole>>,
 {
     cache: C,
 }
 
 impl<C> RolesCacheImpl<C>
 where
-    C: Cache<Vec<DeliveryRole>>,
+    C: Cache<Vec<UserRole>>,
 {
     pub fn new(cache: C) -> Self {
         RolesCacheImpl { cache }
     }
 
-    pub fn get(&self, user_id: UserId) -> Option<Vec<DeliveryRole>> {
+    pub fn get(&self, user_id: UserId) -> Option<Vec<UserRole>> {
         debug!("Getting roles from RolesCache at key '{}'", user_id);
 
-        self.cache.get(user_id.to_string().as_str()).unwrap_or_else(|err| {
+        let roles = self.cache.get(user_id.to_string().as_str()).unwrap_or_else(|err| {
             let err = err.context(format!("Failed to get roles from RolesCache at key '{}'", user_id));
             error!("{}", err);
             None
-        })
+        });
+        metrics::observe_cache("roles", roles.is_some());
+        roles
     }
 
     pub fn remove(&self, user_id: UserId) -> bool {
@@ -39,7 +45,11 @@ where
         })
     }
 
-    pub fn set(&self, user_id: UserId, roles: Vec<DeliveryRole>) {
+    /// Roles read from the replica are not cached, they may be older than the roles removed from the cache
+    pub fn set(&self, user_id: UserId, roles: Vec<UserRole>) {
+        if replica::is_reading() {
+            return;
+        }
         debug!("Setting roles in RolesCache at key '{}'", user_id);
 
         self.cache.set(user_id.to_string().as_str(), roles).unwrap_or_else(|err| {
@@ -0,0 +1,120 @@
+//! Repo for api_keys table. ApiKey is a key used by other services
+//! to call delivery with a limited set of permissions.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use errors::Error;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{ApiKey, ApiKeyRaw, NewApiKeyRaw, UserRole};
+use schema::api_keys::dsl::*;
+
+/// Api keys repository for handling keys of other services
+pub trait ApiKeysRepo {
+    /// Create a new api key
+    fn create(&self, payload: NewApiKeyRaw) -> RepoResult<ApiKey>;
+
+    /// Returns list of all api keys, including revoked ones
+    fn list(&self) -> RepoResult<Vec<ApiKey>>;
+
+    /// Find not revoked api key by hash of the key
+    fn find_active_by_hash(&self, key_hash: String) -> RepoResult<Option<ApiKey>>;
+
+    /// Revoke api key
+    fn revoke(&self, id: i32) -> RepoResult<ApiKey>;
+}
+
+/// Implementation of ApiKeysRepo trait
+pub struct ApiKeysRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, ApiKey>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ApiKeysRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, ApiKey>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ApiKeysRepo for ApiKeysRepoImpl<'a, T> {
+    /// Create a new api key
+    fn create(&self, payload: NewApiKeyRaw) -> RepoResult<ApiKey> {
+        debug!("create new api key {}.", payload.name);
+        acl::check(&*self.acl, Resource::ApiKeys, Action::Create, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(api_keys).values(&payload);
+                query.get_result::<ApiKeyRaw>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .and_then(|api_key| api_key.to_model())
+            .map_err(|e: FailureError| e.context(format!("Create a new api key {} error occurred", payload.name)).into())
+    }
+
+    /// Returns list of all api keys, including revoked ones
+    fn list(&self) -> RepoResult<Vec<ApiKey>> {
+        debug!("list api keys.");
+        acl::check(&*self.acl, Resource::ApiKeys, Action::Read, self, None)
+            .and_then(|_| {
+                let query = api_keys.order(id);
+                query.get_results::<ApiKeyRaw>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .and_then(|results| results.into_iter().map(|api_key| api_key.to_model()).collect())
+            .map_err(|e: FailureError| e.context("List api keys error occurred").into())
+    }
+
+    /// Find not revoked api key by hash of the key
+    fn find_active_by_hash(&self, key_hash_arg: String) -> RepoResult<Option<ApiKey>> {
+        debug!("find active api key by hash.");
+        let query = api_keys.filter(key_hash.eq(key_hash_arg)).filter(revoked_at.is_null());
+        query
+            .get_result::<ApiKeyRaw>(self.db_conn)
+            .optional()
+            .map_err(|e| Error::from(e).into())
+            .and_then(|api_key: Option<ApiKeyRaw>| match api_key {
+                Some(api_key) => {
+                    let api_key = api_key.to_model()?;
+                    acl::check(&*self.acl, Resource::ApiKeys, Action::Read, self, Some(&api_key))?;
+                    Ok(Some(api_key))
+                }
+                None => Ok(None),
+            })
+            .map_err(|e: FailureError| e.context("Find api key by hash error occurred").into())
+    }
+
+    /// Revoke api key
+    fn revoke(&self, id_arg: i32) -> RepoResult<ApiKey> {
+        debug!("revoke api key {}.", id_arg);
+        acl::check(&*self.acl, Resource::ApiKeys, Action::Delete, self, None)
+            .and_then(|_| {
+                let filtered = api_keys.filter(id.eq(id_arg)).filter(revoked_at.is_null());
+                let query = diesel::update(filtered).set(revoked_at.eq(Some(SystemTime::now())));
+                query.get_result::<ApiKeyRaw>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .and_then(|api_key| api_key.to_model())
+            .map_err(|e: FailureError| e.context(format!("Revoke api key {} error occurred", id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, ApiKey>
+    for ApiKeysRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&ApiKey>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Repo for audit_log table. AuditLogEntry is an append-only record
+//! of administrative change, written in the same transaction as the change.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use errors::Error;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::{Action, Resource, Scope};
+use models::{AuditLogEntry, AuditLogSearch, NewAuditLogEntry, UserRole};
+use schema::audit_log::dsl::*;
+
+/// Audit log repository, entries are never updated or deleted
+pub trait AuditLogRepo {
+    /// Write a new audit log entry
+    fn create(&self, payload: NewAuditLogEntry) -> RepoResult<AuditLogEntry>;
+
+    /// Search audit log entries, newest first
+    fn search(&self, search: AuditLogSearch) -> RepoResult<Vec<AuditLogEntry>>;
+}
+
+/// Implementation of AuditLogRepo trait
+pub struct AuditLogRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> AuditLogRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> AuditLogRepo for AuditLogRepoImpl<'a, T> {
+    /// Write a new audit log entry
+    fn create(&self, payload: NewAuditLogEntry) -> RepoResult<AuditLogEntry> {
+        debug!("create new audit log entry {:?}.", payload);
+        acl::check(&*self.acl, Resource::AuditLog, Action::Create, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(audit_log).values(&payload);
+                query.get_result::<AuditLogEntry>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Create a new audit log entry {:?} error occurred", payload)).into())
+    }
+
+    /// Search audit log entries, newest first
+    fn search(&self, search: AuditLogSearch) -> RepoResult<Vec<AuditLogEntry>> {
+        debug!("search audit log entries {:?}.", search);
+        acl::check(&*self.acl, Resource::AuditLog, Action::Read, self, None)
+            .and_then(|_| {
+                let mut query = audit_log.into_boxed();
+
+                if let Some(user_id_value) = search.user_id {
+                    query = query.filter(user_id.eq(Some(user_id_value)));
+                }
+                if let Some(ref correlation_token_value) = search.correlation_token {
+                    query = query.filter(correlation_token.eq(correlation_token_value.clone()));
+                }
+                if let Some(resource_value) = search.resource {
+                    query = query.filter(resource.eq(resource_value));
+                }
+                if let Some(action_value) = search.action {
+                    query = query.filter(action.eq(action_value));
+                }
+                if let Some(created_from) = search.created_from {
+                    query = query.filter(created_at.ge(created_from));
+                }
+                if let Some(created_to) = search.created_to {
+                    query = query.filter(created_at.lt(created_to));
+                }
+
+                query
+                    .order((created_at.desc(), id.desc()))
+                    .offset(search.offset)
+                    .limit(search.count)
+                    .get_results::<AuditLogEntry>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Search audit log entries {:?} error occurred", search)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, AuditLogEntry>
+    for AuditLogRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&AuditLogEntry>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
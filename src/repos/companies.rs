@@ -2,11 +2,9 @@
 
 use diesel;
 use diesel::connection::AnsiTransactionManager;
-use diesel::dsl::sql;
 use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_dsl::RunQueryDsl;
-use diesel::sql_types::VarChar;
 use diesel::Connection;
 
 use errors::Error;
@@ -18,10 +16,13 @@ use models::authorization::*;
 use repos::legacy_acl::*;
 use repos::types::RepoResult;
 
-use models::companies::{Company, CompanyRaw, NewCompany, UpdateCompany};
+use models::companies::{Company, CompanyDeliveryFrom, CompanyRaw, NewCompany, UpdateCompany};
 use models::countries::Country;
+use models::roles::{owns_company, UserRole};
+use repos::countries::check_country_codes;
 use repos::*;
 use schema::companies::dsl::*;
+use schema::companies_deliveries_from::dsl as DslDeliveriesFrom;
 
 /// Companies repository for handling Companies
 pub trait CompaniesRepo {
@@ -55,18 +56,40 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Company>>, countries: Country) -> Self {
         Self { db_conn, acl, countries }
     }
+
+    fn to_companies(&self, raws: Vec<CompanyRaw>) -> RepoResult<Vec<Company>> {
+        let company_ids = raws.iter().map(|raw| raw.id).collect::<Vec<_>>();
+        let deliveries = get_deliveries_from(self.db_conn, &company_ids)?;
+
+        Ok(raws
+            .into_iter()
+            .map(|raw| {
+                let used_codes = deliveries_from_of(&deliveries, raw.id);
+                Company::from_raw(raw, &used_codes, &self.countries)
+            })
+            .collect())
+    }
+
+    fn to_company(&self, raw: CompanyRaw) -> RepoResult<Company> {
+        let used_codes = deliveries_from_of(&get_deliveries_from(self.db_conn, &[raw.id])?, raw.id);
+        Ok(Company::from_raw(raw, &used_codes, &self.countries))
+    }
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CompaniesRepo for CompaniesRepoImpl<'a, T> {
     fn create(&self, payload: NewCompany) -> RepoResult<Company> {
         debug!("create new company {:?}.", payload);
-        let payload = payload.to_raw()?;
+        let (payload, deliveries_from) = payload.to_raw();
+        check_country_codes(&self.countries, &deliveries_from, "deliveries_from")?;
 
         let query = diesel::insert_into(companies).values(&payload);
         query
             .get_result::<CompanyRaw>(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|v| Company::from_raw(v, &self.countries))
+            .and_then(|v| {
+                set_deliveries_from(self.db_conn, v.id, deliveries_from)?;
+                self.to_company(v)
+            })
             .and_then(|company| acl::check(&*self.acl, Resource::Companies, Action::Create, self, Some(&company)).and_then(|_| Ok(company)))
             .map_err(|e: FailureError| e.context(format!("create new company {:?}.", payload)).into())
     }
@@ -79,7 +102,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
         query
             .get_results(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|raws: Vec<CompanyRaw>| raws.into_iter().map(|v| Company::from_raw(v, &self.countries)).collect())
+            .and_then(|raws: Vec<CompanyRaw>| self.to_companies(raws))
             .and_then(|results: Vec<Company>| {
                 for company in &results {
                     acl::check(&*self.acl, Resource::Companies, Action::Read, self, Some(&company))?;
@@ -100,7 +123,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .map_err(|e| Error::from(e).into())
             .and_then(|company_raw: Option<CompanyRaw>| match company_raw {
                 Some(value) => {
-                    let company = Company::from_raw(value, &self.countries)?;
+                    let company = self.to_company(value)?;
                     acl::check(&*self.acl, Resource::Companies, Action::Read, self, Some(&company))?;
                     Ok(Some(company))
                 }
@@ -113,12 +136,15 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     fn find_deliveries_from(&self, country: Alpha3) -> RepoResult<Vec<Company>> {
         debug!("Find in companies with country {:?}.", country);
 
-        let query = companies.filter(sql("deliveries_from ? ").bind::<VarChar, _>(&country));
+        let company_ids = DslDeliveriesFrom::companies_deliveries_from
+            .filter(DslDeliveriesFrom::country_alpha3.eq(country.clone()))
+            .select(DslDeliveriesFrom::company_id);
+        let query = companies.filter(id.eq_any(company_ids)).order(id);
 
         query
             .get_results(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|raw: Vec<CompanyRaw>| raw.into_iter().map(|v| Company::from_raw(v, &self.countries)).collect())
+            .and_then(|raw: Vec<CompanyRaw>| self.to_companies(raw))
             .and_then(|results: Vec<Company>| {
                 for result in &results {
                     acl::check(&*self.acl, Resource::Companies, Action::Read, self, Some(&result))?;
@@ -133,23 +159,33 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
     fn update(&self, id_arg: CompanyId, payload: UpdateCompany) -> RepoResult<Company> {
         debug!("Updating company {} with payload {:?}.", id_arg, payload);
-        let payload = payload.to_raw()?;
+        let (payload, deliveries_from) = payload.to_raw();
+        if let Some(ref deliveries_from) = deliveries_from {
+            check_country_codes(&self.countries, deliveries_from, "deliveries_from")?;
+        }
 
         let query = companies.filter(id.eq(id_arg));
 
         query
             .get_result::<CompanyRaw>(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|v| Company::from_raw(v, &self.countries))
+            .and_then(|v| self.to_company(v))
             .and_then(|company: Company| acl::check(&*self.acl, Resource::Companies, Action::Update, self, Some(&company)))
             .and_then(|_| {
                 let filtered = companies.filter(id.eq(id_arg));
 
-                let query = diesel::update(filtered).set(&payload);
-                query
-                    .get_result::<CompanyRaw>(self.db_conn)
-                    .map_err(|e| Error::from(e).into())
-                    .and_then(|v| Company::from_raw(v, &self.countries))
+                let raw = if payload.is_empty() {
+                    filtered.get_result::<CompanyRaw>(self.db_conn)
+                } else {
+                    diesel::update(filtered).set(&payload).get_result::<CompanyRaw>(self.db_conn)
+                }
+                .map_err(Error::from)?;
+
+                if let Some(deliveries_from) = deliveries_from {
+                    set_deliveries_from(self.db_conn, id_arg, deliveries_from)?;
+                }
+
+                self.to_company(raw)
             })
             .map_err(|e: FailureError| e.context(format!("Updating company payload {:?} failed.", payload)).into())
     }
@@ -159,13 +195,15 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
         acl::check(&*self.acl, Resource::Companies, Action::Delete, self, None)?;
 
+        let used_codes = deliveries_from_of(&get_deliveries_from(self.db_conn, &[id_arg])?, id_arg);
+
         let filtered = companies.filter(id.eq(id_arg));
         let query = diesel::delete(filtered);
 
         query
             .get_result::<CompanyRaw>(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|v| Company::from_raw(v, &self.countries))
+            .map(|v| Company::from_raw(v, &used_codes, &self.countries))
             .map_err(move |e| e.context(format!("delete company id: {}.", id_arg)).into())
     }
 }
@@ -173,10 +211,52 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Company>
     for CompaniesRepoImpl<'a, T>
 {
-    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&Company>) -> bool {
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&Company>) -> bool {
         match *scope {
             Scope::All => true,
-            Scope::Owned => false,
+            Scope::Owned => obj.map(|company| owns_company(user_roles, company.id)).unwrap_or(false),
         }
     }
 }
+
+/// Returns rows of countries the companies deliver from
+pub fn get_deliveries_from<T: Connection<Backend = Pg>>(db_conn: &T, company_ids: &[CompanyId]) -> RepoResult<Vec<CompanyDeliveryFrom>> {
+    let query = DslDeliveriesFrom::companies_deliveries_from
+        .filter(DslDeliveriesFrom::company_id.eq_any(company_ids))
+        .order((DslDeliveriesFrom::company_id, DslDeliveriesFrom::country_alpha3));
+
+    query.get_results(db_conn).map_err(|e| Error::from(e).into())
+}
+
+/// Replaces countries the company delivers from
+pub fn set_deliveries_from<T: Connection<Backend = Pg>>(db_conn: &T, company_id_arg: CompanyId, codes: Vec<Alpha3>) -> RepoResult<()> {
+    let filtered = DslDeliveriesFrom::companies_deliveries_from.filter(DslDeliveriesFrom::company_id.eq(company_id_arg));
+    diesel::delete(filtered).execute(db_conn).map_err(Error::from)?;
+
+    let mut rows: Vec<CompanyDeliveryFrom> = vec![];
+    for code in codes {
+        if !rows.iter().any(|row| row.country_alpha3 == code) {
+            rows.push(CompanyDeliveryFrom {
+                company_id: company_id_arg,
+                country_alpha3: code,
+            });
+        }
+    }
+
+    if !rows.is_empty() {
+        diesel::insert_into(DslDeliveriesFrom::companies_deliveries_from)
+            .values(&rows)
+            .execute(db_conn)
+            .map_err(Error::from)?;
+    }
+
+    Ok(())
+}
+
+/// Picks codes of the countries the company delivers from out of the loaded rows
+pub fn deliveries_from_of(rows: &[CompanyDeliveryFrom], company_id_arg: CompanyId) -> Vec<Alpha3> {
+    rows.iter()
+        .filter(|row| row.company_id == company_id_arg)
+        .map(|row| row.country_alpha3.clone())
+        .collect()
+}
@@ -11,7 +11,7 @@ use errors::Error;
 use failure::Error as FailureError;
 use failure::Fail;
 
-use stq_types::{CompanyId, CompanyPackageId, PackageId, UserId};
+use stq_types::{Alpha3, CompanyId, CompanyPackageId, PackageId, UserId};
 
 use models::authorization::*;
 use repos::legacy_acl::*;
@@ -22,6 +22,7 @@ use models::{
     get_country, AvailablePackages, CompaniesPackagesRaw, Company, CompanyPackage, CompanyRaw, Country, NewCompaniesPackagesRaw,
     NewCompanyPackage, Packages, PackagesRaw,
 };
+use models::roles::{owns_company, UserRole};
 use repos::*;
 use schema::companies::dsl as DslCompanies;
 use schema::companies_packages::dsl::*;
@@ -44,6 +45,9 @@ pub trait CompaniesPackagesRepo {
     /// Returns company package by id
     fn get(&self, id: CompanyPackageId) -> RepoResult<Option<CompanyPackage>>;
 
+    /// Returns all company packages
+    fn list(&self) -> RepoResult<Vec<CompanyPackage>>;
+
     /// Returns companies by package id
     fn get_companies(&self, id: PackageId) -> RepoResult<Vec<Company>>;
 
@@ -104,6 +108,17 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .and_then(|record| transpose(record.map(CompaniesPackagesRaw::to_model)))
     }
 
+    fn list(&self) -> RepoResult<Vec<CompanyPackage>> {
+        debug!("list companies_packages.");
+
+        acl::check(&*self.acl, Resource::CompaniesPackages, Action::Read, self, None)?;
+        let query = companies_packages.order(id);
+        query
+            .get_results::<CompaniesPackagesRaw>(self.db_conn)
+            .map_err(move |e| Error::from(e).context("list companies_packages.").into())
+            .and_then(|records| records.into_iter().map(CompaniesPackagesRaw::to_model).collect())
+    }
+
     /// Getting available packages satisfying the constraints
     fn get_available_packages(
         &self,
@@ -134,12 +149,14 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .get_results::<(CompaniesPackagesRaw, CompanyRaw, PackagesRaw)>(self.db_conn)
             .map_err(|e| Error::from(e).into())
             .and_then(|results| {
+                let package_ids = results.iter().map(|(_, _, package_raw)| package_raw.id).collect::<Vec<_>>();
+                let deliveries = get_package_deliveries_to(self.db_conn, &package_ids)?;
                 let mut data = vec![];
 
                 for result in results {
                     let (companies_package, company_raw, package_raw) = result;
                     let company_package = companies_package.to_model()?;
-                    let used_codes = package_raw.get_deliveries_to()?;
+                    let used_codes = package_deliveries_to_of(&deliveries, package_raw.id);
 
                     let local_available = used_codes.iter().any(|country_code| {
                         get_country(&self.countries, country_code)
@@ -147,7 +164,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
                             .unwrap_or_default()
                     });
 
-                    let package = package_raw.to_packages(&self.countries)?;
+                    let package = package_raw.to_packages(&used_codes, &self.countries);
 
                     data.push(AvailablePackages {
                         id: company_package.id,
@@ -181,10 +198,13 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .get_results::<(CompaniesPackagesRaw, CompanyRaw)>(self.db_conn)
             .map_err(|e| Error::from(e).into())
             .and_then(|results| {
+                let company_ids = results.iter().map(|(_, company_raw)| company_raw.id).collect::<Vec<_>>();
+                let deliveries = get_deliveries_from(self.db_conn, &company_ids)?;
                 let mut data = vec![];
                 for result in results {
                     let (_, company_raw) = result;
-                    let element = Company::from_raw(company_raw, &self.countries)?;
+                    let used_codes = deliveries_from_of(&deliveries, company_raw.id);
+                    let element = Company::from_raw(company_raw, &used_codes, &self.countries);
                     data.push(element);
                 }
 
@@ -203,10 +223,13 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .get_results::<(CompaniesPackagesRaw, PackagesRaw)>(self.db_conn)
             .map_err(|e| Error::from(e).into())
             .and_then(|results| {
+                let package_ids = results.iter().map(|(_, package_raw)| package_raw.id).collect::<Vec<_>>();
+                let deliveries = get_package_deliveries_to(self.db_conn, &package_ids)?;
                 let mut data = vec![];
                 for result in results {
                     let (_, package_raw) = result;
-                    let element = package_raw.to_packages(&self.countries)?;
+                    let used_codes = package_deliveries_to_of(&deliveries, package_raw.id);
+                    let element = package_raw.to_packages(&used_codes, &self.countries);
                     data.push(element);
                 }
 
@@ -221,30 +244,47 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             company_id_arg, package_id_arg
         );
 
-        acl::check(&*self.acl, Resource::CompaniesPackages, Action::Delete, self, None)?;
-        let filtered = companies_packages.filter(company_id.eq(company_id_arg).and(package_id.eq(package_id_arg)));
-        let query = diesel::delete(filtered);
+        let query = companies_packages.filter(company_id.eq(company_id_arg).and(package_id.eq(package_id_arg)));
         query
             .get_result::<CompaniesPackagesRaw>(self.db_conn)
-            .map_err(move |e| {
-                Error::from(e)
-                    .context(format!(
-                        "delete companies_packages company_id: {}, package_id: {}.",
-                        company_id_arg, package_id_arg
-                    ))
-                    .into()
-            })
+            .map_err(|e| Error::from(e).into())
             .and_then(CompaniesPackagesRaw::to_model)
+            .and_then(|company_package| {
+                acl::check(
+                    &*self.acl,
+                    Resource::CompaniesPackages,
+                    Action::Delete,
+                    self,
+                    Some(&company_package),
+                )
+            })
+            .and_then(|_| {
+                let filtered = companies_packages.filter(company_id.eq(company_id_arg).and(package_id.eq(package_id_arg)));
+                let query = diesel::delete(filtered);
+                query
+                    .get_result::<CompaniesPackagesRaw>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+                    .and_then(CompaniesPackagesRaw::to_model)
+            })
+            .map_err(move |e: FailureError| {
+                e.context(format!(
+                    "delete companies_packages company_id: {}, package_id: {}.",
+                    company_id_arg, package_id_arg
+                ))
+                .into()
+            })
     }
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, CompanyPackage>
     for CompaniesPackagesRepoImpl<'a, T>
 {
-    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&CompanyPackage>) -> bool {
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&CompanyPackage>) -> bool {
         match *scope {
             Scope::All => true,
-            Scope::Owned => false,
+            Scope::Owned => obj
+                .map(|company_package| owns_company(user_roles, company_package.company_id))
+                .unwrap_or(false),
         }
     }
 }
@@ -1,47 +1,225 @@
 //! CountryCache is a module that caches received from db information about user and his categories
+//! The tree is kept both in process and in the cache, the version stamp in the cache tells the instances
+//! when their own copy of the tree is outdated, so the whole tree is fetched again only after a change.
+//! Both copies are tagged with the version taken before the tree was read from the db, so a tree read
+//! before an invalidation and written after it is never served.
+use std::sync::{Arc, RwLock};
+
 use failure::Fail;
 use stq_cache::cache::CacheSingle;
+use uuid::Uuid;
 
+use metrics;
 use models::Country;
+use replica;
+
+/// Country tree with the version it was read at
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VersionedCountry {
+    pub version: Uuid,
+    pub country: Country,
+}
 
-pub struct CountryCacheImpl<C>
+pub struct CountryCacheImpl<C, V>
 where
-    C: CacheSingle<Country>,
+    C: CacheSingle<VersionedCountry>,
+    V: CacheSingle<Uuid>,
 {
     cache: C,
+    version_cache: V,
+    local: RwLock<Option<(Uuid, Arc<Country>)>>,
 }
 
-impl<C> CountryCacheImpl<C>
+impl<C, V> CountryCacheImpl<C, V>
 where
-    C: CacheSingle<Country>,
+    C: CacheSingle<VersionedCountry>,
+    V: CacheSingle<Uuid>,
 {
-    pub fn new(cache: C) -> Self {
-        CountryCacheImpl { cache }
+    pub fn new(cache: C, version_cache: V) -> Self {
+        CountryCacheImpl {
+            cache,
+            version_cache,
+            local: RwLock::new(None),
+        }
+    }
+
+    /// Returns the current version, a missing version is created.
+    /// Must be taken before the tree is read from the db. Returns `None` if the cache is not available
+    pub fn version(&self) -> Option<Uuid> {
+        match self.version_cache.get() {
+            Ok(Some(version)) => Some(version),
+            Ok(None) => {
+                let version = Uuid::new_v4();
+                self.set_version(version).map(|_| version)
+            }
+            Err(err) => {
+                error!("{}", err.context("Failed to get version from CountryCache"));
+                None
+            }
+        }
     }
 
-    pub fn get(&self) -> Option<Country> {
+    /// Returns the tree of the version, from the process if possible
+    pub fn get(&self, version: Uuid) -> Option<Arc<Country>> {
         debug!("Getting country from CountryCache");
 
-        self.cache.get().unwrap_or_else(|err| {
-            error!("{}", err.context("Failed to get country from CountryCache"));
-            None
-        })
+        let country = match self.get_local(version) {
+            Some(country) => Some(country),
+            None => {
+                let country = self
+                    .cache
+                    .get()
+                    .unwrap_or_else(|err| {
+                        error!("{}", err.context("Failed to get country from CountryCache"));
+                        None
+                    })
+                    .and_then(|cached| {
+                        if cached.version == version {
+                            Some(Arc::new(cached.country))
+                        } else {
+                            None
+                        }
+                    });
+                if let Some(ref country) = country {
+                    self.set_local(version, country.clone());
+                }
+                country
+            }
+        };
+        metrics::observe_cache("countries", country.is_some());
+        country
     }
 
+    /// Bumps the version, so that every instance drops its copy of the tree
     pub fn remove(&self) -> bool {
         debug!("Removing country from CountryCache");
 
+        self.set_version(Uuid::new_v4());
+        if let Ok(mut local) = self.local.write() {
+            *local = None;
+        }
+
         self.cache.remove().unwrap_or_else(|err| {
             error!("{}", err.context("Failed to remove country from CountryCache"));
             false
         })
     }
 
-    pub fn set(&self, country: &Country) {
+    /// Caches the tree under the version taken before it was read from the db.
+    /// The tree read from the replica is not cached, the replica may not have the change the version was bumped for
+    pub fn set(&self, version: Uuid, country: &Country) {
+        if replica::is_reading() {
+            return;
+        }
         debug!("Setting country in CountryCache");
 
-        self.cache.set(country.clone()).unwrap_or_else(|err| {
-            error!("{}", err.context("Failed to set country in CountryCache"));
+        self.cache
+            .set(VersionedCountry {
+                version,
+                country: country.clone(),
+            })
+            .unwrap_or_else(|err| {
+                error!("{}", err.context("Failed to set country in CountryCache"));
+            });
+        self.set_local(version, Arc::new(country.clone()));
+    }
+
+    fn set_version(&self, version: Uuid) -> Option<()> {
+        self.version_cache.set(version).map(Some).unwrap_or_else(|err| {
+            error!("{}", err.context("Failed to set version in CountryCache"));
+            None
+        })
+    }
+
+    fn get_local(&self, version: Uuid) -> Option<Arc<Country>> {
+        self.local.read().ok().and_then(|local| match *local {
+            Some((local_version, ref country)) if local_version == version => Some(country.clone()),
+            _ => None,
         })
     }
+
+    fn set_local(&self, version: Uuid, country: Arc<Country>) {
+        if let Ok(mut local) = self.local.write() {
+            *local = Some((version, country));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repos::repo_factory::tests::MockCacheSingle;
+    use stq_types::{Alpha2, Alpha3};
+
+    fn create_cache() -> CountryCacheImpl<MockCacheSingle<VersionedCountry>, MockCacheSingle<Uuid>> {
+        CountryCacheImpl::new(MockCacheSingle::default(), MockCacheSingle::default())
+    }
+
+    fn create_country() -> Country {
+        Country {
+            label: "All".to_string().into(),
+            children: vec![],
+            level: 0,
+            parent: None,
+            alpha2: Alpha2("".to_string()),
+            alpha3: Alpha3("XAL".to_string()),
+            numeric: 0,
+            is_selected: false,
+        }
+    }
+
+    #[test]
+    fn test_version() {
+        let cache = create_cache();
+        let version = cache.version().unwrap();
+        assert_eq!(cache.version(), Some(version));
+        assert!(cache.get(version).is_none());
+    }
+
+    #[test]
+    fn test_local_hit() {
+        let cache = create_cache();
+        let version = cache.version().unwrap();
+        cache.set(version, &create_country());
+
+        // the copy of the process is served while the version is the same
+        cache.cache.remove().unwrap();
+        assert_eq!(
+            cache.get(version).map(|country| country.alpha3.clone()),
+            Some(Alpha3("XAL".to_string()))
+        );
+        assert!(cache.get(Uuid::new_v4()).is_none());
+
+        // another instance gets the tree from the cache
+        cache.set(version, &create_country());
+        *cache.local.write().unwrap() = None;
+        assert!(cache.get(version).is_some());
+        assert!(cache.local.read().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_invalidation() {
+        let cache = create_cache();
+        let version = cache.version().unwrap();
+        cache.set(version, &create_country());
+
+        cache.remove();
+        let new_version = cache.version().unwrap();
+        assert_ne!(new_version, version);
+        assert!(cache.get(new_version).is_none());
+
+        // a reader that took the version before the invalidation writes the old tree after it
+        cache.set(version, &create_country());
+        assert_eq!(cache.version(), Some(new_version));
+        assert!(cache.get(new_version).is_none());
+    }
+
+    #[test]
+    fn test_replica_reads_are_not_cached() {
+        let cache = create_cache();
+        let version = cache.version().unwrap();
+        replica::scope(|| cache.set(version, &create_country()));
+        assert!(cache.get(version).is_none());
+        assert!(cache.local.read().unwrap().is_none());
+    }
 }
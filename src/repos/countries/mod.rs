@@ -11,9 +11,10 @@ use failure::Error as FailureError;
 use std::sync::Arc;
 use stq_cache::cache::CacheSingle;
 use stq_types::{self, Alpha3, CountryLabel, UserId};
+use uuid::Uuid;
 
 use models::authorization::*;
-use models::{get_country, Country, NewCountry, RawCountry};
+use models::{get_country, Country, NewCountry, RawCountry, UserRole};
 use repos::acl;
 use repos::legacy_acl::{Acl, CheckScope};
 use repos::types::RepoResult;
@@ -32,14 +33,15 @@ pub enum CountrySearch {
 }
 
 /// Countries repository, responsible for handling countries
-pub struct CountriesRepoImpl<'a, C, T>
+pub struct CountriesRepoImpl<'a, C, V, T>
 where
-    C: CacheSingle<Country>,
+    C: CacheSingle<VersionedCountry>,
+    V: CacheSingle<Uuid>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
     pub db_conn: &'a T,
     pub acl: Box<Acl<Resource, Action, Scope, FailureError, Country>>,
-    pub cache: Arc<CountryCacheImpl<C>>,
+    pub cache: Arc<CountryCacheImpl<C, V>>,
 }
 
 pub trait CountriesRepo {
@@ -59,19 +61,21 @@ pub trait CountriesRepo {
     fn get_all_flatten(&self) -> RepoResult<Vec<Country>>;
 }
 
-impl<'a, C, T> CountriesRepoImpl<'a, C, T>
+impl<'a, C, V, T> CountriesRepoImpl<'a, C, V, T>
 where
-    C: CacheSingle<Country>,
+    C: CacheSingle<VersionedCountry>,
+    V: CacheSingle<Uuid>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
-    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Country>>, cache: Arc<CountryCacheImpl<C>>) -> Self {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Country>>, cache: Arc<CountryCacheImpl<C, V>>) -> Self {
         Self { db_conn, acl, cache }
     }
 }
 
-impl<'a, C, T> CountriesRepo for CountriesRepoImpl<'a, C, T>
+impl<'a, C, V, T> CountriesRepo for CountriesRepoImpl<'a, C, V, T>
 where
-    C: CacheSingle<Country>,
+    C: CacheSingle<VersionedCountry>,
+    V: CacheSingle<Uuid>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
     /// Find specific country by label
@@ -111,7 +115,6 @@ where
     /// Creates new country
     fn create(&self, payload: NewCountry) -> RepoResult<Country> {
         debug!("Create new country {:?}.", payload);
-        self.cache.remove();
         let query = diesel::insert_into(countries).values(&payload);
         query
             .get_result::<RawCountry>(self.db_conn)
@@ -122,9 +125,11 @@ where
     }
 
     fn get_all(&self) -> RepoResult<Country> {
-        if let Some(country) = self.cache.get() {
+        // the version is taken before the db is read, a tree read before an invalidation is cached under the old version
+        let version = self.cache.version();
+        if let Some(country) = version.and_then(|version| self.cache.get(version)) {
             debug!("Get all countries from cache request.");
-            Ok(country)
+            Ok((*country).clone())
         } else {
             debug!("Get all countries from db request.");
             acl::check(&*self.acl, Resource::Countries, Action::Read, self, None)
@@ -135,7 +140,9 @@ where
                         .into_iter()
                         .nth(0)
                         .ok_or_else(|| format_err!("Could not create countries tree"))?;
-                    self.cache.set(&root);
+                    if let Some(version) = version {
+                        self.cache.set(version, &root);
+                    }
                     Ok(root)
                 })
                 .map_err(|e: FailureError| e.context("Get all countries error occured").into())
@@ -155,7 +162,7 @@ where
     }
 }
 
-fn create_tree(countries_: &[RawCountry], parent_arg: Option<Alpha3>) -> RepoResult<Vec<Country>> {
+pub fn create_tree(countries_: &[RawCountry], parent_arg: Option<Alpha3>) -> RepoResult<Vec<Country>> {
     let mut branch = vec![];
     for country in countries_ {
         if country.parent == parent_arg {
@@ -303,12 +310,29 @@ pub fn contains_country_code(country: &Country, country_code: &Alpha3) -> bool {
     }
 }
 
-impl<'a, C, T> CheckScope<Scope, Country> for CountriesRepoImpl<'a, C, T>
+/// Checks that every code is present in the countries tree, unknown codes are reported as a validation error of the field
+pub fn check_country_codes(countries_arg: &Country, codes: &[Alpha3], field: &'static str) -> RepoResult<()> {
+    let unknown_codes = codes
+        .iter()
+        .filter(|code| !contains_country_code(countries_arg, code))
+        .map(|code| code.0.clone())
+        .collect::<Vec<_>>();
+
+    if unknown_codes.is_empty() {
+        Ok(())
+    } else {
+        let msg = format!("Unknown countries: {}", unknown_codes.join(", "));
+        Err(Error::Validate(validation_errors!({ field: ["country" => msg] })).into())
+    }
+}
+
+impl<'a, C, V, T> CheckScope<Scope, Country> for CountriesRepoImpl<'a, C, V, T>
 where
-    C: CacheSingle<Country>,
+    C: CacheSingle<VersionedCountry>,
+    V: CacheSingle<Uuid>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
-    fn is_in_scope(&self, _user_label: UserId, scope: &Scope, _obj: Option<&Country>) -> bool {
+    fn is_in_scope(&self, _user_label: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&Country>) -> bool {
         match *scope {
             Scope::All => true,
             Scope::Owned => false,
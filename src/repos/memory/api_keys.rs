@@ -0,0 +1,114 @@
+//! In-memory api_keys repo
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::authorization::*;
+use models::{ApiKey, ApiKeyRaw, NewApiKeyRaw, UserRole};
+use repos::acl;
+use repos::api_keys::ApiKeysRepo;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+
+use super::store::MemoryStore;
+
+pub struct ApiKeysRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, ApiKey>>,
+}
+
+impl<'a> ApiKeysRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, ApiKey>>) -> Self {
+        Self { store, acl }
+    }
+}
+
+impl<'a> ApiKeysRepo for ApiKeysRepoMemory<'a> {
+    fn create(&self, payload: NewApiKeyRaw) -> RepoResult<ApiKey> {
+        debug!("create new api key {}.", payload.name);
+        acl::check(&*self.acl, Resource::ApiKeys, Action::Create, self, None)
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                if tables.api_keys.iter().any(|api_key| api_key.key_hash == payload.key_hash) {
+                    return Err(Error::not_unique("api key already exists").into());
+                }
+                let api_key = ApiKeyRaw {
+                    id: tables.next_id(),
+                    name: payload.name.clone(),
+                    key_hash: payload.key_hash.clone(),
+                    permissions: payload.permissions.clone(),
+                    created_by: payload.created_by,
+                    created_at: SystemTime::now(),
+                    revoked_at: None,
+                };
+                tables.api_keys.push(api_key.clone());
+                Ok(api_key)
+            })
+            .and_then(|api_key| api_key.to_model())
+            .map_err(|e: FailureError| e.context(format!("Create a new api key {} error occurred", payload.name)).into())
+    }
+
+    fn list(&self) -> RepoResult<Vec<ApiKey>> {
+        debug!("list api keys.");
+        acl::check(&*self.acl, Resource::ApiKeys, Action::Read, self, None)
+            .and_then(|_| {
+                let mut results = self.store.lock().api_keys.clone();
+                results.sort_by_key(|api_key| api_key.id);
+                results.into_iter().map(|api_key| api_key.to_model()).collect()
+            })
+            .map_err(|e: FailureError| e.context("List api keys error occurred").into())
+    }
+
+    fn find_active_by_hash(&self, key_hash_arg: String) -> RepoResult<Option<ApiKey>> {
+        debug!("find active api key by hash.");
+        let api_key = self
+            .store
+            .lock()
+            .api_keys
+            .iter()
+            .find(|api_key| api_key.key_hash == key_hash_arg && api_key.revoked_at.is_none())
+            .cloned();
+
+        match api_key {
+            Some(api_key) => api_key
+                .to_model()
+                .and_then(|api_key| {
+                    acl::check(&*self.acl, Resource::ApiKeys, Action::Read, self, Some(&api_key))?;
+                    Ok(Some(api_key))
+                })
+                .map_err(|e: FailureError| e.context("Find api key by hash error occurred").into()),
+            None => Ok(None),
+        }
+    }
+
+    fn revoke(&self, id_arg: i32) -> RepoResult<ApiKey> {
+        debug!("revoke api key {}.", id_arg);
+        acl::check(&*self.acl, Resource::ApiKeys, Action::Delete, self, None)
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                let api_key = tables
+                    .api_keys
+                    .iter_mut()
+                    .find(|api_key| api_key.id == id_arg && api_key.revoked_at.is_none())
+                    .ok_or_else(|| FailureError::from(Error::NotFound))?;
+                api_key.revoked_at = Some(SystemTime::now());
+                Ok(api_key.clone())
+            })
+            .and_then(|api_key| api_key.to_model())
+            .map_err(|e: FailureError| e.context(format!("Revoke api key {} error occurred", id_arg)).into())
+    }
+}
+
+impl<'a> CheckScope<Scope, ApiKey> for ApiKeysRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&ApiKey>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
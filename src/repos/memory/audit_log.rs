@@ -0,0 +1,101 @@
+//! In-memory audit_log repo
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use models::authorization::{Action, Resource, Scope};
+use models::{AuditLogEntry, AuditLogSearch, NewAuditLogEntry, UserRole};
+use repos::acl;
+use repos::audit_log::AuditLogRepo;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+
+use super::store::MemoryStore;
+
+pub struct AuditLogRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>,
+}
+
+impl<'a> AuditLogRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>) -> Self {
+        Self { store, acl }
+    }
+}
+
+fn matches(entry: &AuditLogEntry, search: &AuditLogSearch) -> bool {
+    search.user_id.map(|user_id| entry.user_id == Some(user_id)).unwrap_or(true)
+        && search
+            .correlation_token
+            .as_ref()
+            .map(|correlation_token| entry.correlation_token == *correlation_token)
+            .unwrap_or(true)
+        && search.resource.map(|resource| entry.resource == resource).unwrap_or(true)
+        && search.action.map(|action| entry.action == action).unwrap_or(true)
+        && search
+            .created_from
+            .map(|created_from| entry.created_at >= created_from)
+            .unwrap_or(true)
+        && search.created_to.map(|created_to| entry.created_at < created_to).unwrap_or(true)
+}
+
+impl<'a> AuditLogRepo for AuditLogRepoMemory<'a> {
+    fn create(&self, payload: NewAuditLogEntry) -> RepoResult<AuditLogEntry> {
+        debug!("create new audit log entry {:?}.", payload);
+        acl::check(&*self.acl, Resource::AuditLog, Action::Create, self, None)
+            .map(|_| {
+                let mut tables = self.store.lock();
+                let entry = AuditLogEntry {
+                    id: tables.next_id(),
+                    user_id: payload.user_id,
+                    correlation_token: payload.correlation_token.clone(),
+                    resource: payload.resource,
+                    action: payload.action,
+                    before: payload.before.clone(),
+                    after: payload.after.clone(),
+                    created_at: SystemTime::now(),
+                };
+                tables.audit_log.push(entry.clone());
+                entry
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new audit log entry {:?} error occurred", payload))
+                    .into()
+            })
+    }
+
+    fn search(&self, search: AuditLogSearch) -> RepoResult<Vec<AuditLogEntry>> {
+        debug!("search audit log entries {:?}.", search);
+        acl::check(&*self.acl, Resource::AuditLog, Action::Read, self, None)
+            .map(|_| {
+                let mut entries = self
+                    .store
+                    .lock()
+                    .audit_log
+                    .iter()
+                    .filter(|entry| matches(entry, &search))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                entries.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+                entries
+                    .into_iter()
+                    .skip(search.offset.max(0) as usize)
+                    .take(search.count.max(0) as usize)
+                    .collect()
+            })
+            .map_err(|e: FailureError| e.context(format!("Search audit log entries {:?} error occurred", search)).into())
+    }
+}
+
+impl<'a> CheckScope<Scope, AuditLogEntry> for AuditLogRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&AuditLogEntry>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
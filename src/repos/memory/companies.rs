@@ -0,0 +1,185 @@
+//! In-memory companies repo
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::{Alpha3, CompanyId, UserId};
+
+use errors::Error;
+use models::authorization::*;
+use models::companies::{Company, CompanyRaw, NewCompany, UpdateCompany};
+use models::countries::Country;
+use models::roles::{owns_company, UserRole};
+use repos::acl;
+use repos::companies::CompaniesRepo;
+use repos::countries::check_country_codes;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+
+use super::store::{remove_where, MemoryStore};
+
+pub struct CompaniesRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Company>>,
+    pub countries: Country,
+}
+
+impl<'a> CompaniesRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, Company>>, countries: Country) -> Self {
+        Self { store, acl, countries }
+    }
+
+    fn read_all<P: Fn(&CompanyRaw) -> bool>(&self, predicate: P) -> RepoResult<Vec<Company>> {
+        let tables = self.store.lock();
+        let mut raws = tables
+            .companies
+            .iter()
+            .filter(|company| predicate(company))
+            .map(|company| (company.clone(), tables.company_deliveries_from(company.id)))
+            .collect::<Vec<_>>();
+        drop(tables);
+        raws.sort_by_key(|(company, _)| company.id.0);
+
+        let results = raws
+            .into_iter()
+            .map(|(raw, used_codes)| Company::from_raw(raw, &used_codes, &self.countries))
+            .collect::<Vec<Company>>();
+        for company in &results {
+            acl::check(&*self.acl, Resource::Companies, Action::Read, self, Some(&company))?;
+        }
+        Ok(results)
+    }
+}
+
+impl<'a> CompaniesRepo for CompaniesRepoMemory<'a> {
+    fn create(&self, payload: NewCompany) -> RepoResult<Company> {
+        debug!("create new company {:?}.", payload);
+        let (payload, deliveries_from) = payload.to_raw();
+        check_country_codes(&self.countries, &deliveries_from, "deliveries_from")?;
+
+        let id = CompanyId(self.store.lock().next_id());
+        let raw = CompanyRaw {
+            id,
+            name: payload.name.clone(),
+            label: payload.label.clone(),
+            description: payload.description.clone(),
+            logo: payload.logo.clone(),
+            currency: payload.currency,
+        };
+        let company = Company::from_raw(raw.clone(), &deliveries_from, &self.countries);
+
+        acl::check(&*self.acl, Resource::Companies, Action::Create, self, Some(&company))
+            .map(|_| {
+                let mut tables = self.store.lock();
+                tables.companies.push(raw);
+                tables.set_company_deliveries_from(id, deliveries_from);
+                company
+            })
+            .map_err(|e: FailureError| e.context(format!("create new company {:?}.", payload)).into())
+    }
+
+    fn list(&self) -> RepoResult<Vec<Company>> {
+        debug!("List companies");
+
+        self.read_all(|_| true)
+            .map_err(|e: FailureError| e.context("Find in companies error occured").into())
+    }
+
+    fn find(&self, id_arg: CompanyId) -> RepoResult<Option<Company>> {
+        debug!("Find in company with id {}.", id_arg);
+
+        self.read_all(|company| company.id == id_arg)
+            .map(|companies| companies.into_iter().next())
+            .map_err(|e: FailureError| e.context(format!("Find company with id: {} error occured", id_arg)).into())
+    }
+
+    fn find_deliveries_from(&self, country: Alpha3) -> RepoResult<Vec<Company>> {
+        debug!("Find in companies with country {:?}.", country);
+
+        let company_ids = self
+            .store
+            .lock()
+            .companies_deliveries_from
+            .iter()
+            .filter(|row| row.country_alpha3 == country)
+            .map(|row| row.company_id)
+            .collect::<Vec<_>>();
+
+        self.read_all(|company| company_ids.contains(&company.id))
+            .map_err(|e: FailureError| {
+                e.context(format!("Find in companies with country {:?} error occured", country))
+                    .into()
+            })
+    }
+
+    fn update(&self, id_arg: CompanyId, payload: UpdateCompany) -> RepoResult<Company> {
+        debug!("Updating company {} with payload {:?}.", id_arg, payload);
+        let (payload, deliveries_from) = payload.to_raw();
+        if let Some(ref deliveries_from) = deliveries_from {
+            check_country_codes(&self.countries, deliveries_from, "deliveries_from")?;
+        }
+
+        self.read_all(|company| company.id == id_arg)
+            .and_then(|companies| companies.into_iter().next().ok_or_else(|| Error::NotFound.into()))
+            .and_then(|company: Company| acl::check(&*self.acl, Resource::Companies, Action::Update, self, Some(&company)))
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                let raw = {
+                    let raw = tables
+                        .companies
+                        .iter_mut()
+                        .find(|company| company.id == id_arg)
+                        .ok_or_else(|| FailureError::from(Error::NotFound))?;
+                    if let Some(ref name) = payload.name {
+                        raw.name = name.clone();
+                    }
+                    if let Some(ref label) = payload.label {
+                        raw.label = label.clone();
+                    }
+                    if let Some(ref description) = payload.description {
+                        raw.description = Some(description.clone());
+                    }
+                    if let Some(ref logo) = payload.logo {
+                        raw.logo = logo.clone();
+                    }
+                    if let Some(currency) = payload.currency {
+                        raw.currency = currency;
+                    }
+                    raw.clone()
+                };
+                if let Some(deliveries_from) = deliveries_from {
+                    tables.set_company_deliveries_from(id_arg, deliveries_from);
+                }
+                Ok(Company::from_raw(raw, &tables.company_deliveries_from(id_arg), &self.countries))
+            })
+            .map_err(|e: FailureError| e.context(format!("Updating company payload {:?} failed.", payload)).into())
+    }
+
+    fn delete(&self, id_arg: CompanyId) -> RepoResult<Company> {
+        debug!("delete company by company_id: {}.", id_arg);
+
+        acl::check(&*self.acl, Resource::Companies, Action::Delete, self, None)?;
+
+        let mut tables = self.store.lock();
+        remove_where(&mut tables.companies, |company| company.id == id_arg)
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::NotFound.into())
+            .map(|raw| {
+                let used_codes = tables.company_deliveries_from(id_arg);
+                remove_where(&mut tables.companies_deliveries_from, |row| row.company_id == id_arg);
+                tables.delete_companies_packages(|company_package| company_package.company_id == id_arg);
+                Company::from_raw(raw, &used_codes, &self.countries)
+            })
+            .map_err(move |e: FailureError| e.context(format!("delete company id: {}.", id_arg)).into())
+    }
+}
+
+impl<'a> CheckScope<Scope, Company> for CompaniesRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&Company>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|company| owns_company(user_roles, company.id)).unwrap_or(false),
+        }
+    }
+}
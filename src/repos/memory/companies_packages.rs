@@ -0,0 +1,257 @@
+//! In-memory companies_packages repo
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::{Alpha3, CompanyId, CompanyPackageId, PackageId, UserId};
+
+use errors::Error;
+use models::authorization::*;
+use models::roles::{owns_company, UserRole};
+use models::{
+    get_country, AvailablePackages, CompaniesPackagesRaw, Company, CompanyPackage, Country, NewCompaniesPackagesRaw, NewCompanyPackage,
+    Packages,
+};
+use repos::acl;
+use repos::companies_packages::CompaniesPackagesRepo;
+use repos::countries::contains_country_code;
+use repos::get_company_package_name;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+
+use super::store::MemoryStore;
+
+pub struct CompaniesPackagesRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, CompanyPackage>>,
+    pub countries: Country,
+}
+
+impl<'a> CompaniesPackagesRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, CompanyPackage>>, countries: Country) -> Self {
+        Self { store, acl, countries }
+    }
+}
+
+impl<'a> CompaniesPackagesRepo for CompaniesPackagesRepoMemory<'a> {
+    fn create(&self, payload: NewCompanyPackage) -> RepoResult<CompanyPackage> {
+        debug!("create new companies_packages {:?}.", payload);
+        let record = NewCompaniesPackagesRaw::from(payload.clone());
+
+        let raw = {
+            let mut tables = self.store.lock();
+            if !tables.companies.iter().any(|company| company.id == record.company_id)
+                || !tables.packages.iter().any(|package| package.id == record.package_id)
+            {
+                return Err(FailureError::from(Error::NotFound)
+                    .context(format!("create new companies_packages {:?}.", payload))
+                    .into());
+            }
+            CompaniesPackagesRaw {
+                id: CompanyPackageId(tables.next_id()),
+                company_id: record.company_id,
+                package_id: record.package_id,
+                shipping_rate_source: record.shipping_rate_source,
+                dimensional_factor: record.dimensional_factor,
+            }
+        };
+
+        raw.clone()
+            .to_model()
+            .and_then(|company_package| {
+                acl::check(
+                    &*self.acl,
+                    Resource::CompaniesPackages,
+                    Action::Create,
+                    self,
+                    Some(&company_package),
+                )?;
+                Ok(company_package)
+            })
+            .map(|company_package| {
+                self.store.lock().companies_packages.push(raw);
+                company_package
+            })
+            .map_err(|e: FailureError| e.context(format!("create new companies_packages {:?}.", payload)).into())
+    }
+
+    fn get(&self, id_arg: CompanyPackageId) -> RepoResult<Option<CompanyPackage>> {
+        debug!("get companies_packages by id: {}.", id_arg);
+
+        acl::check(&*self.acl, Resource::CompaniesPackages, Action::Read, self, None)?;
+        let record = self
+            .store
+            .lock()
+            .companies_packages
+            .iter()
+            .find(|company_package| company_package.id == id_arg)
+            .cloned();
+        match record {
+            Some(record) => record.to_model().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self) -> RepoResult<Vec<CompanyPackage>> {
+        debug!("list companies_packages.");
+
+        acl::check(&*self.acl, Resource::CompaniesPackages, Action::Read, self, None)?;
+        let mut records = self.store.lock().companies_packages.clone();
+        records.sort_by_key(|company_package| company_package.id.0);
+        records.into_iter().map(CompaniesPackagesRaw::to_model).collect()
+    }
+
+    fn get_available_packages(
+        &self,
+        company_id_args: Vec<CompanyId>,
+        size: u32,
+        weight: u32,
+        deliveries_from: Alpha3,
+    ) -> RepoResult<Vec<AvailablePackages>> {
+        let size = size as i32;
+        let weight = weight as i32;
+
+        debug!(
+            "Find in packages with companies: {:?}, size: {}, weight: {}.",
+            company_id_args, size, weight
+        );
+
+        let mut results = {
+            let tables = self.store.lock();
+            tables
+                .companies_packages
+                .iter()
+                .filter(|company_package| company_id_args.contains(&company_package.company_id))
+                .filter_map(|company_package| {
+                    let company = tables.companies.iter().find(|company| company.id == company_package.company_id)?;
+                    let package = tables.packages.iter().find(|package| package.id == company_package.package_id)?;
+                    Some((company_package.clone(), company.clone(), package.clone()))
+                })
+                .filter(|(_, _, package)| {
+                    package.max_size >= size && package.min_size <= size && package.max_weight >= weight && package.min_weight <= weight
+                })
+                .map(|(company_package, company, package)| {
+                    let used_codes = tables.package_deliveries_to(package.id);
+                    (company_package, company, package, used_codes)
+                })
+                .collect::<Vec<_>>()
+        };
+        results.sort_by(|(_, company_a, _, _), (_, company_b, _, _)| company_a.label.cmp(&company_b.label));
+
+        let mut data = vec![];
+        for (companies_package, company_raw, package_raw, used_codes) in results {
+            let company_package = companies_package.to_model()?;
+
+            let local_available = used_codes.iter().any(|country_code| {
+                get_country(&self.countries, country_code)
+                    .map(|c| contains_country_code(&c, &deliveries_from))
+                    .unwrap_or_default()
+            });
+
+            let package = package_raw.to_packages(&used_codes, &self.countries);
+
+            data.push(AvailablePackages {
+                id: company_package.id,
+                name: get_company_package_name(&company_raw.label, &package.name),
+                logo: company_raw.logo,
+                deliveries_to: package.deliveries_to,
+                shipping_rate_source: company_package.shipping_rate_source,
+                currency: company_raw.currency,
+                local_available,
+            });
+        }
+
+        Ok(data)
+    }
+
+    fn get_companies(&self, id_arg: PackageId) -> RepoResult<Vec<Company>> {
+        debug!("get companies_packages by package_id: {}.", id_arg);
+
+        let company_raws = {
+            let tables = self.store.lock();
+            tables
+                .companies_packages
+                .iter()
+                .filter(|company_package| company_package.package_id == id_arg)
+                .filter_map(|company_package| tables.companies.iter().find(|company| company.id == company_package.company_id))
+                .map(|company| (company.clone(), tables.company_deliveries_from(company.id)))
+                .collect::<Vec<_>>()
+        };
+
+        Ok(company_raws
+            .into_iter()
+            .map(|(company_raw, used_codes)| Company::from_raw(company_raw, &used_codes, &self.countries))
+            .collect())
+    }
+
+    fn get_packages(&self, id_arg: CompanyId) -> RepoResult<Vec<Packages>> {
+        debug!("get companies_packages by company_id: {}.", id_arg);
+
+        let package_raws = {
+            let tables = self.store.lock();
+            tables
+                .companies_packages
+                .iter()
+                .filter(|company_package| company_package.company_id == id_arg)
+                .filter_map(|company_package| tables.packages.iter().find(|package| package.id == company_package.package_id))
+                .map(|package| (package.clone(), tables.package_deliveries_to(package.id)))
+                .collect::<Vec<_>>()
+        };
+
+        Ok(package_raws
+            .into_iter()
+            .map(|(package_raw, used_codes)| package_raw.to_packages(&used_codes, &self.countries))
+            .collect())
+    }
+
+    fn delete(&self, company_id_arg: CompanyId, package_id_arg: PackageId) -> RepoResult<CompanyPackage> {
+        debug!(
+            "delete companies_packages by company_id: {}, package_id: {}.",
+            company_id_arg, package_id_arg
+        );
+
+        let is_deleted = |company_package: &CompaniesPackagesRaw| {
+            company_package.company_id == company_id_arg && company_package.package_id == package_id_arg
+        };
+
+        let record = self
+            .store
+            .lock()
+            .companies_packages
+            .iter()
+            .find(|record| is_deleted(record))
+            .cloned();
+        record
+            .ok_or_else(|| Error::NotFound.into())
+            .and_then(CompaniesPackagesRaw::to_model)
+            .and_then(|company_package| {
+                acl::check(
+                    &*self.acl,
+                    Resource::CompaniesPackages,
+                    Action::Delete,
+                    self,
+                    Some(&company_package),
+                )?;
+                self.store.lock().delete_companies_packages(is_deleted);
+                Ok(company_package)
+            })
+            .map_err(move |e: FailureError| {
+                e.context(format!(
+                    "delete companies_packages company_id: {}, package_id: {}.",
+                    company_id_arg, package_id_arg
+                ))
+                .into()
+            })
+    }
+}
+
+impl<'a> CheckScope<Scope, CompanyPackage> for CompaniesPackagesRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&CompanyPackage>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj
+                .map(|company_package| owns_company(user_roles, company_package.company_id))
+                .unwrap_or(false),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Connection of the in-memory backend. It runs no SQL, repos read and write the store directly.
+//! Transactions are emulated with snapshots of the store: `BEGIN` and `SAVEPOINT` copy the tables
+//! and `ROLLBACK` restores the copy. Transactions are serialized, from `BEGIN` until `COMMIT` or `ROLLBACK`
+//! other threads wait to access the store, so a rollback never discards their writes.
+
+use std::cell::RefCell;
+
+use diesel::connection::{AnsiTransactionManager, SimpleConnection};
+use diesel::deserialize::QueryableByName;
+use diesel::pg::Pg;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::result::{ConnectionError, Error as DieselError};
+use diesel::sql_types::HasSqlType;
+use diesel::{Connection, ConnectionResult, QueryResult, Queryable};
+use r2d2::ManageConnection;
+
+use super::store::{MemoryStore, Tables};
+
+pub struct MemoryConnection {
+    store: MemoryStore,
+    snapshots: RefCell<Vec<Tables>>,
+    transaction_manager: AnsiTransactionManager,
+}
+
+impl MemoryConnection {
+    pub fn new(store: MemoryStore) -> Self {
+        Self {
+            store,
+            snapshots: RefCell::new(vec![]),
+            transaction_manager: AnsiTransactionManager::default(),
+        }
+    }
+
+    pub fn store(&self) -> &MemoryStore {
+        &self.store
+    }
+}
+
+fn sql_not_supported() -> DieselError {
+    DieselError::QueryBuilderError("SQL queries are not supported by the in-memory backend".into())
+}
+
+impl Connection for MemoryConnection {
+    type Backend = Pg;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(_database_url: &str) -> ConnectionResult<MemoryConnection> {
+        Err(ConnectionError::BadConnection(
+            "In-memory connections are created by MemoryConnectionManager".to_string(),
+        ))
+    }
+
+    fn execute(&self, _query: &str) -> QueryResult<usize> {
+        Err(sql_not_supported())
+    }
+
+    fn query_by_index<T, U>(&self, _source: T) -> QueryResult<Vec<U>>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<Pg> + QueryId,
+        Pg: HasSqlType<T::SqlType>,
+        U: Queryable<T::SqlType, Pg>,
+    {
+        Err(sql_not_supported())
+    }
+
+    fn query_by_name<T, U>(&self, _source: &T) -> QueryResult<Vec<U>>
+    where
+        T: QueryFragment<Pg> + QueryId,
+        U: QueryableByName<Pg>,
+    {
+        Err(sql_not_supported())
+    }
+
+    fn execute_returning_count<T>(&self, _source: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Pg> + QueryId,
+    {
+        Err(sql_not_supported())
+    }
+
+    fn transaction_manager(&self) -> &Self::TransactionManager {
+        &self.transaction_manager
+    }
+}
+
+impl SimpleConnection for MemoryConnection {
+    /// Handles statements sent by `AnsiTransactionManager`
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        let mut snapshots = self.snapshots.borrow_mut();
+        if query == "BEGIN" {
+            snapshots.push(self.store.begin());
+            Ok(())
+        } else if query.starts_with("SAVEPOINT ") {
+            snapshots.push(self.store.snapshot());
+            Ok(())
+        } else if query == "COMMIT" {
+            snapshots.clear();
+            self.store.finish(None);
+            Ok(())
+        } else if query.starts_with("RELEASE SAVEPOINT ") {
+            snapshots.pop();
+            Ok(())
+        } else if query == "ROLLBACK" {
+            let snapshot = snapshots.drain(..).next();
+            self.store.finish(snapshot);
+            Ok(())
+        } else if query.starts_with("ROLLBACK TO SAVEPOINT ") {
+            if let Some(snapshot) = snapshots.pop() {
+                self.store.restore(snapshot);
+            }
+            Ok(())
+        } else {
+            Err(sql_not_supported())
+        }
+    }
+}
+
+impl Drop for MemoryConnection {
+    /// Rolls back the transaction left open, e.g. by a panic, so other threads don't wait for it forever
+    fn drop(&mut self) {
+        let mut snapshots = self.snapshots.borrow_mut();
+        if !snapshots.is_empty() {
+            let snapshot = snapshots.drain(..).next();
+            self.store.finish(snapshot);
+        }
+    }
+}
+
+/// Creates connections to the store
+#[derive(Clone)]
+pub struct MemoryConnectionManager {
+    store: MemoryStore,
+}
+
+impl MemoryConnectionManager {
+    pub fn new(store: MemoryStore) -> Self {
+        Self { store }
+    }
+}
+
+impl ManageConnection for MemoryConnectionManager {
+    type Connection = MemoryConnection;
+    type Error = ConnectionError;
+
+    fn connect(&self) -> Result<MemoryConnection, ConnectionError> {
+        Ok(MemoryConnection::new(self.store.clone()))
+    }
+
+    fn is_valid(&self, _conn: &mut MemoryConnection) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn has_broken(&self, _conn: &mut MemoryConnection) -> bool {
+        false
+    }
+}
@@ -0,0 +1,119 @@
+//! In-memory countries repo
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::{Alpha3, UserId};
+
+use errors::Error;
+use models::authorization::*;
+use models::{get_country, Country, NewCountry, RawCountry, UserRole};
+use repos::acl;
+use repos::countries::{create_tree, CountriesRepo, CountrySearch};
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+
+use super::store::MemoryStore;
+
+pub struct CountriesRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Country>>,
+}
+
+impl<'a> CountriesRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, Country>>) -> Self {
+        Self { store, acl }
+    }
+}
+
+impl<'a> CountriesRepo for CountriesRepoMemory<'a> {
+    fn find(&self, arg: Alpha3) -> RepoResult<Option<Country>> {
+        debug!("Find in countries with aplha3 {}.", arg);
+        acl::check(&*self.acl, Resource::Countries, Action::Read, self, None)?;
+
+        self.get_all().map(|root| get_country(&root, &arg))
+    }
+
+    fn find_by(&self, search: CountrySearch) -> RepoResult<Option<Country>> {
+        debug!("Get countries by search: {:?}.", search);
+
+        let raw_country = self
+            .store
+            .lock()
+            .countries
+            .iter()
+            .find(|country| match search {
+                CountrySearch::Label(ref value) => country.label == *value,
+                CountrySearch::Alpha2(ref value) => country.alpha2 == *value,
+                CountrySearch::Alpha3(ref value) => country.alpha3 == *value,
+                CountrySearch::Numeric(value) => country.numeric == value,
+            })
+            .cloned();
+
+        match raw_country {
+            Some(raw_country) => {
+                let country: Country = raw_country.into();
+                acl::check(&*self.acl, Resource::Countries, Action::Read, self, Some(&country))
+                    .map(|_| Some(country))
+                    .map_err(|e: FailureError| e.context(format!("Get countries by search: {:?}.", search)).into())
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn create(&self, payload: NewCountry) -> RepoResult<Country> {
+        debug!("Create new country {:?}.", payload);
+
+        let raw_country = RawCountry {
+            label: payload.label.clone(),
+            level: payload.level,
+            alpha2: payload.alpha2.clone(),
+            alpha3: payload.alpha3.clone(),
+            numeric: payload.numeric,
+            parent: payload.parent.clone(),
+        };
+        let country: Country = raw_country.clone().into();
+
+        acl::check(&*self.acl, Resource::Countries, Action::Create, self, Some(&country))
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                if tables.countries.iter().any(|country| country.alpha3 == raw_country.alpha3) {
+                    return Err(Error::not_unique("country with this alpha3 already exists").into());
+                }
+                tables.countries.push(raw_country);
+                Ok(country)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create new country: {:?} error occured", payload)).into())
+    }
+
+    fn get_all(&self) -> RepoResult<Country> {
+        debug!("Get all countries from memory request.");
+
+        acl::check(&*self.acl, Resource::Countries, Action::Read, self, None)
+            .and_then(|_| {
+                let countries = self.store.lock().countries.clone();
+                let tree = create_tree(&countries, None)?;
+                tree.into_iter()
+                    .nth(0)
+                    .ok_or_else(|| format_err!("Could not create countries tree"))
+            })
+            .map_err(|e: FailureError| e.context("Get all countries error occured").into())
+    }
+
+    fn get_all_flatten(&self) -> RepoResult<Vec<Country>> {
+        debug!("Get all countries as vec from memory request.");
+
+        acl::check(&*self.acl, Resource::Countries, Action::Read, self, None)
+            .map(|_| self.store.lock().countries.iter().cloned().map(Country::from).collect())
+            .map_err(|e: FailureError| e.context("Get all flatten countries error occured").into())
+    }
+}
+
+impl<'a> CheckScope<Scope, Country> for CountriesRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&Country>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -0,0 +1,503 @@
+//! Factory of in-memory repos
+
+use std::sync::Arc;
+
+use failure::Error as FailureError;
+
+use stq_types::{Alpha3, CompanyPackageId, UserId};
+
+use models::*;
+use repos::legacy_acl::{Acl, SystemACL};
+use repos::*;
+
+use super::api_keys::ApiKeysRepoMemory;
+use super::audit_log::AuditLogRepoMemory;
+use super::companies::CompaniesRepoMemory;
+use super::companies_packages::CompaniesPackagesRepoMemory;
+use super::connection::MemoryConnection;
+use super::countries::CountriesRepoMemory;
+use super::outbox::OutboxRepoMemory;
+use super::packages::PackagesRepoMemory;
+use super::pickups::PickupsRepoMemory;
+use super::products::ProductsRepoMemory;
+use super::shipping_rates::ShippingRatesRepoMemory;
+use super::user_addresses::UserAddressesRepoMemory;
+use super::user_data_erasures::UserDataErasuresRepoMemory;
+use super::user_roles::UserRolesRepoMemory;
+use super::webhook_deliveries::WebhookDeliveriesRepoMemory;
+use super::webhook_subscriptions::WebhookSubscriptionsRepoMemory;
+
+/// Creates repos working with the store of the connection. ACL is enforced the same way as in `ReposFactoryImpl`,
+/// roles and countries are not cached as reading them from the store is cheap.
+#[derive(Clone)]
+pub struct MemoryReposFactory {
+    acl_policy: Arc<AclPolicy>,
+    api_key_permissions: Option<Arc<Vec<Permission>>>,
+    user_roles: Option<(UserId, Arc<Vec<UserRole>>)>,
+}
+
+impl MemoryReposFactory {
+    pub fn new(acl_policy: Arc<AclPolicy>) -> Self {
+        Self {
+            acl_policy,
+            api_key_permissions: None,
+            user_roles: None,
+        }
+    }
+
+    fn get_acl<T>(&self, db_conn: &MemoryConnection, user_id: Option<UserId>) -> Box<Acl<Resource, Action, Scope, FailureError, T>> {
+        create_acl(&self.acl_policy, &self.api_key_permissions, user_id, |id| {
+            resolved_roles(&self.user_roles, id).map_or_else(|| self.create_user_roles_repo_with_sys_acl(db_conn).get_by_user_id(id), Ok)
+        })
+    }
+
+    fn get_countries(&self, db_conn: &MemoryConnection, user_id: Option<UserId>) -> Country {
+        self.create_countries_repo(db_conn, user_id).get_all().ok().unwrap_or_default()
+    }
+}
+
+impl ReposFactory<MemoryConnection> for MemoryReposFactory {
+    fn create_api_keys_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<ApiKeysRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ApiKeysRepoMemory::new(db_conn.store(), acl)) as Box<ApiKeysRepo>
+    }
+
+    fn create_api_keys_repo_with_sys_acl<'a>(&self, db_conn: &'a MemoryConnection) -> Box<ApiKeysRepo + 'a> {
+        Box::new(ApiKeysRepoMemory::new(
+            db_conn.store(),
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, ApiKey>>,
+        )) as Box<ApiKeysRepo>
+    }
+
+    fn create_audit_log_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<AuditLogRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(AuditLogRepoMemory::new(db_conn.store(), acl)) as Box<AuditLogRepo>
+    }
+
+    fn create_audit_log_repo_with_sys_acl<'a>(&self, db_conn: &'a MemoryConnection) -> Box<AuditLogRepo + 'a> {
+        Box::new(AuditLogRepoMemory::new(
+            db_conn.store(),
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>,
+        )) as Box<AuditLogRepo>
+    }
+
+    fn create_companies_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<CompaniesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        let all_countries = self.get_countries(db_conn, user_id);
+        Box::new(CompaniesRepoMemory::new(db_conn.store(), acl, all_countries)) as Box<CompaniesRepo>
+    }
+
+    fn create_companies_packages_repo<'a>(
+        &self,
+        db_conn: &'a MemoryConnection,
+        user_id: Option<UserId>,
+    ) -> Box<CompaniesPackagesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        let all_countries = self.get_countries(db_conn, user_id);
+        Box::new(CompaniesPackagesRepoMemory::new(db_conn.store(), acl, all_countries)) as Box<CompaniesPackagesRepo>
+    }
+
+    fn create_countries_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<CountriesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(CountriesRepoMemory::new(db_conn.store(), acl)) as Box<CountriesRepo>
+    }
+
+    fn create_outbox_repo_with_sys_acl<'a>(&self, db_conn: &'a MemoryConnection) -> Box<OutboxRepo + 'a> {
+        Box::new(OutboxRepoMemory::new(
+            db_conn.store(),
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, OutboxEvent>>,
+        )) as Box<OutboxRepo>
+    }
+
+    fn create_products_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<ProductsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        let all_countries = self.get_countries(db_conn, user_id);
+        Box::new(ProductsRepoMemory::new(db_conn.store(), acl, all_countries)) as Box<ProductsRepo>
+    }
+
+    fn create_packages_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<PackagesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        let all_countries = self.get_countries(db_conn, user_id);
+        Box::new(PackagesRepoMemory::new(db_conn.store(), acl, all_countries)) as Box<PackagesRepo>
+    }
+
+    fn create_pickups_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<PickupsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(PickupsRepoMemory::new(db_conn.store(), acl)) as Box<PickupsRepo>
+    }
+
+    fn create_shipping_rates_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<ShippingRatesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ShippingRatesRepoMemory::new(db_conn.store(), acl)) as Box<ShippingRatesRepo>
+    }
+
+    fn create_users_addresses_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<UserAddressesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(UserAddressesRepoMemory::new(db_conn.store(), acl)) as Box<UserAddressesRepo>
+    }
+
+    fn create_user_data_erasures_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<UserDataErasuresRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(UserDataErasuresRepoMemory::new(db_conn.store(), acl)) as Box<UserDataErasuresRepo>
+    }
+
+    fn create_user_roles_repo_with_sys_acl<'a>(&self, db_conn: &'a MemoryConnection) -> Box<UserRolesRepo + 'a> {
+        Box::new(UserRolesRepoMemory::new(
+            db_conn.store(),
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, UserRole>>,
+        )) as Box<UserRolesRepo>
+    }
+
+    fn create_user_roles_repo<'a>(&self, db_conn: &'a MemoryConnection, user_id: Option<UserId>) -> Box<UserRolesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(UserRolesRepoMemory::new(db_conn.store(), acl)) as Box<UserRolesRepo>
+    }
+
+    fn create_webhook_deliveries_repo<'a>(
+        &self,
+        db_conn: &'a MemoryConnection,
+        user_id: Option<UserId>,
+    ) -> Box<WebhookDeliveriesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(WebhookDeliveriesRepoMemory::new(db_conn.store(), acl)) as Box<WebhookDeliveriesRepo>
+    }
+
+    fn create_webhook_deliveries_repo_with_sys_acl<'a>(&self, db_conn: &'a MemoryConnection) -> Box<WebhookDeliveriesRepo + 'a> {
+        Box::new(WebhookDeliveriesRepoMemory::new(
+            db_conn.store(),
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>,
+        )) as Box<WebhookDeliveriesRepo>
+    }
+
+    fn create_webhook_subscriptions_repo<'a>(
+        &self,
+        db_conn: &'a MemoryConnection,
+        user_id: Option<UserId>,
+    ) -> Box<WebhookSubscriptionsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(WebhookSubscriptionsRepoMemory::new(db_conn.store(), acl)) as Box<WebhookSubscriptionsRepo>
+    }
+
+    fn create_webhook_subscriptions_repo_with_sys_acl<'a>(&self, db_conn: &'a MemoryConnection) -> Box<WebhookSubscriptionsRepo + 'a> {
+        Box::new(WebhookSubscriptionsRepoMemory::new(
+            db_conn.store(),
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, WebhookSubscription>>,
+        )) as Box<WebhookSubscriptionsRepo>
+    }
+
+    fn invalidate_user_roles(&self, _user_id: UserId) {}
+
+    fn invalidate_countries(&self) {}
+
+    fn invalidate_shipping_rates(&self, _company_package_id: CompanyPackageId, _delivery_from: &Alpha3, _delivery_to: &Alpha3) {}
+
+    fn with_api_key_permissions(&self, permissions: Vec<Permission>) -> Self {
+        Self {
+            api_key_permissions: Some(Arc::new(permissions)),
+            ..self.clone()
+        }
+    }
+
+    fn with_user_roles(&self, user_id: UserId, user_roles: Vec<UserRole>) -> Self {
+        Self {
+            user_roles: Some((user_id, Arc::new(user_roles))),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use diesel::Connection;
+    use failure::Error as FailureError;
+    use serde_json;
+
+    use stq_static_resources::Currency;
+    use stq_types::*;
+
+    use errors::Error;
+    use models::*;
+    use repos::memory::{MemoryConnection, MemoryFixtures, MemoryReposFactory, MemoryStore};
+    use repos::*;
+
+    fn create_store() -> MemoryStore {
+        let fixtures: MemoryFixtures = serde_json::from_str(
+            r#"{
+                "countries": [
+                    { "label": "All", "level": 0, "alpha2": "", "alpha3": "XAL", "numeric": 0, "parent": null },
+                    { "label": "Russian Federation", "level": 1, "alpha2": "RU", "alpha3": "RUS", "numeric": 643, "parent": "XAL" },
+                    { "label": "United States", "level": 1, "alpha2": "US", "alpha3": "USA", "numeric": 840, "parent": "XAL" }
+                ],
+                "roles": [
+                    { "id": "5e32a9d6-0bc6-4d8e-9f6a-0b8c9a3c1f01", "user_id": 1, "name": "superuser", "data": null }
+                ]
+            }"#,
+        )
+        .unwrap();
+        MemoryStore::new(fixtures)
+    }
+
+    fn new_company() -> NewCompany {
+        NewCompany {
+            name: "UPS".to_string(),
+            label: "UPS".to_string(),
+            description: None,
+            deliveries_from: vec![Alpha3("RUS".to_string())],
+            logo: "".to_string(),
+            currency: Currency::USD,
+        }
+    }
+
+    #[test]
+    fn test_acl_is_enforced() {
+        let factory = MemoryReposFactory::new(Arc::new(AclPolicy::default()));
+        let conn = MemoryConnection::new(create_store());
+
+        assert!(factory.create_companies_repo(&conn, None).create(new_company()).is_err());
+        assert!(factory.create_companies_repo(&conn, Some(UserId(2))).create(new_company()).is_err());
+
+        let company = factory.create_companies_repo(&conn, Some(UserId(1))).create(new_company()).unwrap();
+        assert_eq!(company.deliveries_from[0].alpha3, Alpha3("RUS".to_string()));
+        assert_eq!(factory.create_companies_repo(&conn, None).list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_resolved_roles_are_used_instead_of_stored() {
+        let factory = MemoryReposFactory::new(Arc::new(AclPolicy::default()));
+        let conn = MemoryConnection::new(create_store());
+
+        let superuser: UserRole =
+            serde_json::from_str(r#"{ "id": "5e32a9d6-0bc6-4d8e-9f6a-0b8c9a3c1f02", "user_id": 2, "name": "superuser", "data": null }"#)
+                .unwrap();
+        let factory_of_second = factory.with_user_roles(UserId(2), vec![superuser]);
+        assert!(factory_of_second
+            .create_companies_repo(&conn, Some(UserId(2)))
+            .create(new_company())
+            .is_ok());
+        // roles of other users are still loaded from the store
+        assert!(factory_of_second
+            .create_companies_repo(&conn, Some(UserId(1)))
+            .create(new_company())
+            .is_ok());
+
+        let factory_of_first = factory.with_user_roles(UserId(1), vec![]);
+        assert!(factory_of_first
+            .create_companies_repo(&conn, Some(UserId(1)))
+            .create(new_company())
+            .is_err());
+    }
+
+    #[test]
+    fn test_unknown_country_is_rejected() {
+        let factory = MemoryReposFactory::new(Arc::new(AclPolicy::default()));
+        let conn = MemoryConnection::new(create_store());
+
+        let mut payload = new_company();
+        payload.deliveries_from = vec![Alpha3("ZZZ".to_string())];
+        assert!(factory.create_companies_repo(&conn, Some(UserId(1))).create(payload).is_err());
+        assert!(conn.store().lock().companies.is_empty());
+        assert!(conn.store().lock().companies_deliveries_from.is_empty());
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let factory = MemoryReposFactory::new(Arc::new(AclPolicy::default()));
+        let conn = MemoryConnection::new(create_store());
+
+        let result: Result<(), FailureError> = conn.transaction(|| {
+            factory.create_companies_repo(&conn, Some(UserId(1))).create(new_company())?;
+            Err(Error::NotFound.into())
+        });
+        assert!(result.is_err());
+        assert!(conn.store().lock().companies.is_empty());
+
+        conn.transaction::<_, FailureError, _>(|| factory.create_companies_repo(&conn, Some(UserId(1))).create(new_company()))
+            .unwrap();
+        assert_eq!(conn.store().lock().companies.len(), 1);
+    }
+
+    fn new_package(name: &str, deliveries_to: &[&str]) -> NewPackages {
+        NewPackages {
+            name: name.to_string(),
+            max_size: 0,
+            min_size: 0,
+            max_weight: 0,
+            min_weight: 0,
+            deliveries_to: deliveries_to.iter().map(|code| Alpha3(code.to_string())).collect(),
+        }
+    }
+
+    fn is_not_unique(e: &FailureError) -> bool {
+        e.causes().any(|cause| match cause.downcast_ref::<Error>() {
+            Some(Error::Validate(errors)) => serde_json::to_value(errors).map_or(false, |errors| errors["repo"][0]["code"] == "not_unique"),
+            _ => false,
+        })
+    }
+
+    #[test]
+    fn test_rollback_keeps_writes_of_other_threads() {
+        let factory = MemoryReposFactory::new(Arc::new(AclPolicy::default()));
+        let store = create_store();
+        let conn = MemoryConnection::new(store.clone());
+
+        let mut concurrent = None;
+        let result: Result<(), FailureError> = conn.transaction(|| {
+            factory.create_companies_repo(&conn, Some(UserId(1))).create(new_company())?;
+            let factory = factory.clone();
+            let store = store.clone();
+            // the thread waits for the transaction to finish before it writes
+            concurrent = Some(thread::spawn(move || {
+                let conn = MemoryConnection::new(store);
+                factory
+                    .create_companies_repo(&conn, Some(UserId(1)))
+                    .create(new_company())
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }));
+            Err(Error::NotFound.into())
+        });
+        assert!(result.is_err());
+
+        concurrent.unwrap().join().unwrap().unwrap();
+        assert_eq!(store.lock().companies.len(), 1);
+    }
+
+    #[test]
+    fn test_deliveries_filters_and_ordering() {
+        let factory = MemoryReposFactory::new(Arc::new(AclPolicy::default()));
+        let conn = MemoryConnection::new(create_store());
+        let packages_repo = factory.create_packages_repo(&conn, Some(UserId(1)));
+        let companies_repo = factory.create_companies_repo(&conn, Some(UserId(1)));
+
+        let to_usa = packages_repo.create(new_package("to usa", &["USA"])).unwrap();
+        let to_both = packages_repo.create(new_package("to both", &["USA", "RUS", "USA"])).unwrap();
+        let to_russia = packages_repo.create(new_package("to russia", &["RUS"])).unwrap();
+
+        let ids = |packages: Vec<Packages>| packages.into_iter().map(|package| package.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(packages_repo.find_deliveries_to(vec![Alpha3("RUS".to_string())]).unwrap()),
+            vec![to_both.id, to_russia.id]
+        );
+        assert_eq!(
+            ids(packages_repo.find_deliveries_to(vec![Alpha3("USA".to_string())]).unwrap()),
+            vec![to_usa.id, to_both.id]
+        );
+        assert!(packages_repo
+            .find_deliveries_to(vec![Alpha3("XAL".to_string())])
+            .unwrap()
+            .is_empty());
+        assert_eq!(ids(packages_repo.list().unwrap()), vec![to_usa.id, to_both.id, to_russia.id]);
+        // duplicates are collapsed like with the primary key of the table
+        assert_eq!(
+            conn.store().lock().package_deliveries_to(to_both.id),
+            vec![Alpha3("RUS".to_string()), Alpha3("USA".to_string())]
+        );
+
+        let mut from_usa = new_company();
+        from_usa.deliveries_from = vec![Alpha3("USA".to_string())];
+        let from_usa = companies_repo.create(from_usa).unwrap();
+        let from_russia = companies_repo.create(new_company()).unwrap();
+        let found = companies_repo.find_deliveries_from(Alpha3("RUS".to_string())).unwrap();
+        assert_eq!(
+            found.into_iter().map(|company| company.id).collect::<Vec<_>>(),
+            vec![from_russia.id]
+        );
+        let listed = companies_repo.list().unwrap();
+        assert_eq!(
+            listed.into_iter().map(|company| company.id).collect::<Vec<_>>(),
+            vec![from_usa.id, from_russia.id]
+        );
+    }
+
+    #[test]
+    fn test_products_find_available_to() {
+        let factory = MemoryReposFactory::new(Arc::new(AclPolicy::default()));
+        let conn = MemoryConnection::new(create_store());
+        let company = factory.create_companies_repo(&conn, Some(UserId(1))).create(new_company()).unwrap();
+        let package = factory
+            .create_packages_repo(&conn, Some(UserId(1)))
+            .create(new_package("to both", &["RUS", "USA"]))
+            .unwrap();
+        let company_package = factory
+            .create_companies_packages_repo(&conn, Some(UserId(1)))
+            .create(NewCompanyPackage {
+                company_id: company.id,
+                package_id: package.id,
+                shipping_rate_source: None,
+            })
+            .unwrap();
+
+        let new_product = |deliveries_to: &[&str]| NewProducts {
+            base_product_id: BaseProductId(1),
+            store_id: StoreId(1),
+            company_package_id: company_package.id,
+            price: None,
+            deliveries_to: deliveries_to.iter().map(|code| Alpha3(code.to_string())).collect(),
+            shipping: ShippingVariant::Local,
+            measurements: None,
+            delivery_from: None,
+            currency: Currency::USD,
+        };
+        let products_repo = factory.create_products_repo(&conn, Some(UserId(1)));
+        assert!(products_repo
+            .create_many(vec![new_product(&["USA"]), new_product(&["ZZZ"])])
+            .is_err());
+        let products = products_repo.create_many(vec![new_product(&["USA", "RUS", "USA"])]).unwrap();
+        assert_eq!(
+            products[0].deliveries_to,
+            vec![Alpha3("RUS".to_string()), Alpha3("USA".to_string())]
+        );
+
+        let found = products_repo
+            .find_available_to(BaseProductId(1), Alpha3("USA".to_string()))
+            .unwrap();
+        assert_eq!(
+            found.into_iter().map(|package| package.shipping_id).collect::<Vec<_>>(),
+            vec![products[0].id]
+        );
+        assert!(products_repo
+            .find_available_to(BaseProductId(1), Alpha3("XAL".to_string()))
+            .unwrap()
+            .is_empty());
+        assert!(products_repo
+            .find_available_to(BaseProductId(2), Alpha3("USA".to_string()))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unique_violations_are_validation_errors() {
+        let factory = MemoryReposFactory::new(Arc::new(AclPolicy::default()));
+        let conn = MemoryConnection::new(create_store());
+
+        let country = NewCountry {
+            label: CountryLabel("Russia".to_string()),
+            level: 1,
+            alpha2: Alpha2("RU".to_string()),
+            alpha3: Alpha3("RUS".to_string()),
+            numeric: 643,
+            parent: Some(Alpha3("XAL".to_string())),
+        };
+        let e = factory.create_countries_repo(&conn, Some(UserId(1))).create(country).unwrap_err();
+        assert!(is_not_unique(&e));
+
+        let role: NewUserRole =
+            serde_json::from_str(r#"{ "id": "5e32a9d6-0bc6-4d8e-9f6a-0b8c9a3c1f01", "user_id": 1, "name": "superuser", "data": null }"#)
+                .unwrap();
+        let e = factory.create_user_roles_repo(&conn, Some(UserId(1))).create(role).unwrap_err();
+        assert!(is_not_unique(&e));
+        assert_eq!(conn.store().lock().roles.len(), 1);
+    }
+
+    #[test]
+    fn test_fixtures_ids() {
+        let fixtures: MemoryFixtures = serde_json::from_str(include_str!("../../../config/memory_fixtures.json")).unwrap();
+        let store = MemoryStore::new(fixtures);
+        let mut tables = store.lock();
+        assert_eq!(tables.shipping_rates[0].id.0, 4);
+        assert_eq!(tables.next_id(), 5);
+    }
+}
@@ -0,0 +1,81 @@
+//! In-memory backend of the repos, selected with `repo_backend = "memory"` in server config.
+//! The store is seeded from fixtures and lives only as long as the process, so the service
+//! can be started without Postgres, e.g. by frontend developers and contract tests.
+//! Transactions are emulated with snapshots and serialized, see `connection`.
+
+pub mod api_keys;
+pub mod audit_log;
+pub mod companies;
+pub mod companies_packages;
+pub mod connection;
+pub mod countries;
+pub mod factory;
+pub mod outbox;
+pub mod packages;
+pub mod pickups;
+pub mod products;
+pub mod shipping_rates;
+pub mod store;
+pub mod user_addresses;
+pub mod user_data_erasures;
+pub mod user_roles;
+pub mod webhook_deliveries;
+pub mod webhook_subscriptions;
+
+pub use self::connection::{MemoryConnection, MemoryConnectionManager};
+pub use self::factory::MemoryReposFactory;
+pub use self::store::{MemoryFixtures, MemoryStore, Tables};
+
+#[cfg(test)]
+pub mod tests {
+    extern crate stq_http;
+
+    use std::sync::Arc;
+
+    use futures::Stream;
+    use futures_cpupool::CpuPool;
+    use r2d2;
+    use tokio_core::reactor::Handle;
+
+    use stq_types::UserId;
+
+    use super::{MemoryConnection, MemoryConnectionManager, MemoryReposFactory, MemoryStore};
+    use config::Config;
+    use controller::auth::JwtAuthenticator;
+    use controller::context::{DynamicContext, StaticContext};
+    use repos::AclPolicy;
+    use services::Service;
+
+    /// Creates service working with the store, like the server with `repo_backend = "memory"`
+    pub fn create_memory_service(
+        user_id: Option<UserId>,
+        handle: Arc<Handle>,
+        store: MemoryStore,
+    ) -> Service<MemoryConnection, MemoryConnectionManager, MemoryReposFactory> {
+        let db_pool = r2d2::Pool::builder()
+            .max_size(1)
+            .build(MemoryConnectionManager::new(store))
+            .expect("Failed to create in-memory connection pool");
+        let cpu_pool = CpuPool::new(1);
+
+        let config = Config::new().unwrap();
+        let client = stq_http::client::Client::new(&config.to_http_config(), &handle);
+        let client_handle = client.handle();
+        let client_stream = client.stream();
+        handle.spawn(client_stream.for_each(|_| Ok(())));
+        let authenticator = JwtAuthenticator::from_config(config.jwt.as_ref()).unwrap();
+        let acl_policy = Arc::new(AclPolicy::default());
+        let static_context = StaticContext::new(
+            db_pool,
+            cpu_pool,
+            client_handle,
+            Arc::new(config),
+            MemoryReposFactory::new(acl_policy.clone()),
+            authenticator,
+            acl_policy,
+        );
+        let dynamic_context = DynamicContext::new(user_id, String::default());
+
+        Service::new(static_context, dynamic_context)
+    }
+}
@@ -0,0 +1,216 @@
+//! In-memory outbox repo
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::authorization::{Action, Resource, Scope};
+use models::{NewOutboxEvent, OutboxEndpointDelivery, OutboxEvent, UserRole};
+use repos::acl;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::outbox::OutboxRepo;
+use repos::types::RepoResult;
+
+use super::store::MemoryStore;
+
+pub struct OutboxRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, OutboxEvent>>,
+}
+
+impl<'a> OutboxRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, OutboxEvent>>) -> Self {
+        Self { store, acl }
+    }
+
+    fn update<F: FnOnce(&mut OutboxEvent)>(&self, id_arg: i32, f: F) -> RepoResult<OutboxEvent> {
+        let mut tables = self.store.lock();
+        let event = tables
+            .outbox_events
+            .iter_mut()
+            .find(|event| event.id == id_arg)
+            .ok_or_else(|| FailureError::from(Error::NotFound))?;
+        f(event);
+        Ok(event.clone())
+    }
+}
+
+impl<'a> OutboxRepo for OutboxRepoMemory<'a> {
+    fn create(&self, payload: NewOutboxEvent) -> RepoResult<OutboxEvent> {
+        debug!("create new outbox event {:?}.", payload);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Create, self, None)
+            .map(|_| {
+                let mut tables = self.store.lock();
+                let now = SystemTime::now();
+                let event = OutboxEvent {
+                    id: tables.next_id(),
+                    event_type: payload.event_type.clone(),
+                    payload: payload.payload.clone(),
+                    correlation_token: payload.correlation_token.clone(),
+                    created_at: now,
+                    attempts: 0,
+                    next_attempt_at: now,
+                    last_error: None,
+                    sent_at: None,
+                    webhooks_created_at: None,
+                };
+                tables.outbox_events.push(event.clone());
+                event
+            })
+            .map_err(|e: FailureError| e.context(format!("Create a new outbox event {:?} error occurred", payload)).into())
+    }
+
+    fn list_pending(&self, limit: i64) -> RepoResult<Vec<OutboxEvent>> {
+        debug!("list pending outbox events, limit: {}.", limit);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Read, self, None)
+            .map(|_| {
+                let mut events = self
+                    .store
+                    .lock()
+                    .outbox_events
+                    .iter()
+                    .filter(|event| event.sent_at.is_none())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                events.sort_by_key(|event| event.id);
+                events.truncate(limit.max(0) as usize);
+                events
+            })
+            .map_err(|e: FailureError| e.context("List pending outbox events error occurred").into())
+    }
+
+    fn mark_sent(&self, id_arg: i32) -> RepoResult<OutboxEvent> {
+        debug!("mark outbox event {} as sent.", id_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .and_then(|_| {
+                self.update(id_arg, |event| {
+                    event.sent_at = Some(SystemTime::now());
+                    event.attempts += 1;
+                    event.last_error = None;
+                })
+            })
+            .map_err(|e: FailureError| e.context(format!("Mark outbox event {} as sent error occurred", id_arg)).into())
+    }
+
+    fn mark_failed(&self, id_arg: i32, error: String, next_attempt_at_arg: SystemTime) -> RepoResult<OutboxEvent> {
+        debug!("mark outbox event {} as failed: {}.", id_arg, error);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .and_then(|_| {
+                self.update(id_arg, |event| {
+                    event.attempts += 1;
+                    event.last_error = Some(error);
+                    event.next_attempt_at = next_attempt_at_arg;
+                })
+            })
+            .map_err(|e: FailureError| e.context(format!("Mark outbox event {} as failed error occurred", id_arg)).into())
+    }
+
+    fn list_pending_webhooks(&self, limit: i64) -> RepoResult<Vec<OutboxEvent>> {
+        debug!("list outbox events pending webhook deliveries, limit: {}.", limit);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Read, self, None)
+            .map(|_| {
+                let mut events = self
+                    .store
+                    .lock()
+                    .outbox_events
+                    .iter()
+                    .filter(|event| event.webhooks_created_at.is_none())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                events.sort_by_key(|event| event.id);
+                events.truncate(limit.max(0) as usize);
+                events
+            })
+            .map_err(|e: FailureError| e.context("List outbox events pending webhook deliveries error occurred").into())
+    }
+
+    fn mark_webhooks_created(&self, id_arg: i32) -> RepoResult<OutboxEvent> {
+        debug!("mark webhook deliveries of outbox event {} as created.", id_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .and_then(|_| self.update(id_arg, |event| event.webhooks_created_at = Some(SystemTime::now())))
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Mark webhook deliveries of outbox event {} as created error occurred",
+                    id_arg
+                ))
+                .into()
+            })
+    }
+
+    fn list_delivered_endpoints(&self, id_arg: i32) -> RepoResult<Vec<String>> {
+        debug!("list endpoints outbox event {} has been delivered to.", id_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Read, self, None)
+            .map(|_| {
+                self.store
+                    .lock()
+                    .outbox_endpoint_deliveries
+                    .iter()
+                    .filter(|delivery| delivery.outbox_event_id == id_arg)
+                    .map(|delivery| delivery.endpoint.clone())
+                    .collect()
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "List endpoints outbox event {} has been delivered to error occurred",
+                    id_arg
+                ))
+                .into()
+            })
+    }
+
+    fn mark_endpoint_delivered(&self, id_arg: i32, endpoint_arg: String) -> RepoResult<()> {
+        debug!("mark outbox event {} as delivered to {}.", id_arg, endpoint_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .map(|_| {
+                let mut tables = self.store.lock();
+                let is_delivered = tables
+                    .outbox_endpoint_deliveries
+                    .iter()
+                    .any(|delivery| delivery.outbox_event_id == id_arg && delivery.endpoint == endpoint_arg);
+                if !is_delivered {
+                    tables.outbox_endpoint_deliveries.push(OutboxEndpointDelivery {
+                        outbox_event_id: id_arg,
+                        endpoint: endpoint_arg.clone(),
+                        delivered_at: SystemTime::now(),
+                    });
+                }
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Mark outbox event {} as delivered to {} error occurred",
+                    id_arg, endpoint_arg
+                ))
+                .into()
+            })
+    }
+
+    fn acquire_lease(&self, holder_arg: &str, expires_at_arg: SystemTime) -> RepoResult<bool> {
+        debug!("acquire outbox lease for {} until {:?}.", holder_arg, expires_at_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .map(|_| {
+                let mut tables = self.store.lock();
+                let available = match tables.outbox_lease {
+                    Some((ref holder, expires_at)) => holder == holder_arg || expires_at < SystemTime::now(),
+                    None => true,
+                };
+                if available {
+                    tables.outbox_lease = Some((holder_arg.to_string(), expires_at_arg));
+                }
+                available
+            })
+            .map_err(|e: FailureError| e.context(format!("Acquire outbox lease for {} error occurred", holder_arg)).into())
+    }
+}
+
+impl<'a> CheckScope<Scope, OutboxEvent> for OutboxRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&OutboxEvent>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
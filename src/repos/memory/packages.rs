@@ -0,0 +1,185 @@
+//! In-memory packages repo
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::{Alpha3, PackageId, UserId};
+
+use errors::Error;
+use models::authorization::*;
+use models::countries::Country;
+use models::packages::{NewPackages, Packages, PackagesRaw, UpdatePackages};
+use models::roles::UserRole;
+use repos::acl;
+use repos::countries::check_country_codes;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::packages::PackagesRepo;
+use repos::types::RepoResult;
+
+use super::store::{remove_where, MemoryStore};
+
+pub struct PackagesRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Packages>>,
+    pub countries: Country,
+}
+
+impl<'a> PackagesRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, Packages>>, countries: Country) -> Self {
+        Self { store, acl, countries }
+    }
+
+    fn read_all<P: Fn(&PackagesRaw) -> bool>(&self, predicate: P) -> RepoResult<Vec<Packages>> {
+        let tables = self.store.lock();
+        let mut raws = tables
+            .packages
+            .iter()
+            .filter(|package| predicate(package))
+            .map(|package| (package.clone(), tables.package_deliveries_to(package.id)))
+            .collect::<Vec<_>>();
+        drop(tables);
+        raws.sort_by_key(|(package, _)| package.id.0);
+
+        let results = raws
+            .into_iter()
+            .map(|(raw, used_codes)| raw.to_packages(&used_codes, &self.countries))
+            .collect::<Vec<Packages>>();
+        for package in &results {
+            acl::check(&*self.acl, Resource::Packages, Action::Read, self, Some(&package))?;
+        }
+        Ok(results)
+    }
+}
+
+impl<'a> PackagesRepo for PackagesRepoMemory<'a> {
+    fn create(&self, payload: NewPackages) -> RepoResult<Packages> {
+        debug!("create new packages_ {:?}.", payload);
+        let (payload, deliveries_to) = payload.to_raw();
+        check_country_codes(&self.countries, &deliveries_to, "deliveries_to")?;
+
+        let id = PackageId(self.store.lock().next_id());
+        let raw = PackagesRaw {
+            id,
+            name: payload.name.clone(),
+            max_size: payload.max_size,
+            min_size: payload.min_size,
+            max_weight: payload.max_weight,
+            min_weight: payload.min_weight,
+        };
+        let packages_ = raw.clone().to_packages(&deliveries_to, &self.countries);
+
+        acl::check(&*self.acl, Resource::Packages, Action::Create, self, Some(&packages_))
+            .map(|_| {
+                let mut tables = self.store.lock();
+                tables.packages.push(raw);
+                tables.set_package_deliveries_to(id, deliveries_to);
+                packages_
+            })
+            .map_err(|e: FailureError| e.context(format!("create new packages_ {:?}.", payload)).into())
+    }
+
+    fn find_deliveries_to(&self, countries: Vec<Alpha3>) -> RepoResult<Vec<Packages>> {
+        debug!("Find in packages with country {:?}.", countries);
+
+        let package_ids = self
+            .store
+            .lock()
+            .packages_deliveries_to
+            .iter()
+            .filter(|row| countries.contains(&row.country_alpha3))
+            .map(|row| row.package_id)
+            .collect::<Vec<_>>();
+
+        self.read_all(|package| package_ids.contains(&package.id))
+            .map_err(|e: FailureError| {
+                e.context(format!("Find in packages with country {:?} error occured", countries))
+                    .into()
+            })
+    }
+
+    fn list(&self) -> RepoResult<Vec<Packages>> {
+        debug!("List packages");
+
+        self.read_all(|_| true)
+            .map_err(|e: FailureError| e.context("Find in packages error occured").into())
+    }
+
+    fn find(&self, id_arg: PackageId) -> RepoResult<Option<Packages>> {
+        debug!("Find in package with id {}.", id_arg);
+
+        self.read_all(|package| package.id == id_arg)
+            .map(|packages| packages.into_iter().next())
+            .map_err(|e: FailureError| e.context(format!("Find package with id: {} error occured", id_arg)).into())
+    }
+
+    fn update(&self, id_arg: PackageId, payload: UpdatePackages) -> RepoResult<Packages> {
+        debug!("Updating packages_ payload {:?}.", payload);
+        let (payload, deliveries_to) = payload.to_raw();
+        if let Some(ref deliveries_to) = deliveries_to {
+            check_country_codes(&self.countries, deliveries_to, "deliveries_to")?;
+        }
+
+        self.read_all(|package| package.id == id_arg)
+            .and_then(|packages| packages.into_iter().next().ok_or_else(|| Error::NotFound.into()))
+            .and_then(|packages_: Packages| acl::check(&*self.acl, Resource::Packages, Action::Update, self, Some(&packages_)))
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                let raw = {
+                    let raw = tables
+                        .packages
+                        .iter_mut()
+                        .find(|package| package.id == id_arg)
+                        .ok_or_else(|| FailureError::from(Error::NotFound))?;
+                    if let Some(ref name) = payload.name {
+                        raw.name = name.clone();
+                    }
+                    if let Some(max_size) = payload.max_size {
+                        raw.max_size = max_size;
+                    }
+                    if let Some(min_size) = payload.min_size {
+                        raw.min_size = min_size;
+                    }
+                    if let Some(max_weight) = payload.max_weight {
+                        raw.max_weight = max_weight;
+                    }
+                    if let Some(min_weight) = payload.min_weight {
+                        raw.min_weight = min_weight;
+                    }
+                    raw.clone()
+                };
+                if let Some(deliveries_to) = deliveries_to {
+                    tables.set_package_deliveries_to(id_arg, deliveries_to);
+                }
+                Ok(raw.to_packages(&tables.package_deliveries_to(id_arg), &self.countries))
+            })
+            .map_err(|e: FailureError| e.context(format!("Updating packages payload {:?} failed.", payload)).into())
+    }
+
+    fn delete(&self, id_arg: PackageId) -> RepoResult<Packages> {
+        debug!("delete packages_ id: {}.", id_arg);
+
+        acl::check(&*self.acl, Resource::Packages, Action::Delete, self, None)?;
+
+        let mut tables = self.store.lock();
+        remove_where(&mut tables.packages, |package| package.id == id_arg)
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::NotFound.into())
+            .map(|raw| {
+                let used_codes = tables.package_deliveries_to(id_arg);
+                remove_where(&mut tables.packages_deliveries_to, |row| row.package_id == id_arg);
+                tables.delete_companies_packages(|company_package| company_package.package_id == id_arg);
+                raw.to_packages(&used_codes, &self.countries)
+            })
+            .map_err(move |e: FailureError| e.context(format!("delete packages id: {}.", id_arg)).into())
+    }
+}
+
+impl<'a> CheckScope<Scope, Packages> for PackagesRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&Packages>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! In-memory pickups repo
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::{BaseProductId, UserId};
+
+use errors::Error;
+use models::authorization::*;
+use models::pickups::{NewPickups, Pickups, UpdatePickups};
+use models::roles::{owns_store, UserRole};
+use repos::acl;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::pickups::PickupsRepo;
+use repos::types::RepoResult;
+
+use super::store::{remove_where, MemoryStore};
+
+pub struct PickupsRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Pickups>>,
+}
+
+impl<'a> PickupsRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, Pickups>>) -> Self {
+        Self { store, acl }
+    }
+
+    fn find(&self, base_product_id_arg: BaseProductId) -> Option<Pickups> {
+        self.store
+            .lock()
+            .pickups
+            .iter()
+            .find(|pickup| pickup.base_product_id == base_product_id_arg)
+            .cloned()
+    }
+}
+
+impl<'a> PickupsRepo for PickupsRepoMemory<'a> {
+    fn create(&self, payload: NewPickups) -> RepoResult<Pickups> {
+        debug!("create new pickups {:?}.", payload);
+
+        let record = Pickups {
+            id: 0,
+            base_product_id: payload.base_product_id,
+            store_id: payload.store_id,
+            pickup: payload.pickup,
+            price: payload.price,
+        };
+
+        acl::check(&*self.acl, Resource::Pickups, Action::Create, self, Some(&record))
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                if tables.pickups.iter().any(|pickup| pickup.base_product_id == record.base_product_id) {
+                    return Err(Error::not_unique("pickups of the base product already exist").into());
+                }
+                let record = Pickups {
+                    id: tables.next_id(),
+                    ..record
+                };
+                tables.pickups.push(record.clone());
+                Ok(record)
+            })
+            .map_err(|e: FailureError| e.context(format!("create new pickups {:?}.", payload)).into())
+    }
+
+    fn list(&self) -> RepoResult<Vec<Pickups>> {
+        debug!("List pickups");
+
+        let mut results = self.store.lock().pickups.clone();
+        results.sort_by_key(|pickup| pickup.id);
+        for result in &results {
+            acl::check(&*self.acl, Resource::Pickups, Action::Read, self, Some(&result))
+                .map_err(|e: FailureError| e.context("Find in pickups error occured"))?;
+        }
+        Ok(results)
+    }
+
+    fn get(&self, base_product_id_arg: BaseProductId) -> RepoResult<Option<Pickups>> {
+        debug!("Getting pickups by base_product_id {}", base_product_id_arg);
+
+        let result = self.find(base_product_id_arg);
+        if let Some(ref result) = result {
+            acl::check(&*self.acl, Resource::Pickups, Action::Read, self, Some(result))
+                .map_err(|e: FailureError| e.context(format!("Getting pickups by base_product_id {}", base_product_id_arg)))?;
+        }
+        Ok(result)
+    }
+
+    fn update(&self, base_product_id_arg: BaseProductId, payload: UpdatePickups) -> RepoResult<Pickups> {
+        debug!("Updating pickups payload {:?}.", payload);
+
+        self.find(base_product_id_arg)
+            .ok_or_else(|| Error::NotFound.into())
+            .and_then(|pickup_: Pickups| acl::check(&*self.acl, Resource::Pickups, Action::Update, self, Some(&pickup_)))
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                let pickup_ = tables
+                    .pickups
+                    .iter_mut()
+                    .find(|pickup| pickup.base_product_id == base_product_id_arg)
+                    .ok_or_else(|| FailureError::from(Error::NotFound))?;
+                let UpdatePickups { pickup, price } = payload.clone();
+                if let Some(pickup) = pickup {
+                    pickup_.pickup = pickup;
+                }
+                if price.is_some() {
+                    pickup_.price = price;
+                }
+                Ok(pickup_.clone())
+            })
+            .map_err(|e: FailureError| e.context(format!("Updating products payload {:?} failed.", payload)).into())
+    }
+
+    fn delete(&self, base_product_id_arg: BaseProductId) -> RepoResult<Option<Pickups>> {
+        debug!("delete pickups by base_product_id: {}.", base_product_id_arg);
+
+        let pickup_ = self.find(base_product_id_arg);
+        if let Some(ref pickup_) = pickup_ {
+            acl::check(&*self.acl, Resource::Pickups, Action::Delete, self, Some(pickup_))
+                .map_err(|e: FailureError| e.context(format!("delete pickups by base_product_id: {} failed", base_product_id_arg)))?;
+        }
+
+        Ok(remove_where(&mut self.store.lock().pickups, |pickup| {
+            pickup.base_product_id == base_product_id_arg
+        })
+        .into_iter()
+        .next())
+    }
+}
+
+impl<'a> CheckScope<Scope, Pickups> for PickupsRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&Pickups>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|obj| owns_store(user_roles, obj.store_id)).unwrap_or(false),
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! In-memory products repo
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::{Alpha3, BaseProductId, CompanyPackageId, ShippingId, UserId};
+
+use errors::Error;
+use models::authorization::*;
+use models::countries::Country;
+use models::{
+    owns_store, AvailablePackageForUser, CompaniesPackagesRaw, CompanyRaw, NewProducts, NewProductsRaw, PackagesRaw, Products, ProductsRaw,
+    ShippingVariant, UpdateProducts, UpdateProductsRaw, UserRole,
+};
+use repos::acl;
+use repos::countries::check_country_codes;
+use repos::get_company_package_name;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::products::{ProductsRepo, ProductsWithAvailableCountries};
+use repos::types::RepoResult;
+
+use super::store::MemoryStore;
+
+pub struct ProductsRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Products>>,
+    pub countries: Country,
+}
+
+impl<'a> ProductsRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, Products>>, countries: Country) -> Self {
+        Self { store, acl, countries }
+    }
+
+    /// Products matching the predicate joined with their company packages, companies and packages, ordered by company label
+    fn join<P: Fn(&ProductsRaw) -> bool>(&self, predicate: P) -> Vec<(ProductsRaw, CompaniesPackagesRaw, CompanyRaw, PackagesRaw)> {
+        let tables = self.store.lock();
+        let mut results = tables
+            .products
+            .iter()
+            .filter(|product| predicate(product))
+            .filter_map(|product| {
+                let company_package = tables
+                    .companies_packages
+                    .iter()
+                    .find(|company_package| company_package.id == product.company_package_id)?;
+                let company = tables.companies.iter().find(|company| company.id == company_package.company_id)?;
+                let package = tables.packages.iter().find(|package| package.id == company_package.package_id)?;
+                Some((product.clone(), company_package.clone(), company.clone(), package.clone()))
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|(_, _, company_a, _), (_, _, company_b, _)| company_a.label.cmp(&company_b.label));
+        results
+    }
+
+    /// Ids of the products delivered to the country
+    fn delivered_to(&self, country: &Alpha3) -> Vec<ShippingId> {
+        self.store
+            .lock()
+            .products_deliveries_to
+            .iter()
+            .filter(|row| row.country_alpha3 == *country)
+            .map(|row| row.product_id)
+            .collect()
+    }
+
+    fn to_products(&self, raws: Vec<ProductsRaw>) -> Vec<Products> {
+        let tables = self.store.lock();
+        raws.into_iter()
+            .map(|raw| {
+                let used_codes = tables.product_deliveries_to(raw.id);
+                raw.to_products(used_codes)
+            })
+            .collect()
+    }
+
+    fn insert(&self, payload: NewProductsRaw, deliveries_to: Vec<Alpha3>) -> RepoResult<Products> {
+        let raw = ProductsRaw {
+            id: ShippingId(self.store.lock().next_id()),
+            base_product_id: payload.base_product_id,
+            store_id: payload.store_id,
+            company_package_id: payload.company_package_id,
+            price: payload.price,
+            shipping: payload.shipping,
+            currency: payload.currency,
+        };
+
+        let product = raw.clone().to_products(deliveries_to.clone());
+        acl::check(&*self.acl, Resource::Products, Action::Create, self, Some(&product))?;
+
+        let mut tables = self.store.lock();
+        if !tables
+            .companies_packages
+            .iter()
+            .any(|company_package| company_package.id == raw.company_package_id)
+        {
+            return Err(Error::NotFound.into());
+        }
+        let id = raw.id;
+        tables.products.push(raw.clone());
+        tables.set_product_deliveries_to(id, deliveries_to);
+        Ok(raw.to_products(tables.product_deliveries_to(id)))
+    }
+}
+
+fn to_available_package(
+    (product_raw, companies_package, company_raw, package_raw): (ProductsRaw, CompaniesPackagesRaw, CompanyRaw, PackagesRaw),
+) -> AvailablePackageForUser {
+    AvailablePackageForUser {
+        id: companies_package.id,
+        shipping_id: product_raw.id,
+        name: get_company_package_name(&company_raw.label, &package_raw.name),
+        logo: company_raw.logo,
+        price: product_raw.price,
+        currency: product_raw.currency,
+        shipping_variant: product_raw.shipping,
+        store_id: product_raw.store_id,
+        base_product_id: product_raw.base_product_id,
+    }
+}
+
+impl<'a> ProductsRepo for ProductsRepoMemory<'a> {
+    fn create(&self, payload: NewProducts) -> RepoResult<Products> {
+        debug!("create new products {:?}.", payload);
+        let (payload, deliveries_to) = payload.to_raw();
+        check_country_codes(&self.countries, &deliveries_to, "deliveries_to")?;
+
+        self.insert(payload.clone(), deliveries_to)
+            .map_err(|e: FailureError| e.context(format!("create new products {:?}.", payload)).into())
+    }
+
+    fn create_many(&self, payload: Vec<NewProducts>) -> RepoResult<Vec<Products>> {
+        debug!("create many new products {:?}.", payload);
+        let payload = payload.into_iter().map(|v| v.to_raw()).collect::<Vec<_>>();
+        for (_, deliveries_to) in &payload {
+            check_country_codes(&self.countries, deliveries_to, "deliveries_to")?;
+        }
+
+        payload
+            .iter()
+            .cloned()
+            .map(|(product, deliveries_to)| self.insert(product, deliveries_to))
+            .collect::<RepoResult<Vec<Products>>>()
+            .map_err(|e: FailureError| e.context(format!("create many new products {:?}.", payload)).into())
+    }
+
+    fn get_by_base_product_id(&self, base_product_id_arg: BaseProductId) -> RepoResult<Vec<Products>> {
+        debug!("get products by base_product_id {:?}.", base_product_id_arg);
+
+        let mut products = self
+            .store
+            .lock()
+            .products
+            .iter()
+            .filter(|product| product.base_product_id == base_product_id_arg)
+            .cloned()
+            .collect::<Vec<_>>();
+        products.sort_by_key(|product| product.id.0);
+
+        self.to_products(products)
+            .into_iter()
+            .map(|product| {
+                acl::check(&*self.acl, Resource::Products, Action::Read, self, Some(&product))?;
+                Ok(product)
+            })
+            .collect::<RepoResult<Vec<Products>>>()
+            .map_err(|e: FailureError| {
+                e.context(format!("Getting products with base_product_id {:?} failed.", base_product_id_arg))
+                    .into()
+            })
+    }
+
+    fn get_products_countries(&self, base_product_id_arg: BaseProductId) -> RepoResult<Vec<ProductsWithAvailableCountries>> {
+        debug!(
+            "Find in available countries for delivery by base_product_id: {:?}.",
+            base_product_id_arg
+        );
+
+        let mut results = self.join(|product| product.base_product_id == base_product_id_arg);
+        results.sort_by_key(|(_, _, _, package_raw)| package_raw.id.0);
+
+        let tables = self.store.lock();
+        Ok(results
+            .into_iter()
+            .map(|(product_raw, _, _, package_raw)| {
+                let package_codes = tables.package_deliveries_to(package_raw.id);
+                let countries_codes = package_raw
+                    .to_packages(&package_codes, &self.countries)
+                    .deliveries_to
+                    .into_iter()
+                    .map(|c| c.alpha3)
+                    .collect();
+                let product_codes = tables.product_deliveries_to(product_raw.id);
+                ProductsWithAvailableCountries(product_raw.to_products(product_codes), countries_codes)
+            })
+            .collect())
+    }
+
+    fn find_available_to(&self, base_product_id_arg: BaseProductId, user_country: Alpha3) -> RepoResult<Vec<AvailablePackageForUser>> {
+        debug!(
+            "Find available product {} delivery to users country {}.",
+            base_product_id_arg, user_country
+        );
+
+        let product_ids = self.delivered_to(&user_country);
+        let available_packages = self
+            .join(|product| product.base_product_id == base_product_id_arg && product_ids.contains(&product.id))
+            .into_iter()
+            .map(to_available_package)
+            .collect::<Vec<_>>();
+
+        let local_package_ids = available_packages
+            .iter()
+            .filter(|package| package.shipping_variant == ShippingVariant::Local)
+            .map(|package| package.id)
+            .collect::<Vec<_>>();
+
+        Ok(available_packages
+            .into_iter()
+            .filter(|package| package.shipping_variant == ShippingVariant::Local || !local_package_ids.contains(&package.id))
+            .collect())
+    }
+
+    fn get_available_package_for_user(
+        &self,
+        base_product_id_arg: BaseProductId,
+        package_id_arg: CompanyPackageId,
+    ) -> RepoResult<Option<AvailablePackageForUser>> {
+        debug!(
+            "Get available package for base product: {} with select company package id: {}.",
+            base_product_id_arg, package_id_arg
+        );
+
+        Ok(self
+            .join(|product| product.base_product_id == base_product_id_arg && product.company_package_id == package_id_arg)
+            .into_iter()
+            .map(to_available_package)
+            .next())
+    }
+
+    fn get_available_package_for_user_by_shipping_id(
+        &self,
+        shipping_id_arg: ShippingId,
+        delivery_to: Option<Alpha3>,
+    ) -> RepoResult<Option<AvailablePackageForUser>> {
+        debug!("Get available package for shipping id: {}.", shipping_id_arg);
+
+        let product_ids = delivery_to.map(|delivery_to| self.delivered_to(&delivery_to));
+        Ok(self
+            .join(|product| {
+                product.id == shipping_id_arg
+                    && product_ids
+                        .as_ref()
+                        .map(|product_ids| product_ids.contains(&product.id))
+                        .unwrap_or(true)
+            })
+            .into_iter()
+            .map(to_available_package)
+            .next())
+    }
+
+    fn update(
+        &self,
+        base_product_id_arg: BaseProductId,
+        company_package_id_arg: CompanyPackageId,
+        payload: UpdateProducts,
+    ) -> RepoResult<Products> {
+        debug!("Updating products payload {:?}.", payload);
+        let (payload, deliveries_to) = payload.to_raw();
+        if let Some(ref deliveries_to) = deliveries_to {
+            check_country_codes(&self.countries, deliveries_to, "deliveries_to")?;
+        }
+
+        let is_updated =
+            |product: &ProductsRaw| product.base_product_id == base_product_id_arg && product.company_package_id == company_package_id_arg;
+
+        let product = self.store.lock().products.iter().find(|product| is_updated(product)).cloned();
+        product
+            .ok_or_else(|| Error::NotFound.into())
+            .map(|products_: ProductsRaw| {
+                let used_codes = self.store.lock().product_deliveries_to(products_.id);
+                products_.to_products(used_codes)
+            })
+            .and_then(|product: Products| acl::check(&*self.acl, Resource::Products, Action::Update, self, Some(&product)))
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                let raw = {
+                    let raw = tables
+                        .products
+                        .iter_mut()
+                        .find(|product| is_updated(product))
+                        .ok_or_else(|| FailureError::from(Error::NotFound))?;
+                    let UpdateProductsRaw { price, shipping, currency } = payload.clone();
+                    if price.is_some() {
+                        raw.price = price;
+                    }
+                    if let Some(shipping) = shipping {
+                        raw.shipping = shipping;
+                    }
+                    if let Some(currency) = currency {
+                        raw.currency = currency;
+                    }
+                    raw.clone()
+                };
+                if let Some(deliveries_to) = deliveries_to {
+                    tables.set_product_deliveries_to(raw.id, deliveries_to);
+                }
+                let used_codes = tables.product_deliveries_to(raw.id);
+                Ok(raw.to_products(used_codes))
+            })
+            .map_err(|e: FailureError| e.context(format!("Updating products payload {:?} failed.", payload)).into())
+    }
+
+    fn delete(&self, base_product_id_arg: BaseProductId) -> RepoResult<Vec<Products>> {
+        debug!("delete products {:?}.", base_product_id_arg);
+
+        let products = self
+            .store
+            .lock()
+            .products
+            .iter()
+            .filter(|product| product.base_product_id == base_product_id_arg)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.to_products(products)
+            .into_iter()
+            .map(|product| {
+                acl::check(&*self.acl, Resource::Products, Action::Delete, self, Some(&product))?;
+                Ok(product)
+            })
+            .collect::<RepoResult<Vec<Products>>>()
+            .map(|deleted_products| {
+                self.store
+                    .lock()
+                    .delete_products(|product| product.base_product_id == base_product_id_arg);
+                deleted_products
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete products with base product id {:?} failed.", base_product_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a> CheckScope<Scope, Products> for ProductsRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&Products>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|obj| owns_store(user_roles, obj.store_id)).unwrap_or(false),
+        }
+    }
+}
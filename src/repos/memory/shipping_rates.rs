@@ -0,0 +1,208 @@
+//! In-memory shipping_rates repo
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::{Alpha3, CompanyId, CompanyPackageId, PackageId, ShippingRatesId, UserId};
+
+use errors::Error;
+use models::authorization::*;
+use models::roles::{owns_company, UserRole};
+use models::{NewShippingRates, NewShippingRatesRaw, ShippingRates, ShippingRatesRaw};
+use repos::acl;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::shipping_rates::ShippingRatesRepo;
+use repos::types::RepoResult;
+
+use super::store::{remove_where, MemoryStore};
+
+pub struct ShippingRatesRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, CompanyPackageId>>,
+}
+
+impl<'a> ShippingRatesRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, CompanyPackageId>>) -> Self {
+        Self { store, acl }
+    }
+
+    fn read_all<P: Fn(&ShippingRatesRaw) -> bool>(&self, predicate: P) -> RepoResult<Vec<ShippingRates>> {
+        let mut rates = self
+            .store
+            .lock()
+            .shipping_rates
+            .iter()
+            .filter(|rates| predicate(rates))
+            .cloned()
+            .collect::<Vec<_>>();
+        rates.sort_by_key(|rates| rates.id.0);
+        rates.into_iter().map(ShippingRatesRaw::to_model).collect()
+    }
+}
+
+impl<'a> ShippingRatesRepo for ShippingRatesRepoMemory<'a> {
+    fn get_all_rates_from(&self, company_package_id: CompanyPackageId, delivery_from: Alpha3) -> RepoResult<Vec<ShippingRates>> {
+        acl::check(&*self.acl, Resource::ShippingRates, Action::Read, self, None)?;
+
+        self.read_all(|rates| rates.company_package_id == company_package_id && rates.from_alpha3 == delivery_from)
+            .map_err(|e| {
+                e.context(format!(
+                    "error occurred in get_all_rates_from for CompanyPackage with id = {}, from {}",
+                    company_package_id, delivery_from,
+                ))
+                .into()
+            })
+    }
+
+    fn get_multiple_rates(
+        &self,
+        company_package_id: CompanyPackageId,
+        delivery_from: Alpha3,
+        deliveries_to: Vec<Alpha3>,
+    ) -> RepoResult<Vec<ShippingRates>> {
+        acl::check(&*self.acl, Resource::ShippingRates, Action::Read, self, None)?;
+
+        self.read_all(|rates| {
+            rates.company_package_id == company_package_id && rates.from_alpha3 == delivery_from && deliveries_to.contains(&rates.to_alpha3)
+        })
+        .map_err(|e| {
+            e.context(format!(
+                "error occurred in get_multiple_rates for CompanyPackage with id = {}, {} -> {:?}",
+                company_package_id, delivery_from, deliveries_to,
+            ))
+            .into()
+        })
+    }
+
+    fn get_rates(
+        &self,
+        company_package_id: CompanyPackageId,
+        delivery_from: Alpha3,
+        delivery_to: Alpha3,
+    ) -> RepoResult<Option<ShippingRates>> {
+        acl::check(&*self.acl, Resource::ShippingRates, Action::Read, self, None)?;
+
+        self.read_all(|rates| {
+            rates.company_package_id == company_package_id && rates.from_alpha3 == delivery_from && rates.to_alpha3 == delivery_to
+        })
+        .map(|rates| rates.into_iter().last())
+        .map_err(|e| {
+            e.context(format!(
+                "error occurred in get_rates for CompanyPackage with id = {}, {} -> {}",
+                company_package_id, delivery_from, delivery_to,
+            ))
+            .into()
+        })
+    }
+
+    fn delete_all_rates_from(&self, company_package_id: CompanyPackageId, delivery_from: Alpha3) -> RepoResult<Vec<ShippingRates>> {
+        acl::check(&*self.acl, Resource::ShippingRates, Action::Delete, self, Some(&company_package_id))?;
+
+        let mut deleted = remove_where(&mut self.store.lock().shipping_rates, |rates| {
+            rates.company_package_id == company_package_id && rates.from_alpha3 == delivery_from
+        });
+        deleted.sort_by_key(|rates| rates.id.0);
+
+        deleted
+            .into_iter()
+            .map(ShippingRatesRaw::to_model)
+            .collect::<RepoResult<Vec<_>>>()
+            .map_err(|e| {
+                e.context(format!(
+                    "error occurred in delete_all_rates_from for CompanyPackage with id = {}, from {}",
+                    company_package_id, delivery_from,
+                ))
+                .into()
+            })
+    }
+
+    fn get_directions(&self, company_id: CompanyId, package_id: PackageId) -> RepoResult<Vec<(CompanyPackageId, Alpha3, Alpha3)>> {
+        acl::check(&*self.acl, Resource::ShippingRates, Action::Read, self, None)?;
+
+        let tables = self.store.lock();
+        let company_package_ids = tables
+            .companies_packages
+            .iter()
+            .filter(|company_package| company_package.company_id == company_id && company_package.package_id == package_id)
+            .map(|company_package| company_package.id)
+            .collect::<Vec<_>>();
+        let mut directions = tables
+            .shipping_rates
+            .iter()
+            .filter(|rates| company_package_ids.contains(&rates.company_package_id))
+            .map(|rates| (rates.company_package_id, rates.from_alpha3.clone(), rates.to_alpha3.clone()))
+            .collect::<Vec<_>>();
+        directions.sort_by_key(|&(company_package_id, ref from_alpha3, ref to_alpha3)| {
+            (company_package_id.0, from_alpha3.0.clone(), to_alpha3.0.clone())
+        });
+        directions.dedup();
+        Ok(directions)
+    }
+
+    fn insert_many(&self, shipping_rates: Vec<NewShippingRates>) -> RepoResult<Vec<ShippingRates>> {
+        let mut company_package_ids = shipping_rates.iter().map(|rates| rates.company_package_id).collect::<Vec<_>>();
+        company_package_ids.dedup();
+        for company_package_id in &company_package_ids {
+            acl::check(&*self.acl, Resource::ShippingRates, Action::Create, self, Some(company_package_id))?;
+        }
+
+        let shipping_rates = shipping_rates
+            .into_iter()
+            .map(NewShippingRatesRaw::from_model)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut tables = self.store.lock();
+        let mut inserted = vec![];
+        for rates in shipping_rates {
+            let is_duplicate = tables
+                .shipping_rates
+                .iter()
+                .chain(inserted.iter())
+                .any(|existing: &ShippingRatesRaw| {
+                    existing.company_package_id == rates.company_package_id
+                        && existing.from_alpha3 == rates.from_alpha3
+                        && existing.to_alpha3 == rates.to_alpha3
+                });
+            if is_duplicate {
+                return Err(
+                    FailureError::from(Error::not_unique("shipping rates of this direction already exist"))
+                        .context("error occurred in insert_many")
+                        .into(),
+                );
+            }
+            inserted.push(ShippingRatesRaw {
+                id: ShippingRatesId(tables.next_id()),
+                company_package_id: rates.company_package_id,
+                from_alpha3: rates.from_alpha3,
+                to_alpha3: rates.to_alpha3,
+                rates: rates.rates,
+            });
+        }
+        tables.shipping_rates.extend(inserted.iter().cloned());
+
+        inserted
+            .into_iter()
+            .map(ShippingRatesRaw::to_model)
+            .collect::<RepoResult<Vec<_>>>()
+            .map_err(|e| e.context("error occurred in insert_many").into())
+    }
+}
+
+impl<'a> CheckScope<Scope, CompanyPackageId> for ShippingRatesRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&CompanyPackageId>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj
+                .and_then(|company_package_id| {
+                    self.store
+                        .lock()
+                        .companies_packages
+                        .iter()
+                        .find(|company_package| company_package.id == *company_package_id)
+                        .map(|company_package| company_package.company_id)
+                })
+                .map(|company_id| owns_company(user_roles, company_id))
+                .unwrap_or(false),
+        }
+    }
+}
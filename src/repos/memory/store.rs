@@ -0,0 +1,336 @@
+//! Tables of the in-memory backend and fixtures they are seeded from
+
+use std::fs;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+use serde_json;
+
+use stq_types::{Alpha3, CompanyId, PackageId, ShippingId, ShippingRatesId};
+
+use models::*;
+
+/// Rows of all tables. Ids of all tables are taken from one sequence
+#[derive(Clone, Default)]
+pub struct Tables {
+    pub api_keys: Vec<ApiKeyRaw>,
+    pub audit_log: Vec<AuditLogEntry>,
+    pub companies: Vec<CompanyRaw>,
+    pub companies_deliveries_from: Vec<CompanyDeliveryFrom>,
+    pub companies_packages: Vec<CompaniesPackagesRaw>,
+    pub countries: Vec<RawCountry>,
+    pub outbox_endpoint_deliveries: Vec<OutboxEndpointDelivery>,
+    pub outbox_events: Vec<OutboxEvent>,
+    /// Holder of the dispatcher lease and its expiration time
+    pub outbox_lease: Option<(String, SystemTime)>,
+    pub packages: Vec<PackagesRaw>,
+    pub packages_deliveries_to: Vec<PackageDeliveryTo>,
+    pub pickups: Vec<Pickups>,
+    pub products: Vec<ProductsRaw>,
+    pub products_deliveries_to: Vec<ProductDeliveryTo>,
+    pub roles: Vec<UserRole>,
+    pub shipping_rates: Vec<ShippingRatesRaw>,
+    pub user_addresses: Vec<UserAddress>,
+    pub user_data_erasures: Vec<UserDataErasure>,
+    pub webhook_deliveries: Vec<WebhookDelivery>,
+    pub webhook_subscriptions: Vec<WebhookSubscription>,
+    last_id: i32,
+}
+
+impl Tables {
+    /// Returns id for a new row
+    pub fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Deletes company packages matching the predicate with their products and shipping rates, like `ON DELETE CASCADE`
+    pub fn delete_companies_packages<P: FnMut(&CompaniesPackagesRaw) -> bool>(&mut self, predicate: P) -> Vec<CompaniesPackagesRaw> {
+        let deleted = remove_where(&mut self.companies_packages, predicate);
+        let deleted_ids = deleted.iter().map(|company_package| company_package.id).collect::<Vec<_>>();
+        self.delete_products(|product| deleted_ids.contains(&product.company_package_id));
+        remove_where(&mut self.shipping_rates, |rates| deleted_ids.contains(&rates.company_package_id));
+        deleted
+    }
+
+    /// Deletes products matching the predicate with their countries, like `ON DELETE CASCADE`
+    pub fn delete_products<P: FnMut(&ProductsRaw) -> bool>(&mut self, predicate: P) -> Vec<ProductsRaw> {
+        let deleted = remove_where(&mut self.products, predicate);
+        let deleted_ids = deleted.iter().map(|product| product.id).collect::<Vec<_>>();
+        remove_where(&mut self.products_deliveries_to, |row| deleted_ids.contains(&row.product_id));
+        deleted
+    }
+
+    /// Returns codes of the countries the company delivers from
+    pub fn company_deliveries_from(&self, company_id: CompanyId) -> Vec<Alpha3> {
+        sorted_codes(
+            self.companies_deliveries_from
+                .iter()
+                .filter(|row| row.company_id == company_id)
+                .map(|row| &row.country_alpha3),
+        )
+    }
+
+    /// Replaces countries the company delivers from
+    pub fn set_company_deliveries_from(&mut self, company_id: CompanyId, codes: Vec<Alpha3>) {
+        remove_where(&mut self.companies_deliveries_from, |row| row.company_id == company_id);
+        for country_alpha3 in unique_codes(codes) {
+            self.companies_deliveries_from.push(CompanyDeliveryFrom {
+                company_id,
+                country_alpha3,
+            });
+        }
+    }
+
+    /// Returns codes of the countries the package can be delivered to
+    pub fn package_deliveries_to(&self, package_id: PackageId) -> Vec<Alpha3> {
+        sorted_codes(
+            self.packages_deliveries_to
+                .iter()
+                .filter(|row| row.package_id == package_id)
+                .map(|row| &row.country_alpha3),
+        )
+    }
+
+    /// Replaces countries the package can be delivered to
+    pub fn set_package_deliveries_to(&mut self, package_id: PackageId, codes: Vec<Alpha3>) {
+        remove_where(&mut self.packages_deliveries_to, |row| row.package_id == package_id);
+        for country_alpha3 in unique_codes(codes) {
+            self.packages_deliveries_to.push(PackageDeliveryTo {
+                package_id,
+                country_alpha3,
+            });
+        }
+    }
+
+    /// Returns codes of the countries the product can be delivered to
+    pub fn product_deliveries_to(&self, product_id: ShippingId) -> Vec<Alpha3> {
+        sorted_codes(
+            self.products_deliveries_to
+                .iter()
+                .filter(|row| row.product_id == product_id)
+                .map(|row| &row.country_alpha3),
+        )
+    }
+
+    /// Replaces countries the product can be delivered to
+    pub fn set_product_deliveries_to(&mut self, product_id: ShippingId, codes: Vec<Alpha3>) {
+        remove_where(&mut self.products_deliveries_to, |row| row.product_id == product_id);
+        for country_alpha3 in unique_codes(codes) {
+            self.products_deliveries_to.push(ProductDeliveryTo {
+                product_id,
+                country_alpha3,
+            });
+        }
+    }
+}
+
+/// Codes ordered like the db returns them
+fn sorted_codes<'a, I: Iterator<Item = &'a Alpha3>>(codes: I) -> Vec<Alpha3> {
+    let mut codes = codes.cloned().collect::<Vec<_>>();
+    codes.sort_by(|a, b| a.0.cmp(&b.0));
+    codes
+}
+
+/// Drops repeated codes, a country is stored once per row like with the primary key
+fn unique_codes(codes: Vec<Alpha3>) -> Vec<Alpha3> {
+    let mut unique: Vec<Alpha3> = vec![];
+    for code in codes {
+        if !unique.contains(&code) {
+            unique.push(code);
+        }
+    }
+    unique
+}
+
+/// Removes rows matching the predicate, returns the removed rows
+pub fn remove_where<T, P: FnMut(&T) -> bool>(rows: &mut Vec<T>, mut predicate: P) -> Vec<T> {
+    let (removed, kept): (Vec<T>, Vec<T>) = rows.drain(..).partition(|row| predicate(row));
+    *rows = kept;
+    removed
+}
+
+/// Rows the store is seeded from. Companies, packages and company packages are given with ids
+/// to be referenced by other rows, ids of the other rows are generated.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct MemoryFixtures {
+    pub countries: Vec<RawCountry>,
+    pub companies: Vec<CompanyRaw>,
+    pub companies_deliveries_from: Vec<CompanyDeliveryFrom>,
+    pub packages: Vec<PackagesRaw>,
+    pub packages_deliveries_to: Vec<PackageDeliveryTo>,
+    pub companies_packages: Vec<CompaniesPackagesRaw>,
+    pub shipping_rates: Vec<NewShippingRatesRaw>,
+    pub products: Vec<NewProducts>,
+    pub pickups: Vec<NewPickups>,
+    pub roles: Vec<UserRole>,
+    pub user_addresses: Vec<NewUserAddress>,
+}
+
+impl MemoryFixtures {
+    /// Reads fixtures from JSON file
+    pub fn from_file(path: &str) -> Result<Self, FailureError> {
+        let content = fs::read_to_string(path).map_err(|e| e.context(format!("Could not read fixtures file {}", path)))?;
+        serde_json::from_str(&content).map_err(|e| e.context(format!("Could not parse fixtures file {}", path)).into())
+    }
+}
+
+/// Storage of the in-memory backend shared by all connections
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    tables: Arc<Mutex<Tables>>,
+    transaction: Arc<Transaction>,
+}
+
+/// Thread running a transaction. Other threads wait for the transaction to finish before they read or write
+/// the tables, so restoring the snapshot on rollback never discards their writes
+#[derive(Default)]
+struct Transaction {
+    owner: Mutex<Option<ThreadId>>,
+    finished: Condvar,
+}
+
+impl MemoryStore {
+    pub fn new(fixtures: MemoryFixtures) -> Self {
+        let MemoryFixtures {
+            countries,
+            companies,
+            companies_deliveries_from,
+            packages,
+            packages_deliveries_to,
+            companies_packages,
+            shipping_rates,
+            products,
+            pickups,
+            roles,
+            user_addresses,
+        } = fixtures;
+
+        let mut tables = Tables {
+            countries,
+            companies_deliveries_from,
+            packages_deliveries_to,
+            roles,
+            ..Tables::default()
+        };
+        tables.last_id = companies
+            .iter()
+            .map(|company| company.id.0)
+            .chain(packages.iter().map(|package| package.id.0))
+            .chain(companies_packages.iter().map(|company_package| company_package.id.0))
+            .max()
+            .unwrap_or_default();
+        tables.companies = companies;
+        tables.packages = packages;
+        tables.companies_packages = companies_packages;
+
+        for rates in shipping_rates {
+            let id = tables.next_id();
+            tables.shipping_rates.push(ShippingRatesRaw {
+                id: ShippingRatesId(id),
+                company_package_id: rates.company_package_id,
+                from_alpha3: rates.from_alpha3,
+                to_alpha3: rates.to_alpha3,
+                rates: rates.rates,
+            });
+        }
+        for product in products {
+            let id = ShippingId(tables.next_id());
+            let (product, deliveries_to) = product.to_raw();
+            tables.products.push(ProductsRaw {
+                id,
+                base_product_id: product.base_product_id,
+                store_id: product.store_id,
+                company_package_id: product.company_package_id,
+                price: product.price,
+                shipping: product.shipping,
+                currency: product.currency,
+            });
+            tables.set_product_deliveries_to(id, deliveries_to);
+        }
+        for pickup in pickups {
+            let id = tables.next_id();
+            tables.pickups.push(Pickups {
+                id,
+                base_product_id: pickup.base_product_id,
+                store_id: pickup.store_id,
+                pickup: pickup.pickup,
+                price: pickup.price,
+            });
+        }
+        for address in user_addresses {
+            let id = tables.next_id();
+            let now = SystemTime::now();
+            tables.user_addresses.push(UserAddress {
+                id,
+                user_id: address.user_id,
+                administrative_area_level_1: address.administrative_area_level_1,
+                administrative_area_level_2: address.administrative_area_level_2,
+                country: address.country,
+                locality: address.locality,
+                political: address.political,
+                postal_code: address.postal_code,
+                route: address.route,
+                street_number: address.street_number,
+                address: address.address,
+                is_priority: address.is_priority,
+                created_at: now,
+                updated_at: now,
+                country_code: address.country_code,
+            });
+        }
+
+        Self {
+            tables: Arc::new(Mutex::new(tables)),
+            transaction: Arc::new(Transaction::default()),
+        }
+    }
+
+    /// Locks the tables, waits for a transaction of another thread to finish first.
+    /// Lock is not held while ACL is checked, as checking the scope may read the store
+    pub fn lock(&self) -> MutexGuard<Tables> {
+        let _owner = self.wait_for_transaction();
+        self.tables.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns copy of the tables
+    pub fn snapshot(&self) -> Tables {
+        self.lock().clone()
+    }
+
+    /// Replaces the tables with the snapshot
+    pub fn restore(&self, tables: Tables) {
+        *self.lock() = tables;
+    }
+
+    /// Starts transaction of the current thread, returns the tables to restore on rollback
+    pub fn begin(&self) -> Tables {
+        let mut owner = self.wait_for_transaction();
+        *owner = Some(thread::current().id());
+        self.tables.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Finishes transaction of the current thread, the tables are replaced with the snapshot if given
+    pub fn finish(&self, rollback: Option<Tables>) {
+        let mut owner = self.transaction.owner.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(tables) = rollback {
+            *self.tables.lock().unwrap_or_else(PoisonError::into_inner) = tables;
+        }
+        *owner = None;
+        self.transaction.finished.notify_all();
+    }
+
+    /// Waits until no other thread runs a transaction, the returned guard keeps others from starting one
+    fn wait_for_transaction(&self) -> MutexGuard<Option<ThreadId>> {
+        let current = thread::current().id();
+        let mut owner = self.transaction.owner.lock().unwrap_or_else(PoisonError::into_inner);
+        while owner.map_or(false, |id| id != current) {
+            owner = self.transaction.finished.wait(owner).unwrap_or_else(PoisonError::into_inner);
+        }
+        owner
+    }
+}
@@ -0,0 +1,271 @@
+//! In-memory user_addresses repo. Addresses are kept in plaintext
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::authorization::*;
+use models::{NewUserAddress, UpdateUserAddress, UserAddress, UserRole};
+use repos::acl;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+use repos::user_addresses::UserAddressesRepo;
+
+use super::store::{remove_where, MemoryStore, Tables};
+
+pub struct UserAddressesRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserAddress>>,
+}
+
+impl<'a> UserAddressesRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, UserAddress>>) -> Self {
+        Self { store, acl }
+    }
+
+    fn find(&self, id_arg: i32) -> Option<UserAddress> {
+        self.store
+            .lock()
+            .user_addresses
+            .iter()
+            .find(|address| address.id == id_arg)
+            .cloned()
+    }
+}
+
+/// Sets priority of all user addresses, except `except_id`, to false
+fn reset_priority(tables: &mut Tables, user_id_arg: UserId, except_id: Option<i32>) {
+    for address in tables
+        .user_addresses
+        .iter_mut()
+        .filter(|address| address.user_id == user_id_arg && Some(address.id) != except_id)
+    {
+        address.is_priority = false;
+    }
+}
+
+/// Makes the most recently updated address of the user a priority one
+fn promote_last_updated(tables: &mut Tables, user_id_arg: UserId) {
+    if let Some(address) = tables
+        .user_addresses
+        .iter_mut()
+        .filter(|address| address.user_id == user_id_arg)
+        .max_by_key(|address| (address.updated_at, address.id))
+    {
+        address.is_priority = true;
+    }
+}
+
+fn is_same_address(address: &UserAddress, payload: &NewUserAddress) -> bool {
+    address.user_id == payload.user_id
+        && address.country == payload.country
+        && address.postal_code == payload.postal_code
+        && address.route == payload.route
+        && address.street_number == payload.street_number
+        && address.address == payload.address
+        && address.administrative_area_level_1 == payload.administrative_area_level_1
+        && address.administrative_area_level_2 == payload.administrative_area_level_2
+        && address.locality == payload.locality
+        && address.political == payload.political
+        && address.country_code == payload.country_code
+}
+
+impl<'a> UserAddressesRepo for UserAddressesRepoMemory<'a> {
+    fn list_for_user(&self, user_id_value: UserId) -> RepoResult<Vec<UserAddress>> {
+        let mut addresses = self
+            .store
+            .lock()
+            .user_addresses
+            .iter()
+            .filter(|address| address.user_id == user_id_value)
+            .cloned()
+            .collect::<Vec<_>>();
+        addresses.sort_by_key(|address| -address.id);
+
+        for item in &addresses {
+            acl::check(&*self.acl, Resource::UserAddresses, Action::Read, self, Some(&item))
+                .map_err(|e: FailureError| e.context(format!("list of user_address for user {} error occurred", user_id_value)))?;
+        }
+        Ok(addresses)
+    }
+
+    fn get(&self, id_arg: i32) -> RepoResult<Option<UserAddress>> {
+        let address_ = self.find(id_arg);
+        if let Some(ref address_) = address_ {
+            acl::check(&*self.acl, Resource::UserAddresses, Action::Read, self, Some(address_))
+                .map_err(|e: FailureError| e.context(format!("Get user delivery address {} error occurred", id_arg)))?;
+        }
+        Ok(address_)
+    }
+
+    fn create(&self, payload: NewUserAddress) -> RepoResult<UserAddress> {
+        let existing = self
+            .store
+            .lock()
+            .user_addresses
+            .iter()
+            .find(|address| is_same_address(address, &payload))
+            .cloned();
+        if let Some(existing) = existing {
+            return acl::check(&*self.acl, Resource::UserAddresses, Action::Create, self, Some(&existing))
+                .map(|_| existing)
+                .map_err(|e: FailureError| {
+                    e.context(format!("Create a new delivery address for user {} error occurred", payload.user_id))
+                        .into()
+                });
+        }
+
+        let now = SystemTime::now();
+        let mut address_ = UserAddress {
+            id: 0,
+            user_id: payload.user_id,
+            administrative_area_level_1: payload.administrative_area_level_1.clone(),
+            administrative_area_level_2: payload.administrative_area_level_2.clone(),
+            country: payload.country.clone(),
+            locality: payload.locality.clone(),
+            political: payload.political.clone(),
+            postal_code: payload.postal_code.clone(),
+            route: payload.route.clone(),
+            street_number: payload.street_number.clone(),
+            address: payload.address.clone(),
+            is_priority: payload.is_priority,
+            created_at: now,
+            updated_at: now,
+            country_code: payload.country_code.clone(),
+        };
+        acl::check(&*self.acl, Resource::UserAddresses, Action::Create, self, Some(&address_))
+            .map_err(|e: FailureError| e.context(format!("Create a new delivery address for user {} error occurred", payload.user_id)))?;
+
+        let mut tables = self.store.lock();
+        if payload.is_priority {
+            // set all other addresses priority to false before the new priority address appears
+            reset_priority(&mut tables, payload.user_id, None);
+        }
+        address_.id = tables.next_id();
+        tables.user_addresses.push(address_.clone());
+        Ok(address_)
+    }
+
+    fn update(&self, id_arg: i32, payload: UpdateUserAddress) -> RepoResult<UserAddress> {
+        self.find(id_arg)
+            .ok_or_else(|| Error::NotFound.into())
+            .and_then(|address_: UserAddress| {
+                acl::check(&*self.acl, Resource::UserAddresses, Action::Update, self, Some(&address_))?;
+                Ok(address_)
+            })
+            .and_then(|address_| {
+                let mut tables = self.store.lock();
+                if payload.is_priority == Some(true) {
+                    // set all other addresses priority to false before this address becomes priority
+                    reset_priority(&mut tables, address_.user_id, Some(address_.id));
+                }
+
+                let updated = tables
+                    .user_addresses
+                    .iter_mut()
+                    .find(|address| address.id == id_arg)
+                    .ok_or_else(|| FailureError::from(Error::NotFound))?;
+                let UpdateUserAddress {
+                    administrative_area_level_1,
+                    administrative_area_level_2,
+                    country,
+                    locality,
+                    political,
+                    postal_code,
+                    route,
+                    street_number,
+                    address,
+                    is_priority,
+                    country_code,
+                } = payload.clone();
+                if administrative_area_level_1.is_some() {
+                    updated.administrative_area_level_1 = administrative_area_level_1;
+                }
+                if administrative_area_level_2.is_some() {
+                    updated.administrative_area_level_2 = administrative_area_level_2;
+                }
+                if let Some(country) = country {
+                    updated.country = country;
+                }
+                if locality.is_some() {
+                    updated.locality = locality;
+                }
+                if political.is_some() {
+                    updated.political = political;
+                }
+                if let Some(postal_code) = postal_code {
+                    updated.postal_code = postal_code;
+                }
+                if route.is_some() {
+                    updated.route = route;
+                }
+                if street_number.is_some() {
+                    updated.street_number = street_number;
+                }
+                if address.is_some() {
+                    updated.address = address;
+                }
+                if let Some(is_priority) = is_priority {
+                    updated.is_priority = is_priority;
+                }
+                if country_code.is_some() {
+                    updated.country_code = country_code;
+                }
+                updated.updated_at = SystemTime::now();
+                Ok(updated.clone())
+            })
+            .map_err(|e: FailureError| e.context(format!("Update delivery address {} error occurred", id_arg)).into())
+    }
+
+    fn delete(&self, id_arg: i32) -> RepoResult<UserAddress> {
+        self.find(id_arg)
+            .ok_or_else(|| Error::NotFound.into())
+            .and_then(|address_: UserAddress| acl::check(&*self.acl, Resource::UserAddresses, Action::Delete, self, Some(&address_)))
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                let deleted_address = remove_where(&mut tables.user_addresses, |address| address.id == id_arg)
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| FailureError::from(Error::NotFound))?;
+                if deleted_address.is_priority {
+                    promote_last_updated(&mut tables, deleted_address.user_id);
+                }
+                Ok(deleted_address)
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete delivery address {} error occurred", id_arg)).into())
+    }
+
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Vec<UserAddress>> {
+        let addresses = self
+            .store
+            .lock()
+            .user_addresses
+            .iter()
+            .filter(|address| address.user_id == user_id_arg)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        addresses
+            .iter()
+            .map(|item| acl::check(&*self.acl, Resource::UserAddresses, Action::Delete, self, Some(&item)))
+            .collect::<RepoResult<Vec<_>>>()
+            .map(|_| remove_where(&mut self.store.lock().user_addresses, |address| address.user_id == user_id_arg))
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete delivery addresses of user {} error occurred", user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a> CheckScope<Scope, UserAddress> for UserAddressesRepoMemory<'a> {
+    fn is_in_scope(&self, user_id_arg: UserId, _user_roles: &[UserRole], scope: &Scope, obj: Option<&UserAddress>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|address| address.user_id == user_id_arg).unwrap_or(false),
+        }
+    }
+}
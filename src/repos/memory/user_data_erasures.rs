@@ -0,0 +1,61 @@
+//! In-memory user_data_erasures repo
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use models::authorization::*;
+use models::{NewUserDataErasure, UserDataErasure, UserRole};
+use repos::acl;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+use repos::user_data_erasures::UserDataErasuresRepo;
+
+use super::store::MemoryStore;
+
+pub struct UserDataErasuresRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserDataErasure>>,
+}
+
+impl<'a> UserDataErasuresRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, UserDataErasure>>) -> Self {
+        Self { store, acl }
+    }
+}
+
+impl<'a> UserDataErasuresRepo for UserDataErasuresRepoMemory<'a> {
+    fn create(&self, payload: NewUserDataErasure) -> RepoResult<UserDataErasure> {
+        debug!("create new user data erasure {:?}.", payload);
+        acl::check(&*self.acl, Resource::UserDataErasures, Action::Create, self, None)
+            .map(|_| {
+                let mut tables = self.store.lock();
+                let erasure = UserDataErasure {
+                    id: tables.next_id(),
+                    user_id: payload.user_id,
+                    erased_by: payload.erased_by,
+                    addresses_count: payload.addresses_count,
+                    roles_count: payload.roles_count,
+                    created_at: SystemTime::now(),
+                };
+                tables.user_data_erasures.push(erasure.clone());
+                erasure
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new user data erasure {:?} error occurred", payload))
+                    .into()
+            })
+    }
+}
+
+impl<'a> CheckScope<Scope, UserDataErasure> for UserDataErasuresRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&UserDataErasure>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
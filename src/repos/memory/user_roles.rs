@@ -0,0 +1,114 @@
+//! In-memory user_roles repo
+
+use failure::Error as FailureError;
+use failure::Fail;
+use serde_json;
+
+use stq_types::{RoleId, UserId};
+
+use errors::Error;
+use models::authorization::*;
+use models::{NewUserRole, RoleName, UserRole};
+use repos::acl;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+use repos::user_roles::UserRolesRepo;
+
+use super::store::{remove_where, MemoryStore};
+
+pub struct UserRolesRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserRole>>,
+}
+
+impl<'a> UserRolesRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, UserRole>>) -> Self {
+        Self { store, acl }
+    }
+
+    /// Returns roles of the user ordered by name, as they are stored
+    fn get(&self, user_id_arg: UserId) -> Vec<UserRole> {
+        let mut user_roles = self
+            .store
+            .lock()
+            .roles
+            .iter()
+            .filter(|user_role| user_role.user_id == user_id_arg)
+            .cloned()
+            .collect::<Vec<_>>();
+        user_roles.sort_by_key(|user_role| serde_json::to_string(&user_role.name).unwrap_or_default());
+        user_roles
+    }
+}
+
+impl<'a> UserRolesRepo for UserRolesRepoMemory<'a> {
+    fn list_for_user(&self, user_id_value: UserId) -> RepoResult<Vec<RoleName>> {
+        debug!("list user roles for id {}.", user_id_value);
+
+        Ok(self.get(user_id_value).into_iter().map(|user_role| user_role.name).collect())
+    }
+
+    fn get_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Vec<UserRole>> {
+        debug!("get user roles records for id {}.", user_id_arg);
+
+        let user_roles = self.get(user_id_arg);
+        for user_role in &user_roles {
+            acl::check(&*self.acl, Resource::UserRoles, Action::Read, self, Some(user_role))
+                .map_err(|e: FailureError| e.context(format!("Get user roles for user {} error occurred.", user_id_arg)))?;
+        }
+        Ok(user_roles)
+    }
+
+    fn create(&self, payload: NewUserRole) -> RepoResult<UserRole> {
+        debug!("create new user role {:?}.", payload);
+
+        let mut tables = self.store.lock();
+        if tables.roles.iter().any(|user_role| {
+            user_role.id == payload.id
+                || (user_role.user_id == payload.user_id && user_role.name == payload.name && user_role.data == payload.data)
+        }) {
+            return Err(FailureError::from(Error::not_unique("role already exists"))
+                .context(format!("Create a new user role {:?} error occurred", payload))
+                .into());
+        }
+
+        let user_role = UserRole {
+            id: payload.id,
+            user_id: payload.user_id,
+            name: payload.name,
+            data: payload.data,
+        };
+        tables.roles.push(user_role.clone());
+        Ok(user_role)
+    }
+
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Vec<UserRole>> {
+        debug!("delete user {} role.", user_id_arg);
+
+        Ok(remove_where(&mut self.store.lock().roles, |user_role| {
+            user_role.user_id == user_id_arg
+        }))
+    }
+
+    fn delete_by_id(&self, id_arg: RoleId) -> RepoResult<UserRole> {
+        debug!("delete user role by id {}.", id_arg);
+
+        remove_where(&mut self.store.lock().roles, |user_role| user_role.id == id_arg)
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                FailureError::from(Error::NotFound)
+                    .context(format!("Delete role {} error occurred", id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a> CheckScope<Scope, UserRole> for UserRolesRepoMemory<'a> {
+    fn is_in_scope(&self, user_id_arg: UserId, _user_roles: &[UserRole], scope: &Scope, obj: Option<&UserRole>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|user_role| user_role.user_id == user_id_arg).unwrap_or(false),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! In-memory webhook_deliveries repo
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::authorization::{Action, Resource, Scope};
+use models::{NewWebhookDelivery, PendingWebhookDelivery, UserRole, WebhookDelivery, WebhookDeliveryStatus};
+use repos::acl;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+use repos::webhook_deliveries::WebhookDeliveriesRepo;
+
+use super::store::MemoryStore;
+
+pub struct WebhookDeliveriesRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>,
+}
+
+impl<'a> WebhookDeliveriesRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>) -> Self {
+        Self { store, acl }
+    }
+
+    fn update<F: FnOnce(&mut WebhookDelivery)>(&self, id_arg: i32, f: F) -> RepoResult<WebhookDelivery> {
+        let mut tables = self.store.lock();
+        let delivery = tables
+            .webhook_deliveries
+            .iter_mut()
+            .find(|delivery| delivery.id == id_arg)
+            .ok_or_else(|| FailureError::from(Error::NotFound))?;
+        f(delivery);
+        Ok(delivery.clone())
+    }
+}
+
+impl<'a> WebhookDeliveriesRepo for WebhookDeliveriesRepoMemory<'a> {
+    fn create_many(&self, payload: Vec<NewWebhookDelivery>) -> RepoResult<Vec<WebhookDelivery>> {
+        debug!("create webhook deliveries {:?}.", payload);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Create, self, None)
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                let mut created = vec![];
+                for new_delivery in &payload {
+                    let subscription_exists = tables
+                        .webhook_subscriptions
+                        .iter()
+                        .any(|subscription| subscription.id == new_delivery.subscription_id);
+                    let event_exists = tables.outbox_events.iter().any(|event| event.id == new_delivery.outbox_event_id);
+                    if !subscription_exists || !event_exists {
+                        return Err(Error::NotFound.into());
+                    }
+
+                    let now = SystemTime::now();
+                    created.push(WebhookDelivery {
+                        id: tables.next_id(),
+                        subscription_id: new_delivery.subscription_id,
+                        outbox_event_id: new_delivery.outbox_event_id,
+                        status: WebhookDeliveryStatus::Pending,
+                        attempts: 0,
+                        next_attempt_at: now,
+                        last_error: None,
+                        created_at: now,
+                        delivered_at: None,
+                    });
+                }
+                tables.webhook_deliveries.extend(created.iter().cloned());
+                Ok(created)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create webhook deliveries {:?} error occurred", payload)).into())
+    }
+
+    fn list_by_subscription(&self, subscription_id_arg: i32) -> RepoResult<Vec<WebhookDelivery>> {
+        debug!("list deliveries of webhook subscription {}.", subscription_id_arg);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Read, self, None)
+            .map(|_| {
+                let mut deliveries = self
+                    .store
+                    .lock()
+                    .webhook_deliveries
+                    .iter()
+                    .filter(|delivery| delivery.subscription_id == subscription_id_arg)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                deliveries.sort_by_key(|delivery| -delivery.id);
+                deliveries
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "List deliveries of webhook subscription {} error occurred",
+                    subscription_id_arg
+                ))
+                .into()
+            })
+    }
+
+    fn list_pending(&self, limit: i64) -> RepoResult<Vec<PendingWebhookDelivery>> {
+        debug!("list pending webhook deliveries, limit: {}.", limit);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Read, self, None)
+            .map(|_| {
+                let now = SystemTime::now();
+                let tables = self.store.lock();
+                let mut pending = tables
+                    .webhook_deliveries
+                    .iter()
+                    .filter(|delivery| delivery.status == WebhookDeliveryStatus::Pending && delivery.next_attempt_at <= now)
+                    .filter_map(|delivery| {
+                        let subscription = tables
+                            .webhook_subscriptions
+                            .iter()
+                            .find(|subscription| subscription.id == delivery.subscription_id)?;
+                        let event = tables.outbox_events.iter().find(|event| event.id == delivery.outbox_event_id)?;
+                        Some(PendingWebhookDelivery {
+                            delivery: delivery.clone(),
+                            subscription: subscription.clone(),
+                            event: event.clone(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                pending.sort_by_key(|pending| pending.delivery.id);
+                pending.truncate(limit.max(0) as usize);
+                pending
+            })
+            .map_err(|e: FailureError| e.context("List pending webhook deliveries error occurred").into())
+    }
+
+    fn mark_delivered(&self, id_arg: i32) -> RepoResult<WebhookDelivery> {
+        debug!("mark webhook delivery {} as delivered.", id_arg);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Update, self, None)
+            .and_then(|_| {
+                self.update(id_arg, |delivery| {
+                    delivery.status = WebhookDeliveryStatus::Delivered;
+                    delivery.attempts += 1;
+                    delivery.last_error = None;
+                    delivery.delivered_at = Some(SystemTime::now());
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Mark webhook delivery {} as delivered error occurred", id_arg))
+                    .into()
+            })
+    }
+
+    fn mark_failed(&self, id_arg: i32, error: String, next_attempt_at_arg: SystemTime, dead_letter: bool) -> RepoResult<WebhookDelivery> {
+        debug!("mark webhook delivery {} as failed: {}.", id_arg, error);
+        let status_arg = if dead_letter {
+            WebhookDeliveryStatus::DeadLetter
+        } else {
+            WebhookDeliveryStatus::Pending
+        };
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Update, self, None)
+            .and_then(|_| {
+                self.update(id_arg, |delivery| {
+                    delivery.status = status_arg;
+                    delivery.attempts += 1;
+                    delivery.last_error = Some(error);
+                    delivery.next_attempt_at = next_attempt_at_arg;
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Mark webhook delivery {} as failed error occurred", id_arg))
+                    .into()
+            })
+    }
+
+    fn redeliver(&self, id_arg: i32) -> RepoResult<WebhookDelivery> {
+        debug!("redeliver webhook delivery {}.", id_arg);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Update, self, None)
+            .and_then(|_| {
+                self.update(id_arg, |delivery| {
+                    delivery.status = WebhookDeliveryStatus::Pending;
+                    delivery.attempts = 0;
+                    delivery.next_attempt_at = SystemTime::now();
+                    delivery.delivered_at = None;
+                })
+            })
+            .map_err(|e: FailureError| e.context(format!("Redeliver webhook delivery {} error occurred", id_arg)).into())
+    }
+}
+
+impl<'a> CheckScope<Scope, WebhookDelivery> for WebhookDeliveriesRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&WebhookDelivery>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
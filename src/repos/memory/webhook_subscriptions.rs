@@ -0,0 +1,107 @@
+//! In-memory webhook_subscriptions repo
+
+use std::time::SystemTime;
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::authorization::{Action, Resource, Scope};
+use models::{NewWebhookSubscriptionRaw, UserRole, WebhookSubscription};
+use repos::acl;
+use repos::legacy_acl::{Acl, CheckScope};
+use repos::types::RepoResult;
+use repos::webhook_subscriptions::WebhookSubscriptionsRepo;
+
+use super::store::{remove_where, MemoryStore};
+
+pub struct WebhookSubscriptionsRepoMemory<'a> {
+    pub store: &'a MemoryStore,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookSubscription>>,
+}
+
+impl<'a> WebhookSubscriptionsRepoMemory<'a> {
+    pub fn new(store: &'a MemoryStore, acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookSubscription>>) -> Self {
+        Self { store, acl }
+    }
+
+    fn read_all<P: Fn(&WebhookSubscription) -> bool>(&self, predicate: P) -> Vec<WebhookSubscription> {
+        let mut subscriptions = self
+            .store
+            .lock()
+            .webhook_subscriptions
+            .iter()
+            .filter(|subscription| predicate(subscription))
+            .cloned()
+            .collect::<Vec<_>>();
+        subscriptions.sort_by_key(|subscription| subscription.id);
+        subscriptions
+    }
+}
+
+impl<'a> WebhookSubscriptionsRepo for WebhookSubscriptionsRepoMemory<'a> {
+    fn create(&self, payload: NewWebhookSubscriptionRaw) -> RepoResult<WebhookSubscription> {
+        debug!("create new webhook subscription to {}.", payload.url);
+        acl::check(&*self.acl, Resource::WebhookSubscriptions, Action::Create, self, None)
+            .map(|_| {
+                let mut tables = self.store.lock();
+                let subscription = WebhookSubscription {
+                    id: tables.next_id(),
+                    url: payload.url.clone(),
+                    event_types: payload.event_types.clone(),
+                    secret: payload.secret.clone(),
+                    created_by: payload.created_by,
+                    created_at: SystemTime::now(),
+                };
+                tables.webhook_subscriptions.push(subscription.clone());
+                subscription
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new webhook subscription to {} error occurred", payload.url))
+                    .into()
+            })
+    }
+
+    fn list(&self) -> RepoResult<Vec<WebhookSubscription>> {
+        debug!("list webhook subscriptions.");
+        acl::check(&*self.acl, Resource::WebhookSubscriptions, Action::Read, self, None)
+            .map(|_| self.read_all(|_| true))
+            .map_err(|e: FailureError| e.context("List webhook subscriptions error occurred").into())
+    }
+
+    fn list_by_event_type(&self, event_type_arg: String) -> RepoResult<Vec<WebhookSubscription>> {
+        debug!("list webhook subscriptions to {} events.", event_type_arg);
+        acl::check(&*self.acl, Resource::WebhookSubscriptions, Action::Read, self, None)
+            .map(|_| self.read_all(|subscription| subscription.is_subscribed_to(&event_type_arg)))
+            .map_err(|e: FailureError| {
+                e.context(format!("List webhook subscriptions to {} events error occurred", event_type_arg))
+                    .into()
+            })
+    }
+
+    fn delete(&self, id_arg: i32) -> RepoResult<WebhookSubscription> {
+        debug!("delete webhook subscription {}.", id_arg);
+        acl::check(&*self.acl, Resource::WebhookSubscriptions, Action::Delete, self, None)
+            .and_then(|_| {
+                let mut tables = self.store.lock();
+                let subscription = remove_where(&mut tables.webhook_subscriptions, |subscription| subscription.id == id_arg)
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| FailureError::from(Error::NotFound))?;
+                remove_where(&mut tables.webhook_deliveries, |delivery| delivery.subscription_id == id_arg);
+                Ok(subscription)
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete webhook subscription {} error occurred", id_arg)).into())
+    }
+}
+
+impl<'a> CheckScope<Scope, WebhookSubscription> for WebhookSubscriptionsRepoMemory<'a> {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&WebhookSubscription>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
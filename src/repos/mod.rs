@@ -1,7 +1,11 @@
 pub mod acl;
+pub mod api_keys;
+pub mod audit_log;
 pub mod companies;
 pub mod companies_packages;
 pub mod countries;
+pub mod memory;
+pub mod outbox;
 pub mod packages;
 pub mod pickups;
 pub mod products;
@@ -9,12 +13,18 @@ pub mod repo_factory;
 pub mod shipping_rates;
 pub mod types;
 pub mod user_addresses;
+pub mod user_data_erasures;
 pub mod user_roles;
+pub mod webhook_deliveries;
+pub mod webhook_subscriptions;
 
 pub use self::acl::*;
+pub use self::api_keys::*;
+pub use self::audit_log::*;
 pub use self::companies::*;
 pub use self::companies_packages::*;
 pub use self::countries::*;
+pub use self::outbox::*;
 pub use self::packages::*;
 pub use self::pickups::*;
 pub use self::products::*;
@@ -22,18 +32,10 @@ pub use self::repo_factory::*;
 pub use self::shipping_rates::*;
 pub use self::types::*;
 pub use self::user_addresses::*;
+pub use self::user_data_erasures::*;
 pub use self::user_roles::*;
-
-use stq_types::Alpha3;
-
-pub fn get_pg_str_json_array(countries: Vec<Alpha3>) -> String {
-    let res = countries
-        .into_iter()
-        .map(|s| format!("'{}'", s.0))
-        .collect::<Vec<String>>()
-        .join(",");
-    format!("array[{}]", res)
-}
+pub use self::webhook_deliveries::*;
+pub use self::webhook_subscriptions::*;
 
 pub fn get_company_package_name(company_name: &str, package_name: &str) -> String {
     format!("{}-{}", company_name, package_name)
@@ -0,0 +1,237 @@
+//! Repo for outbox_events table. OutboxEvent is a domain event written in the same
+//! transaction as the change and delivered to other services by the dispatcher.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use errors::Error;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::{Action, Resource, Scope};
+use models::{NewOutboxEvent, OutboxEvent, UserRole};
+use schema::outbox_events::dsl::*;
+use schema::{outbox_endpoint_deliveries, outbox_lease};
+
+/// Outbox repository, used by services writing events and by the dispatcher
+pub trait OutboxRepo {
+    /// Write a new event to the outbox
+    fn create(&self, payload: NewOutboxEvent) -> RepoResult<OutboxEvent>;
+
+    /// Returns events not delivered yet, oldest first
+    fn list_pending(&self, limit: i64) -> RepoResult<Vec<OutboxEvent>>;
+
+    /// Marks event as delivered
+    fn mark_sent(&self, id_arg: i32) -> RepoResult<OutboxEvent>;
+
+    /// Records failed delivery attempt, the event is retried not earlier than `next_attempt_at_arg`
+    fn mark_failed(&self, id_arg: i32, error: String, next_attempt_at_arg: SystemTime) -> RepoResult<OutboxEvent>;
+
+    /// Returns events webhook deliveries are not created for yet, oldest first
+    fn list_pending_webhooks(&self, limit: i64) -> RepoResult<Vec<OutboxEvent>>;
+
+    /// Marks that webhook deliveries of the event are created
+    fn mark_webhooks_created(&self, id_arg: i32) -> RepoResult<OutboxEvent>;
+
+    /// Returns endpoints the event has been delivered to
+    fn list_delivered_endpoints(&self, id_arg: i32) -> RepoResult<Vec<String>>;
+
+    /// Records delivery of the event to the endpoint
+    fn mark_endpoint_delivered(&self, id_arg: i32, endpoint_arg: String) -> RepoResult<()>;
+
+    /// Takes the dispatcher lease or extends it until `expires_at_arg`, the lease can't be taken
+    /// while another holder's lease is not expired. Returns `true` if `holder_arg` holds the lease
+    fn acquire_lease(&self, holder_arg: &str, expires_at_arg: SystemTime) -> RepoResult<bool>;
+}
+
+/// Implementation of OutboxRepo trait
+pub struct OutboxRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, OutboxEvent>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> OutboxRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, OutboxEvent>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> OutboxRepo for OutboxRepoImpl<'a, T> {
+    /// Write a new event to the outbox
+    fn create(&self, payload: NewOutboxEvent) -> RepoResult<OutboxEvent> {
+        debug!("create new outbox event {:?}.", payload);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Create, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(outbox_events).values(&payload);
+                query.get_result::<OutboxEvent>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Create a new outbox event {:?} error occurred", payload)).into())
+    }
+
+    /// Returns events not delivered yet, oldest first
+    fn list_pending(&self, limit: i64) -> RepoResult<Vec<OutboxEvent>> {
+        debug!("list pending outbox events, limit: {}.", limit);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Read, self, None)
+            .and_then(|_| {
+                outbox_events
+                    .filter(sent_at.is_null())
+                    .order(id)
+                    .limit(limit)
+                    .get_results::<OutboxEvent>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context("List pending outbox events error occurred").into())
+    }
+
+    /// Marks event as delivered
+    fn mark_sent(&self, id_arg: i32) -> RepoResult<OutboxEvent> {
+        debug!("mark outbox event {} as sent.", id_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(outbox_events.filter(id.eq(id_arg)))
+                    .set((
+                        sent_at.eq(Some(SystemTime::now())),
+                        attempts.eq(attempts + 1),
+                        last_error.eq(None::<String>),
+                    ))
+                    .get_result::<OutboxEvent>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Mark outbox event {} as sent error occurred", id_arg)).into())
+    }
+
+    /// Records failed delivery attempt, the event is retried not earlier than `next_attempt_at_arg`
+    fn mark_failed(&self, id_arg: i32, error: String, next_attempt_at_arg: SystemTime) -> RepoResult<OutboxEvent> {
+        debug!("mark outbox event {} as failed: {}.", id_arg, error);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(outbox_events.filter(id.eq(id_arg)))
+                    .set((
+                        attempts.eq(attempts + 1),
+                        last_error.eq(Some(error)),
+                        next_attempt_at.eq(next_attempt_at_arg),
+                    ))
+                    .get_result::<OutboxEvent>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Mark outbox event {} as failed error occurred", id_arg)).into())
+    }
+
+    /// Returns events webhook deliveries are not created for yet, oldest first
+    fn list_pending_webhooks(&self, limit: i64) -> RepoResult<Vec<OutboxEvent>> {
+        debug!("list outbox events pending webhook deliveries, limit: {}.", limit);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Read, self, None)
+            .and_then(|_| {
+                outbox_events
+                    .filter(webhooks_created_at.is_null())
+                    .order(id)
+                    .limit(limit)
+                    .get_results::<OutboxEvent>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context("List outbox events pending webhook deliveries error occurred").into())
+    }
+
+    /// Marks that webhook deliveries of the event are created
+    fn mark_webhooks_created(&self, id_arg: i32) -> RepoResult<OutboxEvent> {
+        debug!("mark webhook deliveries of outbox event {} as created.", id_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(outbox_events.filter(id.eq(id_arg)))
+                    .set(webhooks_created_at.eq(Some(SystemTime::now())))
+                    .get_result::<OutboxEvent>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Mark webhook deliveries of outbox event {} as created error occurred",
+                    id_arg
+                ))
+                .into()
+            })
+    }
+
+    /// Returns endpoints the event has been delivered to
+    fn list_delivered_endpoints(&self, id_arg: i32) -> RepoResult<Vec<String>> {
+        debug!("list endpoints outbox event {} has been delivered to.", id_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Read, self, None)
+            .and_then(|_| {
+                outbox_endpoint_deliveries::table
+                    .filter(outbox_endpoint_deliveries::outbox_event_id.eq(id_arg))
+                    .select(outbox_endpoint_deliveries::endpoint)
+                    .get_results::<String>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "List endpoints outbox event {} has been delivered to error occurred",
+                    id_arg
+                ))
+                .into()
+            })
+    }
+
+    /// Records delivery of the event to the endpoint
+    fn mark_endpoint_delivered(&self, id_arg: i32, endpoint_arg: String) -> RepoResult<()> {
+        debug!("mark outbox event {} as delivered to {}.", id_arg, endpoint_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::insert_into(outbox_endpoint_deliveries::table)
+                    .values((
+                        outbox_endpoint_deliveries::outbox_event_id.eq(id_arg),
+                        outbox_endpoint_deliveries::endpoint.eq(&endpoint_arg),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(self.db_conn)
+                    .map(|_| ())
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Mark outbox event {} as delivered to {} error occurred",
+                    id_arg, endpoint_arg
+                ))
+                .into()
+            })
+    }
+
+    /// Takes the dispatcher lease or extends it until `expires_at_arg`
+    fn acquire_lease(&self, holder_arg: &str, expires_at_arg: SystemTime) -> RepoResult<bool> {
+        debug!("acquire outbox lease for {} until {:?}.", holder_arg, expires_at_arg);
+        acl::check(&*self.acl, Resource::OutboxEvents, Action::Update, self, None)
+            .and_then(|_| {
+                // the row is locked by the update, so of the concurrent holders only one takes an expired lease
+                let available = outbox_lease::holder
+                    .eq(holder_arg)
+                    .or(outbox_lease::expires_at.lt(SystemTime::now()));
+                diesel::update(outbox_lease::table.filter(available))
+                    .set((outbox_lease::holder.eq(holder_arg), outbox_lease::expires_at.eq(expires_at_arg)))
+                    .execute(self.db_conn)
+                    .map(|updated| updated > 0)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Acquire outbox lease for {} error occurred", holder_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, OutboxEvent>
+    for OutboxRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&OutboxEvent>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
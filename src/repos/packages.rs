@@ -2,13 +2,10 @@
 
 use diesel;
 use diesel::connection::AnsiTransactionManager;
-use diesel::dsl::sql;
-use diesel::pg::types::sql_types::Array;
 use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_dsl::LoadQuery;
 use diesel::query_dsl::RunQueryDsl;
-use diesel::sql_types::VarChar;
 use diesel::Connection;
 
 use errors::Error;
@@ -18,12 +15,15 @@ use stq_types::{Alpha3, PackageId, UserId};
 
 use models::authorization::*;
 use models::countries::Country;
-use models::packages::{NewPackages, Packages, PackagesRaw, UpdatePackages};
+use models::packages::{NewPackages, PackageDeliveryTo, Packages, PackagesRaw, UpdatePackages};
+use models::roles::UserRole;
+use repos::countries::check_country_codes;
 use repos::legacy_acl::*;
 use repos::types::RepoResult;
 use repos::*;
 
 use schema::packages::dsl::*;
+use schema::packages_deliveries_to::dsl as DslDeliveriesTo;
 
 /// Packages repository for handling Packages
 pub trait PackagesRepo {
@@ -61,18 +61,40 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     fn execute_query<Ty: Send + 'static, U: LoadQuery<T, Ty> + Send + 'static>(&self, query: U) -> RepoResult<Ty> {
         query.get_result::<Ty>(self.db_conn).map_err(|e| Error::from(e).into())
     }
+
+    fn to_packages(&self, raws: Vec<PackagesRaw>) -> RepoResult<Vec<Packages>> {
+        let package_ids = raws.iter().map(|raw| raw.id).collect::<Vec<_>>();
+        let deliveries = get_package_deliveries_to(self.db_conn, &package_ids)?;
+
+        Ok(raws
+            .into_iter()
+            .map(|raw| {
+                let used_codes = package_deliveries_to_of(&deliveries, raw.id);
+                raw.to_packages(&used_codes, &self.countries)
+            })
+            .collect())
+    }
+
+    fn to_package(&self, raw: PackagesRaw) -> RepoResult<Packages> {
+        let used_codes = package_deliveries_to_of(&get_package_deliveries_to(self.db_conn, &[raw.id])?, raw.id);
+        Ok(raw.to_packages(&used_codes, &self.countries))
+    }
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> PackagesRepo for PackagesRepoImpl<'a, T> {
     fn create(&self, payload: NewPackages) -> RepoResult<Packages> {
         debug!("create new packages_ {:?}.", payload);
-        let payload = payload.to_raw()?;
+        let (payload, deliveries_to) = payload.to_raw();
+        check_country_codes(&self.countries, &deliveries_to, "deliveries_to")?;
 
         let query = diesel::insert_into(packages).values(&payload);
         query
             .get_result::<PackagesRaw>(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|p| p.to_packages(&self.countries))
+            .and_then(|p| {
+                set_package_deliveries_to(self.db_conn, p.id, deliveries_to)?;
+                self.to_package(p)
+            })
             .and_then(|packages_| {
                 acl::check(&*self.acl, Resource::Packages, Action::Create, self, Some(&packages_)).and_then(|_| Ok(packages_))
             })
@@ -83,19 +105,15 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     fn find_deliveries_to(&self, countries: Vec<Alpha3>) -> RepoResult<Vec<Packages>> {
         debug!("Find in packages with country {:?}.", countries);
 
-        let pg_countries: Vec<String> = countries.iter().cloned().map(|c| c.0).collect();
-
-        let query = packages.filter(sql("deliveries_to ?| ").bind::<Array<VarChar>, _>(pg_countries));
+        let package_ids = DslDeliveriesTo::packages_deliveries_to
+            .filter(DslDeliveriesTo::country_alpha3.eq_any(countries.clone()))
+            .select(DslDeliveriesTo::package_id);
+        let query = packages.filter(id.eq_any(package_ids)).order(id);
 
         query
             .get_results(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|packages_raw: Vec<PackagesRaw>| {
-                packages_raw
-                    .into_iter()
-                    .map(|packages_raw| packages_raw.to_packages(&self.countries))
-                    .collect()
-            })
+            .and_then(|packages_raw: Vec<PackagesRaw>| self.to_packages(packages_raw))
             .and_then(|packages_res: Vec<Packages>| {
                 for packages_ in &packages_res {
                     acl::check(&*self.acl, Resource::Packages, Action::Read, self, Some(&packages_))?;
@@ -117,7 +135,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
         query
             .get_results(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|raws: Vec<PackagesRaw>| raws.into_iter().map(|raw| raw.to_packages(&self.countries)).collect())
+            .and_then(|raws: Vec<PackagesRaw>| self.to_packages(raws))
             .and_then(|results: Vec<Packages>| {
                 for package in &results {
                     acl::check(&*self.acl, Resource::Packages, Action::Read, self, Some(&package))?;
@@ -138,7 +156,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .map_err(|e| Error::from(e).into())
             .and_then(|raw: Option<PackagesRaw>| match raw {
                 Some(value) => {
-                    let package = value.to_packages(&self.countries)?;
+                    let package = self.to_package(value)?;
                     acl::check(&*self.acl, Resource::Packages, Action::Read, self, Some(&package))?;
                     Ok(Some(package))
                 }
@@ -149,19 +167,28 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
     fn update(&self, id_arg: PackageId, payload: UpdatePackages) -> RepoResult<Packages> {
         debug!("Updating packages_ payload {:?}.", payload);
-        let payload = payload.to_raw()?;
+        let (payload, deliveries_to) = payload.to_raw();
+        if let Some(ref deliveries_to) = deliveries_to {
+            check_country_codes(&self.countries, deliveries_to, "deliveries_to")?;
+        }
 
         self.execute_query(packages.filter(id.eq(id_arg)))
-            .and_then(|packages_: PackagesRaw| packages_.to_packages(&self.countries))
+            .and_then(|packages_: PackagesRaw| self.to_package(packages_))
             .and_then(|packages_: Packages| acl::check(&*self.acl, Resource::Packages, Action::Update, self, Some(&packages_)))
             .and_then(|_| {
                 let filtered = packages.filter(id.eq(id_arg));
 
-                let query = diesel::update(filtered).set(payload.clone());
-                query
-                    .get_result::<PackagesRaw>(self.db_conn)
-                    .map_err(|e| Error::from(e).into())
-                    .and_then(|packages_: PackagesRaw| packages_.to_packages(&self.countries))
+                let packages_: PackagesRaw = if payload.is_empty() {
+                    self.execute_query(filtered)?
+                } else {
+                    self.execute_query(diesel::update(filtered).set(payload.clone()))?
+                };
+
+                if let Some(deliveries_to) = deliveries_to {
+                    set_package_deliveries_to(self.db_conn, id_arg, deliveries_to)?;
+                }
+
+                self.to_package(packages_)
             })
             .map_err(|e: FailureError| e.context(format!("Updating packages payload {:?} failed.", payload)).into())
     }
@@ -171,12 +198,14 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
         acl::check(&*self.acl, Resource::Packages, Action::Delete, self, None)?;
 
+        let used_codes = package_deliveries_to_of(&get_package_deliveries_to(self.db_conn, &[id_arg])?, id_arg);
+
         let filtered = packages.filter(id.eq(id_arg));
         let query = diesel::delete(filtered);
         query
             .get_result::<PackagesRaw>(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|packages_: PackagesRaw| packages_.to_packages(&self.countries))
+            .map(|packages_: PackagesRaw| packages_.to_packages(&used_codes, &self.countries))
             .map_err(move |e| e.context(format!("delete packages id: {}.", id_arg)).into())
     }
 }
@@ -184,10 +213,59 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Packages>
     for PackagesRepoImpl<'a, T>
 {
-    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&Packages>) -> bool {
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&Packages>) -> bool {
         match *scope {
             Scope::All => true,
             Scope::Owned => false,
         }
     }
 }
+
+/// Returns rows of countries the packages can be delivered to
+pub fn get_package_deliveries_to<T: Connection<Backend = Pg>>(
+    db_conn: &T,
+    package_ids: &[PackageId],
+) -> RepoResult<Vec<PackageDeliveryTo>> {
+    let query = DslDeliveriesTo::packages_deliveries_to
+        .filter(DslDeliveriesTo::package_id.eq_any(package_ids))
+        .order((DslDeliveriesTo::package_id, DslDeliveriesTo::country_alpha3));
+
+    query.get_results(db_conn).map_err(|e| Error::from(e).into())
+}
+
+/// Replaces countries the package can be delivered to
+pub fn set_package_deliveries_to<T: Connection<Backend = Pg>>(
+    db_conn: &T,
+    package_id_arg: PackageId,
+    codes: Vec<Alpha3>,
+) -> RepoResult<()> {
+    let filtered = DslDeliveriesTo::packages_deliveries_to.filter(DslDeliveriesTo::package_id.eq(package_id_arg));
+    diesel::delete(filtered).execute(db_conn).map_err(Error::from)?;
+
+    let mut rows: Vec<PackageDeliveryTo> = vec![];
+    for code in codes {
+        if !rows.iter().any(|row| row.country_alpha3 == code) {
+            rows.push(PackageDeliveryTo {
+                package_id: package_id_arg,
+                country_alpha3: code,
+            });
+        }
+    }
+
+    if !rows.is_empty() {
+        diesel::insert_into(DslDeliveriesTo::packages_deliveries_to)
+            .values(&rows)
+            .execute(db_conn)
+            .map_err(Error::from)?;
+    }
+
+    Ok(())
+}
+
+/// Picks codes of the countries the package can be delivered to out of the loaded rows
+pub fn package_deliveries_to_of(rows: &[PackageDeliveryTo], package_id_arg: PackageId) -> Vec<Alpha3> {
+    rows.iter()
+        .filter(|row| row.package_id == package_id_arg)
+        .map(|row| row.country_alpha3.clone())
+        .collect()
+}
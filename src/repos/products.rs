@@ -3,26 +3,24 @@
 
 use diesel;
 use diesel::connection::AnsiTransactionManager;
-use diesel::dsl::sql;
-use diesel::pg::types::sql_types::Array;
 use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_dsl::LoadQuery;
 use diesel::query_dsl::RunQueryDsl;
-use diesel::sql_types::VarChar;
 use diesel::Connection;
 use errors::Error;
 use failure::Error as FailureError;
 
-use stq_types::{BaseProductId, CompanyPackageId, ShippingId, UserId};
+use stq_types::{Alpha3, BaseProductId, CompanyPackageId, ShippingId, UserId};
 
 use models::authorization::*;
 use models::countries::Country;
 use models::{
-    AvailablePackageForUser, CompaniesPackagesRaw, CompanyRaw, NewProducts, NewProductsRaw, PackagesRaw, Products, ProductsRaw,
-    ShippingVariant, UpdateProducts, UserRole,
+    owns_store, AvailablePackageForUser, CompaniesPackagesRaw, CompanyRaw, NewProducts, PackagesRaw, ProductDeliveryTo, Products,
+    ProductsRaw, ShippingVariant, UpdateProducts, UserRole,
 };
 
+use repos::countries::check_country_codes;
 use repos::legacy_acl::*;
 use repos::types::RepoResult;
 use repos::*;
@@ -30,7 +28,9 @@ use schema::companies::dsl as DslCompanies;
 use schema::companies_packages::dsl as DslCompaniesPackages;
 use schema::packages::dsl as DslPackages;
 use schema::products::dsl as DslProducts;
-use schema::roles::dsl as Roles;
+use schema::products_deliveries_to::dsl as DslDeliveriesTo;
+
+const DELIVERIES_TO_CHUNK_SIZE: usize = 10_000;
 
 pub struct ProductsWithAvailableCountries(pub Products, pub Vec<Alpha3>);
 
@@ -92,17 +92,40 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     fn execute_query<Ty: Send + 'static, U: LoadQuery<T, Ty> + Send + 'static>(&self, query: U) -> RepoResult<Ty> {
         query.get_result::<Ty>(self.db_conn).map_err(|e| Error::from(e).into())
     }
+
+    fn to_products(&self, raws: Vec<ProductsRaw>) -> RepoResult<Vec<Products>> {
+        let product_ids = raws.iter().map(|raw| raw.id).collect::<Vec<_>>();
+        let deliveries = get_product_deliveries_to(self.db_conn, &product_ids)?;
+
+        Ok(raws
+            .into_iter()
+            .map(|raw| {
+                let used_codes = product_deliveries_to_of(&deliveries, raw.id);
+                raw.to_products(used_codes)
+            })
+            .collect())
+    }
+
+    fn to_product(&self, raw: ProductsRaw) -> RepoResult<Products> {
+        let used_codes = product_deliveries_to_of(&get_product_deliveries_to(self.db_conn, &[raw.id])?, raw.id);
+        Ok(raw.to_products(used_codes))
+    }
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ProductsRepo for ProductsRepoImpl<'a, T> {
     fn create(&self, payload: NewProducts) -> RepoResult<Products> {
         debug!("create new products {:?}.", payload);
-        let payload = payload.to_raw()?;
+        let (payload, deliveries_to) = payload.to_raw();
+        check_country_codes(&self.countries, &deliveries_to, "deliveries_to")?;
+
         let query = diesel::insert_into(DslProducts::products).values(&payload);
         query
             .get_result::<ProductsRaw>(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|products_| products_.to_products())
+            .and_then(|products_| {
+                set_product_deliveries_to(self.db_conn, products_.id, deliveries_to)?;
+                self.to_product(products_)
+            })
             .and_then(|product| {
                 acl::check(&*self.acl, Resource::Products, Action::Create, self, Some(&product))?;
                 Ok(product)
@@ -112,19 +135,22 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
     fn create_many(&self, payload: Vec<NewProducts>) -> RepoResult<Vec<Products>> {
         debug!("create many new products {:?}.", payload);
-        let payload = payload
-            .into_iter()
-            .map(|v| v.to_raw().map_err(From::from))
-            .collect::<RepoResult<Vec<NewProductsRaw>>>()?;
+        let (payload, deliveries_to): (Vec<_>, Vec<_>) = payload.into_iter().map(|v| v.to_raw()).unzip();
+        for codes in &deliveries_to {
+            check_country_codes(&self.countries, codes, "deliveries_to")?;
+        }
 
         let query = diesel::insert_into(DslProducts::products).values(&payload);
         query
             .get_results::<ProductsRaw>(self.db_conn)
             .map_err(|e| Error::from(e).into())
             .and_then(|products_: Vec<ProductsRaw>| {
+                // rows are returned in the order of the inserted values
+                let deliveries = products_.iter().map(|product| product.id).zip(deliveries_to).collect();
+                add_products_deliveries_to(self.db_conn, deliveries)?;
+
                 let mut new_products = vec![];
-                for product in products_ {
-                    let product = product.to_products()?;
+                for product in self.to_products(products_)? {
                     acl::check(&*self.acl, Resource::Products, Action::Create, self, Some(&product))?;
                     new_products.push(product);
                 }
@@ -147,8 +173,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .map_err(|e| Error::from(e).into())
             .and_then(|products_: Vec<ProductsRaw>| {
                 let mut new_products = vec![];
-                for product in products_ {
-                    let product = product.to_products()?;
+                for product in self.to_products(products_)? {
                     acl::check(&*self.acl, Resource::Products, Action::Read, self, Some(&product))?;
                     new_products.push(product);
                 }
@@ -175,17 +200,24 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
         query
             .get_results::<(ProductsRaw, (CompaniesPackagesRaw, PackagesRaw))>(self.db_conn)
             .map_err(|e| Error::from(e).into())
-            .and_then(|results| {
+            .and_then(|results: Vec<(ProductsRaw, (CompaniesPackagesRaw, PackagesRaw))>| {
+                let product_ids = results.iter().map(|(product_raw, _)| product_raw.id).collect::<Vec<_>>();
+                let product_deliveries = get_product_deliveries_to(self.db_conn, &product_ids)?;
+                let package_ids = results.iter().map(|(_, (_, package_raw))| package_raw.id).collect::<Vec<_>>();
+                let package_deliveries = get_package_deliveries_to(self.db_conn, &package_ids)?;
+
                 let mut data = vec![];
                 for result in results {
                     let (product_raw, (_, package_raw)) = result;
+                    let package_codes = package_deliveries_to_of(&package_deliveries, package_raw.id);
                     let countries_codes = package_raw
-                        .to_packages(&self.countries)?
+                        .to_packages(&package_codes, &self.countries)
                         .deliveries_to
                         .into_iter()
                         .map(|c| c.alpha3)
                         .collect();
-                    let element = ProductsWithAvailableCountries(product_raw.to_products()?, countries_codes);
+                    let product_codes = product_deliveries_to_of(&product_deliveries, product_raw.id);
+                    let element = ProductsWithAvailableCountries(product_raw.to_products(product_codes), countries_codes);
 
                     data.push(element);
                 }
@@ -207,11 +239,13 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             base_product_id_arg, user_country
         );
 
-        let pg_countries: Vec<String> = vec![user_country.clone()].into_iter().map(|c| c.0).collect();
+        let product_ids = DslDeliveriesTo::products_deliveries_to
+            .filter(DslDeliveriesTo::country_alpha3.eq(user_country.clone()))
+            .select(DslDeliveriesTo::product_id);
 
         let query = DslProducts::products
             .filter(DslProducts::base_product_id.eq(base_product_id_arg))
-            .filter(sql("products.deliveries_to ?| ").bind::<Array<VarChar>, _>(pg_countries))
+            .filter(DslProducts::id.eq_any(product_ids))
             .inner_join(
                 DslCompaniesPackages::companies_packages
                     .inner_join(DslCompanies::companies)
@@ -336,8 +370,10 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .into_boxed();
 
         if let Some(delivery_to) = delivery_to {
-            let pg_str = get_pg_str_json_array(vec![delivery_to.clone()]);
-            query = query.filter(sql(format!("products.deliveries_to ?| {}", pg_str).as_ref()));
+            let product_ids = DslDeliveriesTo::products_deliveries_to
+                .filter(DslDeliveriesTo::country_alpha3.eq(delivery_to))
+                .select(DslDeliveriesTo::product_id);
+            query = query.filter(DslProducts::id.eq_any(product_ids));
         };
 
         let query = query.order(DslCompanies::label);
@@ -375,29 +411,48 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
         payload: UpdateProducts,
     ) -> RepoResult<Products> {
         debug!("Updating products payload {:?}.", payload);
-        let payload = payload.to_raw()?;
+        let (payload, deliveries_to) = payload.to_raw();
+        if let Some(ref deliveries_to) = deliveries_to {
+            check_country_codes(&self.countries, deliveries_to, "deliveries_to")?;
+        }
+
         self.execute_query(
             DslProducts::products
                 .filter(DslProducts::base_product_id.eq(base_product_id_arg))
                 .filter(DslProducts::company_package_id.eq(company_package_id_arg)),
         )
-        .and_then(|products_: ProductsRaw| products_.to_products())
+        .and_then(|products_: ProductsRaw| self.to_product(products_))
         .and_then(|product: Products| acl::check(&*self.acl, Resource::Products, Action::Update, self, Some(&product)))
         .and_then(|_| {
             let filter = DslProducts::products
                 .filter(DslProducts::base_product_id.eq(base_product_id_arg))
                 .filter(DslProducts::company_package_id.eq(company_package_id_arg));
 
-            let query = diesel::update(filter).set(&payload);
-            query.get_result::<ProductsRaw>(self.db_conn).map_err(|e| Error::from(e).into())
+            let products_: ProductsRaw = if payload.is_empty() {
+                self.execute_query(filter)?
+            } else {
+                self.execute_query(diesel::update(filter).set(&payload))?
+            };
+
+            if let Some(deliveries_to) = deliveries_to {
+                set_product_deliveries_to(self.db_conn, products_.id, deliveries_to)?;
+            }
+
+            self.to_product(products_)
         })
-        .and_then(|products_| products_.to_products())
         .map_err(|e: FailureError| e.context(format!("Updating products payload {:?} failed.", payload)).into())
     }
 
     fn delete(&self, base_product_id_arg: BaseProductId) -> RepoResult<Vec<Products>> {
         debug!("delete products {:?}.", base_product_id_arg);
 
+        let product_ids = DslProducts::products
+            .filter(DslProducts::base_product_id.eq(base_product_id_arg))
+            .select(DslProducts::id)
+            .get_results::<ShippingId>(self.db_conn)
+            .map_err(Error::from)?;
+        let deliveries = get_product_deliveries_to(self.db_conn, &product_ids)?;
+
         let filtered = DslProducts::products.filter(DslProducts::base_product_id.eq(base_product_id_arg));
         let query = diesel::delete(filtered);
 
@@ -407,7 +462,8 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .and_then(|products_: Vec<ProductsRaw>| {
                 let mut delete_products = vec![];
                 for product in products_ {
-                    let product = product.to_products()?;
+                    let used_codes = product_deliveries_to_of(&deliveries, product.id);
+                    let product = product.to_products(used_codes);
                     acl::check(&*self.acl, Resource::Products, Action::Delete, self, Some(&product))?;
                     delete_products.push(product);
                 }
@@ -423,25 +479,70 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Products>
     for ProductsRepoImpl<'a, T>
 {
-    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&Products>) -> bool {
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&Products>) -> bool {
         match *scope {
             Scope::All => true,
-            Scope::Owned => {
-                if let Some(obj) = obj {
-                    Roles::roles
-                        .filter(Roles::user_id.eq(user_id_arg))
-                        .get_results::<UserRole>(self.db_conn)
-                        .map_err(|e| Error::from(e).into())
-                        .map(|user_roles_arg| {
-                            user_roles_arg
-                                .iter()
-                                .any(|user_role_arg| user_role_arg.data.clone().map(|data| data == obj.store_id.0).unwrap_or_default())
-                        })
-                        .unwrap_or_else(|_: FailureError| false)
-                } else {
-                    false
-                }
+            Scope::Owned => obj.map(|obj| owns_store(user_roles, obj.store_id)).unwrap_or(false),
+        }
+    }
+}
+
+/// Returns rows of countries the products can be delivered to
+pub fn get_product_deliveries_to<T: Connection<Backend = Pg>>(
+    db_conn: &T,
+    product_ids: &[ShippingId],
+) -> RepoResult<Vec<ProductDeliveryTo>> {
+    let query = DslDeliveriesTo::products_deliveries_to
+        .filter(DslDeliveriesTo::product_id.eq_any(product_ids))
+        .order((DslDeliveriesTo::product_id, DslDeliveriesTo::country_alpha3));
+
+    query.get_results(db_conn).map_err(|e| Error::from(e).into())
+}
+
+/// Replaces countries the product can be delivered to
+pub fn set_product_deliveries_to<T: Connection<Backend = Pg>>(
+    db_conn: &T,
+    product_id_arg: ShippingId,
+    codes: Vec<Alpha3>,
+) -> RepoResult<()> {
+    let filtered = DslDeliveriesTo::products_deliveries_to.filter(DslDeliveriesTo::product_id.eq(product_id_arg));
+    diesel::delete(filtered).execute(db_conn).map_err(Error::from)?;
+
+    add_products_deliveries_to(db_conn, vec![(product_id_arg, codes)])
+}
+
+/// Saves countries the products can be delivered to, repeated codes of a product are saved once
+pub fn add_products_deliveries_to<T: Connection<Backend = Pg>>(db_conn: &T, deliveries: Vec<(ShippingId, Vec<Alpha3>)>) -> RepoResult<()> {
+    let mut rows: Vec<ProductDeliveryTo> = vec![];
+    for (product_id_arg, codes) in deliveries {
+        let mut product_codes: Vec<Alpha3> = vec![];
+        for code in codes {
+            if !product_codes.contains(&code) {
+                product_codes.push(code);
             }
         }
+
+        rows.extend(product_codes.into_iter().map(|country_alpha3| ProductDeliveryTo {
+            product_id: product_id_arg,
+            country_alpha3,
+        }));
     }
+
+    // keeps the number of bind parameters of a query under the limit of postgres
+    for chunk in rows.chunks(DELIVERIES_TO_CHUNK_SIZE) {
+        diesel::insert_into(DslDeliveriesTo::products_deliveries_to)
+            .values(chunk)
+            .execute(db_conn)
+            .map_err(Error::from)?;
+    }
+
+    Ok(())
+}
+
+/// Picks codes of the countries the product can be delivered to out of the loaded rows
+pub fn product_deliveries_to_of(rows: &[ProductDeliveryTo], product_id_arg: ShippingId) -> Vec<Alpha3> {
+    rows.iter()
+        .filter(|row| row.product_id == product_id_arg)
+        .map(|row| row.country_alpha3.clone())
+        .collect()
 }
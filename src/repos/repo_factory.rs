@@ -5,55 +5,106 @@ use failure::Error as FailureError;
 use std::sync::Arc;
 use stq_cache::cache::{Cache, CacheSingle};
 use stq_types::*;
+use uuid::Uuid;
 
 use models::*;
 use repos::legacy_acl::{Acl, SystemACL};
 use repos::*;
 
 pub trait ReposFactory<C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static>: Clone + Send + 'static {
+    fn create_api_keys_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ApiKeysRepo + 'a>;
+    fn create_api_keys_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ApiKeysRepo + 'a>;
+    fn create_audit_log_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<AuditLogRepo + 'a>;
+    fn create_audit_log_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<AuditLogRepo + 'a>;
     fn create_companies_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<CompaniesRepo + 'a>;
     fn create_companies_packages_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<CompaniesPackagesRepo + 'a>;
     fn create_countries_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<CountriesRepo + 'a>;
+    fn create_outbox_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<OutboxRepo + 'a>;
     fn create_products_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ProductsRepo + 'a>;
     fn create_packages_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<PackagesRepo + 'a>;
     fn create_pickups_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<PickupsRepo + 'a>;
     fn create_shipping_rates_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ShippingRatesRepo + 'a>;
     fn create_users_addresses_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserAddressesRepo + 'a>;
+    fn create_user_data_erasures_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserDataErasuresRepo + 'a>;
     fn create_user_roles_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserRolesRepo + 'a>;
     fn create_user_roles_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserRolesRepo + 'a>;
+    fn create_webhook_deliveries_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WebhookDeliveriesRepo + 'a>;
+    fn create_webhook_deliveries_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<WebhookDeliveriesRepo + 'a>;
+    fn create_webhook_subscriptions_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WebhookSubscriptionsRepo + 'a>;
+    fn create_webhook_subscriptions_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<WebhookSubscriptionsRepo + 'a>;
+    /// Invalidates cached roles of the user, must be called after the roles are changed
+    fn invalidate_user_roles(&self, user_id: UserId);
+    /// Invalidates cached country tree on every instance, must be called after a country is created
+    fn invalidate_countries(&self);
+    /// Invalidates cached rates of the direction, must be called after the rates are replaced or deleted
+    fn invalidate_shipping_rates(&self, company_package_id: CompanyPackageId, delivery_from: &Alpha3, delivery_to: &Alpha3);
+    /// Returns factory creating repos that also allow everything granted by the api key of the request
+    fn with_api_key_permissions(&self, permissions: Vec<Permission>) -> Self;
+    /// Returns factory creating repos that check permissions of the user with the resolved roles instead of loading them
+    fn with_user_roles(&self, user_id: UserId, user_roles: Vec<UserRole>) -> Self;
 }
 
-pub struct ReposFactoryImpl<C1, C2>
+pub struct ReposFactoryImpl<C1, C2, C3, C4, C5>
 where
-    C1: CacheSingle<Country>,
-    C2: Cache<Vec<DeliveryRole>>,
+    C1: CacheSingle<VersionedCountry>,
+    C2: Cache<Vec<UserRole>>,
+    C3: Cache<ShippingRates>,
+    C4: CacheSingle<Uuid>,
+    C5: Cache<Uuid>,
 {
-    country_cache: Arc<CountryCacheImpl<C1>>,
+    country_cache: Arc<CountryCacheImpl<C1, C4>>,
     roles_cache: Arc<RolesCacheImpl<C2>>,
+    shipping_rates_cache: Arc<ShippingRatesCacheImpl<C3, C5>>,
+    user_address_cipher: Arc<UserAddressCipher>,
+    acl_policy: Arc<AclPolicy>,
+    api_key_permissions: Option<Arc<Vec<Permission>>>,
+    user_roles: Option<(UserId, Arc<Vec<UserRole>>)>,
 }
 
-impl<C1, C2> Clone for ReposFactoryImpl<C1, C2>
+impl<C1, C2, C3, C4, C5> Clone for ReposFactoryImpl<C1, C2, C3, C4, C5>
 where
-    C1: CacheSingle<Country>,
-    C2: Cache<Vec<DeliveryRole>>,
+    C1: CacheSingle<VersionedCountry>,
+    C2: Cache<Vec<UserRole>>,
+    C3: Cache<ShippingRates>,
+    C4: CacheSingle<Uuid>,
+    C5: Cache<Uuid>,
 {
     fn clone(&self) -> Self {
         Self {
             country_cache: self.country_cache.clone(),
             roles_cache: self.roles_cache.clone(),
+            shipping_rates_cache: self.shipping_rates_cache.clone(),
+            user_address_cipher: self.user_address_cipher.clone(),
+            acl_policy: self.acl_policy.clone(),
+            api_key_permissions: self.api_key_permissions.clone(),
+            user_roles: self.user_roles.clone(),
         }
     }
 }
 
-impl<C1, C2> ReposFactoryImpl<C1, C2>
+impl<C1, C2, C3, C4, C5> ReposFactoryImpl<C1, C2, C3, C4, C5>
 where
-    C1: CacheSingle<Country> + Send + Sync + 'static,
-    C2: Cache<Vec<DeliveryRole>> + Send + Sync + 'static,
+    C1: CacheSingle<VersionedCountry> + Send + Sync + 'static,
+    C2: Cache<Vec<UserRole>> + Send + Sync + 'static,
+    C3: Cache<ShippingRates> + Send + Sync + 'static,
+    C4: CacheSingle<Uuid> + Send + Sync + 'static,
+    C5: Cache<Uuid> + Send + Sync + 'static,
 {
-    pub fn new(country_cache: CountryCacheImpl<C1>, roles_cache: RolesCacheImpl<C2>) -> Self {
+    pub fn new(
+        country_cache: CountryCacheImpl<C1, C4>,
+        roles_cache: RolesCacheImpl<C2>,
+        shipping_rates_cache: ShippingRatesCacheImpl<C3, C5>,
+        user_address_cipher: UserAddressCipher,
+        acl_policy: Arc<AclPolicy>,
+    ) -> Self {
         Self {
             country_cache: Arc::new(country_cache),
             roles_cache: Arc::new(roles_cache),
+            shipping_rates_cache: Arc::new(shipping_rates_cache),
+            user_address_cipher: Arc::new(user_address_cipher),
+            acl_policy,
+            api_key_permissions: None,
+            user_roles: None,
         }
     }
 
@@ -61,11 +112,8 @@ where
         &self,
         id: UserId,
         db_conn: &'a C,
-    ) -> Vec<DeliveryRole> {
-        self.create_user_roles_repo_with_sys_acl(db_conn)
-            .list_for_user(id)
-            .ok()
-            .unwrap_or_default()
+    ) -> Result<Vec<UserRole>, FailureError> {
+        self.create_user_roles_repo_with_sys_acl(db_conn).get_by_user_id(id)
     }
 
     fn get_acl<'a, T, C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static>(
@@ -73,22 +121,80 @@ where
         db_conn: &'a C,
         user_id: Option<UserId>,
     ) -> Box<Acl<Resource, Action, Scope, FailureError, T>> {
-        user_id.map_or(
-            Box::new(UnauthorizedAcl::default()) as Box<Acl<Resource, Action, Scope, FailureError, T>>,
-            |id| {
-                let roles = self.get_roles(id, db_conn);
-                (Box::new(ApplicationAcl::new(roles, id)) as Box<Acl<Resource, Action, Scope, FailureError, T>>)
-            },
-        )
+        create_acl(&self.acl_policy, &self.api_key_permissions, user_id, |id| {
+            resolved_roles(&self.user_roles, id).map_or_else(|| self.get_roles(id, db_conn), Ok)
+        })
     }
 }
 
-impl<C, C1, C2> ReposFactory<C> for ReposFactoryImpl<C1, C2>
+/// Roles of the user resolved for the request, `None` if they were resolved for another user or not resolved at all
+pub fn resolved_roles(user_roles: &Option<(UserId, Arc<Vec<UserRole>>)>, user_id: UserId) -> Option<Vec<UserRole>> {
+    match *user_roles {
+        Some((roles_user_id, ref roles)) if roles_user_id == user_id => Some(roles.as_ref().clone()),
+        _ => None,
+    }
+}
+
+/// Creates ACL of the user, `get_roles` is called only for authorized users.
+/// Everything granted by the api key permissions is allowed as well.
+/// If the roles can't be loaded, every check of the user fails with `Error::Internal`.
+pub fn create_acl<T, F: FnOnce(UserId) -> Result<Vec<UserRole>, FailureError>>(
+    acl_policy: &Arc<AclPolicy>,
+    api_key_permissions: &Option<Arc<Vec<Permission>>>,
+    user_id: Option<UserId>,
+    get_roles: F,
+) -> Box<Acl<Resource, Action, Scope, FailureError, T>> {
+    let user_acl = user_id.map_or(
+        Box::new(UnauthorizedAcl::default()) as Box<Acl<Resource, Action, Scope, FailureError, T>>,
+        |id| match get_roles(id) {
+            Ok(roles) => Box::new(ApplicationAcl::new(acl_policy.clone(), roles, id)) as Box<Acl<Resource, Action, Scope, FailureError, T>>,
+            Err(e) => {
+                error!("Loading roles of user {} failed: {}", id, e);
+                Box::new(FailedRolesAcl::new(id)) as Box<Acl<Resource, Action, Scope, FailureError, T>>
+            }
+        },
+    );
+    match *api_key_permissions {
+        Some(ref permissions) => {
+            Box::new(ApiKeyAcl::new(permissions.clone(), user_acl)) as Box<Acl<Resource, Action, Scope, FailureError, T>>
+        }
+        None => user_acl,
+    }
+}
+
+impl<C, C1, C2, C3, C4, C5> ReposFactory<C> for ReposFactoryImpl<C1, C2, C3, C4, C5>
 where
     C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
-    C1: CacheSingle<Country> + Send + Sync + 'static,
-    C2: Cache<Vec<DeliveryRole>> + Send + Sync + 'static,
+    C1: CacheSingle<VersionedCountry> + Send + Sync + 'static,
+    C2: Cache<Vec<UserRole>> + Send + Sync + 'static,
+    C3: Cache<ShippingRates> + Send + Sync + 'static,
+    C4: CacheSingle<Uuid> + Send + Sync + 'static,
+    C5: Cache<Uuid> + Send + Sync + 'static,
 {
+    fn create_api_keys_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ApiKeysRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ApiKeysRepoImpl::new(db_conn, acl)) as Box<ApiKeysRepo>
+    }
+
+    fn create_api_keys_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ApiKeysRepo + 'a> {
+        Box::new(ApiKeysRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, ApiKey>>,
+        )) as Box<ApiKeysRepo>
+    }
+
+    fn create_audit_log_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<AuditLogRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(AuditLogRepoImpl::new(db_conn, acl)) as Box<AuditLogRepo>
+    }
+
+    fn create_audit_log_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<AuditLogRepo + 'a> {
+        Box::new(AuditLogRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>,
+        )) as Box<AuditLogRepo>
+    }
+
     fn create_companies_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<CompaniesRepo + 'a> {
         let acl = self.get_acl(db_conn, user_id);
         let all_countries = self.create_countries_repo(db_conn, user_id).get_all().ok().unwrap_or_default();
@@ -107,6 +213,13 @@ where
         Box::new(CountriesRepoImpl::new(db_conn, acl, cache)) as Box<CountriesRepo>
     }
 
+    fn create_outbox_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<OutboxRepo + 'a> {
+        Box::new(OutboxRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, OutboxEvent>>,
+        )) as Box<OutboxRepo>
+    }
+
     fn create_products_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ProductsRepo + 'a> {
         let acl = self.get_acl(db_conn, user_id);
         let all_countries = self.create_countries_repo(db_conn, user_id).get_all().ok().unwrap_or_default();
@@ -126,12 +239,19 @@ where
 
     fn create_shipping_rates_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ShippingRatesRepo + 'a> {
         let acl = self.get_acl(db_conn, user_id);
-        Box::new(ShippingRatesRepoImpl::new(db_conn, acl)) as Box<ShippingRatesRepo>
+        let cache = self.shipping_rates_cache.clone();
+        Box::new(ShippingRatesRepoImpl::new(db_conn, acl, cache)) as Box<ShippingRatesRepo>
     }
 
     fn create_users_addresses_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserAddressesRepo + 'a> {
         let acl = self.get_acl(db_conn, user_id);
-        Box::new(UserAddressesRepoImpl::new(db_conn, acl)) as Box<UserAddressesRepo>
+        let cipher = self.user_address_cipher.clone();
+        Box::new(UserAddressesRepoImpl::new(db_conn, acl, cipher)) as Box<UserAddressesRepo>
+    }
+
+    fn create_user_data_erasures_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserDataErasuresRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(UserDataErasuresRepoImpl::new(db_conn, acl)) as Box<UserDataErasuresRepo>
     }
 
     fn create_user_roles_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserRolesRepo + 'a> {
@@ -147,6 +267,56 @@ where
         let cache = self.roles_cache.clone();
         Box::new(UserRolesRepoImpl::new(db_conn, acl, cache)) as Box<UserRolesRepo>
     }
+
+    fn create_webhook_deliveries_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WebhookDeliveriesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(WebhookDeliveriesRepoImpl::new(db_conn, acl)) as Box<WebhookDeliveriesRepo>
+    }
+
+    fn create_webhook_deliveries_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<WebhookDeliveriesRepo + 'a> {
+        Box::new(WebhookDeliveriesRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>,
+        )) as Box<WebhookDeliveriesRepo>
+    }
+
+    fn create_webhook_subscriptions_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WebhookSubscriptionsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(WebhookSubscriptionsRepoImpl::new(db_conn, acl)) as Box<WebhookSubscriptionsRepo>
+    }
+
+    fn create_webhook_subscriptions_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<WebhookSubscriptionsRepo + 'a> {
+        Box::new(WebhookSubscriptionsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, WebhookSubscription>>,
+        )) as Box<WebhookSubscriptionsRepo>
+    }
+
+    fn invalidate_user_roles(&self, user_id: UserId) {
+        self.roles_cache.remove(user_id);
+    }
+
+    fn invalidate_countries(&self) {
+        self.country_cache.remove();
+    }
+
+    fn invalidate_shipping_rates(&self, company_package_id: CompanyPackageId, delivery_from: &Alpha3, delivery_to: &Alpha3) {
+        self.shipping_rates_cache.remove(company_package_id, delivery_from, delivery_to);
+    }
+
+    fn with_api_key_permissions(&self, permissions: Vec<Permission>) -> Self {
+        Self {
+            api_key_permissions: Some(Arc::new(permissions)),
+            ..self.clone()
+        }
+    }
+
+    fn with_user_roles(&self, user_id: UserId, user_roles: Vec<UserRole>) -> Self {
+        Self {
+            user_roles: Some((user_id, Arc::new(user_roles))),
+            ..self.clone()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -155,9 +325,10 @@ pub mod tests {
     extern crate r2d2;
     extern crate stq_http;
 
+    use std::collections::HashMap;
     use std::error::Error;
     use std::fmt;
-    use std::sync::Arc;
+    use std::sync::{Arc, Mutex};
     use std::time::SystemTime;
 
     use diesel::connection::AnsiTransactionManager;
@@ -175,12 +346,15 @@ pub mod tests {
     use futures::Stream;
     use futures_cpupool::CpuPool;
     use r2d2::ManageConnection;
+    use serde_json;
     use tokio_core::reactor::Handle;
 
+    use stq_cache::cache::{Cache, CacheSingle};
     use stq_static_resources::Currency;
     use stq_types::*;
 
     use config::Config;
+    use controller::auth::JwtAuthenticator;
     use controller::context::{DynamicContext, StaticContext};
     use models::*;
     use repos::*;
@@ -195,6 +369,22 @@ pub mod tests {
     pub struct ReposFactoryMock;
 
     impl<C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ReposFactory<C> for ReposFactoryMock {
+        fn create_api_keys_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<ApiKeysRepo + 'a> {
+            Box::new(ApiKeysRepoMock::default()) as Box<ApiKeysRepo>
+        }
+
+        fn create_api_keys_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<ApiKeysRepo + 'a> {
+            Box::new(ApiKeysRepoMock::default()) as Box<ApiKeysRepo>
+        }
+
+        fn create_audit_log_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<AuditLogRepo + 'a> {
+            Box::new(AuditLogRepoMock::default()) as Box<AuditLogRepo>
+        }
+
+        fn create_audit_log_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<AuditLogRepo + 'a> {
+            Box::new(AuditLogRepoMock::default()) as Box<AuditLogRepo>
+        }
+
         fn create_companies_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<CompaniesRepo + 'a> {
             Box::new(CompaniesRepoMock::default()) as Box<CompaniesRepo>
         }
@@ -207,6 +397,10 @@ pub mod tests {
             Box::new(CountriesRepoMock::default()) as Box<CountriesRepo>
         }
 
+        fn create_outbox_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<OutboxRepo + 'a> {
+            Box::new(OutboxRepoMock::default()) as Box<OutboxRepo>
+        }
+
         fn create_products_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<ProductsRepo + 'a> {
             Box::new(ProductsRepoMock::default()) as Box<ProductsRepo>
         }
@@ -227,12 +421,46 @@ pub mod tests {
             Box::new(UserAddressesRepoMock::default()) as Box<UserAddressesRepo>
         }
 
+        fn create_user_data_erasures_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<UserDataErasuresRepo + 'a> {
+            Box::new(UserDataErasuresRepoMock::default()) as Box<UserDataErasuresRepo>
+        }
+
         fn create_user_roles_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<UserRolesRepo + 'a> {
             Box::new(UserRolesRepoMock::default()) as Box<UserRolesRepo>
         }
         fn create_user_roles_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<UserRolesRepo + 'a> {
             Box::new(UserRolesRepoMock::default()) as Box<UserRolesRepo>
         }
+
+        fn create_webhook_deliveries_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<WebhookDeliveriesRepo + 'a> {
+            Box::new(WebhookDeliveriesRepoMock::default()) as Box<WebhookDeliveriesRepo>
+        }
+
+        fn create_webhook_deliveries_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<WebhookDeliveriesRepo + 'a> {
+            Box::new(WebhookDeliveriesRepoMock::default()) as Box<WebhookDeliveriesRepo>
+        }
+
+        fn create_webhook_subscriptions_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<WebhookSubscriptionsRepo + 'a> {
+            Box::new(WebhookSubscriptionsRepoMock::default()) as Box<WebhookSubscriptionsRepo>
+        }
+
+        fn create_webhook_subscriptions_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<WebhookSubscriptionsRepo + 'a> {
+            Box::new(WebhookSubscriptionsRepoMock::default()) as Box<WebhookSubscriptionsRepo>
+        }
+
+        fn invalidate_user_roles(&self, _user_id: UserId) {}
+
+        fn invalidate_countries(&self) {}
+
+        fn invalidate_shipping_rates(&self, _company_package_id: CompanyPackageId, _delivery_from: &Alpha3, _delivery_to: &Alpha3) {}
+
+        fn with_api_key_permissions(&self, _permissions: Vec<Permission>) -> Self {
+            *self
+        }
+
+        fn with_user_roles(&self, _user_id: UserId, _user_roles: Vec<UserRole>) -> Self {
+            *self
+        }
     }
 
     pub fn create_service(
@@ -248,23 +476,291 @@ pub mod tests {
         let client_handle = client.handle();
         let client_stream = client.stream();
         handle.spawn(client_stream.for_each(|_| Ok(())));
-        let static_context = StaticContext::new(db_pool, cpu_pool, client_handle, Arc::new(config), MOCK_REPO_FACTORY);
+        let authenticator = JwtAuthenticator::from_config(config.jwt.as_ref()).unwrap();
+        let static_context = StaticContext::new(
+            db_pool,
+            cpu_pool,
+            client_handle,
+            Arc::new(config),
+            MOCK_REPO_FACTORY,
+            authenticator,
+            Arc::new(AclPolicy::default()),
+        );
         let dynamic_context = DynamicContext::new(user_id, String::default());
 
         Service::new(static_context, dynamic_context)
     }
 
+    pub const MOCK_API_KEY: &str = "mock-api-key";
+
+    #[derive(Clone, Default)]
+    pub struct ApiKeysRepoMock;
+
+    fn create_api_key(id: i32) -> ApiKey {
+        ApiKey {
+            id,
+            name: "orders".to_string(),
+            permissions: vec![permission!(Resource::ShippingRates, Action::Read)],
+            created_by: Some(MOCK_USER_ID),
+            created_at: SystemTime::now(),
+            revoked_at: None,
+        }
+    }
+
+    impl ApiKeysRepo for ApiKeysRepoMock {
+        fn create(&self, payload: NewApiKeyRaw) -> RepoResult<ApiKey> {
+            let mut api_key = create_api_key(1);
+            api_key.name = payload.name;
+            api_key.created_by = payload.created_by;
+            Ok(api_key)
+        }
+
+        fn list(&self) -> RepoResult<Vec<ApiKey>> {
+            Ok(vec![create_api_key(1)])
+        }
+
+        fn find_active_by_hash(&self, key_hash: String) -> RepoResult<Option<ApiKey>> {
+            Ok(if key_hash == hash_api_key(MOCK_API_KEY) {
+                Some(create_api_key(1))
+            } else {
+                None
+            })
+        }
+
+        fn revoke(&self, id: i32) -> RepoResult<ApiKey> {
+            let mut api_key = create_api_key(id);
+            api_key.revoked_at = Some(SystemTime::now());
+            Ok(api_key)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct AuditLogRepoMock;
+
+    impl AuditLogRepo for AuditLogRepoMock {
+        fn create(&self, payload: NewAuditLogEntry) -> RepoResult<AuditLogEntry> {
+            Ok(AuditLogEntry {
+                id: 1,
+                user_id: payload.user_id,
+                correlation_token: payload.correlation_token,
+                resource: payload.resource,
+                action: payload.action,
+                before: payload.before,
+                after: payload.after,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn search(&self, search: AuditLogSearch) -> RepoResult<Vec<AuditLogEntry>> {
+            Ok(vec![AuditLogEntry {
+                id: 1,
+                user_id: search.user_id,
+                correlation_token: search.correlation_token.unwrap_or_default(),
+                resource: search.resource.unwrap_or(Resource::Companies),
+                action: search.action.unwrap_or(Action::Update),
+                before: None,
+                after: None,
+                created_at: SystemTime::now(),
+            }])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct OutboxRepoMock;
+
+    impl OutboxRepo for OutboxRepoMock {
+        fn create(&self, payload: NewOutboxEvent) -> RepoResult<OutboxEvent> {
+            Ok(OutboxEvent {
+                event_type: payload.event_type,
+                payload: payload.payload,
+                correlation_token: payload.correlation_token,
+                ..create_outbox_event(1)
+            })
+        }
+
+        fn list_pending(&self, _limit: i64) -> RepoResult<Vec<OutboxEvent>> {
+            Ok(vec![])
+        }
+
+        fn mark_sent(&self, id: i32) -> RepoResult<OutboxEvent> {
+            let mut event = create_outbox_event(id);
+            event.attempts = 1;
+            event.sent_at = Some(SystemTime::now());
+            Ok(event)
+        }
+
+        fn mark_failed(&self, id: i32, error: String, next_attempt_at: SystemTime) -> RepoResult<OutboxEvent> {
+            let mut event = create_outbox_event(id);
+            event.attempts = 1;
+            event.last_error = Some(error);
+            event.next_attempt_at = next_attempt_at;
+            Ok(event)
+        }
+
+        fn list_pending_webhooks(&self, _limit: i64) -> RepoResult<Vec<OutboxEvent>> {
+            Ok(vec![])
+        }
+
+        fn mark_webhooks_created(&self, id: i32) -> RepoResult<OutboxEvent> {
+            let mut event = create_outbox_event(id);
+            event.webhooks_created_at = Some(SystemTime::now());
+            Ok(event)
+        }
+
+        fn list_delivered_endpoints(&self, _id: i32) -> RepoResult<Vec<String>> {
+            Ok(vec![])
+        }
+
+        fn mark_endpoint_delivered(&self, _id: i32, _endpoint: String) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn acquire_lease(&self, _holder: &str, _expires_at: SystemTime) -> RepoResult<bool> {
+            Ok(true)
+        }
+    }
+
+    fn create_outbox_event(id: i32) -> OutboxEvent {
+        OutboxEvent {
+            id,
+            event_type: "shipping_deleted".to_string(),
+            payload: serde_json::Value::Null,
+            correlation_token: String::new(),
+            created_at: SystemTime::now(),
+            attempts: 0,
+            next_attempt_at: SystemTime::now(),
+            last_error: None,
+            sent_at: None,
+            webhooks_created_at: None,
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct WebhookSubscriptionsRepoMock;
+
+    impl WebhookSubscriptionsRepo for WebhookSubscriptionsRepoMock {
+        fn create(&self, payload: NewWebhookSubscriptionRaw) -> RepoResult<WebhookSubscription> {
+            Ok(WebhookSubscription {
+                url: payload.url,
+                event_types: payload.event_types,
+                secret: payload.secret,
+                created_by: payload.created_by,
+                ..create_webhook_subscription(1)
+            })
+        }
+
+        fn list(&self) -> RepoResult<Vec<WebhookSubscription>> {
+            Ok(vec![create_webhook_subscription(1)])
+        }
+
+        fn list_by_event_type(&self, event_type: String) -> RepoResult<Vec<WebhookSubscription>> {
+            Ok(vec![create_webhook_subscription(1)]
+                .into_iter()
+                .filter(|subscription| subscription.is_subscribed_to(&event_type))
+                .collect())
+        }
+
+        fn delete(&self, id: i32) -> RepoResult<WebhookSubscription> {
+            Ok(create_webhook_subscription(id))
+        }
+    }
+
+    fn create_webhook_subscription(id: i32) -> WebhookSubscription {
+        WebhookSubscription {
+            id,
+            url: "http://orders/delivery_events".to_string(),
+            event_types: vec!["rates_replaced".to_string()],
+            secret: "secret".to_string(),
+            created_by: Some(MOCK_USER_ID),
+            created_at: SystemTime::now(),
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct WebhookDeliveriesRepoMock;
+
+    impl WebhookDeliveriesRepo for WebhookDeliveriesRepoMock {
+        fn create_many(&self, payload: Vec<NewWebhookDelivery>) -> RepoResult<Vec<WebhookDelivery>> {
+            Ok(payload
+                .into_iter()
+                .enumerate()
+                .map(|(index, new_delivery)| WebhookDelivery {
+                    subscription_id: new_delivery.subscription_id,
+                    outbox_event_id: new_delivery.outbox_event_id,
+                    ..create_webhook_delivery(index as i32 + 1)
+                })
+                .collect())
+        }
+
+        fn list_by_subscription(&self, subscription_id: i32) -> RepoResult<Vec<WebhookDelivery>> {
+            Ok(vec![WebhookDelivery {
+                subscription_id,
+                ..create_webhook_delivery(1)
+            }])
+        }
+
+        fn list_pending(&self, _limit: i64) -> RepoResult<Vec<PendingWebhookDelivery>> {
+            Ok(vec![])
+        }
+
+        fn mark_delivered(&self, id: i32) -> RepoResult<WebhookDelivery> {
+            let mut delivery = create_webhook_delivery(id);
+            delivery.status = WebhookDeliveryStatus::Delivered;
+            delivery.attempts = 1;
+            delivery.delivered_at = Some(SystemTime::now());
+            Ok(delivery)
+        }
+
+        fn mark_failed(&self, id: i32, error: String, next_attempt_at: SystemTime, dead_letter: bool) -> RepoResult<WebhookDelivery> {
+            let mut delivery = create_webhook_delivery(id);
+            if dead_letter {
+                delivery.status = WebhookDeliveryStatus::DeadLetter;
+            }
+            delivery.attempts = 1;
+            delivery.last_error = Some(error);
+            delivery.next_attempt_at = next_attempt_at;
+            Ok(delivery)
+        }
+
+        fn redeliver(&self, id: i32) -> RepoResult<WebhookDelivery> {
+            Ok(create_webhook_delivery(id))
+        }
+    }
+
+    fn create_webhook_delivery(id: i32) -> WebhookDelivery {
+        WebhookDelivery {
+            id,
+            subscription_id: 1,
+            outbox_event_id: 1,
+            status: WebhookDeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: SystemTime::now(),
+            last_error: None,
+            created_at: SystemTime::now(),
+            delivered_at: None,
+        }
+    }
+
     #[derive(Clone, Default)]
     pub struct UserRolesRepoMock;
 
     impl UserRolesRepo for UserRolesRepoMock {
-        fn list_for_user(&self, user_id_value: UserId) -> RepoResult<Vec<DeliveryRole>> {
+        fn list_for_user(&self, user_id_value: UserId) -> RepoResult<Vec<RoleName>> {
             Ok(match user_id_value.0 {
-                1 => vec![DeliveryRole::Superuser],
-                _ => vec![DeliveryRole::User],
+                1 => vec![RoleName::Superuser],
+                _ => vec![RoleName::User],
             })
         }
 
+        fn get_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Vec<UserRole>> {
+            Ok(vec![UserRole {
+                id: RoleId::new(),
+                user_id: user_id_arg,
+                name: RoleName::User,
+                data: None,
+            }])
+        }
+
         fn create(&self, payload: NewUserRole) -> RepoResult<UserRole> {
             Ok(UserRole {
                 id: RoleId::new(),
@@ -278,7 +774,7 @@ pub mod tests {
             Ok(vec![UserRole {
                 id: RoleId::new(),
                 user_id: user_id_arg,
-                name: DeliveryRole::User,
+                name: RoleName::User,
                 data: None,
             }])
         }
@@ -287,7 +783,7 @@ pub mod tests {
             Ok(UserRole {
                 id,
                 user_id: UserId(1),
-                name: DeliveryRole::User,
+                name: RoleName::User,
                 data: None,
             })
         }
@@ -563,21 +1059,20 @@ pub mod tests {
 
     impl CompaniesRepo for CompaniesRepoMock {
         fn create(&self, payload: NewCompany) -> RepoResult<Company> {
-            let payload = payload.to_raw()?;
+            let (payload, deliveries_from) = payload.to_raw();
 
             let raw = CompanyRaw {
                 id: CompanyId(1),
                 name: payload.name,
                 label: payload.label,
                 description: payload.description,
-                deliveries_from: payload.deliveries_from,
                 logo: payload.logo,
                 currency: payload.currency,
             };
 
             let countries_arg = create_mock_countries();
 
-            Ok(Company::from_raw(raw, &countries_arg)?)
+            Ok(Company::from_raw(raw, &deliveries_from, &countries_arg))
         }
 
         fn list(&self) -> RepoResult<Vec<Company>> {
@@ -715,7 +1210,7 @@ pub mod tests {
 
     impl PackagesRepo for PackagesRepoMock {
         fn create(&self, payload: NewPackages) -> RepoResult<Packages> {
-            let payload = payload.to_raw()?;
+            let (payload, deliveries_to) = payload.to_raw();
 
             let raw = PackagesRaw {
                 id: PackageId(1),
@@ -724,12 +1219,11 @@ pub mod tests {
                 min_size: payload.min_size,
                 max_weight: payload.max_weight,
                 min_weight: payload.min_weight,
-                deliveries_to: payload.deliveries_to,
             };
 
             let countries_arg = create_mock_countries();
 
-            Ok(raw.to_packages(&countries_arg)?)
+            Ok(raw.to_packages(&deliveries_to, &countries_arg))
         }
 
         fn find_deliveries_to(&self, _countries: Vec<Alpha3>) -> RepoResult<Vec<Packages>> {
@@ -847,6 +1341,15 @@ pub mod tests {
             }))
         }
 
+        fn list(&self) -> RepoResult<Vec<CompanyPackage>> {
+            Ok(vec![CompanyPackage {
+                id: CompanyPackageId(1),
+                company_id: CompanyId(1),
+                package_id: PackageId(1),
+                shipping_rate_source: ShippingRateSource::NotAvailable,
+            }])
+        }
+
         /// Returns companies by package id
         fn get_companies(&self, _package_id: PackageId) -> RepoResult<Vec<Company>> {
             Ok(vec![Company {
@@ -893,7 +1396,7 @@ pub mod tests {
             Ok(vec![UserAddress {
                 id: 1,
                 user_id,
-                administrative_area_level_1: None,
+                administrative_area_level_1: Some(" Moscow ".to_string()),
                 administrative_area_level_2: None,
                 country: "None".to_string(),
                 locality: None,
@@ -909,6 +1412,27 @@ pub mod tests {
             }])
         }
 
+        /// Returns user delivery address by id
+        fn get(&self, id: i32) -> RepoResult<Option<UserAddress>> {
+            Ok(Some(UserAddress {
+                id,
+                user_id: UserId(1),
+                administrative_area_level_1: None,
+                administrative_area_level_2: None,
+                country: "Russia".to_string(),
+                locality: Some("Moscow".to_string()),
+                political: None,
+                postal_code: "125009".to_string(),
+                route: Some("Tverskaya".to_string()),
+                street_number: Some("7".to_string()),
+                is_priority: true,
+                address: None,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+                country_code: Some("RUS".to_string()),
+            }))
+        }
+
         /// Create a new user delivery address
         fn create(&self, payload: NewUserAddress) -> RepoResult<UserAddress> {
             Ok(UserAddress {
@@ -971,6 +1495,28 @@ pub mod tests {
                 country_code: None,
             })
         }
+
+        /// Delete all delivery addresses of a user
+        fn delete_by_user_id(&self, user_id: UserId) -> RepoResult<Vec<UserAddress>> {
+            self.list_for_user(user_id)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct UserDataErasuresRepoMock;
+
+    impl UserDataErasuresRepo for UserDataErasuresRepoMock {
+        /// Create a new audit record of user data erasure
+        fn create(&self, payload: NewUserDataErasure) -> RepoResult<UserDataErasure> {
+            Ok(UserDataErasure {
+                id: 1,
+                user_id: payload.user_id,
+                erased_by: payload.erased_by,
+                addresses_count: payload.addresses_count,
+                roles_count: payload.roles_count,
+                created_at: SystemTime::now(),
+            })
+        }
     }
 
     #[derive(Clone, Default)]
@@ -989,6 +1535,10 @@ pub mod tests {
             Ok(vec![])
         }
 
+        fn get_directions(&self, _company_id: CompanyId, _package_id: PackageId) -> RepoResult<Vec<(CompanyPackageId, Alpha3, Alpha3)>> {
+            Ok(vec![])
+        }
+
         fn get_multiple_rates(
             &self,
             company_package_id: CompanyPackageId,
@@ -1096,13 +1646,19 @@ pub mod tests {
     }
 
     #[derive(Default)]
-    pub struct MockConnectionManager;
+    pub struct MockConnectionManager {
+        /// Fail to connect, like a database that is down
+        pub unavailable: bool,
+    }
 
     impl ManageConnection for MockConnectionManager {
         type Connection = MockConnection;
         type Error = MockError;
 
         fn connect(&self) -> Result<MockConnection, MockError> {
+            if self.unavailable {
+                return Err(MockError {});
+            }
             Ok(MockConnection::default())
         }
 
@@ -1133,4 +1689,62 @@ pub mod tests {
             None
         }
     }
+
+    /// Cache keeping values in process, stands for Redis shared by the instances
+    pub struct MockCache<T> {
+        values: Mutex<HashMap<String, T>>,
+    }
+
+    impl<T> Default for MockCache<T> {
+        fn default() -> Self {
+            Self {
+                values: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    impl<T: Clone> Cache<T> for MockCache<T> {
+        type Error = MockError;
+
+        fn get(&self, key: &str) -> Result<Option<T>, MockError> {
+            Ok(self.values.lock().unwrap().get(key).cloned())
+        }
+
+        fn set(&self, key: &str, value: T) -> Result<(), MockError> {
+            self.values.lock().unwrap().insert(key.to_string(), value);
+            Ok(())
+        }
+
+        fn remove(&self, key: &str) -> Result<bool, MockError> {
+            Ok(self.values.lock().unwrap().remove(key).is_some())
+        }
+    }
+
+    /// Single value cache keeping the value in process, stands for Redis shared by the instances
+    pub struct MockCacheSingle<T> {
+        value: Mutex<Option<T>>,
+    }
+
+    impl<T> Default for MockCacheSingle<T> {
+        fn default() -> Self {
+            Self { value: Mutex::new(None) }
+        }
+    }
+
+    impl<T: Clone> CacheSingle<T> for MockCacheSingle<T> {
+        type Error = MockError;
+
+        fn get(&self) -> Result<Option<T>, MockError> {
+            Ok(self.value.lock().unwrap().clone())
+        }
+
+        fn set(&self, value: T) -> Result<(), MockError> {
+            *self.value.lock().unwrap() = Some(value);
+            Ok(())
+        }
+
+        fn remove(&self) -> Result<bool, MockError> {
+            Ok(self.value.lock().unwrap().take().is_some())
+        }
+    }
 }
@@ -0,0 +1,171 @@
+//! ShippingRatesCache is a module that caches received from db shipping rates of the company package direction
+//! Rates are stored under a key with the version of the direction. Invalidation replaces the version,
+//! so rates read from the db before the change and written to the cache after it are never read again.
+
+use failure::Fail;
+use stq_cache::cache::Cache;
+use stq_types::{Alpha3, CompanyPackageId};
+use uuid::Uuid;
+
+use metrics;
+use models::ShippingRates;
+use replica;
+
+pub struct ShippingRatesCacheImpl<C, V>
+where
+    C: Cache<ShippingRates>,
+    V: Cache<Uuid>,
+{
+    cache: C,
+    version_cache: V,
+}
+
+fn direction_key(company_package_id: CompanyPackageId, delivery_from: &Alpha3, delivery_to: &Alpha3) -> String {
+    format!("{}:{}:{}", company_package_id, delivery_from, delivery_to)
+}
+
+fn key(version: Uuid, company_package_id: CompanyPackageId, delivery_from: &Alpha3, delivery_to: &Alpha3) -> String {
+    format!("{}:{}", direction_key(company_package_id, delivery_from, delivery_to), version)
+}
+
+impl<C, V> ShippingRatesCacheImpl<C, V>
+where
+    C: Cache<ShippingRates>,
+    V: Cache<Uuid>,
+{
+    pub fn new(cache: C, version_cache: V) -> Self {
+        ShippingRatesCacheImpl { cache, version_cache }
+    }
+
+    /// Returns the version rates of the direction are cached under, a missing version is created.
+    /// Must be taken before the rates are read from the db. Returns `None` if the cache is not available
+    pub fn version(&self, company_package_id: CompanyPackageId, delivery_from: &Alpha3, delivery_to: &Alpha3) -> Option<Uuid> {
+        let direction_key = direction_key(company_package_id, delivery_from, delivery_to);
+        match self.version_cache.get(direction_key.as_str()) {
+            Ok(Some(version)) => Some(version),
+            Ok(None) => {
+                let version = Uuid::new_v4();
+                self.set_version(&direction_key, version).map(|_| version)
+            }
+            Err(err) => {
+                let err = err.context(format!("Failed to get version from ShippingRatesCache at key '{}'", direction_key));
+                error!("{}", err);
+                None
+            }
+        }
+    }
+
+    pub fn get(
+        &self,
+        version: Uuid,
+        company_package_id: CompanyPackageId,
+        delivery_from: &Alpha3,
+        delivery_to: &Alpha3,
+    ) -> Option<ShippingRates> {
+        let key = key(version, company_package_id, delivery_from, delivery_to);
+        debug!("Getting shipping rates from ShippingRatesCache at key '{}'", key);
+
+        let rates = self.cache.get(key.as_str()).unwrap_or_else(|err| {
+            let err = err.context(format!("Failed to get shipping rates from ShippingRatesCache at key '{}'", key));
+            error!("{}", err);
+            None
+        });
+        metrics::observe_cache("shipping_rates", rates.is_some());
+        rates
+    }
+
+    /// Replaces the version of the direction, so that rates cached under the previous one are not read anymore
+    pub fn remove(&self, company_package_id: CompanyPackageId, delivery_from: &Alpha3, delivery_to: &Alpha3) -> bool {
+        let direction_key = direction_key(company_package_id, delivery_from, delivery_to);
+        debug!("Removing shipping rates from ShippingRatesCache at key '{}'", direction_key);
+
+        self.set_version(&direction_key, Uuid::new_v4()).is_some()
+    }
+
+    /// Caches rates under the version taken before they were read from the db.
+    /// Rates read from the replica are not cached, the replica may not have the change the version was replaced for
+    pub fn set(&self, version: Uuid, rates: ShippingRates) {
+        if replica::is_reading() {
+            return;
+        }
+        let key = key(version, rates.company_package_id, &rates.from_alpha3, &rates.to_alpha3);
+        debug!("Setting shipping rates in ShippingRatesCache at key '{}'", key);
+
+        self.cache.set(key.as_str(), rates).unwrap_or_else(|err| {
+            let err = err.context(format!("Failed to set shipping rates in ShippingRatesCache at key '{}'", key));
+            error!("{}", err);
+        })
+    }
+
+    fn set_version(&self, direction_key: &str, version: Uuid) -> Option<()> {
+        self.version_cache.set(direction_key, version).map(Some).unwrap_or_else(|err| {
+            let err = err.context(format!("Failed to set version in ShippingRatesCache at key '{}'", direction_key));
+            error!("{}", err);
+            None
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repos::repo_factory::tests::MockCache;
+    use stq_types::ShippingRatesId;
+
+    fn create_cache() -> ShippingRatesCacheImpl<MockCache<ShippingRates>, MockCache<Uuid>> {
+        ShippingRatesCacheImpl::new(MockCache::default(), MockCache::default())
+    }
+
+    fn create_rates() -> ShippingRates {
+        ShippingRates {
+            id: ShippingRatesId(1),
+            company_package_id: CompanyPackageId(1),
+            from_alpha3: Alpha3("RUS".to_string()),
+            to_alpha3: Alpha3("USA".to_string()),
+            rates: vec![],
+        }
+    }
+
+    #[test]
+    fn test_hit_and_miss() {
+        let cache = create_cache();
+        let (from, to) = (Alpha3("RUS".to_string()), Alpha3("USA".to_string()));
+
+        let version = cache.version(CompanyPackageId(1), &from, &to).unwrap();
+        assert_eq!(cache.version(CompanyPackageId(1), &from, &to), Some(version));
+        assert!(cache.get(version, CompanyPackageId(1), &from, &to).is_none());
+
+        cache.set(version, create_rates());
+        assert!(cache.get(version, CompanyPackageId(1), &from, &to).is_some());
+        assert!(cache.get(version, CompanyPackageId(1), &to, &from).is_none());
+        assert!(cache.get(version, CompanyPackageId(2), &from, &to).is_none());
+    }
+
+    #[test]
+    fn test_invalidation() {
+        let cache = create_cache();
+        let (from, to) = (Alpha3("RUS".to_string()), Alpha3("USA".to_string()));
+
+        let version = cache.version(CompanyPackageId(1), &from, &to).unwrap();
+        cache.set(version, create_rates());
+        assert!(cache.remove(CompanyPackageId(1), &from, &to));
+
+        let new_version = cache.version(CompanyPackageId(1), &from, &to).unwrap();
+        assert_ne!(new_version, version);
+        assert!(cache.get(new_version, CompanyPackageId(1), &from, &to).is_none());
+
+        // a reader that took the version before the invalidation writes the old rates after it
+        cache.set(version, create_rates());
+        assert!(cache.get(new_version, CompanyPackageId(1), &from, &to).is_none());
+    }
+
+    #[test]
+    fn test_replica_reads_are_not_cached() {
+        let cache = create_cache();
+        let (from, to) = (Alpha3("RUS".to_string()), Alpha3("USA".to_string()));
+
+        let version = cache.version(CompanyPackageId(1), &from, &to).unwrap();
+        replica::scope(|| cache.set(version, create_rates()));
+        assert!(cache.get(version, CompanyPackageId(1), &from, &to).is_none());
+    }
+}
@@ -8,8 +8,11 @@ use diesel::query_dsl::RunQueryDsl;
 use diesel::Connection;
 use errors::Error;
 use failure::Error as FailureError;
+use std::sync::Arc;
+use stq_cache::cache::Cache;
+use uuid::Uuid;
 
-use stq_types::{Alpha3, CompanyPackageId, UserId};
+use stq_types::{Alpha3, CompanyId, CompanyPackageId, PackageId, UserId};
 
 use repos::legacy_acl::*;
 
@@ -17,9 +20,15 @@ use super::acl;
 use super::types::RepoResult;
 use extras::option;
 use models::authorization::*;
+use models::roles::{owns_company, UserRole};
 use models::{NewShippingRates, NewShippingRatesRaw, ShippingRates, ShippingRatesRaw};
+use schema::companies_packages::dsl as DslCompaniesPackages;
 use schema::shipping_rates::dsl as DslShippingRates;
 
+pub mod cache;
+
+pub use self::cache::*;
+
 /// Repository for static shipping rates
 pub trait ShippingRatesRepo {
     fn get_all_rates_from(&self, company_package_id: CompanyPackageId, delivery_from: Alpha3) -> RepoResult<Vec<ShippingRates>>;
@@ -41,21 +50,42 @@ pub trait ShippingRatesRepo {
     fn insert_many(&self, shipping_rates: Vec<NewShippingRates>) -> RepoResult<Vec<ShippingRates>>;
 
     fn delete_all_rates_from(&self, company_package_id: CompanyPackageId, delivery_from: Alpha3) -> RepoResult<Vec<ShippingRates>>;
+
+    /// Returns every (company package, from, to) direction having rates in the package of the company
+    fn get_directions(&self, company_id: CompanyId, package_id: PackageId) -> RepoResult<Vec<(CompanyPackageId, Alpha3, Alpha3)>>;
 }
 
-pub struct ShippingRatesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+pub struct ShippingRatesRepoImpl<'a, C, V, T>
+where
+    C: Cache<ShippingRates>,
+    V: Cache<Uuid>,
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+{
     pub db_conn: &'a T,
-    pub acl: Box<Acl<Resource, Action, Scope, FailureError, ()>>,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, CompanyPackageId>>,
+    pub rates_cache: Arc<ShippingRatesCacheImpl<C, V>>,
 }
 
-impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ShippingRatesRepoImpl<'a, T> {
-    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, ()>>) -> Self {
-        Self { db_conn, acl }
+impl<'a, C, V, T> ShippingRatesRepoImpl<'a, C, V, T>
+where
+    C: Cache<ShippingRates>,
+    V: Cache<Uuid>,
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+{
+    pub fn new(
+        db_conn: &'a T,
+        acl: Box<Acl<Resource, Action, Scope, FailureError, CompanyPackageId>>,
+        rates_cache: Arc<ShippingRatesCacheImpl<C, V>>,
+    ) -> Self {
+        Self { db_conn, acl, rates_cache }
     }
 }
 
-impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ShippingRatesRepo
-    for ShippingRatesRepoImpl<'a, T>
+impl<'a, C, V, T> ShippingRatesRepo for ShippingRatesRepoImpl<'a, C, V, T>
+where
+    C: Cache<ShippingRates>,
+    V: Cache<Uuid>,
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
     fn get_all_rates_from(&self, company_package_id: CompanyPackageId, delivery_from: Alpha3) -> RepoResult<Vec<ShippingRates>> {
         acl::check(&*self.acl, Resource::ShippingRates, Action::Read, self, None)?;
@@ -115,6 +145,12 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     ) -> RepoResult<Option<ShippingRates>> {
         acl::check(&*self.acl, Resource::ShippingRates, Action::Read, self, None)?;
 
+        // the version is taken before the db is read, rates read before an invalidation are cached under the old version
+        let version = self.rates_cache.version(company_package_id, &delivery_from, &delivery_to);
+        if let Some(rates) = version.and_then(|version| self.rates_cache.get(version, company_package_id, &delivery_from, &delivery_to)) {
+            return Ok(Some(rates));
+        }
+
         let query = DslShippingRates::shipping_rates
             .filter(
                 DslShippingRates::company_package_id
@@ -129,6 +165,12 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .optional()
             .map_err(FailureError::from)
             .and_then(|rates| option::transpose(rates.map(ShippingRatesRaw::to_model)))
+            .map(|rates| {
+                if let (Some(version), Some(rates)) = (version, rates.as_ref()) {
+                    self.rates_cache.set(version, rates.clone());
+                }
+                rates
+            })
             .map_err(|e| {
                 e.context(format!(
                     "error occurred in get_rates for CompanyPackage with id = {}, {} -> {}",
@@ -139,7 +181,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     }
 
     fn delete_all_rates_from(&self, company_package_id: CompanyPackageId, delivery_from: Alpha3) -> RepoResult<Vec<ShippingRates>> {
-        acl::check(&*self.acl, Resource::ShippingRates, Action::Delete, self, None)?;
+        acl::check(&*self.acl, Resource::ShippingRates, Action::Delete, self, Some(&company_package_id))?;
 
         let command = diesel::delete(
             DslShippingRates::shipping_rates.filter(
@@ -162,8 +204,41 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             })
     }
 
+    fn get_directions(&self, company_id: CompanyId, package_id: PackageId) -> RepoResult<Vec<(CompanyPackageId, Alpha3, Alpha3)>> {
+        acl::check(&*self.acl, Resource::ShippingRates, Action::Read, self, None)?;
+
+        let query = DslShippingRates::shipping_rates
+            .inner_join(DslCompaniesPackages::companies_packages)
+            .filter(
+                DslCompaniesPackages::company_id
+                    .eq(company_id)
+                    .and(DslCompaniesPackages::package_id.eq(package_id)),
+            )
+            .select((
+                DslShippingRates::company_package_id,
+                DslShippingRates::from_alpha3,
+                DslShippingRates::to_alpha3,
+            ))
+            .distinct();
+
+        query
+            .get_results::<(CompanyPackageId, Alpha3, Alpha3)>(self.db_conn)
+            .map_err(|e| Error::from(e).into())
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "error occurred in get_directions for company {} and package {}",
+                    company_id, package_id,
+                ))
+                .into()
+            })
+    }
+
     fn insert_many(&self, shipping_rates: Vec<NewShippingRates>) -> RepoResult<Vec<ShippingRates>> {
-        acl::check(&*self.acl, Resource::ShippingRates, Action::Create, self, None)?;
+        let mut company_package_ids = shipping_rates.iter().map(|rates| rates.company_package_id).collect::<Vec<_>>();
+        company_package_ids.dedup();
+        for company_package_id in &company_package_ids {
+            acl::check(&*self.acl, Resource::ShippingRates, Action::Create, self, Some(company_package_id))?;
+        }
 
         let shipping_rates = shipping_rates
             .into_iter()
@@ -180,10 +255,25 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     }
 }
 
-impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, ()>
-    for ShippingRatesRepoImpl<'a, T>
+impl<'a, C, V, T> CheckScope<Scope, CompanyPackageId> for ShippingRatesRepoImpl<'a, C, V, T>
+where
+    C: Cache<ShippingRates>,
+    V: Cache<Uuid>,
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
-    fn is_in_scope(&self, _user_id_arg: UserId, _scope: &Scope, _obj: Option<&()>) -> bool {
-        true
+    fn is_in_scope(&self, _user_id: UserId, user_roles: &[UserRole], scope: &Scope, obj: Option<&CompanyPackageId>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj
+                .and_then(|company_package_id| {
+                    DslCompaniesPackages::companies_packages
+                        .filter(DslCompaniesPackages::id.eq(*company_package_id))
+                        .select(DslCompaniesPackages::company_id)
+                        .get_result::<CompanyId>(self.db_conn)
+                        .ok()
+                })
+                .map(|company_id| owns_company(user_roles, company_id))
+                .unwrap_or(false),
+        }
     }
 }
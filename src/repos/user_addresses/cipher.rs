@@ -0,0 +1,232 @@
+//! Encryption of personal data stored in user addresses.
+//! Values are sealed with AES-256-GCM under a key picked by id, so keys can be rotated.
+//! Equal addresses are found through a keyed blind index instead of plaintext comparison.
+
+use std::collections::HashMap;
+
+use base64;
+use failure::Error as FailureError;
+use ring::aead::{self, OpeningKey, SealingKey, AES_256_GCM};
+use ring::digest;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use config::Encryption;
+
+const KEY_LEN: usize = 32;
+
+pub struct UserAddressCipher {
+    keys: Option<CipherKeys>,
+    rng: SystemRandom,
+}
+
+struct CipherKeys {
+    current_key_id: String,
+    keys: HashMap<String, Vec<u8>>,
+    blind_index_key: hmac::SigningKey,
+}
+
+impl UserAddressCipher {
+    pub fn new(config: &Encryption) -> Result<Self, FailureError> {
+        let keys = config
+            .keys
+            .iter()
+            .map(|(key_id, key)| decode_key(key).map(|key| (key_id.clone(), key)))
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        if !keys.contains_key(&config.current_key_id) {
+            return Err(format_err!("Encryption key {} is not configured", config.current_key_id));
+        }
+
+        let blind_index_key = decode_key(&config.blind_index_key)?;
+
+        Ok(Self {
+            keys: Some(CipherKeys {
+                current_key_id: config.current_key_id.clone(),
+                keys,
+                blind_index_key: hmac::SigningKey::new(&digest::SHA256, &blind_index_key),
+            }),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Cipher without keys, used if `encryption` is not configured. Addresses written before
+    /// encryption was enabled can be read, encrypting and decrypting fail.
+    pub fn disabled() -> Self {
+        Self {
+            keys: None,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Creates cipher with the keys from `encryption` config section if it is set
+    pub fn from_config(config: Option<&Encryption>) -> Result<Self, FailureError> {
+        match config {
+            Some(config) => Self::new(config),
+            None => {
+                warn!("Encryption keys are not configured, user addresses can't be written or decrypted");
+                Ok(Self::disabled())
+            }
+        }
+    }
+
+    /// Id of the key used to encrypt new values
+    pub fn current_key_id(&self) -> Result<&str, FailureError> {
+        self.get_keys().map(|keys| keys.current_key_id.as_str())
+    }
+
+    /// Encrypts `value` of the `column` with the current key, returns base64 of nonce and sealed value
+    pub fn encrypt(&self, column: &str, value: &str) -> Result<String, FailureError> {
+        let current_key_id = self.current_key_id()?;
+        let key = SealingKey::new(&AES_256_GCM, self.get_key(current_key_id)?)
+            .map_err(|_| format_err!("Invalid encryption key {}", current_key_id))?;
+
+        let mut nonce = vec![0u8; AES_256_GCM.nonce_len()];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| format_err!("Could not generate nonce for column {}", column))?;
+
+        let tag_len = AES_256_GCM.tag_len();
+        let mut in_out = value.as_bytes().to_vec();
+        in_out.extend(vec![0u8; tag_len]);
+
+        let ad = additional_data(current_key_id, column);
+        let out_len =
+            aead::seal_in_place(&key, &nonce, &ad, &mut in_out, tag_len).map_err(|_| format_err!("Could not encrypt column {}", column))?;
+
+        let mut result = nonce;
+        result.extend_from_slice(&in_out[..out_len]);
+        Ok(base64::encode(&result))
+    }
+
+    /// Decrypts `value` of the `column` sealed with the key `key_id`
+    pub fn decrypt(&self, key_id: &str, column: &str, value: &str) -> Result<String, FailureError> {
+        let key = OpeningKey::new(&AES_256_GCM, self.get_key(key_id)?).map_err(|_| format_err!("Invalid encryption key {}", key_id))?;
+
+        let data = base64::decode(value).map_err(|e| format_err!("Encrypted column {} is not valid base64: {}", column, e))?;
+        let nonce_len = AES_256_GCM.nonce_len();
+        if data.len() < nonce_len + AES_256_GCM.tag_len() {
+            return Err(format_err!("Encrypted column {} is too short", column));
+        }
+
+        let (nonce, sealed) = data.split_at(nonce_len);
+        let mut in_out = sealed.to_vec();
+        let ad = additional_data(key_id, column);
+        let plain = aead::open_in_place(&key, nonce, &ad, 0, &mut in_out)
+            .map_err(|_| format_err!("Could not decrypt column {} with key {}", column, key_id))?;
+
+        String::from_utf8(plain.to_vec()).map_err(|e| format_err!("Decrypted column {} is not valid utf-8: {}", column, e))
+    }
+
+    /// Keyed hash of the values, equal for equal values regardless of the encryption key
+    pub fn blind_index(&self, values: &[Option<&str>]) -> Result<String, FailureError> {
+        let blind_index_key = &self.get_keys()?.blind_index_key;
+
+        let mut data = vec![];
+        for value in values {
+            match value {
+                None => data.push(0u8),
+                Some(value) => {
+                    data.push(1u8);
+                    let len = value.len() as u32;
+                    data.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+                    data.extend_from_slice(value.as_bytes());
+                }
+            }
+        }
+
+        Ok(base64::encode(hmac::sign(blind_index_key, &data).as_ref()))
+    }
+
+    fn get_keys(&self) -> Result<&CipherKeys, FailureError> {
+        self.keys
+            .as_ref()
+            .ok_or_else(|| format_err!("Encryption of user addresses is not configured, `encryption` config section is missing"))
+    }
+
+    fn get_key(&self, key_id: &str) -> Result<&[u8], FailureError> {
+        self.get_keys()?
+            .keys
+            .get(key_id)
+            .map(|key| key.as_slice())
+            .ok_or_else(|| format_err!("Encryption key {} is not configured", key_id))
+    }
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>, FailureError> {
+    let key = base64::decode(key).map_err(|e| format_err!("Encryption key is not valid base64: {}", e))?;
+    if key.len() != KEY_LEN {
+        return Err(format_err!("Encryption key must be {} bytes long, got {}", KEY_LEN, key.len()));
+    }
+    Ok(key)
+}
+
+fn additional_data(key_id: &str, column: &str) -> Vec<u8> {
+    format!("{}:{}", key_id, column).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_config(current_key_id: &str) -> Encryption {
+        let mut keys = HashMap::new();
+        keys.insert("k1".to_string(), base64::encode(&[1u8; KEY_LEN]));
+        keys.insert("k2".to_string(), base64::encode(&[2u8; KEY_LEN]));
+        Encryption {
+            current_key_id: current_key_id.to_string(),
+            keys,
+            blind_index_key: base64::encode(&[3u8; KEY_LEN]),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let cipher = UserAddressCipher::new(&create_config("k1")).unwrap();
+        let encrypted = cipher.encrypt("route", "Tverskaya").unwrap();
+        assert_ne!(encrypted, "Tverskaya");
+        assert_ne!(encrypted, cipher.encrypt("route", "Tverskaya").unwrap());
+        assert_eq!(cipher.decrypt("k1", "route", &encrypted).unwrap(), "Tverskaya");
+    }
+
+    #[test]
+    fn test_decrypt_with_previous_key() {
+        let old_cipher = UserAddressCipher::new(&create_config("k1")).unwrap();
+        let encrypted = old_cipher.encrypt("postal_code", "125009").unwrap();
+
+        let cipher = UserAddressCipher::new(&create_config("k2")).unwrap();
+        assert_eq!(cipher.decrypt("k1", "postal_code", &encrypted).unwrap(), "125009");
+        assert!(cipher.decrypt("k2", "postal_code", &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_other_column_fails() {
+        let cipher = UserAddressCipher::new(&create_config("k1")).unwrap();
+        let encrypted = cipher.encrypt("route", "Tverskaya").unwrap();
+        assert!(cipher.decrypt("k1", "address", &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_blind_index_does_not_depend_on_key() {
+        let cipher1 = UserAddressCipher::new(&create_config("k1")).unwrap();
+        let cipher2 = UserAddressCipher::new(&create_config("k2")).unwrap();
+        let index = cipher1.blind_index(&[Some("1"), Some("Tverskaya"), None]).unwrap();
+        assert_eq!(index, cipher2.blind_index(&[Some("1"), Some("Tverskaya"), None]).unwrap());
+        assert_ne!(index, cipher1.blind_index(&[Some("1Tverskaya"), None, None]).unwrap());
+        assert_ne!(index, cipher1.blind_index(&[Some("1"), Some("Tverskaya"), Some("")]).unwrap());
+    }
+
+    #[test]
+    fn test_unknown_current_key() {
+        assert!(UserAddressCipher::new(&create_config("k3")).is_err());
+    }
+
+    #[test]
+    fn test_disabled_cipher_fails() {
+        let cipher = UserAddressCipher::from_config(None).unwrap();
+        assert!(cipher.current_key_id().is_err());
+        assert!(cipher.encrypt("route", "Tverskaya").is_err());
+        assert!(cipher.decrypt("k1", "route", "Tverskaya").is_err());
+        assert!(cipher.blind_index(&[Some("1")]).is_err());
+    }
+}
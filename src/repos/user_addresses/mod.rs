@@ -0,0 +1,525 @@
+//! Repo for user_address table. UserAddress is an entity that connects
+//! users and roles. I.e. this table is for user has-many roles
+//! relationship
+
+pub mod cipher;
+
+pub use self::cipher::UserAddressCipher;
+
+use std::sync::Arc;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::dsl::not;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use errors::Error;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewRawUserAddress, NewUserAddress, RawUserAddress, UpdateRawUserAddress, UpdateUserAddress, UserAddress, UserRole};
+use schema::user_addresses::dsl::*;
+
+/// UserAddress repository for handling UserAddress
+pub trait UserAddressesRepo {
+    /// Returns list of user_address for a specific user
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<UserAddress>>;
+
+    /// Returns user delivery address by id
+    fn get(&self, id: i32) -> RepoResult<Option<UserAddress>>;
+
+    /// Create a new user delivery address
+    fn create(&self, payload: NewUserAddress) -> RepoResult<UserAddress>;
+
+    /// Update a user delivery address
+    fn update(&self, id: i32, payload: UpdateUserAddress) -> RepoResult<UserAddress>;
+
+    /// Delete user delivery address
+    fn delete(&self, id: i32) -> RepoResult<UserAddress>;
+
+    /// Delete all delivery addresses of a user
+    fn delete_by_user_id(&self, user_id: UserId) -> RepoResult<Vec<UserAddress>>;
+}
+
+/// Implementation of UserAddress trait
+pub struct UserAddressesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserAddress>>,
+    pub cipher: Arc<UserAddressCipher>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserAddressesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, UserAddress>>, cipher: Arc<UserAddressCipher>) -> Self {
+        Self { db_conn, acl, cipher }
+    }
+
+    /// Sets priority of all user addresses, except `except_id`, to false.
+    /// Must run in the same transaction as the write that sets a new priority address.
+    fn reset_priority(&self, user_id_arg: UserId, except_id: Option<i32>) -> RepoResult<()> {
+        let except_ids = except_id.into_iter().collect::<Vec<_>>();
+        let filter = user_addresses
+            .filter(user_id.eq(user_id_arg))
+            .filter(is_priority.eq(true))
+            .filter(not(id.eq_any(except_ids)));
+
+        diesel::update(filter)
+            .set(is_priority.eq(false))
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| {
+                Error::from(e)
+                    .context(format!("Reset priority of user {} addresses error occurred", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Makes the most recently updated address of the user a priority one
+    fn promote_last_updated(&self, user_id_arg: UserId) -> RepoResult<()> {
+        let last_updated = user_addresses
+            .filter(user_id.eq(user_id_arg))
+            .order((updated_at.desc(), id.desc()))
+            .select(id)
+            .first::<i32>(self.db_conn)
+            .optional()
+            .map_err(Error::from)?;
+
+        if let Some(last_updated_id) = last_updated {
+            diesel::update(user_addresses.filter(id.eq(last_updated_id)))
+                .set(is_priority.eq(true))
+                .execute(self.db_conn)
+                .map_err(Error::from)?;
+        }
+
+        Ok(())
+    }
+
+    fn decrypt_all(&self, raw_addresses: Vec<RawUserAddress>) -> RepoResult<Vec<UserAddress>> {
+        raw_addresses
+            .into_iter()
+            .map(|raw_address| decrypt_user_address(&self.cipher, raw_address))
+            .collect()
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserAddressesRepo
+    for UserAddressesRepoImpl<'a, T>
+{
+    /// Returns list of user_address for a specific user
+    fn list_for_user(&self, user_id_value: UserId) -> RepoResult<Vec<UserAddress>> {
+        let query = user_addresses.filter(user_id.eq(user_id_value)).order(id.desc());
+        query
+            .get_results::<RawUserAddress>(self.db_conn)
+            .map_err(|e| Error::from(e).into())
+            .and_then(|raw_addresses| self.decrypt_all(raw_addresses))
+            .and_then(|addresses: Vec<UserAddress>| {
+                for item in &addresses {
+                    acl::check(&*self.acl, Resource::UserAddresses, Action::Read, self, Some(&item))?;
+                }
+                Ok(addresses)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("list of user_address for user {} error occurred", user_id_value))
+                    .into()
+            })
+    }
+
+    /// Returns user delivery address by id
+    fn get(&self, id_arg: i32) -> RepoResult<Option<UserAddress>> {
+        let query = user_addresses.find(id_arg);
+        query
+            .get_result::<RawUserAddress>(self.db_conn)
+            .optional()
+            .map_err(|e| Error::from(e).into())
+            .and_then(|raw_address| match raw_address {
+                Some(raw_address) => decrypt_user_address(&self.cipher, raw_address).map(Some),
+                None => Ok(None),
+            })
+            .and_then(|address_: Option<UserAddress>| {
+                if let Some(ref address_) = address_ {
+                    acl::check(&*self.acl, Resource::UserAddresses, Action::Read, self, Some(address_))?;
+                }
+                Ok(address_)
+            })
+            .map_err(|e: FailureError| e.context(format!("Get user delivery address {} error occurred", id_arg)).into())
+    }
+
+    /// Create a new user delivery address
+    fn create(&self, payload: NewUserAddress) -> RepoResult<UserAddress> {
+        // encrypted fields can't be compared directly, so they are matched by the blind index,
+        // addresses stored before the encryption have no blind index and are matched by the plain text fields
+        let blind_index = compute_blind_index(
+            &self.cipher,
+            payload.user_id,
+            &payload.postal_code,
+            payload.route.as_ref().map(String::as_str),
+            payload.street_number.as_ref().map(String::as_str),
+            payload.address.as_ref().map(String::as_str),
+        )?;
+
+        let mut exist_query = user_addresses
+            .filter(user_id.eq(payload.user_id))
+            .filter(country.eq(payload.country.clone()))
+            .filter(
+                address_blind_index.eq(blind_index).or(encryption_key_id
+                    .is_null()
+                    .and(postal_code.eq(payload.postal_code.clone()))
+                    .and(route.is_not_distinct_from(payload.route.clone()))
+                    .and(street_number.is_not_distinct_from(payload.street_number.clone()))
+                    .and(address.is_not_distinct_from(payload.address.clone()))),
+            )
+            .into_boxed();
+
+        if let Some(administrative_area_level_1_arg) = payload.administrative_area_level_1.clone() {
+            exist_query = exist_query.filter(administrative_area_level_1.eq(administrative_area_level_1_arg));
+        } else {
+            exist_query = exist_query.filter(administrative_area_level_1.is_null());
+        };
+        if let Some(administrative_area_level_2_arg) = payload.administrative_area_level_2.clone() {
+            exist_query = exist_query.filter(administrative_area_level_2.eq(administrative_area_level_2_arg));
+        } else {
+            exist_query = exist_query.filter(administrative_area_level_2.is_null())
+        };
+        if let Some(locality_arg) = payload.locality.clone() {
+            exist_query = exist_query.filter(locality.eq(locality_arg));
+        } else {
+            exist_query = exist_query.filter(locality.is_null())
+        };
+        if let Some(political_arg) = payload.political.clone() {
+            exist_query = exist_query.filter(political.eq(political_arg));
+        } else {
+            exist_query = exist_query.filter(political.is_null())
+        };
+        if let Some(country_code_arg) = payload.country_code.clone() {
+            exist_query = exist_query.filter(country_code.eq(country_code_arg));
+        } else {
+            exist_query = exist_query.filter(country_code.is_null());
+        };
+
+        exist_query
+            .get_result::<RawUserAddress>(self.db_conn)
+            .optional()
+            .map_err(|e| Error::from(e).into())
+            .and_then(|raw_address| {
+                if let Some(raw_address) = raw_address {
+                    let user_address_arg = decrypt_user_address(&self.cipher, raw_address)?;
+                    acl::check(&*self.acl, Resource::UserAddresses, Action::Create, self, Some(&user_address_arg))?;
+                    Ok(user_address_arg)
+                } else {
+                    if payload.is_priority {
+                        // set all other addresses priority to false before the new priority address appears
+                        self.reset_priority(payload.user_id, None)?;
+                    }
+
+                    let encrypted = encrypt_fields(
+                        &self.cipher,
+                        payload.user_id,
+                        &payload.postal_code,
+                        payload.route.as_ref().map(String::as_str),
+                        payload.street_number.as_ref().map(String::as_str),
+                        payload.address.as_ref().map(String::as_str),
+                    )?;
+
+                    let new_raw_address = NewRawUserAddress {
+                        user_id: payload.user_id,
+                        administrative_area_level_1: payload.administrative_area_level_1.clone(),
+                        administrative_area_level_2: payload.administrative_area_level_2.clone(),
+                        country: payload.country.clone(),
+                        locality: payload.locality.clone(),
+                        political: payload.political.clone(),
+                        postal_code: encrypted.postal_code.unwrap_or_default(),
+                        route: encrypted.route,
+                        street_number: encrypted.street_number,
+                        address: encrypted.address,
+                        is_priority: payload.is_priority,
+                        country_code: payload.country_code.clone(),
+                        encryption_key_id: encrypted.encryption_key_id.unwrap_or_default(),
+                        address_blind_index: encrypted.address_blind_index.unwrap_or_default(),
+                    };
+
+                    let query = diesel::insert_into(user_addresses).values(&new_raw_address);
+                    query
+                        .get_result::<RawUserAddress>(self.db_conn)
+                        .map_err(|e| Error::from(e).into())
+                        .and_then(|raw_address| decrypt_user_address(&self.cipher, raw_address))
+                        .and_then(|address_| {
+                            acl::check(&*self.acl, Resource::UserAddresses, Action::Create, self, Some(&address_))?;
+                            Ok(address_)
+                        })
+                }
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new delivery address for user {} error occurred", payload.user_id))
+                    .into()
+            })
+    }
+
+    /// Update a user delivery address
+    fn update(&self, id_arg: i32, payload: UpdateUserAddress) -> RepoResult<UserAddress> {
+        let query = user_addresses.find(id_arg);
+
+        query
+            .get_result::<RawUserAddress>(self.db_conn)
+            .map_err(|e| Error::from(e).into())
+            .and_then(|raw_address| decrypt_user_address(&self.cipher, raw_address))
+            .and_then(|address_: UserAddress| {
+                acl::check(&*self.acl, Resource::UserAddresses, Action::Update, self, Some(&address_))?;
+                Ok(address_)
+            })
+            .and_then(|address_| {
+                if payload.is_priority == Some(true) {
+                    // set all other addresses priority to false before this address becomes priority
+                    self.reset_priority(address_.user_id, Some(address_.id))?;
+                }
+
+                // all encrypted fields are written with the current key, so one row never mixes keys
+                let encrypted = encrypt_fields(
+                    &self.cipher,
+                    address_.user_id,
+                    payload.postal_code.as_ref().unwrap_or(&address_.postal_code),
+                    payload.route.as_ref().or_else(|| address_.route.as_ref()).map(String::as_str),
+                    payload.street_number.as_ref().or_else(|| address_.street_number.as_ref()).map(String::as_str),
+                    payload.address.as_ref().or_else(|| address_.address.as_ref()).map(String::as_str),
+                )?;
+
+                let changeset = UpdateRawUserAddress {
+                    administrative_area_level_1: payload.administrative_area_level_1.clone(),
+                    administrative_area_level_2: payload.administrative_area_level_2.clone(),
+                    country: payload.country.clone(),
+                    locality: payload.locality.clone(),
+                    political: payload.political.clone(),
+                    is_priority: payload.is_priority,
+                    country_code: payload.country_code.clone(),
+                    ..encrypted
+                };
+
+                let filter = user_addresses.filter(id.eq(id_arg));
+
+                let query = diesel::update(filter).set(&changeset);
+                query
+                    .get_result::<RawUserAddress>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+                    .and_then(|raw_address| decrypt_user_address(&self.cipher, raw_address))
+            })
+            .map_err(|e: FailureError| e.context(format!("Update delivery address {} error occurred", id_arg)).into())
+    }
+
+    /// Delete user delivery address
+    fn delete(&self, id_arg: i32) -> RepoResult<UserAddress> {
+        let query = user_addresses.find(id_arg);
+
+        query
+            .get_result::<RawUserAddress>(self.db_conn)
+            .map_err(|e| Error::from(e).into())
+            .and_then(|raw_address| decrypt_user_address(&self.cipher, raw_address))
+            .and_then(|address_: UserAddress| acl::check(&*self.acl, Resource::UserAddresses, Action::Delete, self, Some(&address_)))
+            .and_then(|_| {
+                let filtered = user_addresses.filter(id.eq(id_arg));
+                let query = diesel::delete(filtered);
+                query
+                    .get_result::<RawUserAddress>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+                    .and_then(|raw_address| decrypt_user_address(&self.cipher, raw_address))
+            })
+            .and_then(|deleted_address: UserAddress| {
+                if deleted_address.is_priority {
+                    self.promote_last_updated(deleted_address.user_id)?;
+                }
+                Ok(deleted_address)
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete delivery address {} error occurred", id_arg)).into())
+    }
+
+    /// Delete all delivery addresses of a user
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Vec<UserAddress>> {
+        let query = user_addresses.filter(user_id.eq(user_id_arg));
+
+        query
+            .get_results::<RawUserAddress>(self.db_conn)
+            .map_err(|e| Error::from(e).into())
+            .and_then(|raw_addresses| self.decrypt_all(raw_addresses))
+            .and_then(|addresses: Vec<UserAddress>| {
+                for item in &addresses {
+                    acl::check(&*self.acl, Resource::UserAddresses, Action::Delete, self, Some(&item))?;
+                }
+                Ok(())
+            })
+            .and_then(|_| {
+                let filtered = user_addresses.filter(user_id.eq(user_id_arg));
+                let query = diesel::delete(filtered);
+                query
+                    .get_results::<RawUserAddress>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+                    .and_then(|raw_addresses| self.decrypt_all(raw_addresses))
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete delivery addresses of user {} error occurred", user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, UserAddress>
+    for UserAddressesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, _user_roles: &[UserRole], scope: &Scope, obj: Option<&UserAddress>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(addres) = obj {
+                    addres.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// Re-encrypts addresses stored with keys other than the current one, including plaintext
+/// addresses written before encryption was enabled. Returns the number of re-encrypted addresses.
+pub fn reencrypt_user_addresses<T>(db_conn: &T, cipher: &UserAddressCipher, batch_size: i64) -> Result<usize, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+{
+    let current_key_id = cipher.current_key_id()?;
+    let mut total = 0;
+    loop {
+        let reencrypted = db_conn.transaction::<usize, FailureError, _>(|| {
+            let raw_addresses = user_addresses
+                .filter(encryption_key_id.is_distinct_from(current_key_id))
+                .order(id)
+                .limit(batch_size)
+                .for_update()
+                .get_results::<RawUserAddress>(db_conn)
+                .map_err(Error::from)?;
+
+            for raw_address in &raw_addresses {
+                let address_ = decrypt_user_address(cipher, raw_address.clone())?;
+                let changeset = encrypt_fields(
+                    cipher,
+                    address_.user_id,
+                    &address_.postal_code,
+                    address_.route.as_ref().map(String::as_str),
+                    address_.street_number.as_ref().map(String::as_str),
+                    address_.address.as_ref().map(String::as_str),
+                )?;
+
+                diesel::update(user_addresses.filter(id.eq(address_.id)))
+                    .set(&changeset)
+                    .execute(db_conn)
+                    .map_err(Error::from)?;
+            }
+
+            Ok(raw_addresses.len())
+        })?;
+
+        total += reencrypted;
+        if reencrypted < batch_size as usize {
+            return Ok(total);
+        }
+    }
+}
+
+fn decrypt_user_address(cipher: &UserAddressCipher, raw_address: RawUserAddress) -> Result<UserAddress, FailureError> {
+    let (postal_code_value, route_value, street_number_value, address_value) = match raw_address.encryption_key_id {
+        Some(ref key_id) => (
+            cipher.decrypt(key_id, "postal_code", &raw_address.postal_code)?,
+            decrypt_optional(cipher, key_id, "route", raw_address.route)?,
+            decrypt_optional(cipher, key_id, "street_number", raw_address.street_number)?,
+            decrypt_optional(cipher, key_id, "address", raw_address.address)?,
+        ),
+        // plaintext address which is not re-encrypted yet
+        None => (
+            raw_address.postal_code,
+            raw_address.route,
+            raw_address.street_number,
+            raw_address.address,
+        ),
+    };
+
+    Ok(UserAddress {
+        id: raw_address.id,
+        user_id: raw_address.user_id,
+        administrative_area_level_1: raw_address.administrative_area_level_1,
+        administrative_area_level_2: raw_address.administrative_area_level_2,
+        country: raw_address.country,
+        locality: raw_address.locality,
+        political: raw_address.political,
+        postal_code: postal_code_value,
+        route: route_value,
+        street_number: street_number_value,
+        address: address_value,
+        is_priority: raw_address.is_priority,
+        created_at: raw_address.created_at,
+        updated_at: raw_address.updated_at,
+        country_code: raw_address.country_code,
+    })
+}
+
+fn decrypt_optional(cipher: &UserAddressCipher, key_id: &str, column: &str, value: Option<String>) -> Result<Option<String>, FailureError> {
+    match value {
+        Some(value) => cipher.decrypt(key_id, column, &value).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn encrypt_optional(cipher: &UserAddressCipher, column: &str, value: Option<&str>) -> Result<Option<String>, FailureError> {
+    match value {
+        Some(value) => cipher.encrypt(column, value).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Returns changeset with encrypted fields, the current key id and the blind index
+fn encrypt_fields(
+    cipher: &UserAddressCipher,
+    user_id_arg: UserId,
+    postal_code_arg: &str,
+    route_arg: Option<&str>,
+    street_number_arg: Option<&str>,
+    address_arg: Option<&str>,
+) -> Result<UpdateRawUserAddress, FailureError> {
+    Ok(UpdateRawUserAddress {
+        postal_code: Some(cipher.encrypt("postal_code", postal_code_arg)?),
+        route: encrypt_optional(cipher, "route", route_arg)?,
+        street_number: encrypt_optional(cipher, "street_number", street_number_arg)?,
+        address: encrypt_optional(cipher, "address", address_arg)?,
+        encryption_key_id: Some(cipher.current_key_id()?.to_string()),
+        address_blind_index: Some(compute_blind_index(
+            cipher,
+            user_id_arg,
+            postal_code_arg,
+            route_arg,
+            street_number_arg,
+            address_arg,
+        )?),
+        ..Default::default()
+    })
+}
+
+fn compute_blind_index(
+    cipher: &UserAddressCipher,
+    user_id_arg: UserId,
+    postal_code_arg: &str,
+    route_arg: Option<&str>,
+    street_number_arg: Option<&str>,
+    address_arg: Option<&str>,
+) -> Result<String, FailureError> {
+    let user_id_value = user_id_arg.to_string();
+    cipher.blind_index(&[
+        Some(user_id_value.as_str()),
+        Some(postal_code_arg),
+        route_arg,
+        street_number_arg,
+        address_arg,
+    ])
+}
@@ -0,0 +1,69 @@
+//! Repo for user_data_erasures table. UserDataErasure is an audit record
+//! written every time personal data of a user is erased.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use errors::Error;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewUserDataErasure, UserDataErasure, UserRole};
+use schema::user_data_erasures::dsl::*;
+
+/// User data erasures repository for handling audit records of erasures
+pub trait UserDataErasuresRepo {
+    /// Create a new audit record of user data erasure
+    fn create(&self, payload: NewUserDataErasure) -> RepoResult<UserDataErasure>;
+}
+
+/// Implementation of UserDataErasuresRepo trait
+pub struct UserDataErasuresRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserDataErasure>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserDataErasuresRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, UserDataErasure>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserDataErasuresRepo
+    for UserDataErasuresRepoImpl<'a, T>
+{
+    /// Create a new audit record of user data erasure
+    fn create(&self, payload: NewUserDataErasure) -> RepoResult<UserDataErasure> {
+        debug!("create new user data erasure {:?}.", payload);
+        acl::check(&*self.acl, Resource::UserDataErasures, Action::Create, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(user_data_erasures).values(&payload);
+                query.get_result::<UserDataErasure>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new user data erasure {:?} error occurred", payload))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, UserDataErasure>
+    for UserDataErasuresRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&UserDataErasure>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
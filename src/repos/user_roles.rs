@@ -13,10 +13,11 @@ use failure::Error as FailureError;
 use failure::Fail;
 use std::sync::Arc;
 use stq_cache::cache::Cache;
-use stq_types::{DeliveryRole, RoleId, UserId};
+use stq_types::{RoleId, UserId};
 
 use models::authorization::*;
-use models::{NewUserRole, UserRole};
+use models::{NewUserRole, RoleName, UserRole};
+use repos::acl;
 use repos::legacy_acl::*;
 use repos::types::RepoResult;
 use repos::RolesCacheImpl;
@@ -25,7 +26,10 @@ use schema::roles::dsl::*;
 /// UserRoles repository for handling UserRoles
 pub trait UserRolesRepo {
     /// Returns list of user_roles for a specific user
-    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<DeliveryRole>>;
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<RoleName>>;
+
+    /// Returns user_roles records for a specific user
+    fn get_by_user_id(&self, user_id: UserId) -> RepoResult<Vec<UserRole>>;
 
     /// Create a new user role
     fn create(&self, payload: NewUserRole) -> RepoResult<UserRole>;
@@ -40,7 +44,7 @@ pub trait UserRolesRepo {
 /// Implementation of UserRoles trait
 pub struct UserRolesRepoImpl<'a, C, T>
 where
-    C: Cache<Vec<DeliveryRole>>,
+    C: Cache<Vec<UserRole>>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
     pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserRole>>,
@@ -50,7 +54,7 @@ where
 
 impl<'a, C, T> UserRolesRepoImpl<'a, C, T>
 where
-    C: Cache<Vec<DeliveryRole>>,
+    C: Cache<Vec<UserRole>>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
     pub fn new(
@@ -60,46 +64,60 @@ where
     ) -> Self {
         Self { acl, db_conn, roles_cache }
     }
+
+    /// Returns roles of the user from the cache, loading them from db on miss.
+    /// Cache entry must be invalidated after the roles of the user are changed.
+    fn get_cached(&self, user_id_arg: UserId) -> RepoResult<Vec<UserRole>> {
+        if let Some(user_roles) = self.roles_cache.get(user_id_arg) {
+            return Ok(user_roles);
+        }
+
+        let query = roles.filter(user_id.eq(user_id_arg)).order(name);
+        query
+            .get_results::<UserRole>(self.db_conn)
+            .map_err(|e| Error::from(e).into())
+            .map(|user_roles| {
+                if !user_roles.is_empty() {
+                    self.roles_cache.set(user_id_arg, user_roles.clone());
+                }
+                user_roles
+            })
+    }
 }
 
 impl<'a, C, T> UserRolesRepo for UserRolesRepoImpl<'a, C, T>
 where
-    C: Cache<Vec<DeliveryRole>>,
+    C: Cache<Vec<UserRole>>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
     /// Returns list of user_roles for a specific user
-    fn list_for_user(&self, user_id_value: UserId) -> RepoResult<Vec<DeliveryRole>> {
+    fn list_for_user(&self, user_id_value: UserId) -> RepoResult<Vec<RoleName>> {
         debug!("list user roles for id {}.", user_id_value);
 
-        if let Some(user_roles) = self.roles_cache.get(user_id_value) {
-            Ok(user_roles)
-        } else {
-            let query = roles.filter(user_id.eq(user_id_value));
-            query
-                .get_results::<UserRole>(self.db_conn)
-                .map(|user_roles_arg| {
-                    let user_roles = user_roles_arg
-                        .into_iter()
-                        .map(|user_role| user_role.name)
-                        .collect::<Vec<DeliveryRole>>();
-
-                    if !user_roles.is_empty() {
-                        self.roles_cache.set(user_id_value, user_roles.clone());
-                    }
-
-                    user_roles
-                })
-                .map_err(|e| {
-                    e.context(format!("List user roles for user {} error occurred.", user_id_value))
-                        .into()
-                })
-        }
+        self.get_cached(user_id_value)
+            .map(|user_roles| user_roles.into_iter().map(|user_role| user_role.name).collect())
+            .map_err(|e: FailureError| {
+                e.context(format!("List user roles for user {} error occurred.", user_id_value))
+                    .into()
+            })
+    }
+
+    /// Returns user_roles records for a specific user
+    fn get_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Vec<UserRole>> {
+        debug!("get user roles records for id {}.", user_id_arg);
+        self.get_cached(user_id_arg)
+            .and_then(|user_roles: Vec<UserRole>| {
+                for user_role in &user_roles {
+                    acl::check(&*self.acl, Resource::UserRoles, Action::Read, self, Some(user_role))?;
+                }
+                Ok(user_roles)
+            })
+            .map_err(|e: FailureError| e.context(format!("Get user roles for user {} error occurred.", user_id_arg)).into())
     }
 
     /// Create a new user role
     fn create(&self, payload: NewUserRole) -> RepoResult<UserRole> {
         debug!("create new user role {:?}.", payload);
-        self.roles_cache.remove(payload.user_id);
         let query = diesel::insert_into(roles).values(&payload);
         query
             .get_result(self.db_conn)
@@ -109,7 +127,6 @@ where
     /// Delete roles of a user
     fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Vec<UserRole>> {
         debug!("delete user {} role.", user_id_arg);
-        self.roles_cache.remove(user_id_arg);
         let filtered = roles.filter(user_id.eq(user_id_arg));
         let query = diesel::delete(filtered);
         query.get_results(self.db_conn).map_err(|e| {
@@ -127,19 +144,15 @@ where
         query
             .get_result(self.db_conn)
             .map_err(|e| Error::from(e).context(format!("Delete role {} error occurred", id_arg)).into())
-            .map(|user_role: UserRole| {
-                self.roles_cache.remove(user_role.user_id);
-                user_role
-            })
     }
 }
 
 impl<'a, C, T> CheckScope<Scope, UserRole> for UserRolesRepoImpl<'a, C, T>
 where
-    C: Cache<Vec<DeliveryRole>>,
+    C: Cache<Vec<UserRole>>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
-    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&UserRole>) -> bool {
+    fn is_in_scope(&self, user_id_arg: UserId, _user_roles: &[UserRole], scope: &Scope, obj: Option<&UserRole>) -> bool {
         match *scope {
             Scope::All => true,
             Scope::Owned => {
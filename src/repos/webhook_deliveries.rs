@@ -0,0 +1,194 @@
+//! Repo for webhook_deliveries table. WebhookDelivery tracks delivery of an outbox
+//! event to a single subscription, failed deliveries are retried until they become dead letters.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use errors::Error;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::{Action, Resource, Scope};
+use models::{
+    NewWebhookDelivery, OutboxEvent, PendingWebhookDelivery, UserRole, WebhookDelivery, WebhookDeliveryStatus, WebhookSubscription,
+};
+use schema::outbox_events::dsl as DslOutboxEvents;
+use schema::webhook_deliveries::dsl::*;
+use schema::webhook_subscriptions::dsl as DslWebhookSubscriptions;
+
+/// Webhook deliveries repository, used by the dispatcher and for inspecting deliveries
+pub trait WebhookDeliveriesRepo {
+    /// Create deliveries of an event to subscriptions
+    fn create_many(&self, payload: Vec<NewWebhookDelivery>) -> RepoResult<Vec<WebhookDelivery>>;
+
+    /// Returns deliveries to the subscription, newest first
+    fn list_by_subscription(&self, subscription_id_arg: i32) -> RepoResult<Vec<WebhookDelivery>>;
+
+    /// Returns pending deliveries due to be sent, oldest first
+    fn list_pending(&self, limit: i64) -> RepoResult<Vec<PendingWebhookDelivery>>;
+
+    /// Marks delivery as delivered
+    fn mark_delivered(&self, id_arg: i32) -> RepoResult<WebhookDelivery>;
+
+    /// Records failed delivery attempt, the delivery is retried not earlier than `next_attempt_at_arg`
+    /// or moved to dead letters when `dead_letter` is set
+    fn mark_failed(&self, id_arg: i32, error: String, next_attempt_at_arg: SystemTime, dead_letter: bool) -> RepoResult<WebhookDelivery>;
+
+    /// Schedules delivery to be sent again right away with attempts counter reset
+    fn redeliver(&self, id_arg: i32) -> RepoResult<WebhookDelivery>;
+}
+
+/// Implementation of WebhookDeliveriesRepo trait
+pub struct WebhookDeliveriesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WebhookDeliveriesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WebhookDeliveriesRepo
+    for WebhookDeliveriesRepoImpl<'a, T>
+{
+    /// Create deliveries of an event to subscriptions
+    fn create_many(&self, payload: Vec<NewWebhookDelivery>) -> RepoResult<Vec<WebhookDelivery>> {
+        debug!("create webhook deliveries {:?}.", payload);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Create, self, None)
+            .and_then(|_| {
+                if payload.is_empty() {
+                    return Ok(vec![]);
+                }
+                let query = diesel::insert_into(webhook_deliveries).values(&payload);
+                query.get_results::<WebhookDelivery>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Create webhook deliveries {:?} error occurred", payload)).into())
+    }
+
+    /// Returns deliveries to the subscription, newest first
+    fn list_by_subscription(&self, subscription_id_arg: i32) -> RepoResult<Vec<WebhookDelivery>> {
+        debug!("list deliveries of webhook subscription {}.", subscription_id_arg);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Read, self, None)
+            .and_then(|_| {
+                let query = webhook_deliveries.filter(subscription_id.eq(subscription_id_arg)).order(id.desc());
+                query.get_results::<WebhookDelivery>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List deliveries of webhook subscription {} error occurred", subscription_id_arg))
+                    .into()
+            })
+    }
+
+    /// Returns pending deliveries due to be sent, oldest first
+    fn list_pending(&self, limit: i64) -> RepoResult<Vec<PendingWebhookDelivery>> {
+        debug!("list pending webhook deliveries, limit: {}.", limit);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Read, self, None)
+            .and_then(|_| {
+                let query = webhook_deliveries
+                    .filter(status.eq(WebhookDeliveryStatus::Pending))
+                    .filter(next_attempt_at.le(SystemTime::now()))
+                    .inner_join(DslWebhookSubscriptions::webhook_subscriptions)
+                    .inner_join(DslOutboxEvents::outbox_events)
+                    .order(id)
+                    .limit(limit);
+                query
+                    .get_results::<(WebhookDelivery, WebhookSubscription, OutboxEvent)>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map(|results| {
+                results
+                    .into_iter()
+                    .map(|(delivery, subscription, event)| PendingWebhookDelivery {
+                        delivery,
+                        subscription,
+                        event,
+                    })
+                    .collect()
+            })
+            .map_err(|e: FailureError| e.context("List pending webhook deliveries error occurred").into())
+    }
+
+    /// Marks delivery as delivered
+    fn mark_delivered(&self, id_arg: i32) -> RepoResult<WebhookDelivery> {
+        debug!("mark webhook delivery {} as delivered.", id_arg);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(webhook_deliveries.filter(id.eq(id_arg)))
+                    .set((
+                        status.eq(WebhookDeliveryStatus::Delivered),
+                        attempts.eq(attempts + 1),
+                        last_error.eq(None::<String>),
+                        delivered_at.eq(Some(SystemTime::now())),
+                    ))
+                    .get_result::<WebhookDelivery>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Mark webhook delivery {} as delivered error occurred", id_arg)).into())
+    }
+
+    /// Records failed delivery attempt, the delivery is retried not earlier than `next_attempt_at_arg`
+    /// or moved to dead letters when `dead_letter` is set
+    fn mark_failed(&self, id_arg: i32, error: String, next_attempt_at_arg: SystemTime, dead_letter: bool) -> RepoResult<WebhookDelivery> {
+        debug!("mark webhook delivery {} as failed: {}.", id_arg, error);
+        let status_arg = if dead_letter {
+            WebhookDeliveryStatus::DeadLetter
+        } else {
+            WebhookDeliveryStatus::Pending
+        };
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(webhook_deliveries.filter(id.eq(id_arg)))
+                    .set((
+                        status.eq(status_arg),
+                        attempts.eq(attempts + 1),
+                        last_error.eq(Some(error)),
+                        next_attempt_at.eq(next_attempt_at_arg),
+                    ))
+                    .get_result::<WebhookDelivery>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Mark webhook delivery {} as failed error occurred", id_arg)).into())
+    }
+
+    /// Schedules delivery to be sent again right away with attempts counter reset
+    fn redeliver(&self, id_arg: i32) -> RepoResult<WebhookDelivery> {
+        debug!("redeliver webhook delivery {}.", id_arg);
+        acl::check(&*self.acl, Resource::WebhookDeliveries, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(webhook_deliveries.filter(id.eq(id_arg)))
+                    .set((
+                        status.eq(WebhookDeliveryStatus::Pending),
+                        attempts.eq(0),
+                        next_attempt_at.eq(SystemTime::now()),
+                        delivered_at.eq(None::<SystemTime>),
+                    ))
+                    .get_result::<WebhookDelivery>(self.db_conn)
+                    .map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Redeliver webhook delivery {} error occurred", id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, WebhookDelivery>
+    for WebhookDeliveriesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&WebhookDelivery>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
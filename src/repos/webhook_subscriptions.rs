@@ -0,0 +1,115 @@
+//! Repo for webhook_subscriptions table. WebhookSubscription is a url of another
+//! service domain events of the subscribed types are delivered to.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use errors::Error;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::{Action, Resource, Scope};
+use models::{NewWebhookSubscriptionRaw, UserRole, WebhookSubscription};
+use schema::webhook_subscriptions::dsl::*;
+
+/// Webhook subscriptions repository
+pub trait WebhookSubscriptionsRepo {
+    /// Create a new subscription
+    fn create(&self, payload: NewWebhookSubscriptionRaw) -> RepoResult<WebhookSubscription>;
+
+    /// Returns list of all subscriptions
+    fn list(&self) -> RepoResult<Vec<WebhookSubscription>>;
+
+    /// Returns subscriptions to events of the type
+    fn list_by_event_type(&self, event_type: String) -> RepoResult<Vec<WebhookSubscription>>;
+
+    /// Delete subscription together with its deliveries
+    fn delete(&self, id: i32) -> RepoResult<WebhookSubscription>;
+}
+
+/// Implementation of WebhookSubscriptionsRepo trait
+pub struct WebhookSubscriptionsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookSubscription>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WebhookSubscriptionsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookSubscription>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WebhookSubscriptionsRepo
+    for WebhookSubscriptionsRepoImpl<'a, T>
+{
+    /// Create a new subscription
+    fn create(&self, payload: NewWebhookSubscriptionRaw) -> RepoResult<WebhookSubscription> {
+        debug!("create new webhook subscription to {}.", payload.url);
+        acl::check(&*self.acl, Resource::WebhookSubscriptions, Action::Create, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(webhook_subscriptions).values(&payload);
+                query.get_result::<WebhookSubscription>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new webhook subscription to {} error occurred", payload.url))
+                    .into()
+            })
+    }
+
+    /// Returns list of all subscriptions
+    fn list(&self) -> RepoResult<Vec<WebhookSubscription>> {
+        debug!("list webhook subscriptions.");
+        acl::check(&*self.acl, Resource::WebhookSubscriptions, Action::Read, self, None)
+            .and_then(|_| {
+                let query = webhook_subscriptions.order(id);
+                query.get_results::<WebhookSubscription>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context("List webhook subscriptions error occurred").into())
+    }
+
+    /// Returns subscriptions to events of the type
+    fn list_by_event_type(&self, event_type_arg: String) -> RepoResult<Vec<WebhookSubscription>> {
+        debug!("list webhook subscriptions to {} events.", event_type_arg);
+        acl::check(&*self.acl, Resource::WebhookSubscriptions, Action::Read, self, None)
+            .and_then(|_| {
+                let query = webhook_subscriptions.filter(event_types.contains(vec![event_type_arg.clone()])).order(id);
+                query.get_results::<WebhookSubscription>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List webhook subscriptions to {} events error occurred", event_type_arg))
+                    .into()
+            })
+    }
+
+    /// Delete subscription together with its deliveries
+    fn delete(&self, id_arg: i32) -> RepoResult<WebhookSubscription> {
+        debug!("delete webhook subscription {}.", id_arg);
+        acl::check(&*self.acl, Resource::WebhookSubscriptions, Action::Delete, self, None)
+            .and_then(|_| {
+                let filtered = webhook_subscriptions.filter(id.eq(id_arg));
+                let query = diesel::delete(filtered);
+                query.get_result::<WebhookSubscription>(self.db_conn).map_err(|e| Error::from(e).into())
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete webhook subscription {} error occurred", id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, WebhookSubscription>
+    for WebhookSubscriptionsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, _user_roles: &[UserRole], scope: &Scope, _obj: Option<&WebhookSubscription>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
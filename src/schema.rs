@@ -1,15 +1,46 @@
+table! {
+    api_keys (id) {
+        id -> Int4,
+        name -> Varchar,
+        key_hash -> Varchar,
+        permissions -> Jsonb,
+        created_by -> Nullable<Int4>,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Int4,
+        user_id -> Nullable<Int4>,
+        correlation_token -> Varchar,
+        resource -> Varchar,
+        action -> Varchar,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     companies (id) {
         id -> Int4,
         name -> Varchar,
         label -> Varchar,
         description -> Nullable<Varchar>,
-        deliveries_from -> Jsonb,
         logo -> Varchar,
         currency -> Varchar,
     }
 }
 
+table! {
+    companies_deliveries_from (company_id, country_alpha3) {
+        company_id -> Int4,
+        country_alpha3 -> Varchar,
+    }
+}
+
 table! {
     companies_packages (id) {
         id -> Int4,
@@ -31,6 +62,37 @@ table! {
     }
 }
 
+table! {
+    outbox_endpoint_deliveries (outbox_event_id, endpoint) {
+        outbox_event_id -> Int4,
+        endpoint -> Varchar,
+        delivered_at -> Timestamp,
+    }
+}
+
+table! {
+    outbox_events (id) {
+        id -> Int4,
+        event_type -> Varchar,
+        payload -> Jsonb,
+        correlation_token -> Varchar,
+        created_at -> Timestamp,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Varchar>,
+        sent_at -> Nullable<Timestamp>,
+        webhooks_created_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    outbox_lease (id) {
+        id -> Int4,
+        holder -> Varchar,
+        expires_at -> Timestamp,
+    }
+}
+
 table! {
     packages (id) {
         id -> Int4,
@@ -39,7 +101,13 @@ table! {
         min_size -> Int4,
         max_weight -> Int4,
         min_weight -> Int4,
-        deliveries_to -> Jsonb,
+    }
+}
+
+table! {
+    packages_deliveries_to (package_id, country_alpha3) {
+        package_id -> Int4,
+        country_alpha3 -> Varchar,
     }
 }
 
@@ -60,12 +128,18 @@ table! {
         store_id -> Int4,
         company_package_id -> Int4,
         price -> Nullable<Float8>,
-        deliveries_to -> Jsonb,
         shipping -> Varchar,
         currency -> Varchar,
     }
 }
 
+table! {
+    products_deliveries_to (product_id, country_alpha3) {
+        product_id -> Int4,
+        country_alpha3 -> Varchar,
+    }
+}
+
 table! {
     roles (id) {
         id -> Uuid,
@@ -85,6 +159,14 @@ table! {
     }
 }
 
+table! {
+    unmigrated_deliveries (table_name, row_id, country_alpha3) {
+        table_name -> Varchar,
+        row_id -> Int4,
+        country_alpha3 -> Varchar,
+    }
+}
+
 table! {
     user_addresses (id) {
         id -> Int4,
@@ -102,22 +184,78 @@ table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         country_code -> Nullable<Varchar>,
+        encryption_key_id -> Nullable<Varchar>,
+        address_blind_index -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    user_data_erasures (id) {
+        id -> Int4,
+        user_id -> Int4,
+        erased_by -> Int4,
+        addresses_count -> Int4,
+        roles_count -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        subscription_id -> Int4,
+        outbox_event_id -> Int4,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    webhook_subscriptions (id) {
+        id -> Int4,
+        url -> Varchar,
+        event_types -> Array<Varchar>,
+        secret -> Varchar,
+        created_by -> Nullable<Int4>,
+        created_at -> Timestamp,
     }
 }
 
+joinable!(companies_deliveries_from -> companies (company_id));
 joinable!(companies_packages -> companies (company_id));
 joinable!(companies_packages -> packages (package_id));
+joinable!(outbox_endpoint_deliveries -> outbox_events (outbox_event_id));
+joinable!(packages_deliveries_to -> packages (package_id));
 joinable!(products -> companies_packages (company_package_id));
+joinable!(products_deliveries_to -> products (product_id));
 joinable!(shipping_rates -> companies_packages (company_package_id));
+joinable!(webhook_deliveries -> outbox_events (outbox_event_id));
+joinable!(webhook_deliveries -> webhook_subscriptions (subscription_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_keys,
+    audit_log,
     companies,
+    companies_deliveries_from,
     companies_packages,
     countries,
+    outbox_endpoint_deliveries,
+    outbox_events,
+    outbox_lease,
     packages,
+    packages_deliveries_to,
     pickups,
     products,
+    products_deliveries_to,
     roles,
     shipping_rates,
+    unmigrated_deliveries,
     user_addresses,
+    user_data_erasures,
+    webhook_deliveries,
+    webhook_subscriptions,
 );
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use failure::Error;
 use sentry;
 use sentry::integrations::failure::capture_error;
@@ -28,3 +30,11 @@ pub fn log_and_capture_error(error: &Error) {
     error!("Internal server error: {:?}", error);
     capture_error(error);
 }
+
+/// Sends queued events to Sentry, waits at most `timeout`. Returns `false` if some events were not sent
+pub fn flush(timeout: Duration) -> bool {
+    sentry::Hub::current()
+        .client()
+        .map(|client| client.close(Some(timeout)))
+        .unwrap_or(true)
+}
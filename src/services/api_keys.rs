@@ -0,0 +1,119 @@
+//! ApiKeys Services, presents management of api keys and authentication of other services
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use super::types::{Service, ServiceFuture};
+use errors::Error;
+use models::{generate_api_key, hash_api_key, ApiKey, CreatedApiKey, NewApiKey, NewApiKeyRaw};
+use repos::ReposFactory;
+
+pub trait ApiKeysService {
+    /// Creates new api key, the key itself is returned only once
+    fn create_api_key(&self, payload: NewApiKey) -> ServiceFuture<CreatedApiKey>;
+    /// Returns all api keys
+    fn list_api_keys(&self) -> ServiceFuture<Vec<ApiKey>>;
+    /// Revokes api key
+    fn revoke_api_key(&self, id: i32) -> ServiceFuture<ApiKey>;
+    /// Returns active api key of the request, fails with `Error::Unauthorized` on unknown or revoked key
+    fn authenticate_api_key(&self, key: String) -> ServiceFuture<ApiKey>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ApiKeysService for Service<T, M, F>
+{
+    /// Creates new api key, the key itself is returned only once
+    fn create_api_key(&self, payload: NewApiKey) -> ServiceFuture<CreatedApiKey> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let api_keys_repo = repo_factory.create_api_keys_repo(&*conn, current_uid);
+            generate_api_key()
+                .and_then(|key| {
+                    let payload = NewApiKeyRaw::from_model(payload, hash_api_key(&key), current_uid)?;
+                    api_keys_repo.create(payload).map(|api_key| CreatedApiKey { api_key, key })
+                })
+                .map_err(|e: FailureError| e.context("Service ApiKeys, create endpoint error occured.").into())
+        })
+    }
+
+    /// Returns all api keys
+    fn list_api_keys(&self) -> ServiceFuture<Vec<ApiKey>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let api_keys_repo = repo_factory.create_api_keys_repo(&*conn, current_uid);
+            api_keys_repo
+                .list()
+                .map_err(|e: FailureError| e.context("Service ApiKeys, list endpoint error occured.").into())
+        })
+    }
+
+    /// Revokes api key
+    fn revoke_api_key(&self, id: i32) -> ServiceFuture<ApiKey> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let api_keys_repo = repo_factory.create_api_keys_repo(&*conn, current_uid);
+            api_keys_repo
+                .revoke(id)
+                .map_err(|e: FailureError| e.context("Service ApiKeys, revoke endpoint error occured.").into())
+        })
+    }
+
+    /// Returns active api key of the request, fails with `Error::Unauthorized` on unknown or revoked key
+    fn authenticate_api_key(&self, key: String) -> ServiceFuture<ApiKey> {
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let api_keys_repo = repo_factory.create_api_keys_repo_with_sys_acl(&*conn);
+            api_keys_repo.find_active_by_hash(hash_api_key(&key)).and_then(|api_key| {
+                api_key.ok_or_else(|| format_err!("Unknown or revoked api key").context(Error::Unauthorized).into())
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use tokio_core::reactor::Core;
+
+    use models::authorization::*;
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::api_keys::ApiKeysService;
+
+    #[test]
+    fn test_create_api_key() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let work = service.create_api_key(NewApiKey {
+            name: "orders".to_string(),
+            permissions: vec![permission!(Resource::ShippingRates, Action::Read)],
+        });
+        let result = core.run(work).unwrap();
+        assert_eq!(result.api_key.created_by, Some(MOCK_USER_ID));
+        assert!(!result.key.is_empty());
+    }
+
+    #[test]
+    fn test_authenticate_api_key() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        assert!(core.run(service.authenticate_api_key(MOCK_API_KEY.to_string())).is_ok());
+        assert!(core.run(service.authenticate_api_key("unknown".to_string())).is_err());
+    }
+}
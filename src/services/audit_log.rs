@@ -0,0 +1,81 @@
+//! AuditLog Services, presents search of audit log entries
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use super::types::{Service, ServiceFuture};
+use errors::Error;
+use models::{AuditLogEntry, AuditLogSearch};
+use repos::ReposFactory;
+
+const MAX_AUDIT_LOG_SEARCH_COUNT: i64 = 1000;
+
+pub trait AuditLogService {
+    /// Returns audit log entries matching the filters, newest first
+    fn search_audit_log(&self, search: AuditLogSearch) -> ServiceFuture<Vec<AuditLogEntry>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > AuditLogService for Service<T, M, F>
+{
+    /// Returns audit log entries matching the filters, newest first
+    fn search_audit_log(&self, search: AuditLogSearch) -> ServiceFuture<Vec<AuditLogEntry>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            if search.offset < 0 || search.count <= 0 || search.count > MAX_AUDIT_LOG_SEARCH_COUNT {
+                let errors = validation_errors!({
+                    "count": ["range" => format!("Count must be from 1 to {}, offset must not be negative", MAX_AUDIT_LOG_SEARCH_COUNT)]
+                });
+                return Err(Error::Validate(errors).into());
+            }
+
+            let audit_log_repo = repo_factory.create_audit_log_repo(&*conn, user_id);
+            audit_log_repo
+                .search(search)
+                .map_err(|e: FailureError| e.context("Service AuditLog, search endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use tokio_core::reactor::Core;
+
+    use models::authorization::*;
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::audit_log::AuditLogService;
+
+    fn create_search(count: i64) -> AuditLogSearch {
+        AuditLogSearch {
+            user_id: Some(MOCK_USER_ID),
+            correlation_token: None,
+            resource: Some(Resource::Companies),
+            action: None,
+            created_from: None,
+            created_to: None,
+            offset: 0,
+            count,
+        }
+    }
+
+    #[test]
+    fn test_search_audit_log() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let result = core.run(service.search_audit_log(create_search(10))).unwrap();
+        assert_eq!(result[0].resource, Resource::Companies);
+        assert!(core.run(service.search_audit_log(create_search(0))).is_err());
+    }
+}
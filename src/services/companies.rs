@@ -8,7 +8,9 @@ use failure::Error as FailureError;
 
 use stq_types::{Alpha3, CompanyId};
 
+use models::authorization::{Action, Resource};
 use models::companies::{Company, NewCompany, UpdateCompany};
+use models::{DomainEvent, NewOutboxEvent};
 use repos::ReposFactory;
 use services::types::{Service, ServiceFuture};
 
@@ -42,14 +44,17 @@ impl<
     fn create_company(&self, payload: NewCompany) -> ServiceFuture<Company> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::Companies, Action::Create);
 
         self.spawn_on_pool(move |conn| {
             let company_repo = repo_factory.create_companies_repo(&*conn, user_id);
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
             conn.transaction::<Company, FailureError, _>(move || {
-                company_repo
-                    .create(payload)
-                    .map_err(|e| e.context("Service Companies, create endpoint error occured.").into())
+                let company = company_repo.create(payload)?;
+                audit_log_repo.create(audit_log_entry.after(&company)?)?;
+                Ok(company)
             })
+            .map_err(|e| e.context("Service Companies, create endpoint error occured.").into())
         })
     }
 
@@ -96,12 +101,22 @@ impl<
     fn update_company(&self, id: CompanyId, payload: UpdateCompany) -> ServiceFuture<Company> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::Companies, Action::Update);
+        let correlation_token = self.dynamic_context.correlation_token.clone();
 
         self.spawn_on_pool(move |conn| {
             let company_repo = repo_factory.create_companies_repo(&*conn, user_id);
-            company_repo
-                .update(id, payload)
-                .map_err(|e| e.context("Service Companies, update endpoint error occured.").into())
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
+            conn.transaction::<Company, FailureError, _>(move || {
+                let before = company_repo.find(id)?;
+                let company = company_repo.update(id, payload)?;
+                audit_log_repo.create(audit_log_entry.before(&before)?.after(&company)?)?;
+                let event = DomainEvent::CompanyUpdated { company: company.clone() };
+                outbox_repo.create(NewOutboxEvent::new(&event, correlation_token)?)?;
+                Ok(company)
+            })
+            .map_err(|e| e.context("Service Companies, update endpoint error occured.").into())
         })
     }
 
@@ -109,12 +124,17 @@ impl<
     fn delete_company(&self, company_id: CompanyId) -> ServiceFuture<Company> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::Companies, Action::Delete);
 
         self.spawn_on_pool(move |conn| {
             let company_repo = repo_factory.create_companies_repo(&*conn, user_id);
-            company_repo
-                .delete(company_id)
-                .map_err(|e| e.context("Service Companies, delete endpoint error occured.").into())
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            conn.transaction::<Company, FailureError, _>(move || {
+                let company = company_repo.delete(company_id)?;
+                audit_log_repo.create(audit_log_entry.before(&company)?)?;
+                Ok(company)
+            })
+            .map_err(|e| e.context("Service Companies, delete endpoint error occured.").into())
         })
     }
 }
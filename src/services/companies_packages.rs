@@ -10,10 +10,11 @@ use stq_types::{Alpha3, CompanyId, CompanyPackageId, PackageId};
 use validator::Validate;
 
 use errors::Error;
+use metrics;
 use models::{
-    get_countries_from_forest_by, AvailablePackages, Company, CompanyPackage, Country, NewCompanyPackage, NewShippingRates,
-    NewShippingRatesBatch, PackageValidation, Packages, RatesCsvData, ShipmentMeasurements, ShippingRateSource, ShippingRates,
-    ShippingValidation, ZonesCsvData,
+    get_countries_from_forest_by, Action, AvailablePackages, Company, CompanyPackage, Country, DomainEvent, NewCompanyPackage,
+    NewOutboxEvent, NewShippingRates, NewShippingRatesBatch, PackageValidation, Packages, RatesCsvData, Resource, ShipmentMeasurements,
+    ShippingRateSource, ShippingRates, ShippingValidation, ZonesCsvData,
 };
 use repos::ReposFactory;
 use services::types::{Service, ServiceFuture};
@@ -49,6 +50,9 @@ pub trait CompaniesPackagesService {
     /// Returns company package by id
     fn get_company_package(&self, id: CompanyPackageId) -> ServiceFuture<Option<CompanyPackage>>;
 
+    /// Returns all company packages
+    fn list_company_packages(&self) -> ServiceFuture<Vec<CompanyPackage>>;
+
     /// Returns companies by package id
     fn get_companies(&self, id: PackageId) -> ServiceFuture<Vec<Company>>;
 
@@ -82,14 +86,17 @@ impl<
     fn create_company_package(&self, payload: NewCompanyPackage) -> ServiceFuture<CompanyPackage> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::CompaniesPackages, Action::Create);
 
         self.spawn_on_pool(move |conn| {
             let companies_packages_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
             conn.transaction::<CompanyPackage, FailureError, _>(move || {
-                companies_packages_repo
-                    .create(payload)
-                    .map_err(|e| e.context("Service CompaniesPackages, create endpoint error occured.").into())
+                let company_package = companies_packages_repo.create(payload)?;
+                audit_log_repo.create(audit_log_entry.after(&company_package)?)?;
+                Ok(company_package)
             })
+            .map_err(|e| e.context("Service CompaniesPackages, create endpoint error occured.").into())
         })
     }
 
@@ -106,6 +113,19 @@ impl<
         })
     }
 
+    /// Returns all company packages
+    fn list_company_packages(&self) -> ServiceFuture<Vec<CompanyPackage>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let companies_packages_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
+            companies_packages_repo
+                .list()
+                .map_err(|e| e.context("Service CompaniesPackages, list endpoint error occured.").into())
+        })
+    }
+
     /// Returns companies by package id
     fn get_companies(&self, id: PackageId) -> ServiceFuture<Vec<Company>> {
         let repo_factory = self.static_context.repo_factory.clone();
@@ -137,7 +157,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let companies_repo = repo_factory.create_companies_repo(&*conn, user_id);
             let companies_packages_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
             let shipping_rates_repo = repo_factory.create_shipping_rates_repo(&*conn, user_id);
@@ -182,12 +202,32 @@ impl<
     fn delete_company_package(&self, company_id: CompanyId, package_id: PackageId) -> ServiceFuture<CompanyPackage> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let correlation_token = self.dynamic_context.correlation_token.clone();
+        let audit_log_entry = self.audit_log_entry(Resource::CompaniesPackages, Action::Delete);
 
         self.spawn_on_pool(move |conn| {
             let companies_packages_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
-            companies_packages_repo
-                .delete(company_id, package_id)
-                .map_err(|e| e.context("Service CompaniesPackages, delete endpoint error occured.").into())
+            let shipping_rates_repo = repo_factory.create_shipping_rates_repo(&*conn, user_id);
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
+            conn.transaction::<(CompanyPackage, Vec<(CompanyPackageId, Alpha3, Alpha3)>), FailureError, _>(move || {
+                // shipping rates are deleted along with the company package
+                let directions = shipping_rates_repo.get_directions(company_id, package_id)?;
+                let company_package = companies_packages_repo.delete(company_id, package_id)?;
+                audit_log_repo.create(audit_log_entry.before(&company_package)?)?;
+                let event = DomainEvent::CompanyPackageDeleted {
+                    company_package: company_package.clone(),
+                };
+                outbox_repo.create(NewOutboxEvent::new(&event, correlation_token)?)?;
+                Ok((company_package, directions))
+            })
+            .map(|(company_package, directions)| {
+                for (company_package_id, delivery_from, delivery_to) in directions {
+                    repo_factory.invalidate_shipping_rates(company_package_id, &delivery_from, &delivery_to);
+                }
+                company_package
+            })
+            .map_err(|e| e.context("Service CompaniesPackages, delete endpoint error occured.").into())
         })
     }
 
@@ -209,7 +249,7 @@ impl<
             weight_g: weight,
         };
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let companies_repo = repo_factory.create_companies_repo(&*conn, user_id);
             let packages_repo = repo_factory.create_packages_repo(&*conn, user_id);
             let companies_packages_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
@@ -254,13 +294,17 @@ impl<
                         if !shipping_available {
                             None
                         } else {
-                            shipping_rates_repo
+                            let delivery_price = shipping_rates_repo
                                 .get_rates(company_package_id, delivery_from, delivery_to)?
                                 .and_then(|rates| {
                                     rates
                                         .calculate_delivery_price(measurements, dimensional_factor)
                                         .map(|price| DeliveryPrice { currency, value: price })
-                                })
+                                });
+                            if delivery_price.is_none() {
+                                metrics::observe_pricing_miss();
+                            }
+                            delivery_price
                         }
                     }
                 };
@@ -299,6 +343,8 @@ impl<
     ) -> ServiceFuture<Vec<ShippingRates>> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::ShippingRates, Action::Update);
+        let correlation_token = self.dynamic_context.correlation_token.clone();
 
         self.spawn_on_pool(move |conn| {
             let ReplaceShippingRatesPayload {
@@ -351,15 +397,32 @@ impl<
 
             let companies_packages_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
             let shipping_rates_repo = repo_factory.create_shipping_rates_repo(&*conn, user_id);
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
 
             companies_packages_repo
                 .get(company_package_id)
                 .map_err(|e| FailureError::from(e.context("Service CompaniesPackages, replace_shipping_rates endpoint error occured.")))?
                 .ok_or(format_err!("Company package with id = {} not found", company_package_id))?;
 
-            conn.transaction::<Vec<ShippingRates>, FailureError, _>(move || {
-                shipping_rates_repo.delete_all_rates_from(company_package_id, delivery_from)?;
-                shipping_rates_repo.insert_many(new_shipping_rates)
+            conn.transaction::<(Vec<ShippingRates>, Vec<ShippingRates>), FailureError, _>(move || {
+                let before = shipping_rates_repo.delete_all_rates_from(company_package_id, delivery_from.clone())?;
+                let shipping_rates = shipping_rates_repo.insert_many(new_shipping_rates)?;
+                audit_log_repo.create(audit_log_entry.before(&before)?.after(&shipping_rates)?)?;
+                let event = DomainEvent::RatesReplaced {
+                    company_package_id,
+                    delivery_from,
+                    deliveries_to: shipping_rates.iter().map(|rates| rates.to_alpha3.clone()).collect(),
+                };
+                outbox_repo.create(NewOutboxEvent::new(&event, correlation_token)?)?;
+                Ok((before, shipping_rates))
+            })
+            .map(|(before, shipping_rates)| {
+                for rates in before.iter().chain(shipping_rates.iter()) {
+                    repo_factory.invalidate_shipping_rates(rates.company_package_id, &rates.from_alpha3, &rates.to_alpha3);
+                }
+                metrics::observe_shipping_rates_upload();
+                shipping_rates
             })
             .map_err(|e| {
                 e.context("Service CompaniesPackages, replace_shipping_rates endpoint error occured.")
@@ -410,3 +473,54 @@ fn determine_package_availability(
         }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use tokio_core::reactor::Core;
+
+    use serde_json;
+
+    use stq_types::{CompanyId, PackageId, UserId};
+
+    use models::{Action, NewCompanyPackage, Resource};
+    use repos::memory::tests::create_memory_service;
+    use repos::memory::{MemoryFixtures, MemoryStore};
+    use services::companies_packages::CompaniesPackagesService;
+
+    #[test]
+    fn test_company_package_changes_are_audited() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let fixtures: MemoryFixtures = serde_json::from_str(include_str!("../../config/memory_fixtures.json")).unwrap();
+        let store = MemoryStore::new(fixtures);
+        let service = create_memory_service(Some(UserId(1)), handle, store.clone());
+
+        let deleted = core.run(service.delete_company_package(CompanyId(1), PackageId(2))).unwrap();
+        let created = core
+            .run(service.create_company_package(NewCompanyPackage {
+                company_id: CompanyId(1),
+                package_id: PackageId(2),
+                shipping_rate_source: None,
+            }))
+            .unwrap();
+
+        let tables = store.lock();
+        let entries = tables
+            .audit_log
+            .iter()
+            .filter(|entry| entry.resource == Resource::CompaniesPackages)
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].action, Action::Delete);
+        assert_eq!(
+            entries[0].before.as_ref().and_then(|before| before["id"].as_i64()),
+            Some(i64::from(deleted.id.0))
+        );
+        assert_eq!(entries[1].action, Action::Create);
+        assert_eq!(
+            entries[1].after.as_ref().and_then(|after| after["id"].as_i64()),
+            Some(i64::from(created.id.0))
+        );
+    }
+}
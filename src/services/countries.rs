@@ -36,7 +36,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let countries_repo = repo_factory.create_countries_repo(&*conn, user_id);
             countries_repo
                 .find(code)
@@ -49,7 +49,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let countries_repo = repo_factory.create_countries_repo(&*conn, user_id);
             countries_repo
                 .find_by(search)
@@ -65,6 +65,10 @@ impl<
         self.spawn_on_pool(move |conn| {
             let countries_repo = repo_factory.create_countries_repo(&*conn, user_id);
             conn.transaction::<(Country), FailureError, _>(move || countries_repo.create(new_country))
+                .map(|country| {
+                    repo_factory.invalidate_countries();
+                    country
+                })
                 .map_err(|e| e.context("Service Countries, create endpoint error occured.").into())
         })
     }
@@ -74,7 +78,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let countries_repo = repo_factory.create_countries_repo(&*conn, user_id);
             countries_repo
                 .get_all()
@@ -87,7 +91,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let countries_repo = repo_factory.create_countries_repo(&*conn, user_id);
             countries_repo
                 .get_all_flatten()
@@ -0,0 +1,160 @@
+//! Health Services, presents liveness and readiness checks
+
+use std::time::Instant;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use futures::future;
+use r2d2::{ManageConnection, Pool, PooledConnection};
+use r2d2_redis::redis;
+use serde_json;
+
+use super::types::{Service, ServiceFuture};
+use errors::Error;
+use models::{DependencyStatus, Health, HealthStatus, Readiness};
+use repos::ReposFactory;
+
+pub trait HealthService {
+    /// Returns status of the service without checking dependencies
+    fn liveness(&self) -> ServiceFuture<Health>;
+    /// Checks the database, Redis if it is configured and loading of the country tree,
+    /// fails with `Error::NotReady` carrying the report if any check fails or shutdown has started
+    fn readiness(&self) -> ServiceFuture<Readiness>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > HealthService for Service<T, M, F>
+{
+    /// Returns status of the service without checking dependencies
+    fn liveness(&self) -> ServiceFuture<Health> {
+        Box::new(future::ok(Health { status: HealthStatus::Ok }))
+    }
+
+    /// Checks the database, Redis if it is configured and loading of the country tree,
+    /// fails with `Error::NotReady` carrying the report if any check fails or shutdown has started
+    fn readiness(&self) -> ServiceFuture<Readiness> {
+        if self.static_context.shutdown.is_started() {
+            let error: FailureError = match serde_json::to_value(&Readiness::shutting_down()) {
+                Ok(report) => Error::NotReady(report).into(),
+                Err(e) => e.into(),
+            };
+            return Box::new(future::err(error));
+        }
+
+        let db_pool = self.static_context.db_pool.clone();
+        let redis_pool = self.static_context.redis_pool.clone();
+        let repo_factory = self.static_context.repo_factory.clone();
+        let db_name = self.static_context.config.server.repo_backend.name();
+
+        Box::new(self.static_context.cpu_pool.spawn_fn(move || {
+            let mut dependencies = vec![check_dependency(db_name, || try_get(&db_pool).map(|_| ()))];
+
+            if let Some(redis_pool) = redis_pool {
+                dependencies.push(check_dependency("redis", || {
+                    let conn = try_get(&redis_pool)?;
+                    redis::cmd("PING").query::<String>(&*conn)?;
+                    Ok(())
+                }));
+            }
+
+            dependencies.push(check_dependency("countries", || {
+                let conn = try_get(&db_pool)?;
+                repo_factory.create_countries_repo(&*conn, None).get_all().map(|_| ())
+            }));
+
+            let readiness = Readiness::new(dependencies);
+            if readiness.status == HealthStatus::Ok {
+                Ok(readiness)
+            } else {
+                let report = serde_json::to_value(&readiness)?;
+                Err(Error::NotReady(report).into())
+            }
+        }))
+    }
+}
+
+/// Takes an idle connection without waiting for `connection_timeout` of the pool, readiness must answer quickly
+fn try_get<M: ManageConnection>(pool: &Pool<M>) -> Result<PooledConnection<M>, FailureError> {
+    pool.try_get().ok_or_else(|| format_err!("No idle connection in the pool"))
+}
+
+fn check_dependency<Func>(name: &str, check: Func) -> DependencyStatus
+where
+    Func: FnOnce() -> Result<(), FailureError>,
+{
+    let started_at = Instant::now();
+    let result = check();
+    let elapsed = started_at.elapsed();
+    let latency_ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+
+    match result {
+        Ok(()) => DependencyStatus {
+            name: name.to_string(),
+            status: HealthStatus::Ok,
+            latency_ms,
+            error: None,
+        },
+        Err(e) => {
+            error!("Readiness check of {} failed: {}", name, e);
+            DependencyStatus {
+                name: name.to_string(),
+                status: HealthStatus::Error,
+                latency_ms,
+                error: Some(e.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio_core::reactor::Core;
+
+    use models::*;
+    use repos::memory::tests::create_memory_service;
+    use repos::memory::{MemoryFixtures, MemoryStore};
+    use repos::repo_factory::tests::*;
+    use services::health::HealthService;
+
+    #[test]
+    fn test_readiness() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let result = core.run(service.readiness()).unwrap();
+        assert_eq!(result.status, HealthStatus::Ok);
+        assert_eq!(
+            result.dependencies.iter().map(|dependency| dependency.name.as_str()).collect::<Vec<_>>(),
+            vec!["postgres", "countries"]
+        );
+    }
+
+    #[test]
+    fn test_readiness_during_shutdown() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        service.static_context.shutdown.start();
+        assert!(core.run(service.readiness()).is_err());
+        assert!(core.run(service.liveness()).is_ok());
+    }
+
+    #[test]
+    fn test_readiness_does_not_wait_for_busy_pool() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        // the pool of the memory service holds a single connection
+        let service = create_memory_service(None, handle, MemoryStore::new(MemoryFixtures::default()));
+        let _busy = service.static_context.db_pool.get().unwrap();
+        let started_at = Instant::now();
+        assert!(core.run(service.readiness()).is_err());
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,105 @@
+//! Impersonation Services, lets superusers act on behalf of other users
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use stq_types::UserId;
+
+use super::types::{Service, ServiceFuture};
+use errors::Error;
+use models::authorization::{Action, Resource};
+use models::ImpersonatedRequest;
+use repos::ReposFactory;
+
+pub trait ImpersonationService {
+    /// Checks that the real user of the request may act on behalf of the user
+    /// and writes the request to audit log, returns the real user
+    fn impersonate(&self, user_id: UserId, method: String, path: String) -> ServiceFuture<UserId>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ImpersonationService for Service<T, M, F>
+{
+    /// Checks that the real user of the request may act on behalf of the user
+    /// and writes the request to audit log, returns the real user
+    fn impersonate(&self, user_id: UserId, method: String, path: String) -> ServiceFuture<UserId> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let acl_policy = self.static_context.acl_policy.clone();
+        let real_user_id = self.dynamic_context.real_user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::Impersonations, Action::Create);
+
+        self.spawn_on_pool(move |conn| {
+            let real_user_id =
+                real_user_id.ok_or_else(|| format_err!("Denied unauthorized request to impersonate user").context(Error::Unauthorized))?;
+
+            let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+
+            let roles = user_roles_repo.list_for_user(real_user_id)?;
+            let allowed = acl_policy.effective_permissions(&roles).iter().any(|permission| {
+                permission.resource == Resource::Impersonations && (permission.action == Action::Create || permission.action == Action::All)
+            });
+            if !allowed {
+                return Err(format_err!("Denied request from user {} to impersonate user {}", real_user_id, user_id)
+                    .context(Error::Forbidden)
+                    .into());
+            }
+
+            let request = ImpersonatedRequest {
+                user_id,
+                real_user_id,
+                method,
+                path,
+            };
+            audit_log_repo
+                .create(audit_log_entry.after(&request)?)
+                .map(|_| real_user_id)
+                .map_err(|e: FailureError| e.context("Service Impersonation, impersonate error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use tokio_core::reactor::Core;
+
+    use stq_types::*;
+
+    use repos::repo_factory::tests::*;
+    use services::impersonation::ImpersonationService;
+
+    #[test]
+    fn test_impersonate_by_superuser() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let work = service.impersonate(UserId(2), "GET".to_string(), "/users/addresses".to_string());
+        assert_eq!(core.run(work).unwrap(), MOCK_USER_ID);
+    }
+
+    #[test]
+    fn test_impersonate_by_user() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(2)), handle);
+        let work = service.impersonate(UserId(3), "GET".to_string(), "/users/addresses".to_string());
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_impersonate_unauthorized() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.impersonate(UserId(2), "GET".to_string(), "/users/addresses".to_string());
+        assert!(core.run(work).is_err());
+    }
+}
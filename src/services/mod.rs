@@ -1,10 +1,16 @@
+pub mod api_keys;
+pub mod audit_log;
 pub mod companies;
 pub mod companies_packages;
 pub mod countries;
+pub mod health;
+pub mod impersonation;
 pub mod packages;
 pub mod products;
 pub mod types;
 pub mod user_addresses;
+pub mod user_data;
 pub mod user_roles;
+pub mod webhooks;
 
 pub use self::types::Service;
@@ -10,7 +10,9 @@ use failure::Error as FailureError;
 use stq_types::{Alpha3, PackageId};
 
 use super::types::{Service, ServiceFuture};
+use models::authorization::{Action, Resource};
 use models::packages::{NewPackages, Packages, UpdatePackages};
+use models::{DomainEvent, NewOutboxEvent};
 use repos::countries::get_all_parent_codes;
 use repos::ReposFactory;
 
@@ -42,14 +44,21 @@ impl<
     fn create_package(&self, payload: NewPackages) -> ServiceFuture<Packages> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::Packages, Action::Create);
+        let correlation_token = self.dynamic_context.correlation_token.clone();
 
         self.spawn_on_pool(move |conn| {
             let packages_repo = repo_factory.create_packages_repo(&*conn, user_id);
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
             conn.transaction::<Packages, FailureError, _>(move || {
-                packages_repo
-                    .create(payload)
-                    .map_err(|e| e.context("Service Packages, create endpoint error occured.").into())
+                let package = packages_repo.create(payload)?;
+                audit_log_repo.create(audit_log_entry.after(&package)?)?;
+                let event = DomainEvent::PackageCreated { package: package.clone() };
+                outbox_repo.create(NewOutboxEvent::new(&event, correlation_token)?)?;
+                Ok(package)
             })
+            .map_err(|e| e.context("Service Packages, create endpoint error occured.").into())
         })
     }
 
@@ -57,7 +66,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let packages_repo = repo_factory.create_packages_repo(&*conn, user_id);
             let countries_repo = repo_factory.create_countries_repo(&*conn, user_id);
             countries_repo
@@ -99,24 +108,43 @@ impl<
     fn update_package(&self, id: PackageId, payload: UpdatePackages) -> ServiceFuture<Packages> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::Packages, Action::Update);
+        let correlation_token = self.dynamic_context.correlation_token.clone();
 
         self.spawn_on_pool(move |conn| {
             let packages_repo = repo_factory.create_packages_repo(&*conn, user_id);
-            packages_repo
-                .update(id, payload)
-                .map_err(|e| e.context("Service Packages, update endpoint error occured.").into())
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
+            conn.transaction::<Packages, FailureError, _>(move || {
+                let before = packages_repo.find(id)?;
+                let package = packages_repo.update(id, payload)?;
+                audit_log_repo.create(audit_log_entry.before(&before)?.after(&package)?)?;
+                let event = DomainEvent::PackageUpdated { package: package.clone() };
+                outbox_repo.create(NewOutboxEvent::new(&event, correlation_token)?)?;
+                Ok(package)
+            })
+            .map_err(|e| e.context("Service Packages, update endpoint error occured.").into())
         })
     }
 
     fn delete_package(&self, id: PackageId) -> ServiceFuture<Packages> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::Packages, Action::Delete);
+        let correlation_token = self.dynamic_context.correlation_token.clone();
 
         self.spawn_on_pool(move |conn| {
             let packages_repo = repo_factory.create_packages_repo(&*conn, user_id);
-            packages_repo
-                .delete(id)
-                .map_err(|e| e.context("Service Packages, delete endpoint error occured.").into())
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
+            conn.transaction::<Packages, FailureError, _>(move || {
+                let package = packages_repo.delete(id)?;
+                audit_log_repo.create(audit_log_entry.before(&package)?)?;
+                let event = DomainEvent::PackageDeleted { package: package.clone() };
+                outbox_repo.create(NewOutboxEvent::new(&event, correlation_token)?)?;
+                Ok(package)
+            })
+            .map_err(|e| e.context("Service Packages, delete endpoint error occured.").into())
         })
     }
 }
@@ -126,9 +154,13 @@ pub mod tests {
     use std::sync::Arc;
     use tokio_core::reactor::Core;
 
+    use serde_json;
+
     use stq_types::*;
 
     use models::*;
+    use repos::memory::tests::create_memory_service;
+    use repos::memory::{MemoryFixtures, MemoryStore};
     use repos::repo_factory::tests::*;
     use services::packages::PackagesService;
 
@@ -164,4 +196,29 @@ pub mod tests {
         assert_eq!(result.name, "package1".to_string());
     }
 
+    #[test]
+    fn test_package_changes_emit_events() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let fixtures: MemoryFixtures = serde_json::from_str(include_str!("../../config/memory_fixtures.json")).unwrap();
+        let store = MemoryStore::new(fixtures);
+        let service = create_memory_service(Some(UserId(1)), handle, store.clone());
+
+        let created = core
+            .run(service.create_package(create_new_packages("package1".to_string())))
+            .unwrap();
+        core.run(service.delete_package(created.id)).unwrap();
+
+        let tables = store.lock();
+        let events = tables
+            .outbox_events
+            .iter()
+            .map(|event| event.event_type.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec!["package_created", "package_deleted"]);
+        assert_eq!(
+            tables.outbox_events[0].payload["package"]["id"].as_i64(),
+            Some(i64::from(created.id.0))
+        );
+    }
 }
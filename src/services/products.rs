@@ -7,19 +7,21 @@ use validator::Validate;
 
 use r2d2::ManageConnection;
 
-use stq_types::{Alpha3, BaseProductId, CompanyPackageId, ProductPrice, ShippingId};
+use stq_types::{Alpha3, BaseProductId, CompanyPackageId, CountryLabel, ProductPrice, ShippingId};
 
 use errors::Error;
+use metrics;
 use models::{
-    AvailablePackageForUser, AvailableShippingForUser, NewProductValidation, NewProducts, NewShipping, PackageValidation, Products,
-    ShipmentMeasurements, Shipping, ShippingProducts, ShippingRateSource, ShippingValidation, UpdateProducts,
+    Action, AvailablePackageForUser, AvailableShippingForUser, DomainEvent, NewOutboxEvent, NewProductValidation, NewProducts, NewShipping,
+    PackageValidation, Products, Resource, ShipmentMeasurements, Shipping, ShippingProducts, ShippingRateSource, ShippingValidation,
+    UpdateProducts, UserAddress, UserAddressDestination,
 };
 use repos::companies::CompaniesRepo;
 use repos::companies_packages::CompaniesPackagesRepo;
-use repos::countries::create_tree_used_countries;
+use repos::countries::{create_tree_used_countries, CountriesRepo};
 use repos::products::ProductsWithAvailableCountries;
 use repos::shipping_rates::ShippingRatesRepo;
-use repos::ReposFactory;
+use repos::{CountrySearch, ReposFactory};
 use services::types::{Service, ServiceFuture};
 
 pub trait ProductsService {
@@ -46,6 +48,14 @@ pub trait ProductsService {
         weight: u32,
     ) -> ServiceFuture<AvailableShippingForUser>;
 
+    /// find available product delivery to the country of user's saved address,
+    /// priority address is used if `user_address_id` is not provided
+    fn find_available_shipping_for_user_address(
+        &self,
+        base_product_id: BaseProductId,
+        user_address_id: Option<i32>,
+    ) -> ServiceFuture<AvailableShippingForUser>;
+
     /// Update a product
     fn update_products(
         &self,
@@ -87,16 +97,21 @@ impl<
     fn upsert(&self, base_product_id: BaseProductId, payload: NewShipping) -> ServiceFuture<Shipping> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::Products, Action::Update);
+        let correlation_token = self.dynamic_context.correlation_token.clone();
 
         self.spawn_on_pool(move |conn| {
-            conn.transaction::<Shipping, _, _>(|| {
+            conn.transaction::<Shipping, FailureError, _>(|| {
                 let products_repo = repo_factory.create_products_repo(&*conn, user_id);
                 let pickups_repo = repo_factory.create_pickups_repo(&*conn, user_id);
                 let countries_repo = repo_factory.create_countries_repo(&*conn, user_id);
                 let companies_repo = repo_factory.create_companies_repo(&*conn, user_id);
                 let packages_repo = repo_factory.create_packages_repo(&*conn, user_id);
                 let company_packages_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
+                let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+                let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
                 let pickup = payload.pickup.clone();
+                let before = products_repo.get_by_base_product_id(base_product_id)?;
 
                 products_repo
                     .delete(base_product_id)
@@ -171,6 +186,15 @@ impl<
                             pickup: pickups,
                         })
                     })
+                    .and_then(|shipping| {
+                        audit_log_repo.create(audit_log_entry.before(&before)?.after(&shipping)?)?;
+                        let event = DomainEvent::ShippingUpserted {
+                            base_product_id,
+                            shipping: shipping.clone(),
+                        };
+                        outbox_repo.create(NewOutboxEvent::new(&event, correlation_token.clone())?)?;
+                        Ok(shipping)
+                    })
             })
             .map_err(|e: FailureError| e.context("Service Products, upsert endpoint error occured.").into())
         })
@@ -223,15 +247,17 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let products_repo = repo_factory.create_products_repo(&*conn, user_id);
             let pickups_repo = repo_factory.create_pickups_repo(&*conn, user_id);
             products_repo
                 .find_available_to(base_product_id, user_country)
                 .and_then(|packages| {
-                    pickups_repo
-                        .get(base_product_id)
-                        .map(|pickups| AvailableShippingForUser { packages, pickups })
+                    pickups_repo.get(base_product_id).map(|pickups| AvailableShippingForUser {
+                        packages,
+                        pickups,
+                        destination: None,
+                    })
                 })
                 .map_err(|e| e.context("Service Products, find_available_to endpoint error occurred.").into())
         })
@@ -249,7 +275,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let products_repo = repo_factory.create_products_repo(&*conn, user_id);
             let company_package_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
             let company_repo = repo_factory.create_companies_repo(&*conn, user_id);
@@ -277,15 +303,65 @@ impl<
                     .filter_map(|x| x)
                     .collect::<Vec<_>>();
 
-                pickups_repo
-                    .get(base_product_id)
-                    .map(|pickups| AvailableShippingForUser { packages, pickups })
+                pickups_repo.get(base_product_id).map(|pickups| AvailableShippingForUser {
+                    packages,
+                    pickups,
+                    destination: None,
+                })
             };
 
             run().map_err(|e: FailureError| e.context("Service Products, find_available_to endpoint error occurred.").into())
         })
     }
 
+    /// find available product delivery to the country of user's saved address,
+    /// priority address is used if `user_address_id` is not provided
+    fn find_available_shipping_for_user_address(
+        &self,
+        base_product_id: BaseProductId,
+        user_address_id: Option<i32>,
+    ) -> ServiceFuture<AvailableShippingForUser> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+
+        self.spawn_on_read_pool(move |conn| {
+            let products_repo = repo_factory.create_products_repo(&*conn, user_id);
+            let pickups_repo = repo_factory.create_pickups_repo(&*conn, user_id);
+            let countries_repo = repo_factory.create_countries_repo(&*conn, user_id);
+            let users_addresses_repo = repo_factory.create_users_addresses_repo(&*conn, user_id);
+
+            let run = || {
+                let current_user_id =
+                    user_id.ok_or_else(|| format_err!("Denied unauthorized request to get user address").context(Error::Forbidden))?;
+
+                let user_address = users_addresses_repo
+                    .list_for_user(current_user_id)?
+                    .into_iter()
+                    .find(|address| match user_address_id {
+                        Some(user_address_id) => address.id == user_address_id,
+                        None => address.is_priority,
+                    })
+                    .ok_or_else(|| {
+                        format_err!("User address {:?} of user {} not found", user_address_id, current_user_id).context(Error::NotFound)
+                    })?;
+
+                let destination = get_user_address_destination(&*countries_repo, &user_address)?;
+
+                let packages = products_repo.find_available_to(base_product_id, destination.country.clone())?;
+                pickups_repo.get(base_product_id).map(|pickups| AvailableShippingForUser {
+                    packages,
+                    pickups,
+                    destination: Some(destination),
+                })
+            };
+
+            run().map_err(|e: FailureError| {
+                e.context("Service Products, find_available_shipping_for_user_address endpoint error occurred.")
+                    .into()
+            })
+        })
+    }
+
     /// Returns available package for user by id
     /// DEPRECATED. Use `get_available_package_for_user_by_shipping_id_v2` instead.
     fn get_available_package_for_user(
@@ -296,7 +372,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let products_repo = repo_factory.create_products_repo(&*conn, user_id);
 
             products_repo
@@ -313,7 +389,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let products_repo = repo_factory.create_products_repo(&*conn, user_id);
 
             products_repo
@@ -337,7 +413,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_read_pool(move |conn| {
             let products_repo = repo_factory.create_products_repo(&*conn, user_id);
             let company_package_repo = repo_factory.create_companies_packages_repo(&*conn, user_id);
             let company_repo = repo_factory.create_companies_repo(&*conn, user_id);
@@ -381,8 +457,7 @@ impl<
 
         self.spawn_on_pool(move |conn| {
             let products_repo = repo_factory.create_products_repo(&*conn, user_id);
-            products_repo
-                .update(base_product_id_arg, company_package_id, payload)
+            conn.transaction::<Products, FailureError, _>(move || products_repo.update(base_product_id_arg, company_package_id, payload))
                 .map_err(|e| e.context("Service Products, update endpoint error occured.").into())
         })
     }
@@ -390,20 +465,60 @@ impl<
     fn delete_products(&self, base_product_id_arg: BaseProductId) -> ServiceFuture<()> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let correlation_token = self.dynamic_context.correlation_token.clone();
 
         self.spawn_on_pool(move |conn| {
             conn.transaction::<(), _, _>(|| {
                 let products_repo = repo_factory.create_products_repo(&*conn, user_id);
                 let pickups_repo = repo_factory.create_pickups_repo(&*conn, user_id);
+                let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
                 products_repo
                     .delete(base_product_id_arg)
                     .and_then(|_| pickups_repo.delete(base_product_id_arg).and_then(|_| Ok(())))
+                    .and_then(|_| {
+                        let event = DomainEvent::ShippingDeleted {
+                            base_product_id: base_product_id_arg,
+                        };
+                        outbox_repo
+                            .create(NewOutboxEvent::new(&event, correlation_token.clone())?)
+                            .map(|_| ())
+                    })
             })
             .map_err(|e| e.context("Service Products, delete endpoint error occured.").into())
         })
     }
 }
 
+/// Resolves user address country by its alpha3 code, falls back to the country label for addresses without a code.
+/// Subdivision is the first level administrative area of the address
+fn get_user_address_destination(
+    countries_repo: &CountriesRepo,
+    user_address: &UserAddress,
+) -> Result<UserAddressDestination, FailureError> {
+    let search = match user_address.country_code {
+        Some(ref country_code) => CountrySearch::Alpha3(Alpha3(country_code.to_uppercase())),
+        None => CountrySearch::Label(CountryLabel(user_address.country.clone())),
+    };
+
+    let country = countries_repo
+        .find_by(search.clone())?
+        .map(|country| country.alpha3)
+        .ok_or_else(|| {
+            FailureError::from(Error::Validate(validation_errors!({
+                "user_address": ["country" => format!("Country of user address {} not found by {:?}", user_address.id, search)]
+            })))
+        })?;
+
+    let subdivision = user_address
+        .administrative_area_level_1
+        .as_ref()
+        .map(|area| area.trim())
+        .filter(|area| !area.is_empty())
+        .map(|area| area.to_string());
+
+    Ok(UserAddressDestination { country, subdivision })
+}
+
 fn with_price_from_rates<'a>(
     company_package_repo: &'a CompaniesPackagesRepo,
     company_repo: &'a CompaniesRepo,
@@ -430,15 +545,21 @@ fn with_price_from_rates<'a>(
 
     let price = match company_package.shipping_rate_source {
         ShippingRateSource::NotAvailable => None,
-        ShippingRateSource::Static { dimensional_factor } => shipping_rates_repo
-            .get_rates(company_package_id, delivery_from, delivery_to)?
-            .and_then(|rates| {
-                let measurements = ShipmentMeasurements {
-                    volume_cubic_cm: volume,
-                    weight_g: weight,
-                };
-                rates.calculate_delivery_price(measurements, dimensional_factor).map(ProductPrice)
-            }),
+        ShippingRateSource::Static { dimensional_factor } => {
+            let price = shipping_rates_repo
+                .get_rates(company_package_id, delivery_from, delivery_to)?
+                .and_then(|rates| {
+                    let measurements = ShipmentMeasurements {
+                        volume_cubic_cm: volume,
+                        weight_g: weight,
+                    };
+                    rates.calculate_delivery_price(measurements, dimensional_factor).map(ProductPrice)
+                });
+            if price.is_none() {
+                metrics::observe_pricing_miss();
+            }
+            price
+        }
     };
 
     Ok(price.map(|price| {
@@ -447,3 +568,71 @@ fn with_price_from_rates<'a>(
         pkg_for_user
     }))
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+
+    use r2d2::Pool;
+
+    use stq_types::Alpha3;
+
+    use models::UserAddressDestination;
+    use replica::ReadReplica;
+    use repos::repo_factory::tests::*;
+    use services::products::ProductsService;
+
+    #[test]
+    fn test_find_available_shipping_for_priority_user_address() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let work = service.find_available_shipping_for_user_address(MOCK_BASE_PRODUCT_ID, None);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.packages.len(), 1);
+        assert_eq!(result.packages[0].base_product_id, MOCK_BASE_PRODUCT_ID);
+        assert_eq!(
+            result.destination,
+            Some(UserAddressDestination {
+                country: Alpha3("RUS".to_string()),
+                subdivision: Some("Moscow".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_find_available_shipping_for_missing_user_address() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let work = service.find_available_shipping_for_user_address(MOCK_BASE_PRODUCT_ID, Some(2));
+        let result = core.run(work);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_find_available_shipping_for_user_address_unauthorized() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.find_available_shipping_for_user_address(MOCK_BASE_PRODUCT_ID, None);
+        let result = core.run(work);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_find_available_shipping_falls_back_to_primary() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let mut service = create_service(Some(MOCK_USER_ID), handle);
+        let read_db_pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(100))
+            .build_unchecked(MockConnectionManager { unavailable: true });
+        service.static_context.read_replica = Some(ReadReplica::new(read_db_pool, Duration::from_secs(60)));
+        let work = service.find_available_shipping_for_user_address(MOCK_BASE_PRODUCT_ID, None);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.packages.len(), 1);
+    }
+}
@@ -3,11 +3,16 @@ use diesel::pg::Pg;
 use diesel::Connection;
 use failure::Error as FailureError;
 use failure::Fail;
+use futures::future;
 use futures::Future;
 use r2d2::{ManageConnection, PooledConnection};
 
 use controller::context::{DynamicContext, StaticContext};
+use correlation;
 use errors::Error;
+use models::authorization::{Action, Resource};
+use models::NewAuditLogEntry;
+use replica::{self, ReadReplica};
 use repos::repo_factory::*;
 
 /// Service layer Future
@@ -39,13 +44,89 @@ impl<
     }
 
     pub fn spawn_on_pool<R, Func>(&self, f: Func) -> ServiceFuture<R>
+    where
+        Func: FnOnce(PooledConnection<M>) -> Result<R, FailureError> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.spawn_with_pools(None, f)
+    }
+
+    /// Same as `spawn_on_pool`, but the connection is taken from the read replica if it is configured.
+    /// Falls back to the primary if the replica is unavailable. Only for methods that do not write,
+    /// the replica may lag behind the primary, so caches are not filled with what is read from it
+    pub fn spawn_on_read_pool<R, Func>(&self, f: Func) -> ServiceFuture<R>
+    where
+        Func: FnOnce(PooledConnection<M>) -> Result<R, FailureError> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.spawn_with_pools(self.static_context.read_replica.clone(), f)
+    }
+
+    fn spawn_with_pools<R, Func>(&self, read_replica: Option<ReadReplica<M>>, f: Func) -> ServiceFuture<R>
     where
         Func: FnOnce(PooledConnection<M>) -> Result<R, FailureError> + Send + 'static,
         R: Send + 'static,
     {
         let db_pool = self.static_context.db_pool.clone();
         let cpu_pool = self.static_context.cpu_pool.clone();
-        Box::new(cpu_pool.spawn_fn(move || db_pool.get().map_err(|e| e.context(Error::Connection).into()).and_then(f)))
+        let correlation_token = self.dynamic_context.correlation_token.clone();
+        let in_flight = self.static_context.shutdown.track_job();
+        Box::new(cpu_pool.spawn_fn(move || {
+            let _in_flight = in_flight;
+            correlation::scope(&correlation_token, move || {
+                if let Some(conn) = read_replica.and_then(|read_replica| read_replica.get()) {
+                    return replica::scope(move || f(conn));
+                }
+                db_pool.get().map_err(|e| e.context(Error::Connection).into()).and_then(f)
+            })
+        }))
+    }
+
+    /// Loads roles of the effective user once per request, repos created by the factory of the returned service
+    /// check permissions with these roles instead of loading them on every `create_*_repo` call.
+    /// Roles taken from the token are used as they are
+    pub fn resolve_user_roles(self) -> ServiceFuture<Self> {
+        let user_id = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => return Box::new(future::ok(self)),
+        };
+        if let Some(user_roles) = self.dynamic_context.user_roles.clone() {
+            let Service {
+                mut static_context,
+                dynamic_context,
+            } = self;
+            static_context.repo_factory = static_context.repo_factory.with_user_roles(user_id, user_roles);
+            return Box::new(future::ok(Service::new(static_context, dynamic_context)));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        let user_roles = self.spawn_on_pool(move |conn| {
+            repo_factory
+                .create_user_roles_repo_with_sys_acl(&*conn)
+                .get_by_user_id(user_id)
+                .map_err(|e: FailureError| e.context(format!("Resolving roles of user {} error occurred.", user_id)).into())
+        });
+
+        Box::new(user_roles.map(move |user_roles| {
+            let Service {
+                mut static_context,
+                mut dynamic_context,
+            } = self;
+            static_context.repo_factory = static_context.repo_factory.with_user_roles(user_id, user_roles.clone());
+            dynamic_context.user_roles = Some(user_roles);
+            Service::new(static_context, dynamic_context)
+        }))
+    }
+
+    /// Returns audit log entry of the change made in the current request,
+    /// the entry holds the real user if the request is impersonated
+    pub fn audit_log_entry(&self, resource: Resource, action: Action) -> NewAuditLogEntry {
+        NewAuditLogEntry::new(
+            self.dynamic_context.real_user_id,
+            self.dynamic_context.correlation_token.clone(),
+            resource,
+            action,
+        )
     }
 }
 
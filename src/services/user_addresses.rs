@@ -11,7 +11,8 @@ use failure::Error as FailureError;
 use stq_types::UserId;
 
 use super::types::{Service, ServiceFuture};
-use models::{NewUserAddress, UpdateUserAddress, UserAddress};
+use errors::Error;
+use models::{AddressLabel, NewUserAddress, UpdateUserAddress, UserAddress};
 use repos::ReposFactory;
 
 pub trait UserAddressService {
@@ -23,6 +24,8 @@ pub trait UserAddressService {
     fn update_address(&self, id: i32, payload: UpdateUserAddress) -> ServiceFuture<UserAddress>;
     /// Delete user addresses
     fn delete_address(&self, id: i32) -> ServiceFuture<UserAddress>;
+    /// Render user address into label lines using format of its country
+    fn get_address_label(&self, id: i32) -> ServiceFuture<AddressLabel>;
 }
 
 impl<
@@ -51,9 +54,11 @@ impl<
 
         self.spawn_on_pool(move |conn| {
             let users_addresses_repo = repo_factory.create_users_addresses_repo(&*conn, user_id);
-            users_addresses_repo
-                .delete(id)
-                .map_err(|e| e.context("Service UserAddress, delete endpoint error occured.").into())
+            conn.transaction::<UserAddress, FailureError, _>(move || {
+                users_addresses_repo
+                    .delete(id)
+                    .map_err(|e| e.context("Service UserAddress, delete endpoint error occured.").into())
+            })
         })
     }
 
@@ -77,11 +82,150 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
 
+        self.spawn_on_pool(move |conn| {
+            let users_addresses_repo = repo_factory.create_users_addresses_repo(&*conn, user_id);
+            conn.transaction::<UserAddress, FailureError, _>(move || {
+                users_addresses_repo
+                    .update(id, payload)
+                    .map_err(|e| e.context("Service UserAddress, update endpoint error occured.").into())
+            })
+        })
+    }
+
+    /// Render user address into label lines using format of its country
+    fn get_address_label(&self, id: i32) -> ServiceFuture<AddressLabel> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+
         self.spawn_on_pool(move |conn| {
             let users_addresses_repo = repo_factory.create_users_addresses_repo(&*conn, user_id);
             users_addresses_repo
-                .update(id, payload)
-                .map_err(|e| e.context("Service UserAddress, update endpoint error occured.").into())
+                .get(id)
+                .and_then(|user_address| {
+                    user_address
+                        .map(|user_address| AddressLabel::from_user_address(&user_address))
+                        .ok_or_else(|| format_err!("User address {} not found", id).context(Error::NotFound).into())
+                })
+                .map_err(|e: FailureError| e.context("Service UserAddress, get_address_label endpoint error occured.").into())
         })
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use tokio_core::reactor::Core;
+
+    use serde_json;
+
+    use stq_types::UserId;
+
+    use models::{NewUserAddress, UpdateUserAddress, UserAddress};
+    use repos::memory::tests::create_memory_service;
+    use repos::memory::{MemoryFixtures, MemoryStore};
+    use services::user_addresses::UserAddressService;
+
+    const USER_ID: UserId = UserId(1);
+
+    fn create_store() -> MemoryStore {
+        let fixtures: MemoryFixtures = serde_json::from_str(
+            r#"{
+                "roles": [
+                    { "id": "5e32a9d6-0bc6-4d8e-9f6a-0b8c9a3c1f02", "user_id": 1, "name": "user", "data": null }
+                ]
+            }"#,
+        )
+        .unwrap();
+        MemoryStore::new(fixtures)
+    }
+
+    fn new_address(postal_code: &str, is_priority: bool) -> NewUserAddress {
+        NewUserAddress {
+            user_id: USER_ID,
+            administrative_area_level_1: None,
+            administrative_area_level_2: None,
+            country: "Russian Federation".to_string(),
+            locality: None,
+            political: None,
+            postal_code: postal_code.to_string(),
+            route: None,
+            street_number: None,
+            address: None,
+            is_priority,
+            country_code: Some("RUS".to_string()),
+        }
+    }
+
+    fn priority_ids(addresses: &[UserAddress]) -> Vec<i32> {
+        addresses
+            .iter()
+            .filter(|address| address.is_priority)
+            .map(|address| address.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_create_priority_address_resets_others() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_memory_service(Some(USER_ID), handle, create_store());
+        let first = core.run(service.create_address(new_address("125009", true))).unwrap();
+        let second = core.run(service.create_address(new_address("125010", true))).unwrap();
+        let addresses = core.run(service.get_addresses(USER_ID)).unwrap();
+        assert_eq!(priority_ids(&addresses), vec![second.id]);
+        assert!(addresses.iter().any(|address| address.id == first.id && !address.is_priority));
+    }
+
+    #[test]
+    fn test_update_priority_address_resets_others() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_memory_service(Some(USER_ID), handle, create_store());
+        let first = core.run(service.create_address(new_address("125009", false))).unwrap();
+        let second = core.run(service.create_address(new_address("125010", true))).unwrap();
+        let payload = UpdateUserAddress {
+            administrative_area_level_1: None,
+            administrative_area_level_2: None,
+            country: None,
+            locality: None,
+            political: None,
+            postal_code: None,
+            route: None,
+            street_number: None,
+            address: None,
+            is_priority: Some(true),
+            country_code: None,
+        };
+        let updated = core.run(service.update_address(first.id, payload)).unwrap();
+        assert!(updated.is_priority);
+        let addresses = core.run(service.get_addresses(USER_ID)).unwrap();
+        assert_eq!(priority_ids(&addresses), vec![first.id]);
+        assert!(addresses.iter().any(|address| address.id == second.id && !address.is_priority));
+    }
+
+    #[test]
+    fn test_delete_priority_address_promotes_last_updated() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_memory_service(Some(USER_ID), handle, create_store());
+        core.run(service.create_address(new_address("125009", false))).unwrap();
+        let priority = core.run(service.create_address(new_address("125010", true))).unwrap();
+        let last = core.run(service.create_address(new_address("125011", false))).unwrap();
+        core.run(service.delete_address(priority.id)).unwrap();
+        let addresses = core.run(service.get_addresses(USER_ID)).unwrap();
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(priority_ids(&addresses), vec![last.id]);
+    }
+
+    #[test]
+    fn test_delete_other_address_keeps_priority() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_memory_service(Some(USER_ID), handle, create_store());
+        let priority = core.run(service.create_address(new_address("125009", true))).unwrap();
+        let other = core.run(service.create_address(new_address("125010", false))).unwrap();
+        core.run(service.delete_address(other.id)).unwrap();
+        let addresses = core.run(service.get_addresses(USER_ID)).unwrap();
+        assert_eq!(priority_ids(&addresses), vec![priority.id]);
+    }
+}
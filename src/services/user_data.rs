@@ -0,0 +1,129 @@
+//! UserData Services, presents export and erasure of personal data of a user
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use stq_types::UserId;
+
+use super::types::{Service, ServiceFuture};
+use errors::Error;
+use models::{NewUserDataErasure, UserDataErasure, UserDataExport};
+use repos::ReposFactory;
+
+pub trait UserDataService {
+    /// Returns all addresses and roles stored for the user
+    fn export_user_data(&self, user_id: UserId) -> ServiceFuture<UserDataExport>;
+    /// Deletes all addresses and roles of the user and writes an audit record
+    fn erase_user_data(&self, user_id: UserId) -> ServiceFuture<UserDataErasure>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > UserDataService for Service<T, M, F>
+{
+    /// Returns all addresses and roles stored for the user
+    fn export_user_data(&self, user_id_arg: UserId) -> ServiceFuture<UserDataExport> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let users_addresses_repo = repo_factory.create_users_addresses_repo(&*conn, current_uid);
+            let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
+
+            users_addresses_repo
+                .list_for_user(user_id_arg)
+                .and_then(|addresses| {
+                    user_roles_repo.get_by_user_id(user_id_arg).map(|roles| UserDataExport {
+                        user_id: user_id_arg,
+                        addresses,
+                        roles,
+                    })
+                })
+                .map_err(|e: FailureError| e.context("Service UserData, export endpoint error occured.").into())
+        })
+    }
+
+    /// Deletes all addresses and roles of the user and writes an audit record
+    fn erase_user_data(&self, user_id_arg: UserId) -> ServiceFuture<UserDataErasure> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let erased_by =
+                current_uid.ok_or_else(|| format_err!("Denied unauthorized request to erase user data").context(Error::Forbidden))?;
+
+            let users_addresses_repo = repo_factory.create_users_addresses_repo(&*conn, current_uid);
+            let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
+            let user_data_erasures_repo = repo_factory.create_user_data_erasures_repo(&*conn, current_uid);
+
+            let erasure = conn.transaction::<UserDataErasure, FailureError, _>(move || {
+                let addresses = users_addresses_repo.delete_by_user_id(user_id_arg)?;
+                let roles = user_roles_repo.delete_by_user_id(user_id_arg)?;
+
+                user_data_erasures_repo.create(NewUserDataErasure {
+                    user_id: user_id_arg,
+                    erased_by,
+                    addresses_count: addresses.len() as i32,
+                    roles_count: roles.len() as i32,
+                })
+            });
+            // roles are invalidated only when the transaction is over, committed or rolled back,
+            // so that no read of the not yet committed state stays in the cache
+            repo_factory.invalidate_user_roles(user_id_arg);
+
+            erasure.map_err(|e: FailureError| e.context("Service UserData, erase endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use tokio_core::reactor::Core;
+
+    use stq_types::*;
+
+    use repos::repo_factory::tests::*;
+    use services::user_data::UserDataService;
+
+    #[test]
+    fn test_export_user_data() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let work = service.export_user_data(UserId(2));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_id, UserId(2));
+        assert_eq!(result.addresses.len(), 1);
+        assert_eq!(result.roles.len(), 1);
+    }
+
+    #[test]
+    fn test_erase_user_data() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let work = service.erase_user_data(UserId(2));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_id, UserId(2));
+        assert_eq!(result.erased_by, MOCK_USER_ID);
+        assert_eq!(result.addresses_count, 1);
+        assert_eq!(result.roles_count, 1);
+    }
+
+    #[test]
+    fn test_erase_user_data_unauthorized() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.erase_user_data(UserId(2));
+        let result = core.run(work);
+        assert!(result.is_err());
+    }
+}
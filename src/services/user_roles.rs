@@ -7,21 +7,26 @@ use failure::Error as FailureError;
 
 use r2d2::ManageConnection;
 
-use stq_types::{DeliveryRole, RoleId, UserId};
+use stq_types::{RoleId, UserId};
 
 use super::types::{Service, ServiceFuture};
-use models::{NewUserRole, UserRole};
+use models::authorization::{Action, Resource};
+use models::{NewUserRole, RoleName, UserPermissions, UserRole};
 use repos::ReposFactory;
 
 pub trait UserRolesService {
     /// Creates new user_role
     fn create_role(&self, payload: NewUserRole) -> ServiceFuture<UserRole>;
     /// Returns role by user ID
-    fn get_roles(&self, user_id: UserId) -> ServiceFuture<Vec<DeliveryRole>>;
+    fn get_roles(&self, user_id: UserId) -> ServiceFuture<Vec<RoleName>>;
+    /// Returns permissions granted to the user by ACL policy
+    fn get_permissions(&self, user_id: UserId) -> ServiceFuture<UserPermissions>;
     /// Deletes roles for user
     fn delete_by_user_id(&self, user_id_arg: UserId) -> ServiceFuture<Vec<UserRole>>;
     /// Deletes role for user by id
     fn delete_by_id(&self, id_arg: RoleId) -> ServiceFuture<UserRole>;
+    /// Deletes all user roles with the name
+    fn revoke_role(&self, user_id_arg: UserId, role: RoleName) -> ServiceFuture<Vec<UserRole>>;
 }
 impl<
         T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
@@ -30,7 +35,7 @@ impl<
     > UserRolesService for Service<T, M, F>
 {
     /// Returns role by user ID
-    fn get_roles(&self, user_id: UserId) -> ServiceFuture<Vec<DeliveryRole>> {
+    fn get_roles(&self, user_id: UserId) -> ServiceFuture<Vec<RoleName>> {
         let repo_factory = self.static_context.repo_factory.clone();
         let current_uid = self.dynamic_context.user_id;
 
@@ -42,16 +47,54 @@ impl<
         })
     }
 
+    /// Returns permissions granted to the user by ACL policy
+    fn get_permissions(&self, user_id: UserId) -> ServiceFuture<UserPermissions> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let acl_policy = self.static_context.acl_policy.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
+            user_roles_repo
+                .get_by_user_id(user_id)
+                .map(|user_roles| {
+                    // a role is granted several times if it is bound to different companies or stores
+                    let mut roles: Vec<RoleName> = vec![];
+                    for user_role in user_roles {
+                        if !roles.contains(&user_role.name) {
+                            roles.push(user_role.name);
+                        }
+                    }
+                    let permissions = acl_policy.effective_permissions(&roles);
+                    UserPermissions {
+                        user_id,
+                        roles,
+                        permissions,
+                    }
+                })
+                .map_err(|e: FailureError| e.context("Service user_roles, get_permissions endpoint error occured.").into())
+        })
+    }
+
     /// Deletes role for user by id
     fn delete_by_id(&self, id_arg: RoleId) -> ServiceFuture<UserRole> {
         let repo_factory = self.static_context.repo_factory.clone();
         let current_uid = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::UserRoles, Action::Delete);
 
         self.spawn_on_pool(move |conn| {
             let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
-            user_roles_repo
-                .delete_by_id(id_arg)
-                .map_err(|e: FailureError| e.context("Service user_roles, delete_by_id endpoint error occured.").into())
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            conn.transaction::<UserRole, FailureError, _>(move || {
+                let user_role = user_roles_repo.delete_by_id(id_arg)?;
+                audit_log_repo.create(audit_log_entry.before(&user_role)?)?;
+                Ok(user_role)
+            })
+            .map(|user_role| {
+                repo_factory.invalidate_user_roles(user_role.user_id);
+                user_role
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, delete_by_id endpoint error occured.").into())
         })
     }
 
@@ -59,12 +102,48 @@ impl<
     fn delete_by_user_id(&self, user_id_arg: UserId) -> ServiceFuture<Vec<UserRole>> {
         let repo_factory = self.static_context.repo_factory.clone();
         let current_uid = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::UserRoles, Action::Delete);
 
         self.spawn_on_pool(move |conn| {
             let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
-            user_roles_repo
-                .delete_by_user_id(user_id_arg)
-                .map_err(|e: FailureError| e.context("Service user_roles, delete_by_user_id endpoint error occured.").into())
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            conn.transaction::<Vec<UserRole>, FailureError, _>(move || {
+                let user_roles = user_roles_repo.delete_by_user_id(user_id_arg)?;
+                audit_log_repo.create(audit_log_entry.before(&user_roles)?)?;
+                Ok(user_roles)
+            })
+            .map(|user_roles| {
+                repo_factory.invalidate_user_roles(user_id_arg);
+                user_roles
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, delete_by_user_id endpoint error occured.").into())
+        })
+    }
+
+    /// Deletes all user roles with the name
+    fn revoke_role(&self, user_id_arg: UserId, role: RoleName) -> ServiceFuture<Vec<UserRole>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::UserRoles, Action::Delete);
+
+        self.spawn_on_pool(move |conn| {
+            let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            conn.transaction::<Vec<UserRole>, FailureError, _>(move || {
+                let user_roles = user_roles_repo
+                    .get_by_user_id(user_id_arg)?
+                    .into_iter()
+                    .filter(|user_role| user_role.name == role)
+                    .map(|user_role| user_roles_repo.delete_by_id(user_role.id))
+                    .collect::<Result<Vec<_>, _>>()?;
+                audit_log_repo.create(audit_log_entry.before(&user_roles)?)?;
+                Ok(user_roles)
+            })
+            .map(|user_roles| {
+                repo_factory.invalidate_user_roles(user_id_arg);
+                user_roles
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, revoke_role endpoint error occured.").into())
         })
     }
 
@@ -72,11 +151,80 @@ impl<
     fn create_role(&self, new_user_role: NewUserRole) -> ServiceFuture<UserRole> {
         let repo_factory = self.static_context.repo_factory.clone();
         let current_uid = self.dynamic_context.user_id;
+        let audit_log_entry = self.audit_log_entry(Resource::UserRoles, Action::Create);
 
         self.spawn_on_pool(move |conn| {
             let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
-            conn.transaction::<UserRole, FailureError, _>(move || user_roles_repo.create(new_user_role))
-                .map_err(|e: FailureError| e.context("Service user_roles, create endpoint error occured.").into())
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            conn.transaction::<UserRole, FailureError, _>(move || {
+                let user_role = user_roles_repo.create(new_user_role)?;
+                audit_log_repo.create(audit_log_entry.after(&user_role)?)?;
+                Ok(user_role)
+            })
+            .map(|user_role| {
+                repo_factory.invalidate_user_roles(user_role.user_id);
+                user_role
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, create endpoint error occured.").into())
         })
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use tokio_core::reactor::Core;
+
+    use serde_json;
+
+    use stq_types::*;
+
+    use models::authorization::*;
+    use models::RoleName;
+    use repos::memory::tests::create_memory_service;
+    use repos::memory::{MemoryFixtures, MemoryStore};
+    use repos::repo_factory::tests::*;
+    use services::user_roles::UserRolesService;
+
+    #[test]
+    fn test_get_permissions() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let work = service.get_permissions(UserId(2));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.roles, vec![RoleName::User]);
+        assert!(result.permissions.contains(&permission!(Resource::Companies, Action::Read)));
+        assert!(!result.permissions.contains(&permission!(Resource::Companies)));
+    }
+
+    #[test]
+    fn test_get_permissions_without_duplicate_roles() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let fixtures: MemoryFixtures = serde_json::from_str(
+            r#"{
+                "roles": [
+                    { "id": "0d7b3c1e-4f0a-4d6b-9b2e-6a1f3c5d7e01", "user_id": 1, "name": "company_manager", "data": 7 },
+                    { "id": "0d7b3c1e-4f0a-4d6b-9b2e-6a1f3c5d7e02", "user_id": 1, "name": "user", "data": null },
+                    { "id": "0d7b3c1e-4f0a-4d6b-9b2e-6a1f3c5d7e03", "user_id": 1, "name": "company_manager", "data": 8 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let service = create_memory_service(Some(UserId(1)), handle, MemoryStore::new(fixtures));
+        let result = core.run(service.get_permissions(UserId(1))).unwrap();
+        assert_eq!(result.roles, vec![RoleName::CompanyManager, RoleName::User]);
+    }
+
+    #[test]
+    fn test_revoke_role() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let revoked = core.run(service.revoke_role(UserId(2), RoleName::User)).unwrap();
+        assert_eq!(revoked.len(), 1);
+        let revoked = core.run(service.revoke_role(UserId(2), RoleName::Superuser)).unwrap();
+        assert!(revoked.is_empty());
+    }
+}
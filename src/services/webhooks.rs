@@ -0,0 +1,140 @@
+//! Webhooks Services, presents management of webhook subscriptions of other services and their deliveries
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use super::types::{Service, ServiceFuture};
+use models::{
+    generate_webhook_secret, CreatedWebhookSubscription, NewWebhookSubscription, NewWebhookSubscriptionRaw, WebhookDelivery,
+    WebhookSubscription,
+};
+use repos::ReposFactory;
+
+pub trait WebhooksService {
+    /// Creates new subscription, the secret deliveries are signed with is returned only once
+    fn create_webhook_subscription(&self, payload: NewWebhookSubscription) -> ServiceFuture<CreatedWebhookSubscription>;
+    /// Returns all subscriptions
+    fn list_webhook_subscriptions(&self) -> ServiceFuture<Vec<WebhookSubscription>>;
+    /// Deletes subscription with its deliveries
+    fn delete_webhook_subscription(&self, id: i32) -> ServiceFuture<WebhookSubscription>;
+    /// Returns deliveries to the subscription, newest first
+    fn list_webhook_deliveries(&self, subscription_id: i32) -> ServiceFuture<Vec<WebhookDelivery>>;
+    /// Schedules delivery, usually a dead letter, to be sent again
+    fn redeliver_webhook_delivery(&self, id: i32) -> ServiceFuture<WebhookDelivery>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > WebhooksService for Service<T, M, F>
+{
+    /// Creates new subscription, the secret deliveries are signed with is returned only once
+    fn create_webhook_subscription(&self, payload: NewWebhookSubscription) -> ServiceFuture<CreatedWebhookSubscription> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let webhook_subscriptions_repo = repo_factory.create_webhook_subscriptions_repo(&*conn, current_uid);
+            generate_webhook_secret()
+                .and_then(|secret| {
+                    let payload = NewWebhookSubscriptionRaw::from_model(payload, secret.clone(), current_uid);
+                    webhook_subscriptions_repo
+                        .create(payload)
+                        .map(|subscription| CreatedWebhookSubscription { subscription, secret })
+                })
+                .map_err(|e: FailureError| e.context("Service Webhooks, create_subscription endpoint error occured.").into())
+        })
+    }
+
+    /// Returns all subscriptions
+    fn list_webhook_subscriptions(&self) -> ServiceFuture<Vec<WebhookSubscription>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let webhook_subscriptions_repo = repo_factory.create_webhook_subscriptions_repo(&*conn, current_uid);
+            webhook_subscriptions_repo
+                .list()
+                .map_err(|e: FailureError| e.context("Service Webhooks, list_subscriptions endpoint error occured.").into())
+        })
+    }
+
+    /// Deletes subscription with its deliveries
+    fn delete_webhook_subscription(&self, id: i32) -> ServiceFuture<WebhookSubscription> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let webhook_subscriptions_repo = repo_factory.create_webhook_subscriptions_repo(&*conn, current_uid);
+            webhook_subscriptions_repo
+                .delete(id)
+                .map_err(|e: FailureError| e.context("Service Webhooks, delete_subscription endpoint error occured.").into())
+        })
+    }
+
+    /// Returns deliveries to the subscription, newest first
+    fn list_webhook_deliveries(&self, subscription_id: i32) -> ServiceFuture<Vec<WebhookDelivery>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let webhook_deliveries_repo = repo_factory.create_webhook_deliveries_repo(&*conn, current_uid);
+            webhook_deliveries_repo
+                .list_by_subscription(subscription_id)
+                .map_err(|e: FailureError| e.context("Service Webhooks, list_deliveries endpoint error occured.").into())
+        })
+    }
+
+    /// Schedules delivery, usually a dead letter, to be sent again
+    fn redeliver_webhook_delivery(&self, id: i32) -> ServiceFuture<WebhookDelivery> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let current_uid = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let webhook_deliveries_repo = repo_factory.create_webhook_deliveries_repo(&*conn, current_uid);
+            webhook_deliveries_repo
+                .redeliver(id)
+                .map_err(|e: FailureError| e.context("Service Webhooks, redeliver endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use tokio_core::reactor::Core;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::webhooks::WebhooksService;
+
+    #[test]
+    fn test_create_webhook_subscription() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let work = service.create_webhook_subscription(NewWebhookSubscription {
+            url: "http://orders/delivery_events".to_string(),
+            event_types: vec!["rates_replaced".to_string()],
+        });
+        let result = core.run(work).unwrap();
+        assert_eq!(result.subscription.created_by, Some(MOCK_USER_ID));
+        assert_eq!(result.subscription.secret, result.secret);
+        assert!(!result.secret.is_empty());
+    }
+
+    #[test]
+    fn test_redeliver_webhook_delivery() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_USER_ID), handle);
+        let result = core.run(service.redeliver_webhook_delivery(1)).unwrap();
+        assert_eq!(result.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(result.attempts, 0);
+    }
+}
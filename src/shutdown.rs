@@ -0,0 +1,154 @@
+//! Graceful shutdown. On SIGTERM or SIGINT the server reports not ready, stops accepting connections
+//! after `server.shutdown_delay_ms` and waits for in-flight requests and cpu pool jobs to finish before exit,
+//! at most `server.shutdown_timeout_ms`.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::prelude::*;
+use tokio_core::reactor::{Handle, Interval, Timeout};
+use tokio_signal;
+use tokio_signal::unix::{Signal, SIGTERM};
+
+/// Interval of checking whether in-flight work is finished
+const DRAIN_CHECK_INTERVAL_MS: u64 = 50;
+
+/// Shutdown state shared by the server, the services and the outbox dispatcher
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    started: Arc<AtomicBool>,
+    active_requests: Arc<AtomicUsize>,
+    active_jobs: Arc<AtomicUsize>,
+}
+
+/// Marks work in flight until dropped
+pub struct InFlight {
+    counter: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts shutdown, readiness check fails from now on
+    pub fn start(&self) {
+        self.started.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::SeqCst)
+    }
+
+    /// Tracks http request until the returned guard is dropped
+    pub fn track_request(&self) -> InFlight {
+        track(&self.active_requests)
+    }
+
+    /// Tracks cpu pool job until the returned guard is dropped
+    pub fn track_job(&self) -> InFlight {
+        track(&self.active_jobs)
+    }
+
+    pub fn active_requests(&self) -> usize {
+        self.active_requests.load(Ordering::SeqCst)
+    }
+
+    pub fn active_jobs(&self) -> usize {
+        self.active_jobs.load(Ordering::SeqCst)
+    }
+
+    pub fn is_drained(&self) -> bool {
+        self.active_requests() == 0 && self.active_jobs() == 0
+    }
+
+    /// Resolves with `true` once in-flight requests and jobs are finished
+    /// or with `false` if some are still running after `timeout`
+    pub fn drain(&self, timeout: Duration, handle: &Handle) -> Box<Future<Item = bool, Error = io::Error>> {
+        let interval = match Interval::new(Duration::from_millis(DRAIN_CHECK_INTERVAL_MS), handle) {
+            Ok(interval) => interval,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let timeout = match Timeout::new(timeout, handle) {
+            Ok(timeout) => timeout,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        let shutdown = self.clone();
+        let drained = interval
+            .take_while(move |_| Ok(!shutdown.is_drained()))
+            .for_each(|_| Ok(()))
+            .map(|_| true);
+
+        Box::new(
+            drained
+                .select(timeout.map(|_| false))
+                .map(|(drained, _)| drained)
+                .map_err(|(e, _)| e),
+        )
+    }
+}
+
+fn track(counter: &Arc<AtomicUsize>) -> InFlight {
+    counter.fetch_add(1, Ordering::SeqCst);
+    InFlight { counter: counter.clone() }
+}
+
+/// Resolves with the name of the first received SIGINT or SIGTERM
+pub fn signal() -> Box<Future<Item = &'static str, Error = io::Error>> {
+    let sigint = tokio_signal::ctrl_c().flatten_stream().map(|_| "SIGINT");
+    let sigterm = Signal::new(SIGTERM).flatten_stream().map(|_| "SIGTERM");
+
+    Box::new(
+        sigint
+            .select(sigterm)
+            .into_future()
+            .map(|(signal, _)| signal.unwrap_or("end of signal stream"))
+            .map_err(|(e, _)| e),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn test_in_flight_tracking() {
+        let shutdown = Shutdown::new();
+        let request = shutdown.track_request();
+        let job = shutdown.track_job();
+        assert_eq!(shutdown.active_requests(), 1);
+        assert_eq!(shutdown.active_jobs(), 1);
+        assert!(!shutdown.is_drained());
+
+        drop(request);
+        drop(job);
+        assert!(shutdown.is_drained());
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let shutdown = Shutdown::new();
+        shutdown.start();
+        assert!(shutdown.is_started());
+
+        let job = shutdown.track_job();
+        let drained = core.run(shutdown.drain(Duration::from_millis(200), &handle)).unwrap();
+        assert!(!drained);
+
+        drop(job);
+        let drained = core.run(shutdown.drain(Duration::from_millis(200), &handle)).unwrap();
+        assert!(drained);
+    }
+}
@@ -20,13 +20,19 @@ use std::sync::mpsc::channel;
 use std::thread;
 
 pub fn setup() -> String {
+    setup_with_config(|_| {})
+}
+
+/// Starts the server with the default config changed by `update_config`
+pub fn setup_with_config<U: FnOnce(&mut lib::config::Config) + Send + 'static>(update_config: U) -> String {
     let (tx, rx) = channel::<bool>();
     let mut rng = rand::thread_rng();
     let port = rng.gen_range(40000, 60000);
     thread::spawn({
         let tx = tx.clone();
         move || {
-            let config = lib::config::Config::new().expect("Can't load app config!");
+            let mut config = lib::config::Config::new().expect("Can't load app config!");
+            update_config(&mut config);
             lib::start_server(config, Some(port), move || {
                 let _ = tx.send(true);
             });
@@ -60,7 +66,7 @@ pub fn create_user_role(
 ) -> Result<UserRole, client::Error> {
     let new_role = NewUserRole {
         user_id,
-        name: DeliveryRole::User,
+        name: RoleName::User,
         id: RoleId::new(),
         data: None,
     };
@@ -85,7 +91,7 @@ pub fn create_user_store_role(
 ) -> Result<UserRole, client::Error> {
     let new_role = NewUserRole {
         user_id,
-        name: DeliveryRole::StoreManager,
+        name: RoleName::StoreManager,
         id: RoleId::new(),
         data: Some(serde_json::to_value(store_id.0).unwrap()),
     };
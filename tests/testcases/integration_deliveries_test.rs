@@ -0,0 +1,251 @@
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use hyper::Method;
+use rand::Rng;
+
+use stq_http::client::{self, ClientHandle as HttpClientHandle};
+use stq_static_resources::Currency;
+use stq_types::*;
+
+use lib::models::*;
+
+fn alpha3s(codes: &[&str]) -> Vec<Alpha3> {
+    codes.iter().map(|code| Alpha3(code.to_string())).collect()
+}
+
+fn create_company(name: &str, deliveries_from: &[&str]) -> NewCompany {
+    NewCompany {
+        name: name.to_string(),
+        label: name.to_string(),
+        description: None,
+        deliveries_from: alpha3s(deliveries_from),
+        logo: "".to_string(),
+        currency: Currency::STQ,
+    }
+}
+
+fn create_package(name: &str, deliveries_to: &[&str]) -> NewPackages {
+    NewPackages {
+        name: name.to_string(),
+        max_size: 1_000_000,
+        min_size: 0,
+        max_weight: 10_000,
+        min_weight: 0,
+        deliveries_to: alpha3s(deliveries_to),
+    }
+}
+
+// super user
+fn create_shipping(
+    base_product_id: BaseProductId,
+    company_package_id: CompanyPackageId,
+    deliveries_to: &[&str],
+    core: &mut tokio_core::reactor::Core,
+    http_client: &HttpClientHandle,
+    base_url: String,
+) -> Result<Shipping, client::Error> {
+    let new_product = NewProducts {
+        base_product_id,
+        store_id: StoreId(1),
+        company_package_id,
+        price: None,
+        shipping: ShippingVariant::Local,
+        deliveries_to: alpha3s(deliveries_to),
+        measurements: Some(ShipmentMeasurements {
+            volume_cubic_cm: 100,
+            weight_g: 20,
+        }),
+        delivery_from: None,
+        currency: Currency::USD,
+    };
+
+    let shipping = NewShipping {
+        items: vec![new_product],
+        pickup: None,
+    };
+
+    let body: String = serde_json::to_string(&shipping).unwrap().to_string();
+    core.run(http_client.request_with_auth_header::<Shipping>(
+        Method::Post,
+        format!("{}/products/{}", base_url, base_product_id),
+        Some(body),
+        Some(UserId(1).to_string()),
+    ))
+}
+
+fn find_available_to(
+    base_product_id: BaseProductId,
+    user_country: &str,
+    core: &mut tokio_core::reactor::Core,
+    http_client: &HttpClientHandle,
+    base_url: String,
+) -> Vec<CompanyPackageId> {
+    let read_result = core.run(http_client.request_with_auth_header::<AvailableShippingForUser>(
+        Method::Get,
+        format!(
+            "{}/available_packages_for_user/{}?user_country={}",
+            base_url, base_product_id, user_country
+        ),
+        None,
+        None,
+    ));
+    println!("find available packages to {} {:?}", user_country, read_result);
+    read_result.unwrap().packages.into_iter().map(|package| package.id).collect()
+}
+
+fn find_deliveries_from(
+    country: &str,
+    core: &mut tokio_core::reactor::Core,
+    http_client: &HttpClientHandle,
+    base_url: String,
+) -> Vec<CompanyPackageId> {
+    let read_result = core.run(http_client.request_with_auth_header::<Vec<AvailablePackages>>(
+        Method::Get,
+        format!("{}/available_packages?country={}&size=0&weight=0", base_url, country),
+        None,
+        None,
+    ));
+    println!("find available packages from {} {:?}", country, read_result);
+    read_result.unwrap().into_iter().map(|package| package.id).collect()
+}
+
+#[test]
+fn test_find_available_to() {
+    let (mut core, http_client) = super::common::make_utils();
+    let base_url = super::common::setup();
+    let user_id = UserId(1);
+    let base_product_id = BaseProductId(rand::thread_rng().gen_range(100_000, 1_000_000));
+
+    let payload = (
+        create_company("Available to", &["RUS"]),
+        create_package("Available to", &["BRA", "USA", "RUS"]),
+        ShippingRateSource::NotAvailable,
+    );
+    let ids = super::common::create_delivery_objects(payload, &mut core, &http_client, base_url.clone(), Some(user_id));
+    let companies_package_id = ids.2.clone();
+
+    // unknown countries are rejected
+    let create_result = create_shipping(
+        base_product_id.clone(),
+        companies_package_id.clone(),
+        &["USA", "ZZZ"],
+        &mut core,
+        &http_client,
+        base_url.clone(),
+    );
+    assert!(create_result.is_err());
+
+    // countries are returned sorted, repeated ones once
+    let shipping = create_shipping(
+        base_product_id.clone(),
+        companies_package_id.clone(),
+        &["USA", "RUS", "USA"],
+        &mut core,
+        &http_client,
+        base_url.clone(),
+    )
+    .unwrap();
+    assert_eq!(shipping.items[0].product.deliveries_to, alpha3s(&["RUS", "USA"]));
+
+    assert_eq!(
+        find_available_to(base_product_id.clone(), "USA", &mut core, &http_client, base_url.clone()),
+        vec![companies_package_id]
+    );
+    assert!(find_available_to(base_product_id.clone(), "BRA", &mut core, &http_client, base_url.clone()).is_empty());
+
+    let delete_result = core.run(http_client.request_with_auth_header::<()>(
+        Method::Delete,
+        format!("{}/products/{}", base_url, base_product_id),
+        None,
+        Some(user_id.to_string()),
+    ));
+    assert!(delete_result.is_ok());
+    super::common::delete_deliveries_objects(ids, &mut core, &http_client, base_url, user_id);
+}
+
+#[test]
+fn test_find_deliveries_from() {
+    let (mut core, http_client) = super::common::make_utils();
+    let base_url = super::common::setup();
+    let user_id = UserId(1);
+
+    let payload = (
+        create_company("From USA", &["USA"]),
+        create_package("From USA", &["RUS"]),
+        ShippingRateSource::NotAvailable,
+    );
+    let from_usa = super::common::create_delivery_objects(payload, &mut core, &http_client, base_url.clone(), Some(user_id));
+    let payload = (
+        create_company("From Brazil", &["BRA"]),
+        create_package("From Brazil", &["RUS"]),
+        ShippingRateSource::NotAvailable,
+    );
+    let from_brazil = super::common::create_delivery_objects(payload, &mut core, &http_client, base_url.clone(), Some(user_id));
+
+    let found = find_deliveries_from("USA", &mut core, &http_client, base_url.clone());
+    assert!(found.contains(&from_usa.2));
+    assert!(!found.contains(&from_brazil.2));
+
+    super::common::delete_deliveries_objects(from_usa, &mut core, &http_client, base_url.clone(), user_id);
+    super::common::delete_deliveries_objects(from_brazil, &mut core, &http_client, base_url, user_id);
+}
+
+#[test]
+fn test_normalize_deliveries_migration() {
+    let config = lib::config::Config::new().expect("Can't load app config!");
+    let conn = PgConnection::establish(&config.server.database).unwrap();
+
+    let count = |query: &str| diesel::select(sql::<BigInt>(query)).get_result::<i64>(&conn).unwrap();
+    let text = |query: &str| diesel::select(sql::<Text>(query)).get_result::<String>(&conn).unwrap();
+
+    // the whole run is rolled back, tables are locked until then
+    conn.test_transaction::<_, diesel::result::Error, _>(|| {
+        conn.batch_execute(include_str!("../../migrations/2019-03-12-101530_normalize_deliveries/down.sql"))?;
+        conn.batch_execute(
+            r#"
+            INSERT INTO companies (id, name, label, logo, deliveries_from) VALUES (-1, 'migration', 'migration', '', '["USA", "ZZZ", "USA"]');
+            INSERT INTO packages (id, name, max_size, min_size, max_weight, min_weight, deliveries_to) VALUES (-1, 'migration', 0, 0, 0, 0, '["RUS", "ZZZ"]');
+            INSERT INTO companies_packages (id, company_id, package_id) VALUES (-1, -1, -1);
+            INSERT INTO products (id, base_product_id, store_id, company_package_id, shipping, deliveries_to)
+            VALUES (-1, -1, -1, -1, 'Local', '["USA", "RUS", "YYY", "USA"]');
+            "#,
+        )?;
+        conn.batch_execute(include_str!("../../migrations/2019-03-12-101530_normalize_deliveries/up.sql"))?;
+
+        assert_eq!(
+            text("(SELECT string_agg(country_alpha3, ',' ORDER BY country_alpha3) FROM companies_deliveries_from WHERE company_id = -1)"),
+            "USA"
+        );
+        assert_eq!(
+            text("(SELECT string_agg(country_alpha3, ',' ORDER BY country_alpha3) FROM packages_deliveries_to WHERE package_id = -1)"),
+            "RUS"
+        );
+        assert_eq!(
+            text("(SELECT string_agg(country_alpha3, ',' ORDER BY country_alpha3) FROM products_deliveries_to WHERE product_id = -1)"),
+            "RUS,USA"
+        );
+        // unknown codes are kept aside
+        assert_eq!(
+            text(
+                "(SELECT string_agg(table_name || ':' || country_alpha3, ',' ORDER BY table_name) FROM unmigrated_deliveries WHERE row_id = -1)"
+            ),
+            "companies:ZZZ,packages:ZZZ,products:YYY"
+        );
+
+        // and put back on the way down
+        conn.batch_execute(include_str!("../../migrations/2019-03-12-101530_normalize_deliveries/down.sql"))?;
+        assert_eq!(text("(SELECT deliveries_from::text FROM companies WHERE id = -1)"), r#"["USA", "ZZZ"]"#);
+        assert_eq!(text("(SELECT deliveries_to::text FROM packages WHERE id = -1)"), r#"["RUS", "ZZZ"]"#);
+        assert_eq!(text("(SELECT deliveries_to::text FROM products WHERE id = -1)"), r#"["RUS", "USA", "YYY"]"#);
+        assert_eq!(
+            count("(SELECT count(*) FROM information_schema.tables WHERE table_name = 'unmigrated_deliveries')"),
+            0
+        );
+        assert_eq!(count("(SELECT count(*) FROM pg_constraint WHERE conname = 'countries_alpha3_key')"), 0);
+
+        Ok(())
+    });
+}
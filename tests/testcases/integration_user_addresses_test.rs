@@ -1,4 +1,11 @@
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use futures::future::{self, Future};
 use hyper::Method;
+use rand::Rng;
 
 use lib::models::*;
 use stq_types::*;
@@ -67,6 +74,195 @@ fn get_url_request(base_url: String) -> String {
     format!("{}/{}", base_url, MOCK_USER_ADDRESSES_ENDPOINT)
 }
 
+fn create_priority_address(
+    user_id: UserId,
+    postal_code: &str,
+    core: &mut tokio_core::reactor::Core,
+    http_client: &HttpClientHandle,
+    base_url: String,
+) -> Result<UserAddress, client::Error> {
+    let new_address = NewUserAddress {
+        user_id,
+        administrative_area_level_1: None,
+        administrative_area_level_2: None,
+        country: "Russian Federation".to_string(),
+        country_code: Some("RUS".to_string()),
+        locality: None,
+        political: None,
+        postal_code: postal_code.to_string(),
+        route: None,
+        street_number: None,
+        is_priority: true,
+        address: None,
+    };
+
+    let body: String = serde_json::to_string(&new_address).unwrap().to_string();
+    core.run(http_client.request_with_auth_header::<UserAddress>(
+        Method::Post,
+        get_url_request(base_url),
+        Some(body),
+        Some(UserId(1).to_string()),
+    ))
+}
+
+fn get_priority_ids(user_id: UserId, core: &mut tokio_core::reactor::Core, http_client: &HttpClientHandle, base_url: String) -> Vec<i32> {
+    let read_result = core.run(http_client.request_with_auth_header::<Vec<UserAddress>>(
+        Method::Get,
+        get_url_request_by_user_id(base_url, user_id),
+        None,
+        Some(UserId(1).to_string()),
+    ));
+    read_result
+        .unwrap()
+        .into_iter()
+        .filter(|address| address.is_priority)
+        .map(|address| address.id)
+        .collect()
+}
+
+#[test]
+fn test_user_address_priority() {
+    let (mut core, http_client) = super::common::make_utils();
+    let base_url = super::common::setup();
+    let user_id = UserId(rand::thread_rng().gen_range(100_000, 1_000_000));
+
+    // a new priority address resets the others
+    let first = create_priority_address(user_id, "125009", &mut core, &http_client, base_url.clone()).unwrap();
+    let second = create_priority_address(user_id, "125010", &mut core, &http_client, base_url.clone()).unwrap();
+    assert_eq!(
+        get_priority_ids(user_id, &mut core, &http_client, base_url.clone()),
+        vec![second.id]
+    );
+
+    // so does an address updated to be the priority one
+    let mut update_address = create_update_address();
+    update_address.is_priority = Some(true);
+    let update_body: String = serde_json::to_string(&update_address).unwrap().to_string();
+    let update_result = core.run(http_client.request_with_auth_header::<UserAddress>(
+        Method::Put,
+        get_url_request_by_address_id(base_url.clone(), first.id),
+        Some(update_body),
+        Some(UserId(1).to_string()),
+    ));
+    assert!(update_result.unwrap().is_priority);
+    assert_eq!(get_priority_ids(user_id, &mut core, &http_client, base_url.clone()), vec![first.id]);
+
+    // deleting the priority address promotes the most recently updated one
+    let delete_result = core.run(http_client.request_with_auth_header::<UserAddress>(
+        Method::Delete,
+        get_url_request_by_address_id(base_url.clone(), first.id),
+        None,
+        Some(UserId(1).to_string()),
+    ));
+    assert!(delete_result.is_ok());
+    assert_eq!(
+        get_priority_ids(user_id, &mut core, &http_client, base_url.clone()),
+        vec![second.id]
+    );
+
+    // concurrent requests leave a single priority address, the unique index rejects the rest
+    let requests = (0..5)
+        .map(|n| {
+            let new_address = NewUserAddress {
+                user_id,
+                administrative_area_level_1: None,
+                administrative_area_level_2: None,
+                country: "Russian Federation".to_string(),
+                country_code: Some("RUS".to_string()),
+                locality: None,
+                political: None,
+                postal_code: format!("12502{}", n),
+                route: None,
+                street_number: None,
+                is_priority: true,
+                address: None,
+            };
+            let body: String = serde_json::to_string(&new_address).unwrap().to_string();
+            http_client
+                .request_with_auth_header::<UserAddress>(
+                    Method::Post,
+                    get_url_request(base_url.clone()),
+                    Some(body),
+                    Some(UserId(1).to_string()),
+                )
+                .then(|result| Ok::<_, ()>(result.is_ok()))
+        })
+        .collect::<Vec<_>>();
+    let created = core.run(future::join_all(requests)).unwrap();
+    assert!(created.into_iter().any(|is_ok| is_ok));
+    assert_eq!(get_priority_ids(user_id, &mut core, &http_client, base_url.clone()).len(), 1);
+
+    let read_result = core.run(http_client.request_with_auth_header::<Vec<UserAddress>>(
+        Method::Get,
+        get_url_request_by_user_id(base_url.clone(), user_id),
+        None,
+        Some(UserId(1).to_string()),
+    ));
+    for address in read_result.unwrap() {
+        let delete_result = core.run(http_client.request_with_auth_header::<UserAddress>(
+            Method::Delete,
+            get_url_request_by_address_id(base_url.clone(), address.id),
+            None,
+            Some(UserId(1).to_string()),
+        ));
+        assert!(delete_result.is_ok());
+    }
+}
+
+#[test]
+fn test_create_matches_plain_text_address() {
+    let (mut core, http_client) = super::common::make_utils();
+    let base_url = super::common::setup();
+    let user_id = UserId(rand::thread_rng().gen_range(100_000, 1_000_000));
+
+    // an address stored before the encryption, without the key and the blind index
+    let config = lib::config::Config::new().expect("Can't load app config!");
+    let conn = PgConnection::establish(&config.server.database).unwrap();
+    conn.batch_execute(&format!(
+        "INSERT INTO user_addresses (user_id, country, country_code, postal_code, route, is_priority) \
+         VALUES ({}, 'Russian Federation', 'RUS', '125011', 'Tverskaya', false)",
+        user_id
+    ))
+    .unwrap();
+    let plain_text_id = diesel::select(sql::<Integer>(&format!(
+        "(SELECT id FROM user_addresses WHERE user_id = {})",
+        user_id
+    )))
+    .get_result::<i32>(&conn)
+    .unwrap();
+
+    let new_address = NewUserAddress {
+        user_id,
+        administrative_area_level_1: None,
+        administrative_area_level_2: None,
+        country: "Russian Federation".to_string(),
+        country_code: Some("RUS".to_string()),
+        locality: None,
+        political: None,
+        postal_code: "125011".to_string(),
+        route: Some("Tverskaya".to_string()),
+        street_number: None,
+        is_priority: false,
+        address: None,
+    };
+    let body: String = serde_json::to_string(&new_address).unwrap().to_string();
+    let create_result = core.run(http_client.request_with_auth_header::<UserAddress>(
+        Method::Post,
+        get_url_request(base_url.clone()),
+        Some(body),
+        Some(UserId(1).to_string()),
+    ));
+    assert_eq!(create_result.unwrap().id, plain_text_id);
+
+    let delete_result = core.run(http_client.request_with_auth_header::<UserAddress>(
+        Method::Delete,
+        get_url_request_by_address_id(base_url, plain_text_id),
+        None,
+        Some(UserId(1).to_string()),
+    ));
+    assert!(delete_result.is_ok());
+}
+
 #[test]
 fn test_user_address() {
     let (mut core, http_client) = super::common::make_utils();
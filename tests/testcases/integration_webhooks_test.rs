@@ -0,0 +1,275 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use futures::prelude::*;
+use hyper::header::Headers;
+use hyper::server::{Http, Request, Response, Service};
+use hyper::{Error as HyperError, Method, StatusCode};
+use rand::Rng;
+
+use lib::config::Outbox;
+use lib::models::*;
+use stq_static_resources::Currency;
+use stq_types::*;
+
+use stq_http::client::ClientHandle as HttpClientHandle;
+
+/// Request received by the stub subscriber
+#[derive(Clone)]
+struct StubRequest {
+    headers: Headers,
+    body: String,
+}
+
+/// Subscriber recording webhook requests, responds with 500 while `failing` is set
+/// and with an empty `204` while `empty_body` is set
+#[derive(Clone, Default)]
+struct StubSubscriber {
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+    failing: Arc<AtomicBool>,
+    empty_body: Arc<AtomicBool>,
+}
+
+impl Service for StubSubscriber {
+    type Request = Request;
+    type Response = Response;
+    type Error = HyperError;
+    type Future = Box<Future<Item = Response, Error = HyperError>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let requests = self.requests.clone();
+        let failing = self.failing.load(Ordering::SeqCst);
+        let empty_body = self.empty_body.load(Ordering::SeqCst);
+        let headers = req.headers().clone();
+        Box::new(req.body().concat2().map(move |body| {
+            let body = String::from_utf8_lossy(&body).to_string();
+            requests.lock().unwrap().push(StubRequest { headers, body });
+            if failing {
+                Response::new().with_status(StatusCode::InternalServerError).with_body("{}")
+            } else if empty_body {
+                Response::new().with_status(StatusCode::NoContent)
+            } else {
+                Response::new().with_status(StatusCode::Ok).with_body("{}")
+            }
+        }))
+    }
+}
+
+fn start_stub_subscriber(subscriber: StubSubscriber) -> String {
+    let port = rand::thread_rng().gen_range(40000, 60000);
+    let address = format!("127.0.0.1:{}", port).parse().unwrap();
+    let (tx, rx) = channel::<bool>();
+    thread::spawn(move || {
+        let server = Http::new()
+            .bind(&address, move || Ok(subscriber.clone()))
+            .expect("Can't start stub subscriber");
+        let _ = tx.send(true);
+        server.run().expect("Stub subscriber failed");
+    });
+    rx.recv().unwrap();
+
+    format!("http://127.0.0.1:{}/delivery_events", port)
+}
+
+fn raw_header(headers: &Headers, name: &str) -> Option<String> {
+    headers
+        .get_raw(name)
+        .and_then(|raw| raw.one())
+        .map(|value| String::from_utf8_lossy(value).to_string())
+}
+
+/// Returns delivery id and request of the `company_updated` event of the company
+fn find_company_request(subscriber: &StubSubscriber, company_id: CompanyId) -> Option<(i32, StubRequest)> {
+    subscriber
+        .requests
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .filter_map(|request| {
+            let message = serde_json::from_str::<OutboxMessage>(&request.body).ok()?;
+            if message.event_type != "company_updated" || message.payload["company"]["id"] != company_id.0 {
+                return None;
+            }
+            let delivery_id = raw_header(&request.headers, WEBHOOK_DELIVERY_HEADER)?.parse().ok()?;
+            Some((delivery_id, request.clone()))
+        })
+        .next()
+}
+
+fn wait_for<T, Func: FnMut() -> Option<T>>(mut f: Func) -> T {
+    for _ in 0..100 {
+        if let Some(value) = f() {
+            return value;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("Timed out waiting for webhook delivery");
+}
+
+fn get_delivery(
+    core: &mut tokio_core::reactor::Core,
+    http_client: &HttpClientHandle,
+    base_url: String,
+    subscription_id: i32,
+    delivery_id: i32,
+) -> Option<WebhookDelivery> {
+    core.run(http_client.request_with_auth_header::<Vec<WebhookDelivery>>(
+        Method::Get,
+        format!("{}/webhook_subscriptions/{}/deliveries", base_url, subscription_id),
+        None,
+        Some(UserId(1).to_string()),
+    ))
+    .unwrap()
+    .into_iter()
+    .find(|delivery| delivery.id == delivery_id)
+}
+
+#[test]
+fn test_webhooks() {
+    let subscriber = StubSubscriber::default();
+    subscriber.failing.store(true, Ordering::SeqCst);
+    let subscriber_url = start_stub_subscriber(subscriber.clone());
+
+    let (mut core, http_client) = super::common::make_utils();
+    let base_url = super::common::setup_with_config(|config| {
+        config.outbox = Outbox {
+            poll_interval_ms: 100,
+            retry_delay_ms: 10,
+            max_retry_delay_ms: 10,
+            webhook_max_attempts: 2,
+            ..Outbox::default()
+        };
+    });
+    let user_id = UserId(1);
+
+    // subscribe
+    println!("run create webhook subscription");
+    let new_subscription = NewWebhookSubscription {
+        url: subscriber_url,
+        event_types: vec!["company_updated".to_string()],
+    };
+    let created = core
+        .run(http_client.request_with_auth_header::<CreatedWebhookSubscription>(
+            Method::Post,
+            format!("{}/webhook_subscriptions", base_url),
+            Some(serde_json::to_string(&new_subscription).unwrap()),
+            Some(user_id.to_string()),
+        ))
+        .unwrap();
+    let subscription_id = created.subscription.id;
+
+    // trigger company_updated event
+    println!("run create and update company");
+    let new_company = NewCompany {
+        name: "Webhooks UPS".to_string(),
+        label: "UPS".to_string(),
+        description: None,
+        deliveries_from: vec![Alpha3("RUS".to_string())],
+        logo: "".to_string(),
+        currency: Currency::STQ,
+    };
+    let company = core
+        .run(http_client.request_with_auth_header::<Company>(
+            Method::Post,
+            format!("{}/companies", base_url),
+            Some(serde_json::to_string(&new_company).unwrap()),
+            Some(user_id.to_string()),
+        ))
+        .unwrap();
+    let update_company = UpdateCompany {
+        name: Some("Webhooks UPS 2".to_string()),
+        label: None,
+        description: None,
+        deliveries_from: None,
+        logo: None,
+        currency: None,
+    };
+    core.run(http_client.request_with_auth_header::<Company>(
+        Method::Put,
+        format!("{}/companies/{}", base_url, company.id),
+        Some(serde_json::to_string(&update_company).unwrap()),
+        Some(user_id.to_string()),
+    ))
+    .unwrap();
+
+    // failed deliveries end up in dead letters
+    println!("run wait for dead letter");
+    let (delivery_id, _) = wait_for(|| find_company_request(&subscriber, company.id));
+    let dead_letter = wait_for(|| {
+        get_delivery(&mut core, &http_client, base_url.clone(), subscription_id, delivery_id)
+            .filter(|delivery| delivery.status == WebhookDeliveryStatus::DeadLetter)
+    });
+    assert_eq!(dead_letter.attempts, 2);
+    assert!(dead_letter.last_error.is_some());
+
+    // redelivery
+    println!("run redeliver webhook");
+    subscriber.failing.store(false, Ordering::SeqCst);
+    let redelivered = core
+        .run(http_client.request_with_auth_header::<WebhookDelivery>(
+            Method::Post,
+            format!("{}/webhook_deliveries/{}/redeliver", base_url, delivery_id),
+            None,
+            Some(user_id.to_string()),
+        ))
+        .unwrap();
+    assert_eq!(redelivered.status, WebhookDeliveryStatus::Pending);
+    wait_for(|| {
+        get_delivery(&mut core, &http_client, base_url.clone(), subscription_id, delivery_id)
+            .filter(|delivery| delivery.status == WebhookDeliveryStatus::Delivered)
+    });
+
+    // signature
+    let (_, request) = find_company_request(&subscriber, company.id).unwrap();
+    assert_eq!(
+        raw_header(&request.headers, WEBHOOK_SIGNATURE_HEADER),
+        Some(sign_webhook_payload(&created.secret, &request.body))
+    );
+    assert_eq!(
+        raw_header(&request.headers, WEBHOOK_EVENT_HEADER),
+        Some("company_updated".to_string())
+    );
+
+    // a response without body is accepted
+    println!("run deliver webhook to subscriber responding with empty body");
+    subscriber.empty_body.store(true, Ordering::SeqCst);
+    let update_company = UpdateCompany {
+        name: Some("Webhooks UPS 3".to_string()),
+        ..update_company
+    };
+    core.run(http_client.request_with_auth_header::<Company>(
+        Method::Put,
+        format!("{}/companies/{}", base_url, company.id),
+        Some(serde_json::to_string(&update_company).unwrap()),
+        Some(user_id.to_string()),
+    ))
+    .unwrap();
+    let (empty_body_delivery_id, _) = wait_for(|| find_company_request(&subscriber, company.id).filter(|&(id, _)| id != delivery_id));
+    wait_for(|| {
+        get_delivery(&mut core, &http_client, base_url.clone(), subscription_id, empty_body_delivery_id)
+            .filter(|delivery| delivery.status == WebhookDeliveryStatus::Delivered)
+    });
+
+    // cleanup
+    println!("run delete webhook subscription and company");
+    assert!(core
+        .run(http_client.request_with_auth_header::<WebhookSubscription>(
+            Method::Delete,
+            format!("{}/webhook_subscriptions/{}", base_url, subscription_id),
+            None,
+            Some(user_id.to_string()),
+        ))
+        .is_ok());
+    assert!(core
+        .run(http_client.request_with_auth_header::<Company>(
+            Method::Delete,
+            format!("{}/companies/{}", base_url, company.id),
+            None,
+            Some(user_id.to_string()),
+        ))
+        .is_ok());
+}
@@ -1,4 +1,5 @@
 extern crate delivery_lib as lib;
+extern crate diesel;
 extern crate futures;
 extern crate hyper;
 extern crate rand;
@@ -13,6 +14,8 @@ mod common;
 mod integration_companies_packages_test;
 mod integration_companies_test;
 mod integration_countries_test;
+mod integration_deliveries_test;
 mod integration_packages_test;
 mod integration_products_test;
 mod integration_user_addresses_test;
+mod integration_webhooks_test;